@@ -11,7 +11,7 @@ use std::{
 
 use hftbacktest::{
     prelude::get_precision,
-    types::{ErrorKind, LiveError, LiveEvent, Order, Status, Value},
+    types::{ErrorKind, LiveError, LiveEvent, Order, SelfTradePrevention, Status, Value},
 };
 use serde::Deserialize;
 use thiserror::Error;
@@ -22,7 +22,7 @@ use tracing::{error, warn};
 use crate::{
     binancefutures::{
         ordermanager::{OrderManager, SharedOrderManager},
-        rest::BinanceFuturesClient,
+        rest::{BinanceFuturesClient, MAX_BATCH_CANCELS, MAX_BATCH_ORDERS},
     },
     connector::{Connector, ConnectorBuilder, GetOrders, PublishEvent},
     utils::{ExponentialBackoff, Retry},
@@ -230,18 +230,28 @@ impl Connector for BinanceFutures {
 
             match client_order_id {
                 Some(client_order_id) => {
-                    let result = client
-                        .submit_order(
-                            &client_order_id,
-                            &symbol,
-                            order.side,
-                            order.price_tick as f64 * order.tick_size,
-                            get_precision(order.tick_size),
-                            order.qty,
-                            order.order_type,
-                            order.time_in_force,
-                        )
-                        .await;
+                    // Binance Futures does not provide one-cancels-other orders.
+                    let result = if order.oco {
+                        Err(BinanceFuturesError::InvalidRequest)
+                    } else {
+                        client
+                            .submit_order(
+                                &client_order_id,
+                                &symbol,
+                                order.side,
+                                order.price_tick as f64 * order.tick_size,
+                                get_precision(order.tick_size),
+                                order.qty,
+                                order.order_type,
+                                order.time_in_force,
+                                order.self_trade_prevention,
+                                order.trigger_price(),
+                                order.trigger_price_type,
+                                order.expire_timestamp,
+                                order.reduce_only,
+                            )
+                            .await
+                    };
                     match result {
                         Ok(resp) => {
                             if let Some(order) = order_manager
@@ -350,4 +360,287 @@ impl Connector for BinanceFutures {
             }
         });
     }
+
+    fn submit_orders(&self, symbol: String, orders: Vec<Order>, tx: UnboundedSender<PublishEvent>) {
+        let client = self.client.clone();
+        let order_manager = self.order_manager.clone();
+
+        tokio::spawn(async move {
+            let mut requests = Vec::with_capacity(orders.len());
+            for mut order in orders {
+                let client_order_id = order_manager
+                    .lock()
+                    .unwrap()
+                    .prepare_client_order_id(symbol.clone(), order.clone());
+
+                match client_order_id {
+                    // Binance Futures does not provide one-cancels-other orders or the decrement
+                    // self-trade prevention mode, so the order is rejected here rather than failing
+                    // the whole batch.
+                    Some(client_order_id)
+                        if order.oco
+                            || matches!(
+                                order.self_trade_prevention,
+                                SelfTradePrevention::Decrement | SelfTradePrevention::Unsupported
+                            ) =>
+                    {
+                        let error = BinanceFuturesError::InvalidRequest;
+                        let order = order_manager
+                            .lock()
+                            .unwrap()
+                            .update_submit_fail(&client_order_id, &error);
+                        publish_order(&tx, &symbol, order);
+                        publish_order_error(&tx, error);
+                    }
+                    Some(client_order_id) => {
+                        requests.push((client_order_id, order));
+                    }
+                    None => {
+                        warn!(
+                            ?order,
+                            "Coincidentally, creates a duplicated client order id. \
+                            This order request will be expired."
+                        );
+                        order.req = Status::None;
+                        order.status = Status::Expired;
+                        publish_order(&tx, &symbol, Some(order));
+                    }
+                }
+            }
+
+            for chunk in requests.chunks(MAX_BATCH_ORDERS) {
+                let batch = chunk
+                    .iter()
+                    .map(|(client_order_id, order)| {
+                        (
+                            client_order_id.clone(),
+                            symbol.clone(),
+                            order.side,
+                            order.price_tick as f64 * order.tick_size,
+                            get_precision(order.tick_size),
+                            order.qty,
+                            order.order_type,
+                            order.time_in_force,
+                            order.self_trade_prevention,
+                            order.trigger_price(),
+                            order.trigger_price_type,
+                            order.expire_timestamp,
+                            order.reduce_only,
+                        )
+                    })
+                    .collect();
+                match client.submit_orders(batch).await {
+                    Ok(results) => {
+                        for ((client_order_id, _), result) in chunk.iter().zip(results) {
+                            match result {
+                                Ok(resp) => {
+                                    let order = order_manager
+                                        .lock()
+                                        .unwrap()
+                                        .update_from_rest(client_order_id, &resp);
+                                    publish_order(&tx, &symbol, order);
+                                }
+                                Err(error) => {
+                                    let order = order_manager
+                                        .lock()
+                                        .unwrap()
+                                        .update_submit_fail(client_order_id, &error);
+                                    publish_order(&tx, &symbol, order);
+                                    publish_order_error(&tx, error);
+                                }
+                            }
+                        }
+                    }
+                    Err(error) => {
+                        // The whole batch is rejected.
+                        for (client_order_id, _) in chunk {
+                            let order = order_manager
+                                .lock()
+                                .unwrap()
+                                .update_submit_fail(client_order_id, &error);
+                            publish_order(&tx, &symbol, order);
+                        }
+                        publish_order_error(&tx, error);
+                    }
+                }
+            }
+        });
+    }
+
+    fn cancel_orders(&self, symbol: String, orders: Vec<Order>, tx: UnboundedSender<PublishEvent>) {
+        let client = self.client.clone();
+        let order_manager = self.order_manager.clone();
+
+        tokio::spawn(async move {
+            let mut client_order_ids = Vec::with_capacity(orders.len());
+            for order in orders {
+                let client_order_id = order_manager
+                    .lock()
+                    .unwrap()
+                    .get_client_order_id(&symbol, order.order_id);
+
+                match client_order_id {
+                    Some(client_order_id) => {
+                        client_order_ids.push(client_order_id);
+                    }
+                    None => {
+                        warn!(
+                            order_id = order.order_id,
+                            "client_order_id corresponding to order_id is not found; \
+                            this may be due to the order already being canceled or filled."
+                        );
+                    }
+                }
+            }
+
+            for chunk in client_order_ids.chunks(MAX_BATCH_CANCELS) {
+                match client.cancel_orders(&symbol, chunk.to_vec()).await {
+                    Ok(results) => {
+                        for (client_order_id, result) in chunk.iter().zip(results) {
+                            match result {
+                                Ok(resp) => {
+                                    let order = order_manager
+                                        .lock()
+                                        .unwrap()
+                                        .update_from_rest(client_order_id, &resp);
+                                    publish_order(&tx, &symbol, order);
+                                }
+                                Err(error) => {
+                                    let order = order_manager
+                                        .lock()
+                                        .unwrap()
+                                        .update_cancel_fail(client_order_id, &error);
+                                    publish_order(&tx, &symbol, order);
+                                    publish_order_error(&tx, error);
+                                }
+                            }
+                        }
+                    }
+                    Err(error) => {
+                        // The whole batch is rejected.
+                        for client_order_id in chunk {
+                            let order = order_manager
+                                .lock()
+                                .unwrap()
+                                .update_cancel_fail(client_order_id, &error);
+                            publish_order(&tx, &symbol, order);
+                        }
+                        publish_order_error(&tx, error);
+                    }
+                }
+            }
+        });
+    }
+
+    fn cancel_all(&self, symbol: String, tx: UnboundedSender<PublishEvent>) {
+        let client = self.client.clone();
+        let order_manager = self.order_manager.clone();
+
+        tokio::spawn(async move {
+            if let Err(error) = user_data_stream::cancel_all(
+                client,
+                symbol.clone(),
+                order_manager.clone(),
+                tx.clone(),
+            )
+            .await
+            {
+                // Clears the cancel requests of the open orders, which remain open.
+                let orders = order_manager.lock().unwrap().orders(Some(symbol.clone()));
+                for mut order in orders {
+                    order.req = Status::None;
+                    publish_order(&tx, &symbol, Some(order));
+                }
+                publish_order_error(&tx, error);
+            }
+        });
+    }
+
+    fn modify(&self, symbol: String, order: Order, tx: UnboundedSender<PublishEvent>) {
+        let client = self.client.clone();
+        let order_manager = self.order_manager.clone();
+
+        tokio::spawn(async move {
+            let client_order_id = order_manager
+                .lock()
+                .unwrap()
+                .get_client_order_id(&symbol, order.order_id);
+
+            match client_order_id {
+                Some(client_order_id) => {
+                    let result = client
+                        .modify_order(
+                            &client_order_id,
+                            &symbol,
+                            order.side,
+                            order.price_tick as f64 * order.tick_size,
+                            get_precision(order.tick_size),
+                            order.qty,
+                        )
+                        .await;
+                    match result {
+                        Ok(resp) => {
+                            if let Some(order) = order_manager
+                                .lock()
+                                .unwrap()
+                                .update_from_rest(&client_order_id, &resp)
+                            {
+                                tx.send(PublishEvent::LiveEvent(LiveEvent::Order {
+                                    symbol,
+                                    order,
+                                }))
+                                .unwrap();
+                            }
+                        }
+                        Err(error) => {
+                            if let Some(order) = order_manager
+                                .lock()
+                                .unwrap()
+                                .update_modify_fail(&client_order_id, &error)
+                            {
+                                tx.send(PublishEvent::LiveEvent(LiveEvent::Order {
+                                    symbol,
+                                    order,
+                                }))
+                                .unwrap();
+                            }
+
+                            tx.send(PublishEvent::LiveEvent(LiveEvent::Error(LiveError::with(
+                                ErrorKind::OrderError,
+                                error.into(),
+                            ))))
+                            .unwrap();
+                        }
+                    }
+                }
+                None => {
+                    warn!(
+                        order_id = order.order_id,
+                        "client_order_id corresponding to order_id is not found; \
+                        this may be due to the order already being canceled or filled."
+                    );
+                }
+            }
+        });
+    }
+}
+
+/// Publishes the order update to the bot, if there is one.
+fn publish_order(tx: &UnboundedSender<PublishEvent>, symbol: &str, order: Option<Order>) {
+    if let Some(order) = order {
+        tx.send(PublishEvent::LiveEvent(LiveEvent::Order {
+            symbol: symbol.to_string(),
+            order,
+        }))
+        .unwrap();
+    }
+}
+
+/// Publishes the order error to the bot.
+fn publish_order_error(tx: &UnboundedSender<PublishEvent>, error: BinanceFuturesError) {
+    tx.send(PublishEvent::LiveEvent(LiveEvent::Error(LiveError::with(
+        ErrorKind::OrderError,
+        error.into(),
+    ))))
+    .unwrap();
 }
@@ -50,12 +50,15 @@ where
     match s {
         "LIMIT" => Ok(OrdType::Limit),
         "MARKET" => Ok(OrdType::Market),
-        // "STOP" => Ok(OrdType::StopLimit),
-        // "TAKE_PROFIT" => Ok(OrdType::TakeProfitLimit),
-        // "STOP_MARKET" => Ok(OrdType::StopMarket),
-        // "TAKE_PROFIT_MARKET" => Ok(OrdType::TakeProfitMarket),
+        "STOP" => Ok(OrdType::StopLimit),
+        "TAKE_PROFIT" => Ok(OrdType::TakeProfitLimit),
+        "STOP_MARKET" => Ok(OrdType::StopMarket),
+        "TAKE_PROFIT_MARKET" => Ok(OrdType::TakeProfitMarket),
         // "TRAILING_STOP_MARKET" => Ok(OrdType::TrailingStopMarket),
-        s => Err(Error::invalid_value(Unexpected::Other(s), &"LIMIT,MARKET")),
+        s => Err(Error::invalid_value(
+            Unexpected::Other(s),
+            &"LIMIT,MARKET,STOP,TAKE_PROFIT,STOP_MARKET,TAKE_PROFIT_MARKET",
+        )),
     }
 }
 
@@ -69,10 +72,10 @@ where
         "IOC" => Ok(TimeInForce::IOC),
         "FOK" => Ok(TimeInForce::FOK),
         "GTX" => Ok(TimeInForce::GTX),
-        // "GTD" => Ok(TimeInForce::GTD),
+        "GTD" => Ok(TimeInForce::GTD),
         s => Err(Error::invalid_value(
             Unexpected::Other(s),
-            &"GTC,IOC,FOK,GTX",
+            &"GTC,IOC,FOK,GTX,GTD",
         )),
     }
 }
@@ -2,7 +2,7 @@ use std::sync::{Arc, Mutex};
 
 use chrono::Utc;
 use hashbrown::HashMap;
-use hftbacktest::types::{Order, OrderId, Status};
+use hftbacktest::types::{OrdType, Order, OrderId, Status};
 use tracing::error;
 
 use crate::{
@@ -71,6 +71,10 @@ impl OrderManager {
         let already_removed = order_ext.removed_by_ws || order_ext.removed_by_rest;
         if resp.transaction_time * 1_000_000 >= order_ext.order.exch_timestamp {
             order_ext.order.qty = resp.order.original_qty;
+            if resp.order.order_type == OrdType::Limit {
+                order_ext.order.price_tick =
+                    (resp.order.original_price / order_ext.order.tick_size).round() as i64;
+            }
             order_ext.order.leaves_qty =
                 resp.order.original_qty - resp.order.order_filled_accumulated_qty;
             order_ext.order.side = resp.order.side;
@@ -78,6 +82,7 @@ impl OrderManager {
             order_ext.order.exch_timestamp = resp.transaction_time * 1_000_000;
             order_ext.order.status = resp.order.order_status;
             order_ext.order.exec_qty = resp.order.order_last_filled_qty;
+            order_ext.order.exch_order_id = resp.order.order_id as u64;
             order_ext.order.order_type = resp.order.order_type;
         }
 
@@ -153,6 +158,25 @@ impl OrderManager {
         }
     }
 
+    pub fn update_modify_fail(
+        &mut self,
+        client_order_id: &ClientOrderId,
+        error: &BinanceFuturesError,
+    ) -> Option<Order> {
+        match error {
+            BinanceFuturesError::OrderError { code: -2013, .. } => {
+                // The given order may no longer exist; it could have already been filled or
+                // canceled. But, it cannot determine the order status because it lacks the
+                // necessary information.
+                self.update_from_rest_fail(client_order_id, Some(Status::None))
+            }
+            error => {
+                error!(?error, "modify error");
+                self.update_from_rest_fail(client_order_id, None)
+            }
+        }
+    }
+
     pub fn update_from_rest_fail(
         &mut self,
         client_order_id: &ClientOrderId,
@@ -203,12 +227,17 @@ impl OrderManager {
         let already_removed = order_ext.removed_by_ws || order_ext.removed_by_rest;
         if resp.update_time * 1_000_000 >= order_ext.order.exch_timestamp {
             order_ext.order.qty = resp.orig_qty;
+            if resp.ty == OrdType::Limit {
+                order_ext.order.price_tick =
+                    (resp.price / order_ext.order.tick_size).round() as i64;
+            }
             order_ext.order.leaves_qty = resp.orig_qty - resp.cum_qty;
             order_ext.order.side = resp.side;
             order_ext.order.time_in_force = resp.time_in_force;
             order_ext.order.exch_timestamp = resp.update_time * 1_000_000;
             order_ext.order.status = resp.status;
             order_ext.order.exec_qty = resp.executed_qty;
+            order_ext.order.exch_order_id = resp.order_id as u64;
             order_ext.order.order_type = resp.ty;
             order_ext.order.req = Status::None;
         }
@@ -312,6 +341,7 @@ impl OrderManager {
 
             order_ext.removed_by_rest = true;
             order_ext.order.status = Status::Canceled;
+            order_ext.order.req = Status::None;
             // todo: check if the exchange timestamp exists in the REST response.
             order_ext.order.exch_timestamp = Utc::now().timestamp_nanos_opt().unwrap();
             if !already_removed {
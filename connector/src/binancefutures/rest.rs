@@ -1,5 +1,5 @@
 use chrono::Utc;
-use hftbacktest::types::{OrdType, Side, TimeInForce};
+use hftbacktest::types::{OrdType, SelfTradePrevention, Side, TimeInForce, TriggerPriceType};
 use serde::Deserialize;
 
 use super::msg::{rest, rest::PositionInformationV2};
@@ -11,9 +11,15 @@ use crate::{
         },
         BinanceFuturesError,
     },
-    utils::sign_hmac_sha256,
+    utils::{percent_encode, sign_hmac_sha256},
 };
 
+/// The maximum number of orders that can be placed in a single batch order request.
+pub const MAX_BATCH_ORDERS: usize = 5;
+
+/// The maximum number of orders that can be canceled in a single batch cancel request.
+pub const MAX_BATCH_CANCELS: usize = 10;
+
 #[derive(Clone)]
 pub struct BinanceFuturesClient {
     client: reqwest::Client,
@@ -168,6 +174,11 @@ impl BinanceFuturesClient {
         qty: f64,
         order_type: OrdType,
         time_in_force: TimeInForce,
+        self_trade_prevention: SelfTradePrevention,
+        trigger_price: f64,
+        trigger_price_type: TriggerPriceType,
+        expire_timestamp: i64,
+        reduce_only: bool,
     ) -> Result<OrderResponse, BinanceFuturesError> {
         let mut body = String::with_capacity(200);
         body.push_str("newClientOrderId=");
@@ -184,6 +195,31 @@ impl BinanceFuturesClient {
         body.push_str(order_type.as_ref());
         body.push_str("&timeInForce=");
         body.push_str(time_in_force.as_ref());
+        match self_trade_prevention {
+            SelfTradePrevention::None => {}
+            // Binance Futures only accepts EXPIRE_TAKER, EXPIRE_MAKER, and EXPIRE_BOTH.
+            SelfTradePrevention::Decrement | SelfTradePrevention::Unsupported => {
+                return Err(BinanceFuturesError::InvalidRequest);
+            }
+            self_trade_prevention => {
+                body.push_str("&selfTradePreventionMode=");
+                body.push_str(self_trade_prevention.as_ref());
+            }
+        }
+        if order_type.is_conditional() {
+            body.push_str("&stopPrice=");
+            body.push_str(&format!("{:.prec$}", trigger_price, prec = price_prec));
+            body.push_str("&workingType=");
+            body.push_str(trigger_price_type.as_ref());
+        }
+        if time_in_force == TimeInForce::GTD {
+            // goodTillDate is in milliseconds.
+            body.push_str("&goodTillDate=");
+            body.push_str(&(expire_timestamp / 1_000_000).to_string());
+        }
+        if reduce_only {
+            body.push_str("&reduceOnly=true");
+        }
 
         let resp: OrderResponseResult = self.post("/fapi/v1/order", body).await?;
         match resp {
@@ -195,15 +231,39 @@ impl BinanceFuturesClient {
         }
     }
 
+    #[allow(clippy::type_complexity)]
     pub async fn submit_orders(
         &self,
-        orders: Vec<(String, String, Side, f64, usize, f64, OrdType, TimeInForce)>,
+        orders: Vec<(
+            String,
+            String,
+            Side,
+            f64,
+            usize,
+            f64,
+            OrdType,
+            TimeInForce,
+            SelfTradePrevention,
+            f64,
+            TriggerPriceType,
+            i64,
+            bool,
+        )>,
     ) -> Result<Vec<Result<OrderResponse, BinanceFuturesError>>, BinanceFuturesError> {
-        if orders.len() > 5 {
+        if orders.len() > MAX_BATCH_ORDERS {
+            return Err(BinanceFuturesError::InvalidRequest);
+        }
+        // Binance Futures only accepts EXPIRE_TAKER, EXPIRE_MAKER, and EXPIRE_BOTH.
+        if orders.iter().any(|order| {
+            matches!(
+                order.8,
+                SelfTradePrevention::Decrement | SelfTradePrevention::Unsupported
+            )
+        }) {
             return Err(BinanceFuturesError::InvalidRequest);
         }
         let mut body = String::with_capacity(2000 * orders.len());
-        body.push_str("{\"batchOrders\":[");
+        body.push('[');
         for (i, order) in orders.iter().enumerate() {
             if i > 0 {
                 body.push(',');
@@ -222,9 +282,28 @@ impl BinanceFuturesClient {
             body.push_str(order.6.as_ref());
             body.push_str("\",\"timeInForce\":\"");
             body.push_str(order.7.as_ref());
+            if order.8 != SelfTradePrevention::None {
+                body.push_str("\",\"selfTradePreventionMode\":\"");
+                body.push_str(order.8.as_ref());
+            }
+            if order.6.is_conditional() {
+                body.push_str("\",\"stopPrice\":\"");
+                body.push_str(&format!("{:.prec$}", order.9, prec = order.4));
+                body.push_str("\",\"workingType\":\"");
+                body.push_str(order.10.as_ref());
+            }
+            if order.7 == TimeInForce::GTD {
+                body.push_str("\",\"goodTillDate\":\"");
+                body.push_str(&(order.11 / 1_000_000).to_string());
+            }
+            if order.12 {
+                body.push_str("\",\"reduceOnly\":\"true");
+            }
             body.push_str("\"}");
         }
-        body.push_str("]}");
+        body.push(']');
+        // The order list is sent as a URL-encoded JSON array.
+        let body = format!("batchOrders={}", percent_encode(&body));
 
         let resp: Vec<OrderResponseResult> = self.post("/fapi/v1/batchOrders", body).await?;
         Ok(resp
@@ -296,23 +375,28 @@ impl BinanceFuturesClient {
         symbol: &str,
         client_order_ids: Vec<String>,
     ) -> Result<Vec<Result<OrderResponse, BinanceFuturesError>>, BinanceFuturesError> {
-        if client_order_ids.len() > 10 {
+        if client_order_ids.len() > MAX_BATCH_CANCELS {
             return Err(BinanceFuturesError::InvalidRequest);
         }
-        let mut body = String::with_capacity(100);
-        body.push_str("{\"symbol\":\"");
-        body.push_str(symbol);
-        body.push_str("\",\"origClientOrderIdList\":[");
+        let mut list = String::with_capacity(30 * client_order_ids.len());
+        list.push('[');
         for (i, client_order_id) in client_order_ids.iter().enumerate() {
             if i > 0 {
-                body.push(',');
+                list.push(',');
             }
-            body.push('\"');
-            body.push_str(client_order_id);
-            body.push('\"');
+            list.push('\"');
+            list.push_str(client_order_id);
+            list.push('\"');
         }
-        body.push_str("]}");
-        let resp: Vec<OrderResponseResult> = self.post("/fapi/v1/batchOrders", body).await?;
+        list.push(']');
+        let mut body = String::with_capacity(100);
+        body.push_str("symbol=");
+        body.push_str(symbol);
+        // The client order ID list is sent as a URL-encoded JSON array.
+        body.push_str("&origClientOrderIdList=");
+        body.push_str(&percent_encode(&list));
+
+        let resp: Vec<OrderResponseResult> = self.delete("/fapi/v1/batchOrders", body).await?;
         Ok(resp
             .into_iter()
             .map(|resp| match resp {
@@ -4,7 +4,7 @@ use std::{
     sync::{Arc, Mutex},
 };
 
-use hftbacktest::types::{ErrorKind, LiveError, LiveEvent, Order, Value};
+use hftbacktest::types::{ErrorKind, LiveError, LiveEvent, Order, Status, Value};
 use serde::Deserialize;
 use thiserror::Error;
 use tokio::sync::{broadcast, broadcast::Sender, mpsc::UnboundedSender};
@@ -12,9 +12,10 @@ use tracing::error;
 
 use crate::{
     bybit::{
-        ordermanager::{OrderManager, SharedOrderManager},
+        msg::Order as BybitOrder,
+        ordermanager::{OrderExt, OrderManager, SharedOrderManager},
         public_stream::PublicStream,
-        rest::BybitClient,
+        rest::{BybitClient, MAX_BATCH_ORDERS},
         trade_stream::OrderOp,
     },
     connector::{Connector, ConnectorBuilder, GetOrders, PublishEvent},
@@ -255,6 +256,80 @@ impl Bybit {
     }
 }
 
+impl Bybit {
+    /// Sends the orders through the batch endpoints over REST, unlike the single order requests
+    /// that are sent through the trade stream. Only the failures need to be handled here, as the
+    /// order updates are delivered through the private stream.
+    fn send_batch(
+        &self,
+        bybit_orders: Vec<BybitOrder>,
+        cancel: bool,
+        ev_tx: UnboundedSender<PublishEvent>,
+    ) {
+        if bybit_orders.is_empty() {
+            return;
+        }
+        let client = self.client.clone();
+        let category = self.config.category.clone();
+        let order_manager = self.order_manager.clone();
+
+        tokio::spawn(async move {
+            for chunk in bybit_orders.chunks(MAX_BATCH_ORDERS) {
+                let result = if cancel {
+                    client.cancel_orders(&category, chunk).await
+                } else {
+                    client.submit_orders(&category, chunk).await
+                };
+                let errors: Vec<(&str, Value)> = match result {
+                    Ok(results) => chunk
+                        .iter()
+                        .zip(results)
+                        .filter_map(|(bybit_order, result)| {
+                            result
+                                .err()
+                                .map(|error| (bybit_order.order_link_id.as_str(), error.to_value()))
+                        })
+                        .collect(),
+                    Err(error) => {
+                        // The whole batch is rejected.
+                        let error = error.to_value();
+                        chunk
+                            .iter()
+                            .map(|bybit_order| (bybit_order.order_link_id.as_str(), error.clone()))
+                            .collect()
+                    }
+                };
+                for (order_link_id, value) in errors {
+                    let result = {
+                        let mut order_man_ = order_manager.lock().unwrap();
+                        if cancel {
+                            order_man_.update_cancel_fail(order_link_id)
+                        } else {
+                            order_man_.update_submit_fail(order_link_id)
+                        }
+                    };
+                    match result {
+                        Ok(OrderExt { symbol, order }) => {
+                            ev_tx
+                                .send(PublishEvent::LiveEvent(LiveEvent::Order { symbol, order }))
+                                .unwrap();
+                        }
+                        Err(error) => {
+                            error!(?error, %order_link_id, "Couldn't find the failed order.");
+                        }
+                    }
+                    ev_tx
+                        .send(PublishEvent::LiveEvent(LiveEvent::Error(LiveError::with(
+                            ErrorKind::OrderError,
+                            value,
+                        ))))
+                        .unwrap();
+                }
+            }
+        });
+    }
+}
+
 impl ConnectorBuilder for Bybit {
     type Error = BybitError;
 
@@ -350,4 +425,125 @@ impl Connector for Bybit {
             }
         }
     }
+
+    fn submit_orders(
+        &self,
+        asset: String,
+        orders: Vec<Order>,
+        ev_tx: UnboundedSender<PublishEvent>,
+    ) {
+        let mut bybit_orders = Vec::with_capacity(orders.len());
+        for order in orders {
+            match self
+                .order_manager
+                .lock()
+                .unwrap()
+                .new_order(&asset, &self.config.category, order)
+            {
+                Ok(bybit_order) => {
+                    bybit_orders.push(bybit_order);
+                }
+                Err(error) => {
+                    ev_tx
+                        .send(PublishEvent::LiveEvent(LiveEvent::Error(LiveError::with(
+                            ErrorKind::OrderError,
+                            error.to_value(),
+                        ))))
+                        .unwrap();
+                }
+            }
+        }
+        self.send_batch(bybit_orders, false, ev_tx);
+    }
+
+    fn cancel_orders(
+        &self,
+        asset: String,
+        orders: Vec<Order>,
+        ev_tx: UnboundedSender<PublishEvent>,
+    ) {
+        let mut bybit_orders = Vec::with_capacity(orders.len());
+        for order in orders {
+            match self.order_manager.lock().unwrap().cancel_order(
+                &asset,
+                &self.config.category,
+                order.order_id,
+            ) {
+                Ok(bybit_order) => {
+                    bybit_orders.push(bybit_order);
+                }
+                Err(error) => {
+                    ev_tx
+                        .send(PublishEvent::LiveEvent(LiveEvent::Error(LiveError::with(
+                            ErrorKind::OrderError,
+                            error.to_value(),
+                        ))))
+                        .unwrap();
+                }
+            }
+        }
+        self.send_batch(bybit_orders, true, ev_tx);
+    }
+
+    fn cancel_all(&self, asset: String, ev_tx: UnboundedSender<PublishEvent>) {
+        let client = self.client.clone();
+        let category = self.config.category.clone();
+        let order_manager = self.order_manager.clone();
+
+        tokio::spawn(async move {
+            if let Err(error) = private_stream::cancel_all(
+                client,
+                category,
+                asset.clone(),
+                order_manager.clone(),
+                ev_tx.clone(),
+            )
+            .await
+            {
+                // Clears the cancel requests of the open orders, which remain open.
+                let orders = order_manager.lock().unwrap().orders(Some(asset.clone()));
+                for mut order in orders {
+                    order.req = Status::None;
+                    ev_tx
+                        .send(PublishEvent::LiveEvent(LiveEvent::Order {
+                            symbol: asset.clone(),
+                            order,
+                        }))
+                        .unwrap();
+                }
+                ev_tx
+                    .send(PublishEvent::LiveEvent(LiveEvent::Error(LiveError::with(
+                        ErrorKind::OrderError,
+                        error.to_value(),
+                    ))))
+                    .unwrap();
+            }
+        });
+    }
+
+    fn modify(&self, asset: String, order: Order, ev_tx: UnboundedSender<PublishEvent>) {
+        match self
+            .order_manager
+            .lock()
+            .unwrap()
+            .modify_order(&asset, &self.config.category, order)
+        {
+            Ok(bybit_order) => {
+                self.order_tx
+                    .send(OrderOp {
+                        op: "order.amend",
+                        bybit_order,
+                    })
+                    .unwrap();
+            }
+            Err(error) => {
+                ev_tx
+                    .send(PublishEvent::LiveEvent(LiveEvent::Error(LiveError::with(
+                        ErrorKind::OrderError,
+                        error.to_value(),
+                    ))))
+                    .unwrap();
+            }
+        }
+    }
 }
@@ -116,13 +116,13 @@ impl<'de> Visitor<'de> for StatusVisitor {
         match s {
             "New" => Ok(Status::New),
             "PartiallyFilled" => Ok(Status::PartiallyFilled),
-            "Untriggered" => Ok(Status::Unsupported),
+            "Untriggered" => Ok(Status::New),
             "Rejected" => Ok(Status::Expired),
             "PartiallyFilledCanceled" => Ok(Status::Canceled),
             "Filled" => Ok(Status::Filled),
             "Cancelled" => Ok(Status::Canceled),
-            "Triggered" => Ok(Status::Unsupported),
-            "Deactivated" => Ok(Status::Unsupported),
+            "Triggered" => Ok(Status::New),
+            "Deactivated" => Ok(Status::Canceled),
             s => Err(Error::invalid_value(Unexpected::Other(s), &"IOC or GTC")),
         }
     }
@@ -572,10 +572,37 @@ pub struct Order {
     #[serde(rename = "timeInForce")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub time_in_force: Option<String>,
+    #[serde(rename = "smpType")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub smp_type: Option<String>,
+    #[serde(rename = "triggerPrice")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trigger_price: Option<String>,
+    #[serde(rename = "triggerDirection")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trigger_direction: Option<i64>,
+    #[serde(rename = "triggerBy")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trigger_by: Option<String>,
+    #[serde(rename = "reduceOnly")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reduce_only: Option<bool>,
     #[serde(rename = "orderLinkId")]
     pub order_link_id: String,
 }
 
+#[derive(Serialize, Debug)]
+pub struct BatchOrder<'a> {
+    pub category: &'a str,
+    pub request: &'a [Order],
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BatchResult {
+    pub code: i64,
+    pub msg: String,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct RestResult {
     pub list: Option<serde_json::Value>,
@@ -3,7 +3,16 @@ use std::sync::{Arc, Mutex};
 use hashbrown::HashMap;
 use hftbacktest::{
     prelude::get_precision,
-    types::{OrdType, Order, OrderId, Side, Status, TimeInForce},
+    types::{
+        OrdType,
+        Order,
+        OrderId,
+        SelfTradePrevention,
+        Side,
+        Status,
+        TimeInForce,
+        TriggerPriceType,
+    },
 };
 
 use crate::{
@@ -51,6 +60,11 @@ impl OrderManager {
         order.order.req = Status::None;
         order.order.status = data.order_status;
         order.order.exch_timestamp = data.updated_time * 1_000_000;
+        order.order.qty = data.qty;
+        order.order.leaves_qty = data.leaves_qty;
+        if data.order_type == OrdType::Limit {
+            order.order.price_tick = (data.price / order.order.tick_size).round() as i64;
+        }
         let is_active = order.order.active();
         if !is_active {
             self.order_id_map
@@ -99,6 +113,10 @@ impl OrderManager {
         order: Order,
     ) -> Result<BybitOrder, BybitError> {
         let price_prec = get_precision(order.tick_size);
+        // Bybit does not provide one-cancels-other orders for derivatives.
+        if order.oco {
+            return Err(BybitError::InvalidArg("oco"));
+        }
         let order_link_id = format!("{}{}", self.prefix, generate_rand_string(16));
         let bybit_order = BybitOrder {
             symbol: symbol.to_string(),
@@ -111,8 +129,12 @@ impl OrderManager {
             }),
             order_type: Some({
                 match order.order_type {
-                    OrdType::Limit => "Limit".to_string(),
-                    OrdType::Market => "Market".to_string(),
+                    OrdType::Limit | OrdType::StopLimit | OrdType::TakeProfitLimit => {
+                        "Limit".to_string()
+                    }
+                    OrdType::Market | OrdType::StopMarket | OrdType::TakeProfitMarket => {
+                        "Market".to_string()
+                    }
                     OrdType::Unsupported => return Err(BybitError::InvalidArg("order_type")),
                 }
             }),
@@ -129,11 +151,47 @@ impl OrderManager {
                     TimeInForce::GTX => "PostOnly".to_string(),
                     TimeInForce::FOK => "FOK".to_string(),
                     TimeInForce::IOC => "IOC".to_string(),
-                    TimeInForce::Unsupported => {
+                    // Bybit does not provide good-till-date orders.
+                    TimeInForce::GTD | TimeInForce::Unsupported => {
                         return Err(BybitError::InvalidArg("time_in_force"));
                     }
                 }
             }),
+            smp_type: {
+                match order.self_trade_prevention {
+                    SelfTradePrevention::None => None,
+                    SelfTradePrevention::CancelNewest => Some("CancelTaker".to_string()),
+                    SelfTradePrevention::CancelOldest => Some("CancelMaker".to_string()),
+                    SelfTradePrevention::CancelBoth => Some("CancelBoth".to_string()),
+                    SelfTradePrevention::Decrement | SelfTradePrevention::Unsupported => {
+                        return Err(BybitError::InvalidArg("self_trade_prevention"));
+                    }
+                }
+            },
+            trigger_price: order
+                .order_type
+                .is_conditional()
+                .then(|| format!("{:.prec$}", order.trigger_price(), prec = price_prec)),
+            trigger_direction: {
+                match (order.order_type, order.side) {
+                    (OrdType::StopMarket | OrdType::StopLimit, Side::Buy)
+                    | (OrdType::TakeProfitMarket | OrdType::TakeProfitLimit, Side::Sell) => Some(1),
+                    (OrdType::StopMarket | OrdType::StopLimit, Side::Sell)
+                    | (OrdType::TakeProfitMarket | OrdType::TakeProfitLimit, Side::Buy) => Some(2),
+                    _ => None,
+                }
+            },
+            trigger_by: {
+                match (order.order_type.is_conditional(), order.trigger_price_type) {
+                    (false, _) => None,
+                    (true, TriggerPriceType::LastPrice) => Some("LastPrice".to_string()),
+                    (true, TriggerPriceType::MarkPrice) => Some("MarkPrice".to_string()),
+                    (true, TriggerPriceType::Unsupported) => {
+                        return Err(BybitError::InvalidArg("trigger_price_type"));
+                    }
+                }
+            },
+            reduce_only: order.reduce_only.then_some(true),
             order_link_id: order_link_id.clone(),
         };
 
@@ -176,11 +234,49 @@ impl OrderManager {
             price: None,
             category: category.to_string(),
             time_in_force: None,
+            smp_type: None,
+            trigger_price: None,
+            trigger_direction: None,
+            trigger_by: None,
+            reduce_only: None,
             order_link_id: order_link_id.clone(),
         };
         Ok(order)
     }
 
+    pub fn modify_order(
+        &mut self,
+        symbol: &str,
+        category: &str,
+        order: Order,
+    ) -> Result<BybitOrder, BybitError> {
+        let price_prec = get_precision(order.tick_size);
+        let order_link_id = self
+            .order_id_map
+            .get(&RefSymbolOrderId::new(symbol, order.order_id))
+            .ok_or(BybitError::OrderNotFound)?;
+        let bybit_order = BybitOrder {
+            symbol: symbol.to_string(),
+            side: None,
+            order_type: None,
+            qty: Some(format!("{:.5}", order.qty)),
+            price: Some(format!(
+                "{:.prec$}",
+                order.price_tick as f64 * order.tick_size,
+                prec = price_prec
+            )),
+            category: category.to_string(),
+            time_in_force: None,
+            smp_type: None,
+            trigger_price: None,
+            trigger_direction: None,
+            trigger_by: None,
+            reduce_only: None,
+            order_link_id: order_link_id.clone(),
+        };
+        Ok(bybit_order)
+    }
+
     pub fn update_submit_fail(&mut self, order_link_id: &str) -> Result<OrderExt, BybitError> {
         let mut order = self
             .orders
@@ -203,6 +299,16 @@ impl OrderManager {
         Ok(order_info)
     }
 
+    pub fn update_modify_fail(&mut self, order_link_id: &str) -> Result<OrderExt, BybitError> {
+        let mut order_info = self
+            .orders
+            .get_mut(order_link_id)
+            .cloned()
+            .ok_or(BybitError::OrderNotFound)?;
+        order_info.order.req = Status::None;
+        Ok(order_info)
+    }
+
     pub fn cancel_all(&mut self, symbol: &str) -> Vec<Order> {
         let mut removed_order_ids = Vec::new();
         for (order_link_id, order_ext) in &mut self.orders {
@@ -211,6 +317,7 @@ impl OrderManager {
             }
 
             order_ext.order.status = Status::Canceled;
+            order_ext.order.req = Status::None;
 
             self.order_id_map
                 .remove(&RefSymbolOrderId::new(symbol, order_ext.order.order_id));
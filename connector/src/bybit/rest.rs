@@ -3,12 +3,15 @@ use serde::Deserialize;
 
 use crate::{
     bybit::{
-        msg::{Position, RestResponse},
+        msg::{BatchOrder, BatchResult, Order, Position, RestResponse},
         BybitError,
     },
     utils::sign_hmac_sha256,
 };
 
+/// The maximum number of orders in a single batch request, which is the limit for spot trading.
+pub const MAX_BATCH_ORDERS: usize = 10;
+
 #[derive(Clone)]
 pub struct BybitClient {
     client: reqwest::Client,
@@ -94,6 +97,61 @@ impl BybitClient {
         }
     }
 
+    /// Places the orders in a batch and returns the result of each order.
+    pub async fn submit_orders(
+        &self,
+        category: &str,
+        orders: &[Order],
+    ) -> Result<Vec<Result<(), BybitError>>, BybitError> {
+        self.batch("/v5/order/create-batch", category, orders).await
+    }
+
+    /// Cancels the orders in a batch and returns the result of each order.
+    pub async fn cancel_orders(
+        &self,
+        category: &str,
+        orders: &[Order],
+    ) -> Result<Vec<Result<(), BybitError>>, BybitError> {
+        self.batch("/v5/order/cancel-batch", category, orders).await
+    }
+
+    async fn batch(
+        &self,
+        path: &str,
+        category: &str,
+        orders: &[Order],
+    ) -> Result<Vec<Result<(), BybitError>>, BybitError> {
+        if orders.len() > MAX_BATCH_ORDERS {
+            return Err(BybitError::InvalidArg("orders"));
+        }
+        let body = serde_json::to_string(&BatchOrder {
+            category,
+            request: orders,
+        })?;
+        let mut resp: RestResponse = self.post(path, body, &self.api_key, &self.secret).await?;
+        if resp.ret_code != 0 {
+            return Err(BybitError::OrderError {
+                code: resp.ret_code,
+                msg: resp.ret_msg,
+            });
+        }
+        // The result of each order is delivered in the same order as the request.
+        let results: Vec<BatchResult> = serde_json::from_value(resp.ret_ext_info["list"].take())?;
+        Ok(results
+            .into_iter()
+            .map(|result| {
+                if result.code != 0 {
+                    Err(BybitError::OrderError {
+                        code: result.code,
+                        msg: result.msg,
+                    })
+                } else {
+                    Ok(())
+                }
+            })
+            .collect())
+    }
+
     pub async fn get_position_information(
         &self,
         category: &str,
@@ -233,6 +233,36 @@ impl TradeStream {
                     ))))
                     .unwrap();
             }
+        } else if stream.op == "order.amend" {
+            let req_id = stream.req_id.ok_or(BybitError::InvalidReqId)?;
+            if stream.ret_code != 0 {
+                /*
+                10404: 1. op type is not found; 2. category is not correct/supported
+                10429: System level frequency protection
+                20006: reqId is duplicated
+                10016: 1. internal server error; 2. Service is restarting
+                10019: ws trade service is restarting, do not accept new request,
+                       but the request in the process is not affected.
+                       You can build new connection to be routed to normal service
+                10001: Param error
+                 */
+                let mut order_man_ = self.order_manager.lock().unwrap();
+                let order_link_id = req_id.split('/').next().ok_or(BybitError::InvalidReqId)?;
+                let OrderExt { symbol, order } = order_man_.update_modify_fail(order_link_id)?;
+                self.ev_tx
+                    .send(PublishEvent::LiveEvent(LiveEvent::Order { symbol, order }))
+                    .unwrap();
+                self.ev_tx
+                    .send(PublishEvent::LiveEvent(LiveEvent::Error(LiveError::with(
+                        ErrorKind::OrderError,
+                        BybitError::OrderError {
+                            code: stream.ret_code,
+                            msg: stream.ret_msg.clone(),
+                        }
+                        .to_value(),
+                    ))))
+                    .unwrap();
+            }
         } else {
             info!(?stream, "trade stream");
         }
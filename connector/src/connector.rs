@@ -50,6 +50,26 @@ pub trait Connector {
     /// through the channel using [`PublishEvent`]. The returned error should not be related to the
     /// exchange; instead, it should indicate a connector internal error.
     fn cancel(&self, symbol: String, order: Order, tx: UnboundedSender<PublishEvent>);
+
+    /// Submits multiple new orders for the symbol at once, using the exchange's batch order
+    /// endpoint where available. This method should not block, and the responses should be
+    /// returned through the channel using [`PublishEvent`].
+    fn submit_orders(&self, symbol: String, orders: Vec<Order>, tx: UnboundedSender<PublishEvent>);
+
+    /// Cancels multiple open orders for the symbol at once, using the exchange's batch cancel
+    /// endpoint where available. This method should not block, and the responses should be
+    /// returned through the channel using [`PublishEvent`].
+    fn cancel_orders(&self, symbol: String, orders: Vec<Order>, tx: UnboundedSender<PublishEvent>);
+
+    /// Cancels all open orders for the symbol. This method should not block, and the responses
+    /// should be returned through the channel using [`PublishEvent`].
+    fn cancel_all(&self, symbol: String, tx: UnboundedSender<PublishEvent>);
+
+    /// Modifies the price and quantity of an open order. This method should not block, and the
+    /// response should be returned through the channel using [`PublishEvent`]. The returned error
+    /// should not be related to the exchange; instead, it should indicate a connector internal
+    /// error.
+    fn modify(&self, symbol: String, order: Order, tx: UnboundedSender<PublishEvent>);
 }
 
 /// Provides `orders` method to get the current working orders.
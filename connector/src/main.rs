@@ -74,10 +74,50 @@ fn run_receive_task(
                                 // Requests to the Connector cancel the order.
                                 connector.cancel(asset, order, tx.clone());
                             }
+                            Status::Replaced => {
+                                // Requests to the Connector modify the order.
+                                connector.modify(asset, order, tx.clone());
+                            }
                             status => {
                                 error!(?status, "An invalid request was received from the bot.");
                             }
                         },
+                        LiveRequest::Orders {
+                            symbol: asset,
+                            orders,
+                        } => {
+                            let mut new_orders = Vec::new();
+                            let mut cancel_orders = Vec::new();
+                            for order in orders {
+                                match order.req {
+                                    Status::New => new_orders.push(order),
+                                    Status::Canceled => cancel_orders.push(order),
+                                    Status::Replaced => {
+                                        // There is no batch modification, so each order is
+                                        // modified separately.
+                                        connector.modify(asset.clone(), order, tx.clone());
+                                    }
+                                    status => {
+                                        error!(
+                                            ?status,
+                                            "An invalid request was received from the bot."
+                                        );
+                                    }
+                                }
+                            }
+                            if !new_orders.is_empty() {
+                                // Requests to the Connector submit the new orders at once.
+                                connector.submit_orders(asset.clone(), new_orders, tx.clone());
+                            }
+                            if !cancel_orders.is_empty() {
+                                // Requests to the Connector cancel the orders at once.
+                                connector.cancel_orders(asset, cancel_orders, tx.clone());
+                            }
+                        }
+                        LiveRequest::CancelAll { symbol: asset } => {
+                            // Requests to the Connector cancel all open orders of the asset.
+                            connector.cancel_all(asset, tx.clone());
+                        }
                         LiveRequest::RegisterInstrument {
                             symbol,
                             tick_size,
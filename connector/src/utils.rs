@@ -304,11 +304,28 @@ pub fn generate_rand_string(length: usize) -> String {
         .collect()
 }
 
+/// Percent-encodes the string for use in a query string or a request body, leaving only the
+/// unreserved characters as they are.
+pub fn percent_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len() * 3);
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char);
+            }
+            _ => {
+                write!(encoded, "%{byte:02X}").unwrap();
+            }
+        }
+    }
+    encoded
+}
+
 #[cfg(test)]
 mod tests {
     use hashbrown::HashMap;
 
-    use crate::utils::{RefSymbolOrderId, SymbolOrderId};
+    use crate::utils::{percent_encode, RefSymbolOrderId, SymbolOrderId};
 
     #[test]
     fn equivalent_symbol_order_id() {
@@ -323,4 +340,12 @@ mod tests {
             "value1"
         )
     }
+
+    #[test]
+    fn percent_encode_json() {
+        assert_eq!(
+            percent_encode("[\"abc-1\",\"d_e.f~\"]"),
+            "%5B%22abc-1%22%2C%22d_e.f~%22%5D"
+        );
+    }
 }
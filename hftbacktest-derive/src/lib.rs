@@ -222,11 +222,9 @@ pub fn build_asset(input: TokenStream) -> TokenStream {
 
                         let l3 = qm_ident_str == "L3FIFOQueueModel";
                         let (local_ident, exch_ident) = if l3 {
-                            // todo: L3PartialFillExchange is unsupported. This is verified within
-                            //  the build functions in the py-hftbacktest module.
                             (
                                 Ident::new("L3Local", Span::call_site()),
-                                Ident::new("L3NoPartialFillExchange", Span::call_site()),
+                                Ident::new(&format!("L3{}", em_ident), Span::call_site()),
                             )
                         } else {
                             (Ident::new("Local", Span::call_site()), em_ident.clone())
@@ -329,7 +327,9 @@ pub fn build_asset(input: TokenStream) -> TokenStream {
 
                             Asset {
                                 local,
-                                exch
+                                exch,
+                                custom: Vec::new(),
+                                funding: None
                             }
                         },
                     });
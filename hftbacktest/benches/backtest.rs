@@ -0,0 +1,98 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use hftbacktest::{
+    backtest::{
+        assettype::LinearAsset,
+        data::{Data, DataSource},
+        models::{CommonFees, ConstantLatency, RiskAdverseQueueModel, TradingValueFeeModel},
+        Backtest,
+        L2AssetBuilder,
+    },
+    prelude::{Bot, HashMapMarketDepth},
+    types::{Event, BUY_EVENT, DEPTH_EVENT, EXCH_EVENT, LOCAL_EVENT, SELL_EVENT, TRADE_EVENT},
+};
+
+const NUM_ROWS: i64 = 10_000;
+
+/// Generates the feed data of an asset, one row every 1µs. The timestamps are offset by the asset
+/// number so that the events of the assets interleave.
+fn feed_data(asset_no: usize) -> Data<Event> {
+    let events: Vec<_> = (0..NUM_ROWS)
+        .map(|i| {
+            let (ev, px, qty) = match i % 4 {
+                0 => (DEPTH_EVENT | BUY_EVENT, 100.0, 1.0 + (i % 5) as f64),
+                1 => (DEPTH_EVENT | SELL_EVENT, 100.1, 1.0),
+                2 => (TRADE_EVENT | SELL_EVENT, 100.0, 0.5),
+                _ => (TRADE_EVENT | BUY_EVENT, 100.1, 0.5),
+            };
+            let exch_ts = i * 1000 + asset_no as i64;
+            Event {
+                ev: EXCH_EVENT | LOCAL_EVENT | ev,
+                exch_ts,
+                local_ts: exch_ts + 100,
+                px,
+                qty,
+                order_id: 0,
+                ival: 0,
+                fval: 0.0,
+            }
+        })
+        .collect();
+    Data::from_items(&events)
+}
+
+fn build(data: &[Data<Event>]) -> Backtest<HashMapMarketDepth> {
+    let mut builder = Backtest::builder();
+    for data in data {
+        builder = builder.add_asset(
+            L2AssetBuilder::new()
+                .data(vec![DataSource::Data(data.clone())])
+                .latency_model(ConstantLatency::new(1000, 1000))
+                .asset_type(LinearAsset::new(1.0))
+                .fee_model(TradingValueFeeModel::new(CommonFees::new(0.0, 0.0)))
+                .queue_model(RiskAdverseQueueModel::new())
+                .depth(|| HashMapMarketDepth::new(0.1, 0.01))
+                .build()
+                .unwrap(),
+        );
+    }
+    builder.build().unwrap()
+}
+
+/// Measures the event scheduling throughput of a backtest that runs through the data of the
+/// given number of assets, with and without a periodic timer.
+fn backtest(c: &mut Criterion) {
+    let mut group = c.benchmark_group("backtest");
+    group.sample_size(10);
+    for num_assets in [1, 2, 5, 100] {
+        let data: Vec<_> = (0..num_assets).map(feed_data).collect();
+        group.throughput(Throughput::Elements(num_assets as u64 * NUM_ROWS as u64));
+
+        group.bench_with_input(
+            BenchmarkId::new("goto_end", num_assets),
+            &data,
+            |b, data| {
+                b.iter_batched(
+                    || build(data),
+                    |mut hbt| hbt.goto_end().unwrap(),
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+
+        group.bench_with_input(BenchmarkId::new("timer", num_assets), &data, |b, data| {
+            b.iter_batched(
+                || {
+                    let mut hbt = build(data);
+                    hbt.set_timer(1, 0, 100_000);
+                    hbt
+                },
+                |mut hbt| while hbt.elapse(1_000_000_000).unwrap() {},
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, backtest);
+criterion_main!(benches);
@@ -97,21 +97,26 @@ where
                 .filter(|(order_id, _)| !orders.contains_key(&order_id))
                 .map(|v| v)
                 .collect();
-            for order_id in cancel_order_ids {
-                hbt.cancel(0, order_id, false).unwrap();
-            }
-            for (order_id, order_price) in new_orders {
-                hbt.submit_buy_order(
-                    0,
+            // Sends the requests in batches rather than one by one.
+            hbt.cancel_orders(0, &cancel_order_ids, false).unwrap();
+            let new_orders = new_orders
+                .into_iter()
+                .map(|(order_id, order_price)| OrderRequest {
                     order_id,
-                    order_price,
-                    order_qty,
-                    TimeInForce::GTX,
-                    OrdType::Limit,
-                    false,
-                )
-                .unwrap();
-            }
+                    price: order_price,
+                    qty: order_qty,
+                    side: Side::Buy,
+                    time_in_force: TimeInForce::GTX,
+                    order_type: OrdType::Limit,
+                    self_trade_prevention: SelfTradePrevention::None,
+                    trigger_price: 0.0,
+                    trigger_price_type: TriggerPriceType::LastPrice,
+                    oco_order_id: None,
+                    expire_timestamp: 0,
+                    reduce_only: false,
+                })
+                .collect();
+            hbt.submit_orders(0, new_orders, false).unwrap();
         }
 
         {
@@ -143,21 +148,26 @@ where
                 .filter(|(order_id, _)| !orders.contains_key(&order_id))
                 .map(|v| v)
                 .collect();
-            for order_id in cancel_order_ids {
-                hbt.cancel(0, order_id, false).unwrap();
-            }
-            for (order_id, order_price) in new_orders {
-                hbt.submit_sell_order(
-                    0,
+            // Sends the requests in batches rather than one by one.
+            hbt.cancel_orders(0, &cancel_order_ids, false).unwrap();
+            let new_orders = new_orders
+                .into_iter()
+                .map(|(order_id, order_price)| OrderRequest {
                     order_id,
-                    order_price,
-                    order_qty,
-                    TimeInForce::GTX,
-                    OrdType::Limit,
-                    false,
-                )
-                .unwrap();
-            }
+                    price: order_price,
+                    qty: order_qty,
+                    side: Side::Sell,
+                    time_in_force: TimeInForce::GTX,
+                    order_type: OrdType::Limit,
+                    self_trade_prevention: SelfTradePrevention::None,
+                    trigger_price: 0.0,
+                    trigger_price_type: TriggerPriceType::LastPrice,
+                    oco_order_id: None,
+                    expire_timestamp: 0,
+                    reduce_only: false,
+                })
+                .collect();
+            hbt.submit_orders(0, new_orders, false).unwrap();
         }
     }
     Ok(())
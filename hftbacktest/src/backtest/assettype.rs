@@ -1,3 +1,5 @@
+use crate::types::Side;
+
 /// Calculates the value amount and the equity according to the asset type.
 pub trait AssetType {
     /// Calculates the value amount.
@@ -5,6 +7,23 @@ pub trait AssetType {
 
     /// Calculates the equity.
     fn equity(&self, price: f64, balance: f64, position: f64, fee: f64) -> f64;
+
+    /// Calculates the change in the balance when the funding is settled for the position at the
+    /// mark price. Long positions pay short positions when the funding rate is positive.
+    fn funding(&self, mark_price: f64, position: f64, funding_rate: f64) -> f64;
+
+    /// Calculates the deductions from the position and the balance when the fee is charged for a
+    /// fill, returned as `(position, balance)`. By default, the fee is accumulated separately and
+    /// nothing is deducted.
+    fn fee_deduction(&self, _side: Side, _exec_price: f64, _fee: f64) -> (f64, f64) {
+        (0.0, 0.0)
+    }
+
+    /// Returns `true` if an order must be covered by the inventories, the position for sell orders
+    /// and the balance for buy orders, to be accepted by the exchange.
+    fn requires_inventory(&self) -> bool {
+        false
+    }
 }
 
 /// The common type of asset where the contract's notional value is linear to the quote currency.
@@ -28,6 +47,10 @@ impl AssetType for LinearAsset {
     fn equity(&self, price: f64, balance: f64, position: f64, fee: f64) -> f64 {
         balance + self.contract_size * position * price - fee
     }
+
+    fn funding(&self, mark_price: f64, position: f64, funding_rate: f64) -> f64 {
+        -self.contract_size * position * mark_price * funding_rate
+    }
 }
 
 /// The contract’s notional value is denominated in the quote currency.
@@ -51,4 +74,66 @@ impl AssetType for InverseAsset {
     fn equity(&self, price: f64, balance: f64, position: f64, fee: f64) -> f64 {
         -balance - self.contract_size * position / price - fee
     }
+
+    fn funding(&self, mark_price: f64, position: f64, funding_rate: f64) -> f64 {
+        // The balance is negated in the equity.
+        self.contract_size * position / mark_price * funding_rate
+    }
+}
+
+/// The currency in which the fee of a spot asset is charged.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SpotFeeCurrency {
+    /// The fee is charged in the received currency, which is the base currency for buys and the
+    /// quote currency for sells, and is deducted from the received amount.
+    Received,
+    /// The fee is charged in a third currency, such as the exchange's own token, and is
+    /// accumulated separately as a quote-currency value.
+    Other,
+}
+
+/// A spot asset, which is exchanged for the quote currency. The position is the base currency
+/// inventory and the balance is the quote currency inventory, and the exchange rejects sell orders
+/// that the base inventory cannot cover and buy orders that the quote inventory cannot cover,
+/// including the open orders.
+#[derive(Clone)]
+pub struct SpotAsset {
+    fee_currency: SpotFeeCurrency,
+}
+
+impl SpotAsset {
+    /// Constructs an instance of `SpotAsset` with the currency in which the fee is charged.
+    pub fn new(fee_currency: SpotFeeCurrency) -> Self {
+        Self { fee_currency }
+    }
+}
+
+impl AssetType for SpotAsset {
+    fn amount(&self, exec_price: f64, qty: f64) -> f64 {
+        exec_price * qty
+    }
+
+    fn equity(&self, price: f64, balance: f64, position: f64, fee: f64) -> f64 {
+        match self.fee_currency {
+            // The fee is already deducted from the inventories.
+            SpotFeeCurrency::Received => balance + position * price,
+            SpotFeeCurrency::Other => balance + position * price - fee,
+        }
+    }
+
+    fn funding(&self, _mark_price: f64, _position: f64, _funding_rate: f64) -> f64 {
+        0.0
+    }
+
+    fn fee_deduction(&self, side: Side, exec_price: f64, fee: f64) -> (f64, f64) {
+        match (self.fee_currency, side) {
+            (SpotFeeCurrency::Received, Side::Buy) => (fee / exec_price, 0.0),
+            (SpotFeeCurrency::Received, Side::Sell) => (0.0, fee),
+            _ => (0.0, 0.0),
+        }
+    }
+
+    fn requires_inventory(&self) -> bool {
+        true
+    }
 }
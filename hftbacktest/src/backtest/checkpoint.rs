@@ -0,0 +1,138 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+};
+
+use bincode::{config, Decode, Encode};
+
+use crate::{backtest::BacktestError, types::TimerId};
+
+/// Captures the state of a running backtest at a point in time.
+///
+/// It holds the current timestamp, the event timestamps, the timers, and the state of each local
+/// and exchange processor, including the market depth, the orders, the in-flight orders, the
+/// trading state values, the queue position states, and the position in the feed data.
+///
+/// A checkpoint can be restored to a backtest that is built in the same way as the backtest the
+/// checkpoint is taken from, which means the same data in the same order and the same models for
+/// each asset. Since a `Checkpoint` can be restored several times, it can also be used to fork a
+/// backtest and run different strategies from an identical market state.
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct Checkpoint {
+    pub(crate) cur_ts: i64,
+    pub(crate) evs_timestamps: Vec<i64>,
+    pub(crate) evs_invalid: usize,
+    pub(crate) evs_timers: Vec<(TimerId, i64, i64)>,
+    pub(crate) local: Vec<Vec<u8>>,
+    pub(crate) exch: Vec<Vec<u8>>,
+    pub(crate) custom: Vec<Vec<u8>>,
+    pub(crate) funding: Vec<Vec<u8>>,
+}
+
+impl Checkpoint {
+    /// Returns the backtesting timestamp at which this checkpoint is taken.
+    pub fn timestamp(&self) -> i64 {
+        self.cur_ts
+    }
+
+    /// Returns the number of assets in this checkpoint.
+    pub fn num_assets(&self) -> usize {
+        self.local.len()
+    }
+
+    /// Saves this checkpoint to the file.
+    pub fn save(&self, path: &str) -> Result<(), BacktestError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        bincode::encode_into_std_write(self, &mut writer, config::standard())
+            .map_err(|error| BacktestError::CheckpointError(error.to_string()))?;
+        Ok(())
+    }
+
+    /// Loads a checkpoint from the file.
+    pub fn load(path: &str) -> Result<Self, BacktestError> {
+        let mut reader = BufReader::new(File::open(path)?);
+        bincode::decode_from_std_read(&mut reader, config::standard())
+            .map_err(|error| BacktestError::CheckpointError(error.to_string()))
+    }
+}
+
+/// Encodes the value into bytes to be stored in a [`Checkpoint`].
+pub fn encode<T: Encode>(value: &T) -> Result<Vec<u8>, BacktestError> {
+    bincode::encode_to_vec(value, config::standard())
+        .map_err(|error| BacktestError::CheckpointError(error.to_string()))
+}
+
+/// Decodes the value from bytes stored in a [`Checkpoint`].
+pub fn decode<T: Decode>(bytes: &[u8]) -> Result<T, BacktestError> {
+    let (value, _) = bincode::decode_from_slice(bytes, config::standard())
+        .map_err(|error| BacktestError::CheckpointError(error.to_string()))?;
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backtest::checkpoint::{decode, encode, Checkpoint},
+        types::{OrdType, Order, Side, StateValues, TimeInForce},
+    };
+
+    #[test]
+    fn test_save_load() {
+        let checkpoint = Checkpoint {
+            cur_ts: 100,
+            evs_timestamps: vec![100, 200, i64::MAX, 300],
+            evs_invalid: 1,
+            evs_timers: vec![(1, 150, 0), (2, 200, 100)],
+            local: vec![vec![1, 2, 3]],
+            exch: vec![vec![4, 5]],
+            custom: vec![vec![6]],
+            funding: vec![vec![]],
+        };
+        let path = std::env::temp_dir().join("hftbacktest_test_checkpoint.bin");
+        let path = path.to_str().unwrap();
+        checkpoint.save(path).unwrap();
+        let loaded = Checkpoint::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(loaded.timestamp(), 100);
+        assert_eq!(loaded.num_assets(), 1);
+        assert_eq!(loaded.evs_timestamps, checkpoint.evs_timestamps);
+        assert_eq!(loaded.evs_invalid, 1);
+        assert_eq!(loaded.evs_timers, checkpoint.evs_timers);
+        assert_eq!(loaded.local, checkpoint.local);
+        assert_eq!(loaded.exch, checkpoint.exch);
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let order = Order::new(
+            1,
+            1000,
+            0.1,
+            2.0,
+            Side::Buy,
+            OrdType::Limit,
+            TimeInForce::GTC,
+        );
+        let state_values = StateValues {
+            position: 1.0,
+            balance: -100.0,
+            fee: 0.1,
+            num_trades: 1,
+            trading_volume: 1.0,
+            trading_value: 100.0,
+            funding: 0.5,
+            initial_margin: 10.0,
+            maintenance_margin: 1.0,
+            num_liquidations: 0,
+            settlement: 0.0,
+        };
+        let bytes = encode(&(vec![(order, 10i64)], state_values.clone())).unwrap();
+        let (orders, decoded): (Vec<(Order, i64)>, StateValues) = decode(&bytes).unwrap();
+        assert_eq!(decoded, state_values);
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].0.order_id, 1);
+        assert_eq!(orders[0].0.price_tick, 1000);
+        assert_eq!(orders[0].1, 10);
+    }
+}
@@ -0,0 +1,244 @@
+use std::{any::Any, io::Error as IoError, mem, ptr, slice};
+
+use bincode::{Decode, Encode};
+
+use crate::{
+    backtest::{
+        checkpoint,
+        data::{Data, DataSource, NpyDTyped, Reader},
+        BacktestError,
+    },
+    types::Event,
+};
+
+/// Provides the timestamp at which an item of custom data is received by the local, which is when
+/// the strategy can see the item.
+///
+/// **Example**
+/// ```ignore
+/// #[repr(C)]
+/// #[derive(Clone, Debug, NpyDTyped)]
+/// pub struct MarkPrice {
+///     pub local_ts: i64,
+///     pub px: f64,
+/// }
+///
+/// unsafe impl POD for MarkPrice {}
+///
+/// impl CustomData for MarkPrice {
+///     fn local_ts(&self) -> i64 {
+///         self.local_ts
+///     }
+/// }
+/// ```
+pub trait CustomData: NpyDTyped + Clone + 'static {
+    /// Returns the local receipt timestamp.
+    fn local_ts(&self) -> i64;
+}
+
+impl CustomData for Event {
+    fn local_ts(&self) -> i64 {
+        self.local_ts
+    }
+}
+
+/// Provides an auxiliary data stream of an asset, such as alpha signals, the mark price, or the
+/// index price, which is scheduled along with the feed data by the local receipt timestamp.
+pub trait CustomStream {
+    /// Prepares to process the data. This is invoked when the backtesting is initiated.
+    /// If successful, returns the timestamp of the first item.
+    fn initialize_data(&mut self) -> Result<i64, BacktestError>;
+
+    /// Makes the next item the latest item. This is invoked when the backtesting time reaches the
+    /// timestamp of the item.
+    /// If successful, returns the timestamp of the next item.
+    fn process_data(&mut self) -> Result<i64, BacktestError>;
+
+    /// Returns the latest item, or `None` if no item has been received yet.
+    fn latest(&self) -> Option<&dyn Any>;
+
+    /// Returns the encoded state of this stream to be stored in a
+    /// [`Checkpoint`](crate::backtest::checkpoint::Checkpoint).
+    fn checkpoint(&self) -> Result<Vec<u8>, BacktestError>;
+
+    /// Restores the state returned by [`CustomStream::checkpoint`].
+    fn restore(&mut self, state: &[u8]) -> Result<(), BacktestError>;
+}
+
+/// Builds a [`CustomStream`] given whether to load the next data in parallel.
+pub(crate) type CustomStreamBuilder =
+    Box<dyn FnOnce(bool) -> Result<Box<dyn CustomStream>, IoError>>;
+
+/// A [`CustomStream`] that reads the items of type `D` from the given data.
+pub struct CustomDataStream<D>
+where
+    D: CustomData,
+{
+    reader: Reader<D>,
+    data: Data<D>,
+    row_num: usize,
+    latest: Option<D>,
+}
+
+/// The custom data stream's state stored in a checkpoint.
+#[derive(Encode, Decode)]
+struct CustomStreamState {
+    data_pos: usize,
+    row_num: usize,
+    // The raw bytes of the latest item.
+    latest: Option<Vec<u8>>,
+}
+
+impl<D> CustomDataStream<D>
+where
+    D: CustomData,
+{
+    /// Constructs a `CustomDataStream` that reads the given data. The items in the `data` vector
+    /// should be arranged in the chronological order.
+    pub fn new(data: Vec<DataSource<D>>, parallel_load: bool) -> Result<Self, IoError> {
+        Ok(Self {
+            reader: Reader::builder()
+                .parallel_load(parallel_load)
+                .data(data)
+                .build()?,
+            data: Data::empty(),
+            row_num: 0,
+            latest: None,
+        })
+    }
+
+    /// Moves on to the next non-empty data, releasing the current one.
+    fn next_data(&mut self) -> Result<(), BacktestError> {
+        loop {
+            let next_data = self.reader.next_data()?;
+            let data = mem::replace(&mut self.data, next_data);
+            self.reader.release(data);
+            if !self.data.is_empty() {
+                self.row_num = 0;
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl<D> CustomStream for CustomDataStream<D>
+where
+    D: CustomData,
+{
+    fn initialize_data(&mut self) -> Result<i64, BacktestError> {
+        self.next_data()?;
+        Ok(self.data[0].local_ts())
+    }
+
+    fn process_data(&mut self) -> Result<i64, BacktestError> {
+        self.latest = Some(self.data[self.row_num].clone());
+        if self.row_num + 1 < self.data.len() {
+            self.row_num += 1;
+        } else {
+            self.next_data()?;
+        }
+        Ok(self.data[self.row_num].local_ts())
+    }
+
+    fn latest(&self) -> Option<&dyn Any> {
+        self.latest.as_ref().map(|latest| latest as &dyn Any)
+    }
+
+    fn checkpoint(&self) -> Result<Vec<u8>, BacktestError> {
+        checkpoint::encode(&CustomStreamState {
+            data_pos: self.reader.position(),
+            row_num: self.row_num,
+            latest: self.latest.as_ref().map(|latest| {
+                // D is plain old data.
+                unsafe { slice::from_raw_parts(latest as *const D as *const u8, size_of::<D>()) }
+                    .to_vec()
+            }),
+        })
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<(), BacktestError> {
+        let state: CustomStreamState = checkpoint::decode(state)?;
+        if state.data_pos > 0 {
+            self.reader.seek(state.data_pos - 1);
+            let restored = self.reader.next_data()?;
+            let released = mem::replace(&mut self.data, restored);
+            self.reader.release(released);
+        }
+        self.row_num = state.row_num;
+        self.latest = match state.latest {
+            Some(bytes) if bytes.len() == size_of::<D>() => {
+                // D is plain old data.
+                Some(unsafe { ptr::read_unaligned(bytes.as_ptr() as *const D) })
+            }
+            Some(_) => {
+                return Err(BacktestError::CheckpointError(
+                    "the custom data type does not match".to_string(),
+                ));
+            }
+            None => None,
+        };
+        Ok(())
+    }
+}
+
+/// Holds the custom data streams of all assets, numbered across all assets in the order of the
+/// assets.
+pub(crate) struct CustomStreams {
+    streams: Vec<Box<dyn CustomStream>>,
+    // The stream number of the first stream of each asset, followed by the total number of
+    // streams.
+    offset: Vec<usize>,
+}
+
+impl CustomStreams {
+    pub fn new(custom: Vec<Vec<Box<dyn CustomStream>>>) -> Self {
+        let mut offset = Vec::with_capacity(custom.len() + 1);
+        offset.push(0);
+        for streams in custom.iter() {
+            offset.push(offset.last().unwrap() + streams.len());
+        }
+        Self {
+            streams: custom.into_iter().flatten().collect(),
+            offset,
+        }
+    }
+
+    /// Returns the total number of streams.
+    pub fn len(&self) -> usize {
+        self.streams.len()
+    }
+
+    /// Returns the stream given the stream number across all assets.
+    pub fn get_mut(&mut self, stream_no: usize) -> &mut dyn CustomStream {
+        self.streams[stream_no].as_mut()
+    }
+
+    /// Returns the latest item of the asset's stream.
+    ///
+    /// Panics if the stream does not exist or its item type is not `D`.
+    pub fn latest<D>(&self, asset_no: usize, stream_no: usize) -> Option<&D>
+    where
+        D: 'static,
+    {
+        let streams = &self.streams[self.offset[asset_no]..self.offset[asset_no + 1]];
+        streams[stream_no].latest().map(|latest| {
+            latest
+                .downcast_ref::<D>()
+                .expect("the custom data type does not match")
+        })
+    }
+
+    pub fn checkpoint(&self) -> Result<Vec<Vec<u8>>, BacktestError> {
+        self.streams
+            .iter()
+            .map(|stream| stream.checkpoint())
+            .collect()
+    }
+
+    pub fn restore(&mut self, states: &[Vec<u8>]) -> Result<(), BacktestError> {
+        for (stream, state) in self.streams.iter_mut().zip(states.iter()) {
+            stream.restore(state)?;
+        }
+        Ok(())
+    }
+}
@@ -3,14 +3,25 @@ mod reader;
 
 use std::{
     marker::PhantomData,
-    mem::size_of,
+    mem::{size_of, size_of_val},
     ops::{Index, IndexMut},
+    ptr,
     ptr::null_mut,
     rc::Rc,
     slice::SliceIndex,
+    sync::Arc,
 };
 
-pub use npy::{read_npy_file, read_npz_file, write_npy, Field, NpyDTyped, NpyHeader};
+pub use npy::{
+    read_npy_file,
+    read_npz_file,
+    write_npy,
+    write_npy_data,
+    write_npy_header,
+    Field,
+    NpyDTyped,
+    NpyHeader,
+};
 pub use reader::{Cache, DataPreprocess, DataSource, FeedLatencyAdjustment, Reader, ReaderBuilder};
 
 use crate::utils::{AlignedArray, CACHE_LINE_SIZE};
@@ -59,6 +70,38 @@ where
         }
     }
 
+    /// Constructs `Data` by copying the given items.
+    pub fn from_items(items: &[D]) -> Self {
+        if items.is_empty() {
+            return Self::empty();
+        }
+        let size = size_of_val(items);
+        let ptr = DataPtr::new(size);
+        unsafe {
+            ptr::copy_nonoverlapping(items.as_ptr() as *const u8, ptr.at(0) as *mut u8, size);
+            Self::from_data_ptr(ptr, 0)
+        }
+    }
+
+    /// Constructs `Data` by copying the underlying buffer of this `Data` from its offset, so that
+    /// the resulting `Data` owns its own buffer.
+    pub fn deep_copy(&self) -> Self {
+        if self.ptr.len() == 0 {
+            return Self::empty();
+        }
+        let size = self.ptr.len() - self.offset;
+        let ptr = DataPtr::new(size);
+        unsafe {
+            ptr::copy_nonoverlapping(self.ptr.at(self.offset), ptr.at(0) as *mut u8, size);
+            Self::from_data_ptr(ptr, 0)
+        }
+    }
+
+    /// Returns `true` if this `Data` refers to the buffer of a [`SharedData`].
+    pub fn is_shared(&self) -> bool {
+        self.ptr.shared.is_some()
+    }
+
     /// Constructs `Data` from [`DataPtr`] with the specified offset.
     ///
     /// # Safety
@@ -112,6 +155,9 @@ where
     D: POD + Clone,
 {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        if self.ptr.shared.is_some() {
+            panic!("Shared data is read-only.");
+        }
         let size = size_of::<D>();
         let i = self.offset + index * size;
         if i + size > self.ptr.len() {
@@ -121,10 +167,120 @@ where
     }
 }
 
+/// Provides read-only access to an array of structs from the buffer, which can be shared across
+/// threads.
+///
+/// [`Data`] is bound to the thread that owns it, so running several backtests on different threads
+/// would otherwise require each thread to load and decompress the same data on its own.
+/// `SharedData` allows the data to be loaded once and then read concurrently by multiple backtests.
+/// Use [`SharedData::to_data`] to obtain a [`Data`] that refers to the shared buffer without
+/// copying, which can be given to a backtest through [`DataSource::Data`].
+///
+/// Since the buffer is shared, it is read-only. Any preprocessing, such as
+/// [`FeedLatencyAdjustment`], should be applied before the data is shared; otherwise, the data is
+/// copied before being preprocessed.
+#[derive(Clone, Debug)]
+pub struct SharedData<D>
+where
+    D: POD + Clone,
+{
+    ptr: Arc<SharedDataPtr>,
+    offset: usize,
+    _d_marker: PhantomData<D>,
+}
+
+impl<D> SharedData<D>
+where
+    D: POD + Clone,
+{
+    /// Returns the length of the array.
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        let size = size_of::<D>();
+        (self.ptr.0.len() - self.offset) / size
+    }
+
+    /// Returns `true` if the `SharedData` is empty.
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.ptr.0.len() == 0
+    }
+
+    /// Returns a [`Data`] that refers to the shared buffer without copying. The buffer is kept
+    /// alive until all `SharedData` and [`Data`] referring to it are dropped.
+    ///
+    /// The returned [`Data`] is read-only, as it may be read by other threads at the same time;
+    /// modifying it through [`IndexMut`] panics. Use [`Data::deep_copy`] to obtain a modifiable
+    /// copy.
+    pub fn to_data(&self) -> Data<D> {
+        let ptr = DataPtr {
+            ptr: self.ptr.0.ptr,
+            managed: false,
+            shared: Some(self.ptr.clone()),
+        };
+        unsafe { Data::from_data_ptr(ptr, self.offset) }
+    }
+}
+
+impl<D> From<Data<D>> for SharedData<D>
+where
+    D: POD + Clone,
+{
+    /// Converts [`Data`] into `SharedData` without copying if the `Data` is not referenced
+    /// elsewhere and owns its buffer; otherwise, the buffer is copied.
+    fn from(data: Data<D>) -> Self {
+        if let Some(shared) = &data.ptr.shared {
+            return Self {
+                ptr: shared.clone(),
+                offset: data.offset,
+                _d_marker: PhantomData,
+            };
+        }
+        let data = if Rc::strong_count(&data.ptr) == 1 && data.ptr.managed {
+            data
+        } else {
+            data.deep_copy()
+        };
+        let offset = data.offset;
+        let ptr = Rc::try_unwrap(data.ptr).unwrap();
+        Self {
+            ptr: Arc::new(SharedDataPtr(ptr)),
+            offset,
+            _d_marker: PhantomData,
+        }
+    }
+}
+
+impl<D> Index<usize> for SharedData<D>
+where
+    D: POD + Clone,
+{
+    type Output = D;
+
+    #[inline(always)]
+    fn index(&self, index: usize) -> &Self::Output {
+        let size = size_of::<D>();
+        let i = self.offset + index * size;
+        if i + size > self.ptr.0.len() {
+            panic!("Out of the size.");
+        }
+        unsafe { &*(self.ptr.0.at(i) as *const D) }
+    }
+}
+
+/// Wraps the [`DataPtr`] of [`SharedData`]. The buffer is only read through `SharedData`, so it
+/// can be safely shared between threads.
+#[derive(Debug)]
+struct SharedDataPtr(DataPtr);
+
+unsafe impl Send for SharedDataPtr {}
+unsafe impl Sync for SharedDataPtr {}
+
 #[derive(Debug)]
 pub struct DataPtr {
     ptr: *mut [u8],
     managed: bool,
+    shared: Option<Arc<SharedDataPtr>>,
 }
 
 impl DataPtr {
@@ -133,6 +289,7 @@ impl DataPtr {
         Self {
             ptr: arr.into_raw(),
             managed: true,
+            shared: None,
         }
     }
 
@@ -147,6 +304,7 @@ impl DataPtr {
         Self {
             ptr,
             managed: false,
+            shared: None,
         }
     }
 
@@ -168,6 +326,7 @@ impl Default for DataPtr {
         Self {
             ptr: null_mut::<[u8; 0]>() as *mut [u8],
             managed: false,
+            shared: None,
         }
     }
 }
@@ -265,6 +265,40 @@ pub fn write_npy<W: Write, T: NpyDTyped>(write: &mut W, data: &[T]) -> std::io::
     Ok(())
 }
 
+/// Writes the header of a structured array `numpy` file with `len` items, followed by which the
+/// items can be appended using [`write_npy_data`]. The header has the same size regardless of
+/// `len`, so it can be overwritten in place once the final number of items is known.
+pub fn write_npy_header<W: Write, T: NpyDTyped>(write: &mut W, len: usize) -> std::io::Result<()> {
+    let header = |len| {
+        NpyHeader {
+            descr: T::descr(),
+            fortran_order: false,
+            shape: vec![len],
+        }
+        .to_string_padding()
+    };
+    // Pads the header to the size of the longest one, which keeps the data aligned.
+    let max_len = header(usize::MAX).len();
+    let mut header_str = header(len);
+    header_str.pop();
+    while header_str.len() + 1 < max_len {
+        header_str.push(' ');
+    }
+    header_str.push('\n');
+
+    write.write_all(b"\x93NUMPY\x01\x00")?;
+    let len = header_str.len() as u16;
+    write.write_all(&len.to_le_bytes())?;
+    write.write_all(header_str.as_bytes())?;
+    Ok(())
+}
+
+/// Appends the items to a structured array `numpy` file whose header is written by
+/// [`write_npy_header`].
+pub fn write_npy_data<W: Write, T: NpyDTyped>(write: &mut W, data: &[T]) -> std::io::Result<()> {
+    write.write_all(vec_as_bytes(data))
+}
+
 fn vec_as_bytes<T>(vec: &[T]) -> &[u8] {
     let len = std::mem::size_of_val(vec);
     let ptr = vec.as_ptr() as *const u8;
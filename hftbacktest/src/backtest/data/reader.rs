@@ -295,6 +295,10 @@ where
         let mut cache = self.cache.clone();
         for (key, mut data) in self.temporary_data {
             if let Some(p) = &self.preprocessor {
+                // Shared data is read-only as it can be read by other threads at the same time.
+                if data.is_shared() {
+                    data = data.deep_copy();
+                }
                 p.preprocess(&mut data)?;
             }
             cache.insert(key, data)
@@ -337,6 +341,17 @@ where
         ReaderBuilder::default()
     }
 
+    /// Returns the position of the next [`Data`] to be retrieved by [`Reader::next_data`], which
+    /// is the index in the sequence of data given to the [`ReaderBuilder`].
+    pub fn position(&self) -> usize {
+        self.data_num
+    }
+
+    /// Sets the position of the next [`Data`] to be retrieved by [`Reader::next_data`].
+    pub fn seek(&mut self, position: usize) {
+        self.data_num = position;
+    }
+
     /// Releases this [`Data`] from the `Cache`. The `Cache` will delete the [`Data`] if there are
     /// no readers accessing it.
     pub fn release(&mut self, data: Data<D>) {
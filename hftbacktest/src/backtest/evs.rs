@@ -1,6 +1,9 @@
 use std::mem;
 
-use crate::utils::{AlignedArray, CACHE_LINE_SIZE};
+use crate::{
+    types::TimerId,
+    utils::{AlignedArray, CACHE_LINE_SIZE},
+};
 
 #[derive(Clone, Copy)]
 #[repr(C, align(32))]
@@ -19,30 +22,68 @@ pub enum EventIntentKind {
     LocalOrder = 1,
     ExchData = 2,
     ExchOrder = 3,
+    Timer = 4,
+    CustomData = 5,
+    Funding = 6,
+}
+
+/// A user-registered timer.
+#[derive(Clone, Copy, Debug)]
+pub struct Timer {
+    pub timer_id: TimerId,
+    pub timestamp: i64,
+    pub interval: i64,
 }
 
 /// Manages the event timestamps to determine the next event to be processed.
+///
+/// The timestamps are kept in a tournament tree, in which each internal node holds the index of the
+/// earliest timestamp among its children. This allows the next event to be found in constant time
+/// and a timestamp to be updated in `O(log n)` time, which matters when backtesting a large number
+/// of assets. When timestamps are equal, the event with the lower index, which is ordered by asset
+/// number and then by event kind, is processed first.
+///
+/// The custom data streams are placed after the events of all assets, so they are processed after
+/// the data and orders that have the same timestamp. For a custom data event, `asset_no` of the
+/// [`EventIntent`] is the stream number across all assets. The funding settlements of each asset
+/// follow, and the earliest of the user-registered timers is placed last, so the timers fire after
+/// everything else that has the same timestamp.
 pub struct EventSet {
     timestamp: AlignedArray<i64, CACHE_LINE_SIZE>,
+    winner: AlignedArray<usize, CACHE_LINE_SIZE>,
+    size: usize,
     invalid: usize,
     num_assets: usize,
+    num_streams: usize,
+    timers: Vec<Timer>,
+    fired_timers: Vec<TimerId>,
 }
 
 impl EventSet {
-    /// Constructs an instance of `EventSet`.
-    pub fn new(num_assets: usize) -> Self {
+    /// Constructs an instance of `EventSet` for the given number of assets and the total number of
+    /// custom data streams.
+    pub fn new(num_assets: usize, num_streams: usize) -> Self {
         if num_assets == 0 {
             panic!();
         }
-        let mut timestamp = AlignedArray::<i64, CACHE_LINE_SIZE>::new(num_assets * 4);
-        for i in 0..(num_assets * 4) {
+        let size = (num_assets * 5 + num_streams + 1).next_power_of_two();
+        let mut timestamp = AlignedArray::<i64, CACHE_LINE_SIZE>::new(size);
+        for i in 0..size {
             timestamp[i] = i64::MAX;
         }
-        Self {
+        let winner = AlignedArray::<usize, CACHE_LINE_SIZE>::new(size);
+        let mut evs = Self {
             timestamp,
+            winner,
+            size,
             invalid: 0,
             num_assets,
-        }
+            num_streams,
+            timers: Vec::new(),
+            fired_timers: Vec::new(),
+        };
+        evs.rebuild();
+        evs
     }
 
     /// Returns the next event to be processed, which has the earliest timestamp.
@@ -50,13 +91,28 @@ impl EventSet {
         if self.invalid == self.num_assets * 2 {
             return None;
         }
-        let mut evst_no = 0;
-        let mut timestamp = unsafe { *self.timestamp.get_unchecked(0) };
-        for (i, &ev_timestamp) in self.timestamp[1..].iter().enumerate() {
-            if ev_timestamp < timestamp {
-                timestamp = ev_timestamp;
-                evst_no = i + 1;
-            }
+        let evst_no = unsafe { *self.winner.get_unchecked(1) };
+        let timestamp = unsafe { *self.timestamp.get_unchecked(evst_no) };
+        if evst_no == self.timer_no() {
+            return Some(EventIntent {
+                timestamp,
+                asset_no: 0,
+                kind: EventIntentKind::Timer,
+            });
+        }
+        if evst_no >= self.num_assets * 4 + self.num_streams {
+            return Some(EventIntent {
+                timestamp,
+                asset_no: evst_no - self.num_assets * 4 - self.num_streams,
+                kind: EventIntentKind::Funding,
+            });
+        }
+        if evst_no >= self.num_assets * 4 {
+            return Some(EventIntent {
+                timestamp,
+                asset_no: evst_no - self.num_assets * 4,
+                kind: EventIntentKind::CustomData,
+            });
         }
         let asset_no = evst_no >> 2;
         let ty = unsafe { mem::transmute::<usize, EventIntentKind>(evst_no & 3) };
@@ -67,10 +123,150 @@ impl EventSet {
         })
     }
 
+    #[inline(always)]
+    fn child_winner(&self, node: usize) -> usize {
+        if node >= self.size {
+            node - self.size
+        } else {
+            unsafe { *self.winner.get_unchecked(node) }
+        }
+    }
+
+    #[inline(always)]
+    fn play(&mut self, node: usize) {
+        let left = self.child_winner(node << 1);
+        let right = self.child_winner((node << 1) | 1);
+        let winner = unsafe {
+            if *self.timestamp.get_unchecked(left) <= *self.timestamp.get_unchecked(right) {
+                left
+            } else {
+                right
+            }
+        };
+        unsafe {
+            *self.winner.get_unchecked_mut(node) = winner;
+        }
+    }
+
+    fn rebuild(&mut self) {
+        for node in (1..self.size).rev() {
+            self.play(node);
+        }
+    }
+
+    /// Returns the event timestamps and the number of invalidated data streams.
+    pub fn timestamps(&self) -> (Vec<i64>, usize) {
+        (
+            self.timestamp[..self.num_assets * 5 + self.num_streams].to_vec(),
+            self.invalid,
+        )
+    }
+
+    /// Restores the event timestamps and the number of invalidated data streams returned by
+    /// [`EventSet::timestamps`].
+    pub fn restore(&mut self, timestamps: &[i64], invalid: usize) {
+        self.timestamp[..self.num_assets * 5 + self.num_streams].copy_from_slice(timestamps);
+        self.invalid = invalid;
+        self.rebuild();
+    }
+
+    #[inline(always)]
+    fn timer_no(&self) -> usize {
+        self.num_assets * 5 + self.num_streams
+    }
+
+    /// Updates the timer entry in the tree to the earliest timestamp of the registered timers.
+    fn update_timers(&mut self) {
+        let timestamp = self
+            .timers
+            .iter()
+            .map(|timer| timer.timestamp)
+            .min()
+            .unwrap_or(i64::MAX);
+        self.update(self.timer_no(), timestamp);
+    }
+
+    /// Sets the timer. If the timer with the same ID exists, it is replaced.
+    pub fn set_timer(&mut self, timer_id: TimerId, timestamp: i64, interval: i64) {
+        self.timers.retain(|timer| timer.timer_id != timer_id);
+        self.timers.push(Timer {
+            timer_id,
+            timestamp,
+            interval,
+        });
+        self.update_timers();
+    }
+
+    /// Cancels the timer. Returns `true` if the timer exists.
+    pub fn cancel_timer(&mut self, timer_id: TimerId) -> bool {
+        let len = self.timers.len();
+        self.timers.retain(|timer| timer.timer_id != timer_id);
+        self.update_timers();
+        self.timers.len() != len
+    }
+
+    /// Fires the timers that are due at the given timestamp. One-shot timers are removed, and
+    /// periodic timers are rescheduled to their next timestamp.
+    pub fn fire_timers(&mut self, timestamp: i64) {
+        for timer in self.timers.iter_mut() {
+            if timer.timestamp <= timestamp {
+                self.fired_timers.push(timer.timer_id);
+                if timer.interval > 0 {
+                    while timer.timestamp <= timestamp {
+                        timer.timestamp += timer.interval;
+                    }
+                }
+            }
+        }
+        self.timers
+            .retain(|timer| timer.interval > 0 || timer.timestamp > timestamp);
+        self.update_timers();
+    }
+
+    /// Returns the IDs of the fired timers that are not cleared yet.
+    pub fn fired_timers(&self) -> &[TimerId] {
+        &self.fired_timers
+    }
+
+    /// Clears the fired timers.
+    pub fn clear_fired_timers(&mut self) {
+        self.fired_timers.clear();
+    }
+
+    /// Returns the timer ID, the next timestamp, and the interval of the registered timers.
+    pub fn timers(&self) -> Vec<(TimerId, i64, i64)> {
+        self.timers
+            .iter()
+            .map(|timer| (timer.timer_id, timer.timestamp, timer.interval))
+            .collect()
+    }
+
+    /// Restores the timers returned by [`EventSet::timers`].
+    pub fn restore_timers(&mut self, timers: &[(TimerId, i64, i64)]) {
+        self.timers = timers
+            .iter()
+            .map(|&(timer_id, timestamp, interval)| Timer {
+                timer_id,
+                timestamp,
+                interval,
+            })
+            .collect();
+        self.fired_timers.clear();
+        self.update_timers();
+    }
+
     #[inline]
     fn update(&mut self, evst_no: usize, timestamp: i64) {
         let item = unsafe { self.timestamp.get_unchecked_mut(evst_no) };
+        if *item == timestamp {
+            return;
+        }
         *item = timestamp;
+        let mut node = (evst_no + self.size) >> 1;
+        while node > 0 {
+            self.play(node);
+            node >>= 1;
+        }
     }
 
     #[inline]
@@ -93,10 +289,19 @@ impl EventSet {
         self.update(4 * asset_no + 3, timestamp);
     }
 
+    #[inline]
+    pub fn update_custom_data(&mut self, stream_no: usize, timestamp: i64) {
+        self.update(4 * self.num_assets + stream_no, timestamp);
+    }
+
+    #[inline]
+    pub fn update_funding(&mut self, asset_no: usize, timestamp: i64) {
+        self.update(4 * self.num_assets + self.num_streams + asset_no, timestamp);
+    }
+
     #[inline]
     fn invalidate(&mut self, evst_no: usize) {
-        let item = unsafe { self.timestamp.get_unchecked_mut(evst_no) };
-        *item = i64::MAX;
+        self.update(evst_no, i64::MAX);
         self.invalid += 1;
     }
 
@@ -109,4 +314,172 @@ impl EventSet {
     pub fn invalidate_exch_data(&mut self, asset_no: usize) {
         self.invalidate(4 * asset_no + 2);
     }
+
+    /// Invalidates the custom data stream. Unlike the feed data, the end of a custom data stream
+    /// does not count towards the end of the backtest.
+    #[inline]
+    pub fn invalidate_custom_data(&mut self, stream_no: usize) {
+        self.update(4 * self.num_assets + stream_no, i64::MAX);
+    }
+
+    /// Invalidates the funding settlement of the asset, which does not count towards the end of
+    /// the backtest either.
+    #[inline]
+    pub fn invalidate_funding(&mut self, asset_no: usize) {
+        self.update(4 * self.num_assets + self.num_streams + asset_no, i64::MAX);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backtest::evs::{EventIntentKind, EventSet};
+
+    #[test]
+    fn test_next() {
+        let mut evs = EventSet::new(3, 0);
+        evs.update_local_data(0, 300);
+        evs.update_exch_data(0, 200);
+        evs.update_local_data(2, 100);
+
+        let ev = evs.next().unwrap();
+        assert_eq!(ev.timestamp, 100);
+        assert_eq!(ev.asset_no, 2);
+        assert!(ev.kind == EventIntentKind::LocalData);
+
+        // The event with the lower index comes first if the timestamps are equal.
+        evs.update_local_data(2, 200);
+        evs.update_exch_order(1, 200);
+        let ev = evs.next().unwrap();
+        assert_eq!(ev.asset_no, 0);
+        assert!(ev.kind == EventIntentKind::ExchData);
+
+        evs.invalidate_exch_data(0);
+        let ev = evs.next().unwrap();
+        assert_eq!(ev.asset_no, 1);
+        assert!(ev.kind == EventIntentKind::ExchOrder);
+    }
+
+    #[test]
+    fn test_next_matches_linear_scan() {
+        let num_assets = 37;
+        let mut evs = EventSet::new(num_assets, 0);
+        let mut timestamps = vec![i64::MAX; num_assets * 4];
+        let mut seed: u64 = 42;
+        for _ in 0..10_000 {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let evst_no = (seed >> 33) as usize % (num_assets * 4);
+            let timestamp = (seed >> 20) as i64 % 1000;
+            timestamps[evst_no] = timestamp;
+            match evst_no & 3 {
+                0 => evs.update_local_data(evst_no >> 2, timestamp),
+                1 => evs.update_local_order(evst_no >> 2, timestamp),
+                2 => evs.update_exch_data(evst_no >> 2, timestamp),
+                _ => evs.update_exch_order(evst_no >> 2, timestamp),
+            }
+
+            let (expected_no, &expected_ts) = timestamps
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, &timestamp)| timestamp)
+                .unwrap();
+            let ev = evs.next().unwrap();
+            assert_eq!(ev.timestamp, expected_ts);
+            assert_eq!(ev.asset_no * 4 + ev.kind as usize, expected_no);
+        }
+    }
+
+    #[test]
+    fn test_custom_data() {
+        let mut evs = EventSet::new(2, 3);
+        evs.update_local_data(0, 100);
+        evs.update_exch_data(1, 100);
+        evs.update_custom_data(1, 100);
+        evs.update_custom_data(2, 50);
+
+        let ev = evs.next().unwrap();
+        assert_eq!(ev.timestamp, 50);
+        assert_eq!(ev.asset_no, 2);
+        assert!(ev.kind == EventIntentKind::CustomData);
+
+        // Custom data is processed after the feed data that has the same timestamp.
+        evs.invalidate_custom_data(2);
+        let ev = evs.next().unwrap();
+        assert_eq!(ev.asset_no, 0);
+        assert!(ev.kind == EventIntentKind::LocalData);
+
+        evs.update_local_data(0, 200);
+        evs.update_exch_data(1, 200);
+        let ev = evs.next().unwrap();
+        assert_eq!(ev.asset_no, 1);
+        assert!(ev.kind == EventIntentKind::CustomData);
+
+        // The end of the custom data streams does not end the backtest, but the end of the feed
+        // data does.
+        evs.invalidate_custom_data(1);
+        assert!(evs.next().unwrap().kind == EventIntentKind::LocalData);
+        for asset_no in 0..2 {
+            evs.invalidate_local_data(asset_no);
+            evs.invalidate_exch_data(asset_no);
+        }
+        evs.update_custom_data(0, 300);
+        assert!(evs.next().is_none());
+    }
+
+    #[test]
+    fn test_funding() {
+        let mut evs = EventSet::new(2, 1);
+        evs.update_local_data(1, 100);
+        evs.update_custom_data(0, 100);
+        evs.update_funding(1, 100);
+
+        // The funding is settled after the custom data that has the same timestamp.
+        let ev = evs.next().unwrap();
+        assert!(ev.kind == EventIntentKind::LocalData);
+        evs.update_local_data(1, 200);
+        let ev = evs.next().unwrap();
+        assert!(ev.kind == EventIntentKind::CustomData);
+        evs.invalidate_custom_data(0);
+        let ev = evs.next().unwrap();
+        assert_eq!(ev.timestamp, 100);
+        assert_eq!(ev.asset_no, 1);
+        assert!(ev.kind == EventIntentKind::Funding);
+
+        evs.invalidate_funding(1);
+        assert!(evs.next().unwrap().kind == EventIntentKind::LocalData);
+        assert_eq!(evs.timestamps().0.len(), 11);
+    }
+
+    #[test]
+    fn test_timers() {
+        let mut evs = EventSet::new(2, 1);
+        evs.update_local_data(0, 100);
+        evs.update_funding(1, 100);
+        evs.set_timer(1, 300, 0);
+        evs.set_timer(2, 100, 150);
+
+        // The timers fire after everything else that has the same timestamp.
+        assert!(evs.next().unwrap().kind == EventIntentKind::LocalData);
+        evs.update_local_data(0, 200);
+        assert!(evs.next().unwrap().kind == EventIntentKind::Funding);
+        evs.invalidate_funding(1);
+        let ev = evs.next().unwrap();
+        assert_eq!(ev.timestamp, 100);
+        assert!(ev.kind == EventIntentKind::Timer);
+
+        // The periodic timer is rescheduled, and the earliest of the timers comes next.
+        evs.fire_timers(100);
+        assert_eq!(evs.fired_timers(), &[2]);
+        evs.update_local_data(0, 400);
+        let ev = evs.next().unwrap();
+        assert_eq!(ev.timestamp, 250);
+        assert!(ev.kind == EventIntentKind::Timer);
+
+        assert!(evs.cancel_timer(2));
+        assert_eq!(evs.next().unwrap().timestamp, 300);
+        evs.fire_timers(300);
+        assert!(evs.timers().is_empty());
+        assert!(evs.next().unwrap().kind == EventIntentKind::LocalData);
+    }
 }
@@ -0,0 +1,98 @@
+use bincode::{Decode, Encode};
+
+/// The expiry of a dated futures contract, such as a quarterly futures contract.
+///
+/// When the backtest time reaches the expiry timestamp, the processors expire the open orders,
+/// settle the position at the settlement price, and reject further orders. If no settlement price
+/// is configured, the mid price at the expiry is used. If the data ends before the expiry
+/// timestamp, the contract does not expire.
+#[derive(Clone, Copy, Debug, Encode, Decode)]
+pub struct Expiry {
+    timestamp: i64,
+    settlement_price: Option<f64>,
+    expired: bool,
+}
+
+impl Expiry {
+    /// Constructs an `Expiry` at the given timestamp with the settlement price. If
+    /// `settlement_price` is `None`, the mid price at the expiry is used.
+    pub fn new(timestamp: i64, settlement_price: Option<f64>) -> Self {
+        Self {
+            timestamp,
+            settlement_price,
+            expired: false,
+        }
+    }
+
+    /// Returns the expiry timestamp.
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    /// Returns `true` if the contract has expired.
+    pub fn is_expired(&self) -> bool {
+        self.expired
+    }
+
+    /// Returns `true` if the contract expires before the event at the given timestamp.
+    pub fn is_due(&self, timestamp: i64) -> bool {
+        !self.expired && timestamp >= self.timestamp
+    }
+
+    /// Returns the timestamp at which the processor should be invoked next, which is the earlier
+    /// of the next event's timestamp and the expiry timestamp, until the contract expires.
+    pub fn next_timestamp(&self, timestamp: i64) -> i64 {
+        if self.expired {
+            timestamp
+        } else {
+            timestamp.min(self.timestamp)
+        }
+    }
+
+    /// Returns the settlement price. Unless it is configured, this is `None` until the contract
+    /// expires.
+    pub fn settlement_price(&self) -> Option<f64> {
+        self.settlement_price
+    }
+
+    /// Expires the contract and returns the settlement price, which is the configured one or the
+    /// given mid price otherwise.
+    pub fn expire(&mut self, mid_price: f64) -> f64 {
+        let settlement_price = self.settlement_price.unwrap_or(mid_price);
+        self.expired = true;
+        self.settlement_price = Some(settlement_price);
+        settlement_price
+    }
+}
+
+impl Default for Expiry {
+    /// Constructs an `Expiry` that never expires.
+    fn default() -> Self {
+        Self::new(i64::MAX, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backtest::expiry::Expiry;
+
+    #[test]
+    fn test_expiry() {
+        let mut expiry = Expiry::new(100, None);
+        assert_eq!(expiry.next_timestamp(50), 50);
+        assert_eq!(expiry.next_timestamp(150), 100);
+        assert!(!expiry.is_due(99));
+        assert!(expiry.is_due(100));
+        // The mid price is used if no settlement price is configured.
+        assert_eq!(expiry.expire(101.5), 101.5);
+        assert_eq!(expiry.settlement_price(), Some(101.5));
+        assert!(expiry.is_expired());
+        assert!(!expiry.is_due(150));
+        assert_eq!(expiry.next_timestamp(150), 150);
+
+        let mut expiry = Expiry::new(100, Some(100.0));
+        assert_eq!(expiry.expire(101.5), 100.0);
+
+        assert!(!Expiry::default().is_due(i64::MAX - 1));
+    }
+}
@@ -0,0 +1,178 @@
+use crate::{
+    backtest::{
+        assettype::LinearAsset,
+        data::Data,
+        models::{
+            CommonFees,
+            ConstantLatency,
+            L3QueueModel,
+            RiskAdverseQueueModel,
+            TradingValueFeeModel,
+        },
+        Backtest,
+        DataSource,
+        ExchangeKind,
+        L2AssetBuilder,
+        L3AssetBuilder,
+    },
+    depth::HashMapMarketDepth,
+    types::{
+        Event,
+        OrdType,
+        OrderRequest,
+        SelfTradePrevention,
+        Side,
+        TimeInForce,
+        TriggerPriceType,
+        BUY_EVENT,
+        DEPTH_EVENT,
+        EXCH_EVENT,
+        LOCAL_EVENT,
+        SELL_EVENT,
+        TRADE_EVENT,
+    },
+};
+
+pub(crate) type TestAssetBuilder = L2AssetBuilder<
+    ConstantLatency,
+    LinearAsset,
+    RiskAdverseQueueModel<HashMapMarketDepth>,
+    HashMapMarketDepth,
+    TradingValueFeeModel<CommonFees>,
+>;
+
+/// Generates `n` rows of feed data, one every 1ms, which the local receives 100ns after the
+/// exchange. The rows alternate between a bid depth at 100.0 with a quantity cycling from 1 to 5
+/// and an ask depth at 100.1 with a quantity of 1. If `trades` is set, every second pair is
+/// replaced by a sell trade at 100.0 and a buy trade at 100.1, each with a quantity of 0.5.
+pub(crate) fn feed_events(n: i64, trades: bool) -> Vec<Event> {
+    (0..n)
+        .map(|i| {
+            let (ev, px, qty) = match if trades { i % 4 } else { i % 2 } {
+                0 => (DEPTH_EVENT | BUY_EVENT, 100.0, 1.0 + (i % 5) as f64),
+                1 => (DEPTH_EVENT | SELL_EVENT, 100.1, 1.0),
+                2 => (TRADE_EVENT | SELL_EVENT, 100.0, 0.5),
+                _ => (TRADE_EVENT | BUY_EVENT, 100.1, 0.5),
+            };
+            Event {
+                ev: EXCH_EVENT | LOCAL_EVENT | ev,
+                exch_ts: i * 1000,
+                local_ts: i * 1000 + 100,
+                px,
+                qty,
+                order_id: 0,
+                ival: 0,
+                fval: 0.0,
+            }
+        })
+        .collect()
+}
+
+/// Returns 200 rows of [`feed_events`] with trades.
+pub(crate) fn test_data() -> Data<Event> {
+    Data::from_items(&feed_events(200, true))
+}
+
+/// Returns an asset builder set up with a 1µs order entry and response latency, a linear asset,
+/// no fees, [`RiskAdverseQueueModel`], and a tick size of 0.1 and a lot size of 0.01. The tests
+/// override the parts they need.
+pub(crate) fn asset_builder(data: &Data<Event>) -> TestAssetBuilder {
+    L2AssetBuilder::new()
+        .data(vec![DataSource::Data(data.clone())])
+        .latency_model(ConstantLatency::new(1000, 1000))
+        .asset_type(LinearAsset::new(1.0))
+        .fee_model(TradingValueFeeModel::new(CommonFees::new(0.0, 0.0)))
+        .queue_model(RiskAdverseQueueModel::new())
+        .depth(|| HashMapMarketDepth::new(0.1, 0.01))
+}
+
+/// Builds a single-asset backtest from the builder.
+pub(crate) fn build(builder: TestAssetBuilder) -> Backtest<HashMapMarketDepth> {
+    Backtest::builder()
+        .add_asset(builder.build().unwrap())
+        .build()
+        .unwrap()
+}
+
+/// Builds a single-asset backtest from [`asset_builder`] without any changes.
+pub(crate) fn build_backtest(data: &Data<Event>) -> Backtest<HashMapMarketDepth> {
+    build(asset_builder(data))
+}
+
+/// Returns a GTC order request without self-trade prevention, a trigger, an OCO pair, or
+/// reduce-only. The tests override the fields they need.
+pub(crate) fn order_request(
+    order_id: u64,
+    side: Side,
+    order_type: OrdType,
+    price: f64,
+    qty: f64,
+) -> OrderRequest {
+    OrderRequest {
+        order_id,
+        price,
+        qty,
+        side,
+        time_in_force: TimeInForce::GTC,
+        order_type,
+        self_trade_prevention: SelfTradePrevention::None,
+        trigger_price: 0.0,
+        trigger_price_type: TriggerPriceType::LastPrice,
+        oco_order_id: None,
+        expire_timestamp: 0,
+        reduce_only: false,
+    }
+}
+
+/// Returns Level3 Market-By-Order feed data from `(timestamp, ev, px, qty, order_id)` rows, which
+/// the local receives at the same time as the exchange.
+pub(crate) fn l3_data(rows: &[(i64, u64, f64, f64, u64)]) -> Data<Event> {
+    let events: Vec<_> = rows
+        .iter()
+        .map(|&(ts, ev, px, qty, order_id)| Event {
+            ev: EXCH_EVENT | LOCAL_EVENT | ev,
+            exch_ts: ts,
+            local_ts: ts,
+            px,
+            qty,
+            order_id,
+            ival: 0,
+            fval: 0.0,
+        })
+        .collect();
+    Data::from_items(&events)
+}
+
+/// Returns best bid and offer feed data from `(timestamp, ev, px, qty)` rows, which the local
+/// receives at the same time as the exchange.
+pub(crate) fn bbo_data(rows: &[(i64, u64, f64, f64)]) -> Data<Event> {
+    let rows: Vec<_> = rows
+        .iter()
+        .map(|&(ts, ev, px, qty)| (ts, ev, px, qty, 0))
+        .collect();
+    l3_data(&rows)
+}
+
+/// Builds a single-asset Level3 backtest with the exchange model and the queue model, a 1µs order
+/// entry and response latency, a linear asset, no fees, and a tick size of 0.1 and a lot size of
+/// 0.1.
+pub(crate) fn build_l3<QM>(
+    data: &Data<Event>,
+    exch_kind: ExchangeKind,
+    queue_model: QM,
+) -> Backtest<HashMapMarketDepth>
+where
+    QM: L3QueueModel<HashMapMarketDepth> + 'static,
+{
+    let asset = L3AssetBuilder::new()
+        .data(vec![DataSource::Data(data.clone())])
+        .latency_model(ConstantLatency::new(1000, 1000))
+        .asset_type(LinearAsset::new(1.0))
+        .fee_model(TradingValueFeeModel::new(CommonFees::new(0.0, 0.0)))
+        .queue_model(queue_model)
+        .exchange(exch_kind)
+        .depth(|| HashMapMarketDepth::new(0.1, 0.1))
+        .build()
+        .unwrap();
+    Backtest::builder().add_asset(asset).build().unwrap()
+}
@@ -0,0 +1,204 @@
+use std::{io::Error as IoError, mem};
+
+use bincode::{Decode, Encode};
+use hftbacktest_derive::NpyDTyped;
+
+use crate::backtest::{
+    checkpoint,
+    data::{Data, DataSource, Reader, POD},
+    BacktestError,
+};
+
+/// The default funding interval, which is 8 hours in nanoseconds.
+pub const DEFAULT_FUNDING_INTERVAL: i64 = 8 * 60 * 60 * 1_000_000_000;
+
+/// A funding rate and the mark price of a perpetual contract, which take effect from `timestamp`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, NpyDTyped, Encode, Decode)]
+pub struct FundingRate {
+    /// Exchange timestamp from which the funding rate and the mark price take effect.
+    pub timestamp: i64,
+    /// Funding rate; long positions pay short positions if it is positive.
+    pub rate: f64,
+    /// Mark price at which the position is valued when the funding is settled.
+    pub mark_px: f64,
+}
+
+unsafe impl POD for FundingRate {}
+
+/// Settles the funding of a perpetual contract at every multiple of the funding interval since the
+/// Unix epoch, using the latest funding rate and mark price from the funding rate data.
+///
+/// Funding rates that take effect at a settlement time are applied to that settlement. No funding
+/// is settled until the first funding rate takes effect, and after the funding rate data is
+/// exhausted, the last funding rate continues to be used.
+pub struct Funding {
+    reader: Reader<FundingRate>,
+    data: Data<FundingRate>,
+    row_num: usize,
+    exhausted: bool,
+    interval: i64,
+    next_settlement: i64,
+    latest: Option<FundingRate>,
+}
+
+/// The funding's state stored in a checkpoint.
+#[derive(Encode, Decode)]
+struct FundingState {
+    data_pos: usize,
+    row_num: usize,
+    exhausted: bool,
+    next_settlement: i64,
+    latest: Option<FundingRate>,
+}
+
+impl Funding {
+    /// Constructs a `Funding` that reads the given funding rate data and settles the funding every
+    /// `interval` nanoseconds. The items in the `data` vector should be arranged in the
+    /// chronological order.
+    pub fn new(
+        data: Vec<DataSource<FundingRate>>,
+        interval: i64,
+        parallel_load: bool,
+    ) -> Result<Self, IoError> {
+        Ok(Self {
+            reader: Reader::builder()
+                .parallel_load(parallel_load)
+                .data(data)
+                .build()?,
+            data: Data::empty(),
+            row_num: 0,
+            exhausted: false,
+            interval,
+            next_settlement: i64::MAX,
+            latest: None,
+        })
+    }
+
+    /// Prepares to process the data. If successful, returns the timestamp of the first funding
+    /// rate.
+    pub fn initialize_data(&mut self) -> Result<i64, BacktestError> {
+        self.next_data()?;
+        let timestamp = self.data[0].timestamp;
+        self.next_settlement = timestamp.div_euclid(self.interval) * self.interval;
+        if self.next_settlement < timestamp {
+            self.next_settlement += self.interval;
+        }
+        Ok(timestamp)
+    }
+
+    /// Processes the funding rate or the settlement that is due at the given timestamp. Returns the
+    /// funding rate to settle, if this is a settlement, and the timestamp of the next event.
+    pub fn process(&mut self, timestamp: i64) -> Result<(Option<FundingRate>, i64), BacktestError> {
+        if !self.exhausted && self.data[self.row_num].timestamp <= timestamp {
+            self.latest = Some(self.data[self.row_num]);
+            if self.row_num + 1 < self.data.len() {
+                self.row_num += 1;
+            } else {
+                match self.next_data() {
+                    Ok(()) => {}
+                    Err(BacktestError::EndOfData) => {
+                        self.exhausted = true;
+                    }
+                    Err(e) => {
+                        return Err(e);
+                    }
+                }
+            }
+            return Ok((None, self.next_timestamp()));
+        }
+        while self.next_settlement <= timestamp {
+            self.next_settlement += self.interval;
+        }
+        Ok((self.latest, self.next_timestamp()))
+    }
+
+    /// Returns the latest mark price, or `None` if no funding rate has taken effect yet.
+    pub fn mark_price(&self) -> Option<f64> {
+        self.latest.map(|latest| latest.mark_px)
+    }
+
+    fn next_timestamp(&self) -> i64 {
+        if self.exhausted {
+            self.next_settlement
+        } else {
+            self.data[self.row_num].timestamp.min(self.next_settlement)
+        }
+    }
+
+    /// Moves on to the next non-empty data, releasing the current one.
+    fn next_data(&mut self) -> Result<(), BacktestError> {
+        loop {
+            let next_data = self.reader.next_data()?;
+            let data = mem::replace(&mut self.data, next_data);
+            self.reader.release(data);
+            if !self.data.is_empty() {
+                self.row_num = 0;
+                return Ok(());
+            }
+        }
+    }
+
+    /// Returns the encoded state to be stored in a
+    /// [`Checkpoint`](crate::backtest::checkpoint::Checkpoint).
+    pub fn checkpoint(&self) -> Result<Vec<u8>, BacktestError> {
+        checkpoint::encode(&FundingState {
+            data_pos: self.reader.position(),
+            row_num: self.row_num,
+            exhausted: self.exhausted,
+            next_settlement: self.next_settlement,
+            latest: self.latest,
+        })
+    }
+
+    /// Restores the state returned by [`Funding::checkpoint`].
+    pub fn restore(&mut self, state: &[u8]) -> Result<(), BacktestError> {
+        let state: FundingState = checkpoint::decode(state)?;
+        if state.data_pos > 0 {
+            self.reader.seek(state.data_pos - 1);
+            let restored = self.reader.next_data()?;
+            let released = mem::replace(&mut self.data, restored);
+            self.reader.release(released);
+        }
+        self.row_num = state.row_num;
+        self.exhausted = state.exhausted;
+        self.next_settlement = state.next_settlement;
+        self.latest = state.latest;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backtest::{
+        data::{Data, DataSource},
+        funding::{Funding, FundingRate},
+    };
+
+    #[test]
+    fn test_settlement() {
+        let rates = [
+            FundingRate {
+                timestamp: 50,
+                rate: 0.01,
+                mark_px: 100.0,
+            },
+            FundingRate {
+                timestamp: 200,
+                rate: 0.02,
+                mark_px: 110.0,
+            },
+        ];
+        let mut funding =
+            Funding::new(vec![DataSource::Data(Data::from_items(&rates))], 100, false).unwrap();
+
+        assert_eq!(funding.initialize_data().unwrap(), 50);
+        assert_eq!(funding.process(50).unwrap(), (None, 100));
+        assert_eq!(funding.process(100).unwrap(), (Some(rates[0]), 200));
+        // The funding rate that takes effect at the settlement time is applied.
+        assert_eq!(funding.process(200).unwrap(), (None, 200));
+        assert_eq!(funding.process(200).unwrap(), (Some(rates[1]), 300));
+        // The last funding rate continues to be used.
+        assert_eq!(funding.process(300).unwrap(), (Some(rates[1]), 400));
+    }
+}
@@ -1,4 +1,9 @@
-use std::{collections::HashMap, io::Error as IoError, marker::PhantomData};
+use std::{
+    collections::{HashMap, HashSet},
+    io::Error as IoError,
+    marker::PhantomData,
+    rc::Rc,
+};
 
 pub use data::DataSource;
 use data::Reader;
@@ -6,33 +11,57 @@ use models::FeeModel;
 use thiserror::Error;
 
 pub use crate::backtest::{
+    checkpoint::Checkpoint,
     models::L3QueueModel,
-    proc::{L3Local, L3NoPartialFillExchange},
+    proc::{L3Local, L3NoPartialFillExchange, L3PartialFillExchange},
 };
 use crate::{
     backtest::{
         assettype::AssetType,
-        data::FeedLatencyAdjustment,
+        custom::{CustomData, CustomDataStream, CustomStream, CustomStreamBuilder, CustomStreams},
+        data::{read_npy_file, read_npz_file, Data, FeedLatencyAdjustment},
         evs::{EventIntentKind, EventSet},
-        models::{LatencyModel, QueueModel},
+        expiry::Expiry,
+        funding::{Funding, FundingRate, DEFAULT_FUNDING_INTERVAL},
+        models::{LatencyModel, MarginModel, MarketImpact, QueueModel, TouchQueueModel},
         order::OrderBus,
-        proc::{Local, LocalProcessor, NoPartialFillExchange, PartialFillExchange, Processor},
-        state::State,
+        proc::{
+            Local,
+            LocalProcessor,
+            NoPartialFillExchange,
+            PartialFillExchange,
+            Processor,
+            LIQUIDATION_ORDER_ID,
+        },
+        state::{Margin, State},
+        trace::Tracer,
+    },
+    depth::{
+        ApplySnapshot,
+        BBOMarketDepth,
+        HashMapMarketDepth,
+        L2MarketDepth,
+        L3MarketDepth,
+        MarketDepth,
     },
-    depth::{HashMapMarketDepth, L2MarketDepth, L3MarketDepth, MarketDepth},
     prelude::{
         Bot,
+        L3Bot,
         OrdType,
         Order,
         OrderId,
         OrderRequest,
+        SelfTradePrevention,
         Side,
         StateValues,
+        Status,
         TimeInForce,
+        TimerId,
+        TriggerPriceType,
         WaitOrderResponse,
         UNTIL_END_OF_DATA,
     },
-    types::{BuildError, Event},
+    types::{BuildError, Event, DEPTH_BBO_EVENT, DEPTH_CLEAR_EVENT, TRADE_EVENT},
 };
 
 /// Provides asset types.
@@ -55,6 +84,27 @@ pub mod recorder;
 pub mod data;
 mod evs;
 
+/// Checkpoint to save and restore the state of a running backtest.
+pub mod checkpoint;
+
+/// Parameter sweep that runs backtests concurrently over shared data.
+pub mod sweep;
+
+/// Deterministic trace of what the backtester does, for debugging.
+pub mod trace;
+
+/// Auxiliary custom data streams read by the strategy.
+pub mod custom;
+
+/// Funding settlement of perpetual contracts.
+pub mod funding;
+
+/// Expiry and settlement of dated futures contracts.
+pub mod expiry;
+
+#[cfg(test)]
+pub(crate) mod fixture;
+
 /// Errors that can occur during backtesting.
 #[derive(Error, Debug)]
 pub enum BacktestError {
@@ -72,12 +122,16 @@ pub enum BacktestError {
     EndOfData,
     #[error("data error: {0:?}")]
     DataError(#[from] IoError),
+    #[error("checkpoint error: {0}")]
+    CheckpointError(String),
 }
 
 /// Backtesting Asset
 pub struct Asset<L: ?Sized, E: ?Sized> {
     pub local: Box<L>,
     pub exch: Box<E>,
+    pub custom: Vec<Box<dyn CustomStream>>,
+    pub funding: Option<Funding>,
 }
 
 impl<L, E> Asset<L, E> {
@@ -87,14 +141,26 @@ impl<L, E> Asset<L, E> {
         Self {
             local: Box::new(local),
             exch: Box::new(exch),
+            custom: Vec::new(),
+            funding: None,
         }
     }
 
+    /// Returns an `L1AssetBuilder`.
+    pub fn l1_builder<LM, AT, FM>(tick_size: f64, lot_size: f64) -> L1AssetBuilder<LM, AT, FM>
+    where
+        AT: AssetType + Clone + 'static,
+        LM: LatencyModel + Clone + 'static,
+        FM: FeeModel + Clone + 'static,
+    {
+        L1AssetBuilder::new(tick_size, lot_size)
+    }
+
     /// Returns an `L2AssetBuilder`.
     pub fn l2_builder<LM, AT, QM, MD, FM>() -> L2AssetBuilder<LM, AT, QM, MD, FM>
     where
         AT: AssetType + Clone + 'static,
-        MD: MarketDepth + L2MarketDepth + 'static,
+        MD: MarketDepth + L2MarketDepth + ApplySnapshot + 'static,
         QM: QueueModel<MD> + 'static,
         LM: LatencyModel + Clone + 'static,
         FM: FeeModel + Clone + 'static,
@@ -118,12 +184,199 @@ impl<L, E> Asset<L, E> {
 
 /// Exchange model kind.
 pub enum ExchangeKind {
-    /// Uses [NoPartialFillExchange](`NoPartialFillExchange`).
+    /// Uses [NoPartialFillExchange](`NoPartialFillExchange`), or
+    /// [L3NoPartialFillExchange](`L3NoPartialFillExchange`) for a level-3 asset.
     NoPartialFillExchange,
-    /// Uses [PartialFillExchange](`PartialFillExchange`).
+    /// Uses [PartialFillExchange](`PartialFillExchange`), or
+    /// [L3PartialFillExchange](`L3PartialFillExchange`) for a level-3 asset.
     PartialFillExchange,
 }
 
+/// A level-1 asset builder.
+///
+/// This builds an asset that is backtested using only the best bid and offer, such as
+/// bookTicker-style feed data. Since nothing beyond the touch is known, the market depth is fixed
+/// to [`BBOMarketDepth`] and the queue model is fixed to [`TouchQueueModel`]. Otherwise, it is
+/// configured in the same way as [`L2AssetBuilder`].
+///
+/// The feed data must consist only of [`DEPTH_BBO_EVENT`], [`DEPTH_CLEAR_EVENT`], and
+/// [`TRADE_EVENT`]. [`build`](L1AssetBuilder::build) checks the feed data, reading the files once,
+/// and returns [`BuildError::InvalidArgument`] if it contains any other event.
+pub struct L1AssetBuilder<LM, AT, FM> {
+    builder: L2AssetBuilder<LM, AT, TouchQueueModel<BBOMarketDepth>, BBOMarketDepth, FM>,
+}
+
+impl<LM, AT, FM> L1AssetBuilder<LM, AT, FM>
+where
+    AT: AssetType + Clone + 'static,
+    LM: LatencyModel + Clone + 'static,
+    FM: FeeModel + Clone + 'static,
+{
+    /// Constructs an `L1AssetBuilder` with the tick size and the lot size of the asset.
+    pub fn new(tick_size: f64, lot_size: f64) -> Self {
+        Self {
+            builder: L2AssetBuilder::new()
+                .queue_model(TouchQueueModel::new())
+                .depth(move || BBOMarketDepth::new(tick_size, lot_size)),
+        }
+    }
+
+    /// Sets the feed data, which must consist only of the best bid and offer and trade events.
+    pub fn data(self, data: Vec<DataSource<Event>>) -> Self {
+        Self {
+            builder: self.builder.data(data),
+        }
+    }
+
+    /// Adds a custom data stream. See [`L2AssetBuilder::custom_data`].
+    pub fn custom_data<D>(self, data: Vec<DataSource<D>>) -> Self
+    where
+        D: CustomData,
+    {
+        Self {
+            builder: self.builder.custom_data(data),
+        }
+    }
+
+    /// Sets the funding rate data of a perpetual contract. See [`L2AssetBuilder::funding`].
+    pub fn funding(self, data: Vec<DataSource<FundingRate>>) -> Self {
+        Self {
+            builder: self.builder.funding(data),
+        }
+    }
+
+    /// Sets the funding interval in nanoseconds. The default value is 8 hours.
+    pub fn funding_interval(self, funding_interval: i64) -> Self {
+        Self {
+            builder: self.builder.funding_interval(funding_interval),
+        }
+    }
+
+    /// Sets the margin model and the collateral. See [`L2AssetBuilder::margin`].
+    pub fn margin<MM>(self, margin_model: MM, collateral: f64) -> Self
+    where
+        MM: MarginModel + 'static,
+    {
+        Self {
+            builder: self.builder.margin(margin_model, collateral),
+        }
+    }
+
+    /// Sets the starting inventories, which are the position and the balance. The default value is
+    /// zero for both.
+    pub fn initial_inventory(self, position: f64, balance: f64) -> Self {
+        Self {
+            builder: self.builder.initial_inventory(position, balance),
+        }
+    }
+
+    /// Sets the expiry timestamp of a dated futures contract and the settlement price. See
+    /// [`L2AssetBuilder::expiry`].
+    pub fn expiry(self, timestamp: i64, settlement_price: Option<f64>) -> Self {
+        Self {
+            builder: self.builder.expiry(timestamp, settlement_price),
+        }
+    }
+
+    /// Sets the [`MarketImpact`] model. See [`L2AssetBuilder::market_impact`].
+    pub fn market_impact(self, market_impact: MarketImpact) -> Self {
+        Self {
+            builder: self.builder.market_impact(market_impact),
+        }
+    }
+
+    /// Sets whether to load the next data in parallel with backtesting. The default value is
+    /// `false`.
+    pub fn parallel_load(self, parallel_load: bool) -> Self {
+        Self {
+            builder: self.builder.parallel_load(parallel_load),
+        }
+    }
+
+    /// Sets the latency offset to adjust the feed latency by the specified amount.
+    pub fn latency_offset(self, latency_offset: i64) -> Self {
+        Self {
+            builder: self.builder.latency_offset(latency_offset),
+        }
+    }
+
+    /// Sets a latency model.
+    pub fn latency_model(self, latency_model: LM) -> Self {
+        Self {
+            builder: self.builder.latency_model(latency_model),
+        }
+    }
+
+    /// Sets an asset type.
+    pub fn asset_type(self, asset_type: AT) -> Self {
+        Self {
+            builder: self.builder.asset_type(asset_type),
+        }
+    }
+
+    /// Sets a fee model.
+    pub fn fee_model(self, fee_model: FM) -> Self {
+        Self {
+            builder: self.builder.fee_model(fee_model),
+        }
+    }
+
+    /// Sets an exchange model. The default value is [`NoPartialFillExchange`].
+    pub fn exchange(self, exch_kind: ExchangeKind) -> Self {
+        Self {
+            builder: self.builder.exchange(exch_kind),
+        }
+    }
+
+    /// Sets the initial capacity of the vector storing the last market trades.
+    /// The default value is `0`, indicating that no last trades are stored.
+    pub fn last_trades_capacity(self, capacity: usize) -> Self {
+        Self {
+            builder: self.builder.last_trades_capacity(capacity),
+        }
+    }
+
+    /// Builds an `Asset`.
+    pub fn build(
+        self,
+    ) -> Result<Asset<dyn LocalProcessor<BBOMarketDepth>, dyn Processor>, BuildError> {
+        for source in &self.builder.data {
+            let loaded;
+            let data = match source {
+                DataSource::File(filepath) => {
+                    loaded = if filepath.ends_with(".npz") {
+                        read_npz_file(filepath, "data")
+                    } else if filepath.ends_with(".npy") {
+                        read_npy_file(filepath)
+                    } else {
+                        // The reader reports the unsupported data type.
+                        continue;
+                    }
+                    .map_err(|err| BuildError::Error(err.into()))?;
+                    &loaded
+                }
+                DataSource::Data(data) => data,
+            };
+            if !is_bbo_feed(data) {
+                return Err(BuildError::InvalidArgument(
+                    "data must consist only of the best bid and offer and trade events",
+                ));
+            }
+        }
+        self.builder.build()
+    }
+}
+
+/// Checks if the feed data consists only of the best bid and offer and trade events.
+fn is_bbo_feed(data: &Data<Event>) -> bool {
+    (0..data.len()).all(|i| {
+        matches!(
+            data[i].ev & 0xff,
+            DEPTH_BBO_EVENT | DEPTH_CLEAR_EVENT | TRADE_EVENT
+        )
+    })
+}
+
 /// A level-2 asset builder.
 pub struct L2AssetBuilder<LM, AT, QM, MD, FM> {
     latency_model: Option<LM>,
@@ -136,12 +389,19 @@ pub struct L2AssetBuilder<LM, AT, QM, MD, FM> {
     last_trades_cap: usize,
     queue_model: Option<QM>,
     depth_builder: Option<Box<dyn Fn() -> MD>>,
+    custom: Vec<CustomStreamBuilder>,
+    funding_data: Vec<DataSource<FundingRate>>,
+    funding_interval: i64,
+    margin: Option<Margin>,
+    initial_inventory: (f64, f64),
+    expiry: Expiry,
+    market_impact: Option<MarketImpact>,
 }
 
 impl<LM, AT, QM, MD, FM> L2AssetBuilder<LM, AT, QM, MD, FM>
 where
     AT: AssetType + Clone + 'static,
-    MD: MarketDepth + L2MarketDepth + 'static,
+    MD: MarketDepth + L2MarketDepth + ApplySnapshot + 'static,
     QM: QueueModel<MD> + 'static,
     LM: LatencyModel + Clone + 'static,
     FM: FeeModel + Clone + 'static,
@@ -159,6 +419,13 @@ where
             last_trades_cap: 0,
             queue_model: None,
             depth_builder: None,
+            custom: vec![],
+            funding_data: vec![],
+            funding_interval: DEFAULT_FUNDING_INTERVAL,
+            margin: None,
+            initial_inventory: (0.0, 0.0),
+            expiry: Default::default(),
+            market_impact: None,
         }
     }
 
@@ -167,9 +434,87 @@ where
         Self { data, ..self }
     }
 
+    /// Adds a custom data stream, such as alpha signals or the mark price. The strategy can read
+    /// the latest item received by the local through [`Bot::custom_data`], with the stream number
+    /// being the order in which the stream is added, starting from zero.
+    pub fn custom_data<D>(self, data: Vec<DataSource<D>>) -> Self
+    where
+        D: CustomData,
+    {
+        let mut custom = self.custom;
+        custom.push(Box::new(move |parallel_load| {
+            Ok(Box::new(CustomDataStream::new(data, parallel_load)?) as Box<dyn CustomStream>)
+        }));
+        Self { custom, ..self }
+    }
+
+    /// Sets the funding rate data of a perpetual contract. The funding is settled at every funding
+    /// interval using the latest funding rate and mark price, and is applied to the balance. See
+    /// [`Funding`].
+    pub fn funding(self, data: Vec<DataSource<FundingRate>>) -> Self {
+        Self {
+            funding_data: data,
+            ..self
+        }
+    }
+
+    /// Sets the funding interval in nanoseconds. The default value is 8 hours.
+    pub fn funding_interval(self, funding_interval: i64) -> Self {
+        Self {
+            funding_interval,
+            ..self
+        }
+    }
+
+    /// Sets the margin model and the collateral. The exchange rejects new orders if the collateral
+    /// plus the equity cannot cover the initial margin of the position and the open orders, and
+    /// liquidates the position at the mark price if the collateral plus the equity falls below the
+    /// maintenance margin. The mark price is taken from the funding rate data if it is set, or
+    /// the mid price is used otherwise. By default, the margin is not simulated.
+    pub fn margin<MM>(self, margin_model: MM, collateral: f64) -> Self
+    where
+        MM: MarginModel + 'static,
+    {
+        Self {
+            margin: Some(Margin::new(Rc::new(margin_model), collateral)),
+            ..self
+        }
+    }
+
+    /// Sets the starting inventories, which are the position and the balance. For a
+    /// [`SpotAsset`](crate::backtest::assettype::SpotAsset), these are the base currency and
+    /// the quote currency inventories. The default value is zero for both.
+    pub fn initial_inventory(self, position: f64, balance: f64) -> Self {
+        Self {
+            initial_inventory: (position, balance),
+            ..self
+        }
+    }
+
+    /// Sets the expiry timestamp of a dated futures contract and the settlement price. At the
+    /// expiry, the open orders are expired, the position is settled at the settlement price, and
+    /// further orders are rejected. If `settlement_price` is `None`, the mid price at the expiry
+    /// is used. See [`Expiry`]. By default, the contract does not expire.
+    pub fn expiry(self, timestamp: i64, settlement_price: Option<f64>) -> Self {
+        Self {
+            expiry: Expiry::new(timestamp, settlement_price),
+            ..self
+        }
+    }
+
+    /// Sets the [`MarketImpact`] model, which tracks the liquidity consumed by your
+    /// liquidity-taking orders and lets them sweep the price levels. By default, liquidity-taking
+    /// orders do not affect the liquidity available to the subsequent ones.
+    pub fn market_impact(self, market_impact: MarketImpact) -> Self {
+        Self {
+            market_impact: Some(market_impact),
+            ..self
+        }
+    }
+
     /// Sets whether to load the next data in parallel with backtesting. This can speed up the
     /// backtest by reducing data loading time, but it also increases memory usage.
-    /// The default value is `true`.
+    /// The default value is `false`.
     pub fn parallel_load(self, parallel_load: bool) -> Self {
         Self {
             parallel_load,
@@ -246,6 +591,25 @@ where
 
     /// Builds an `Asset`.
     pub fn build(self) -> Result<Asset<dyn LocalProcessor<MD>, dyn Processor>, BuildError> {
+        let custom = self
+            .custom
+            .into_iter()
+            .map(|build| build(self.parallel_load))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| BuildError::Error(err.into()))?;
+        if self.funding_interval <= 0 {
+            return Err(BuildError::InvalidArgument("funding_interval"));
+        }
+        let (position, balance) = self.initial_inventory;
+        let funding = if self.funding_data.is_empty() {
+            None
+        } else {
+            Some(
+                Funding::new(self.funding_data, self.funding_interval, self.parallel_load)
+                    .map_err(|err| BuildError::Error(err.into()))?,
+            )
+        };
+
         let reader = if self.latency_offset == 0 {
             Reader::builder()
                 .parallel_load(self.parallel_load)
@@ -281,15 +645,19 @@ where
             .clone()
             .ok_or(BuildError::BuilderIncomplete("fee_model"))?;
 
-        let local = Local::new(
+        let mut local = Local::new(
             reader.clone(),
             create_depth(),
-            State::new(asset_type, fee_model),
+            State::new(asset_type, fee_model).with_inventory(position, balance),
             order_latency,
             self.last_trades_cap,
             ob_local_to_exch.clone(),
             ob_exch_to_local.clone(),
         );
+        if let Some(margin) = self.margin.clone() {
+            local.set_margin(margin);
+        }
+        local.set_expiry(self.expiry);
 
         let order_latency = self
             .latency_model
@@ -309,35 +677,53 @@ where
 
         match self.exch_kind {
             ExchangeKind::NoPartialFillExchange => {
-                let exch = NoPartialFillExchange::new(
+                let mut exch = NoPartialFillExchange::new(
                     reader.clone(),
                     create_depth(),
-                    State::new(asset_type, fee_model),
+                    State::new(asset_type, fee_model).with_inventory(position, balance),
                     order_latency,
                     queue_model,
                     ob_exch_to_local,
                     ob_local_to_exch,
                 );
+                exch.set_expiry(self.expiry);
+                if let Some(margin) = self.margin {
+                    exch.set_margin(margin);
+                }
+                if let Some(market_impact) = self.market_impact {
+                    exch.set_market_impact(market_impact);
+                }
 
                 Ok(Asset {
                     local: Box::new(local),
                     exch: Box::new(exch),
+                    custom,
+                    funding,
                 })
             }
             ExchangeKind::PartialFillExchange => {
-                let exch = PartialFillExchange::new(
+                let mut exch = PartialFillExchange::new(
                     reader.clone(),
                     create_depth(),
-                    State::new(asset_type, fee_model),
+                    State::new(asset_type, fee_model).with_inventory(position, balance),
                     order_latency,
                     queue_model,
                     ob_exch_to_local,
                     ob_local_to_exch,
                 );
+                exch.set_expiry(self.expiry);
+                if let Some(margin) = self.margin {
+                    exch.set_margin(margin);
+                }
+                if let Some(market_impact) = self.market_impact {
+                    exch.set_market_impact(market_impact);
+                }
 
                 Ok(Asset {
                     local: Box::new(local),
                     exch: Box::new(exch),
+                    custom,
+                    funding,
                 })
             }
         }
@@ -347,7 +733,7 @@ where
 impl<LM, AT, QM, MD, FM> Default for L2AssetBuilder<LM, AT, QM, MD, FM>
 where
     AT: AssetType + Clone + 'static,
-    MD: MarketDepth + L2MarketDepth + 'static,
+    MD: MarketDepth + L2MarketDepth + ApplySnapshot + 'static,
     QM: QueueModel<MD> + 'static,
     LM: LatencyModel + Clone + 'static,
     FM: FeeModel + Clone + 'static,
@@ -369,6 +755,12 @@ pub struct L3AssetBuilder<LM, AT, QM, MD, FM> {
     last_trades_cap: usize,
     queue_model: Option<QM>,
     depth_builder: Option<Box<dyn Fn() -> MD>>,
+    custom: Vec<CustomStreamBuilder>,
+    funding_data: Vec<DataSource<FundingRate>>,
+    funding_interval: i64,
+    margin: Option<Margin>,
+    initial_inventory: (f64, f64),
+    expiry: Expiry,
 }
 
 impl<LM, AT, QM, MD, FM> L3AssetBuilder<LM, AT, QM, MD, FM>
@@ -393,6 +785,12 @@ where
             last_trades_cap: 0,
             queue_model: None,
             depth_builder: None,
+            custom: vec![],
+            funding_data: vec![],
+            funding_interval: DEFAULT_FUNDING_INTERVAL,
+            margin: None,
+            initial_inventory: (0.0, 0.0),
+            expiry: Default::default(),
         }
     }
 
@@ -401,9 +799,77 @@ where
         Self { data, ..self }
     }
 
+    /// Adds a custom data stream, such as alpha signals or the mark price. The strategy can read
+    /// the latest item received by the local through [`Bot::custom_data`], with the stream number
+    /// being the order in which the stream is added, starting from zero.
+    pub fn custom_data<D>(self, data: Vec<DataSource<D>>) -> Self
+    where
+        D: CustomData,
+    {
+        let mut custom = self.custom;
+        custom.push(Box::new(move |parallel_load| {
+            Ok(Box::new(CustomDataStream::new(data, parallel_load)?) as Box<dyn CustomStream>)
+        }));
+        Self { custom, ..self }
+    }
+
+    /// Sets the funding rate data of a perpetual contract. The funding is settled at every funding
+    /// interval using the latest funding rate and mark price, and is applied to the balance. See
+    /// [`Funding`].
+    pub fn funding(self, data: Vec<DataSource<FundingRate>>) -> Self {
+        Self {
+            funding_data: data,
+            ..self
+        }
+    }
+
+    /// Sets the funding interval in nanoseconds. The default value is 8 hours.
+    pub fn funding_interval(self, funding_interval: i64) -> Self {
+        Self {
+            funding_interval,
+            ..self
+        }
+    }
+
+    /// Sets the margin model and the collateral. The exchange rejects new orders if the collateral
+    /// plus the equity cannot cover the initial margin of the position and the open orders, and
+    /// liquidates the position at the mark price if the collateral plus the equity falls below the
+    /// maintenance margin. The mark price is taken from the funding rate data if it is set, or
+    /// the mid price is used otherwise. By default, the margin is not simulated.
+    pub fn margin<MM>(self, margin_model: MM, collateral: f64) -> Self
+    where
+        MM: MarginModel + 'static,
+    {
+        Self {
+            margin: Some(Margin::new(Rc::new(margin_model), collateral)),
+            ..self
+        }
+    }
+
+    /// Sets the starting inventories, which are the position and the balance. For a
+    /// [`SpotAsset`](crate::backtest::assettype::SpotAsset), these are the base currency and
+    /// the quote currency inventories. The default value is zero for both.
+    pub fn initial_inventory(self, position: f64, balance: f64) -> Self {
+        Self {
+            initial_inventory: (position, balance),
+            ..self
+        }
+    }
+
+    /// Sets the expiry timestamp of a dated futures contract and the settlement price. At the
+    /// expiry, the open orders are expired, the position is settled at the settlement price, and
+    /// further orders are rejected. If `settlement_price` is `None`, the mid price at the expiry
+    /// is used. See [`Expiry`]. By default, the contract does not expire.
+    pub fn expiry(self, timestamp: i64, settlement_price: Option<f64>) -> Self {
+        Self {
+            expiry: Expiry::new(timestamp, settlement_price),
+            ..self
+        }
+    }
+
     /// Sets whether to load the next data in parallel with backtesting. This can speed up the
     /// backtest by reducing data loading time, but it also increases memory usage.
-    /// The default value is `true`.
+    /// The default value is `false`.
     pub fn parallel_load(self, parallel_load: bool) -> Self {
         Self {
             parallel_load,
@@ -445,7 +911,8 @@ where
         }
     }
 
-    /// Sets an exchange model. The default value is [`NoPartialFillExchange`].
+    /// Sets an exchange model. The default value is [`NoPartialFillExchange`], which uses
+    /// [`L3NoPartialFillExchange`], and [`PartialFillExchange`] uses [`L3PartialFillExchange`].
     pub fn exchange(self, exch_kind: ExchangeKind) -> Self {
         Self { exch_kind, ..self }
     }
@@ -480,6 +947,25 @@ where
 
     /// Builds an `Asset`.
     pub fn build(self) -> Result<Asset<dyn LocalProcessor<MD>, dyn Processor>, BuildError> {
+        let custom = self
+            .custom
+            .into_iter()
+            .map(|build| build(self.parallel_load))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| BuildError::Error(err.into()))?;
+        if self.funding_interval <= 0 {
+            return Err(BuildError::InvalidArgument("funding_interval"));
+        }
+        let (position, balance) = self.initial_inventory;
+        let funding = if self.funding_data.is_empty() {
+            None
+        } else {
+            Some(
+                Funding::new(self.funding_data, self.funding_interval, self.parallel_load)
+                    .map_err(|err| BuildError::Error(err.into()))?,
+            )
+        };
+
         let reader = if self.latency_offset == 0 {
             Reader::builder()
                 .parallel_load(self.parallel_load)
@@ -515,15 +1001,19 @@ where
             .clone()
             .ok_or(BuildError::BuilderIncomplete("fee_model"))?;
 
-        let local = L3Local::new(
+        let mut local = L3Local::new(
             reader.clone(),
             create_depth(),
-            State::new(asset_type, fee_model),
+            State::new(asset_type, fee_model).with_inventory(position, balance),
             order_latency,
             self.last_trades_cap,
             ob_local_to_exch.clone(),
             ob_exch_to_local.clone(),
         );
+        if let Some(margin) = self.margin.clone() {
+            local.set_margin(margin);
+        }
+        local.set_expiry(self.expiry);
 
         let order_latency = self
             .latency_model
@@ -543,23 +1033,48 @@ where
 
         match self.exch_kind {
             ExchangeKind::NoPartialFillExchange => {
-                let exch = L3NoPartialFillExchange::new(
+                let mut exch = L3NoPartialFillExchange::new(
                     reader.clone(),
                     create_depth(),
-                    State::new(asset_type, fee_model),
+                    State::new(asset_type, fee_model).with_inventory(position, balance),
                     order_latency,
                     queue_model,
                     ob_exch_to_local,
                     ob_local_to_exch,
                 );
+                exch.set_expiry(self.expiry);
+                if let Some(margin) = self.margin {
+                    exch.set_margin(margin);
+                }
 
                 Ok(Asset {
                     local: Box::new(local),
                     exch: Box::new(exch),
+                    custom,
+                    funding,
                 })
             }
             ExchangeKind::PartialFillExchange => {
-                unimplemented!();
+                let mut exch = L3PartialFillExchange::new(
+                    reader.clone(),
+                    create_depth(),
+                    State::new(asset_type, fee_model).with_inventory(position, balance),
+                    order_latency,
+                    queue_model,
+                    ob_exch_to_local,
+                    ob_local_to_exch,
+                );
+                exch.set_expiry(self.expiry);
+                if let Some(margin) = self.margin {
+                    exch.set_margin(margin);
+                }
+
+                Ok(Asset {
+                    local: Box::new(local),
+                    exch: Box::new(exch),
+                    custom,
+                    funding,
+                })
             }
         }
     }
@@ -583,6 +1098,9 @@ where
 pub struct BacktestBuilder<MD> {
     local: Vec<Box<dyn LocalProcessor<MD>>>,
     exch: Vec<Box<dyn Processor>>,
+    custom: Vec<Vec<Box<dyn CustomStream>>>,
+    funding: Vec<Option<Funding>>,
+    tracer: Option<Tracer>,
 }
 
 impl<MD> BacktestBuilder<MD> {
@@ -591,20 +1109,42 @@ impl<MD> BacktestBuilder<MD> {
         let mut self_ = Self { ..self };
         self_.local.push(asset.local);
         self_.exch.push(asset.exch);
+        self_.custom.push(asset.custom);
+        self_.funding.push(asset.funding);
         self_
     }
 
+    /// Sets the [`Tracer`] to which the backtester and the processors of all assets write.
+    pub fn tracer(self, tracer: Tracer) -> Self {
+        Self {
+            tracer: Some(tracer),
+            ..self
+        }
+    }
+
     /// Builds [`Backtest`].
     pub fn build(self) -> Result<Backtest<MD>, BuildError> {
         let num_assets = self.local.len();
         if self.local.len() != num_assets || self.exch.len() != num_assets {
             panic!();
         }
+        let mut local = self.local;
+        let mut exch = self.exch;
+        if let Some(tracer) = &self.tracer {
+            for (asset_no, (local, exch)) in local.iter_mut().zip(exch.iter_mut()).enumerate() {
+                local.set_tracer(tracer.clone(), asset_no);
+                exch.set_tracer(tracer.clone(), asset_no);
+            }
+        }
+        let custom = CustomStreams::new(self.custom);
         Ok(Backtest {
             cur_ts: i64::MAX,
-            evs: EventSet::new(num_assets),
-            local: self.local,
-            exch: self.exch,
+            evs: EventSet::new(num_assets, custom.len()),
+            local,
+            exch,
+            custom,
+            funding: self.funding,
+            tracer: self.tracer,
         })
     }
 }
@@ -617,6 +1157,9 @@ pub struct Backtest<MD> {
     evs: EventSet,
     local: Vec<Box<dyn LocalProcessor<MD>>>,
     exch: Vec<Box<dyn Processor>>,
+    custom: CustomStreams,
+    funding: Vec<Option<Funding>>,
+    tracer: Option<Tracer>,
 }
 
 impl<MD> Backtest<MD>
@@ -627,6 +1170,9 @@ where
         BacktestBuilder {
             local: vec![],
             exch: vec![],
+            custom: vec![],
+            funding: vec![],
+            tracer: None,
         }
     }
 
@@ -637,9 +1183,12 @@ where
         }
         Self {
             cur_ts: i64::MAX,
-            evs: EventSet::new(num_assets),
+            evs: EventSet::new(num_assets, 0),
             local,
             exch,
+            custom: CustomStreams::new(vec![]),
+            funding: (0..num_assets).map(|_| None).collect(),
+            tracer: None,
         }
     }
 
@@ -666,6 +1215,92 @@ where
                 }
             }
         }
+        for stream_no in 0..self.custom.len() {
+            match self.custom.get_mut(stream_no).initialize_data() {
+                Ok(ts) => self.evs.update_custom_data(stream_no, ts),
+                Err(BacktestError::EndOfData) => {
+                    self.evs.invalidate_custom_data(stream_no);
+                }
+                Err(e) => {
+                    return Err(e);
+                }
+            }
+        }
+        for (asset_no, funding) in self.funding.iter_mut().enumerate() {
+            if let Some(funding) = funding {
+                match funding.initialize_data() {
+                    Ok(ts) => self.evs.update_funding(asset_no, ts),
+                    Err(BacktestError::EndOfData) => {
+                        self.evs.invalidate_funding(asset_no);
+                    }
+                    Err(e) => {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes a [`Checkpoint`] of the current state of this backtest.
+    pub fn checkpoint(&self) -> Result<Checkpoint, BacktestError> {
+        let (evs_timestamps, evs_invalid) = self.evs.timestamps();
+        Ok(Checkpoint {
+            cur_ts: self.cur_ts,
+            evs_timestamps,
+            evs_invalid,
+            evs_timers: self.evs.timers(),
+            local: self
+                .local
+                .iter()
+                .map(|local| local.checkpoint())
+                .collect::<Result<_, _>>()?,
+            exch: self
+                .exch
+                .iter()
+                .map(|exch| exch.checkpoint())
+                .collect::<Result<_, _>>()?,
+            custom: self.custom.checkpoint()?,
+            funding: self
+                .funding
+                .iter()
+                .map(|funding| match funding {
+                    Some(funding) => funding.checkpoint(),
+                    None => Ok(Vec::new()),
+                })
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Restores the state from the [`Checkpoint`]. This backtest should be built with the same
+    /// assets, in the same order, as the backtest from which the checkpoint is taken.
+    pub fn restore(&mut self, checkpoint: &Checkpoint) -> Result<(), BacktestError> {
+        if checkpoint.local.len() != self.local.len()
+            || checkpoint.exch.len() != self.exch.len()
+            || checkpoint.custom.len() != self.custom.len()
+            || checkpoint.funding.len() != self.local.len()
+            || checkpoint.evs_timestamps.len() != self.local.len() * 5 + self.custom.len()
+        {
+            return Err(BacktestError::CheckpointError(
+                "the number of assets does not match".to_string(),
+            ));
+        }
+        for (local, state) in self.local.iter_mut().zip(checkpoint.local.iter()) {
+            local.restore(state)?;
+        }
+        for (exch, state) in self.exch.iter_mut().zip(checkpoint.exch.iter()) {
+            exch.restore(state)?;
+        }
+        self.custom.restore(&checkpoint.custom)?;
+        for (funding, state) in self.funding.iter_mut().zip(checkpoint.funding.iter()) {
+            if let Some(funding) = funding {
+                funding.restore(state)?;
+            }
+        }
+        self.evs
+            .restore(&checkpoint.evs_timestamps, checkpoint.evs_invalid);
+        self.evs.restore_timers(&checkpoint.evs_timers);
+        self.cur_ts = checkpoint.cur_ts;
         Ok(())
     }
 
@@ -684,12 +1319,45 @@ where
         self.goto::<false>(UNTIL_END_OF_DATA, WaitOrderResponse::None)
     }
 
+    /// Waits for the responses of the specified orders, skipping the orders that have no request
+    /// in process.
+    fn wait_order_responses(
+        &mut self,
+        asset_no: usize,
+        order_ids: &[OrderId],
+    ) -> Result<bool, BacktestError> {
+        for &order_id in order_ids {
+            let in_process = self
+                .local
+                .get(asset_no)
+                .unwrap()
+                .orders()
+                .get(&order_id)
+                .map(|order| order.req != Status::None)
+                .unwrap_or(false);
+            if in_process
+                && !self.goto::<false>(
+                    UNTIL_END_OF_DATA,
+                    WaitOrderResponse::Specified { asset_no, order_id },
+                )?
+            {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
     fn goto<const WAIT_NEXT_FEED: bool>(
         &mut self,
         timestamp: i64,
         wait_order_response: WaitOrderResponse,
     ) -> Result<bool, BacktestError> {
         let mut timestamp = timestamp;
+        let wait_specified_order =
+            matches!(wait_order_response, WaitOrderResponse::Specified { .. });
+        if !wait_specified_order {
+            self.evs.clear_fired_timers();
+        }
         for (asset_no, local) in self.local.iter().enumerate() {
             self.evs
                 .update_exch_order(asset_no, local.earliest_send_order_timestamp());
@@ -703,6 +1371,9 @@ where
                         self.cur_ts = timestamp;
                         return Ok(true);
                     }
+                    if let Some(tracer) = &self.tracer {
+                        tracer.event_intent(ev.timestamp, ev.asset_no, ev.kind as usize);
+                    }
                     match ev.kind {
                         EventIntentKind::LocalData => {
                             let local = unsafe { self.local.get_unchecked_mut(ev.asset_no) };
@@ -765,6 +1436,54 @@ where
                                 ev.asset_no,
                                 exch.earliest_recv_order_timestamp(),
                             );
+                            self.evs.update_local_order(
+                                ev.asset_no,
+                                exch.earliest_send_order_timestamp(),
+                            );
+                        }
+                        EventIntentKind::Timer => {
+                            self.evs.fire_timers(ev.timestamp);
+                            if !wait_specified_order {
+                                timestamp = ev.timestamp;
+                            }
+                        }
+                        EventIntentKind::CustomData => {
+                            match self.custom.get_mut(ev.asset_no).process_data() {
+                                Ok(next_ts) => {
+                                    self.evs.update_custom_data(ev.asset_no, next_ts);
+                                }
+                                Err(BacktestError::EndOfData) => {
+                                    self.evs.invalidate_custom_data(ev.asset_no);
+                                }
+                                Err(e) => {
+                                    return Err(e);
+                                }
+                            }
+                            if WAIT_NEXT_FEED {
+                                timestamp = ev.timestamp;
+                            }
+                        }
+                        EventIntentKind::Funding => {
+                            let funding = self.funding[ev.asset_no].as_mut().unwrap();
+                            let (settlement, next_ts) = funding.process(ev.timestamp)?;
+                            let local = &mut self.local[ev.asset_no];
+                            let exch = &mut self.exch[ev.asset_no];
+                            if let Some(mark_price) = funding.mark_price() {
+                                local.update_mark_price(mark_price);
+                                exch.on_mark_price(mark_price, ev.timestamp)?;
+                            }
+                            if let Some(funding_rate) = settlement {
+                                let (mark_price, rate) = (funding_rate.mark_px, funding_rate.rate);
+                                local.on_funding(mark_price, rate, ev.timestamp)?;
+                                exch.on_funding(mark_price, rate, ev.timestamp)?;
+                            }
+                            // The exchange can respond on its own, such as when liquidating the
+                            // position.
+                            self.evs.update_local_order(
+                                ev.asset_no,
+                                exch.earliest_send_order_timestamp(),
+                            );
+                            self.evs.update_funding(ev.asset_no, next_ts);
                         }
                     }
                 }
@@ -825,6 +1544,14 @@ where
         }
     }
 
+    #[inline]
+    fn custom_data<D>(&self, asset_no: usize, stream_no: usize) -> Option<&D>
+    where
+        D: 'static,
+    {
+        self.custom.latest(asset_no, stream_no)
+    }
+
     #[inline]
     fn orders(&self, asset_no: usize) -> &HashMap<u64, Order> {
         self.local.get(asset_no).unwrap().orders()
@@ -843,12 +1570,20 @@ where
     ) -> Result<bool, Self::Error> {
         let local = self.local.get_mut(asset_no).unwrap();
         local.submit_order(
-            order_id,
-            Side::Buy,
-            price,
-            qty,
-            order_type,
-            time_in_force,
+            OrderRequest {
+                order_id,
+                price,
+                qty,
+                side: Side::Buy,
+                time_in_force,
+                order_type,
+                self_trade_prevention: SelfTradePrevention::None,
+                trigger_price: 0.0,
+                trigger_price_type: TriggerPriceType::LastPrice,
+                oco_order_id: None,
+                expire_timestamp: 0,
+                reduce_only: false,
+            },
             self.cur_ts,
         )?;
 
@@ -874,12 +1609,20 @@ where
     ) -> Result<bool, Self::Error> {
         let local = self.local.get_mut(asset_no).unwrap();
         local.submit_order(
-            order_id,
-            Side::Sell,
-            price,
-            qty,
-            order_type,
-            time_in_force,
+            OrderRequest {
+                order_id,
+                price,
+                qty,
+                side: Side::Sell,
+                time_in_force,
+                order_type,
+                self_trade_prevention: SelfTradePrevention::None,
+                trigger_price: 0.0,
+                trigger_price_type: TriggerPriceType::LastPrice,
+                oco_order_id: None,
+                expire_timestamp: 0,
+                reduce_only: false,
+            },
             self.cur_ts,
         )?;
 
@@ -898,24 +1641,14 @@ where
         order: OrderRequest,
         wait: bool,
     ) -> Result<bool, Self::Error> {
+        let order_id = order.order_id;
         let local = self.local.get_mut(asset_no).unwrap();
-        local.submit_order(
-            order.order_id,
-            Side::Sell,
-            order.price,
-            order.qty,
-            order.order_type,
-            order.time_in_force,
-            self.cur_ts,
-        )?;
+        local.submit_order(order, self.cur_ts)?;
 
         if wait {
             return self.goto::<false>(
                 UNTIL_END_OF_DATA,
-                WaitOrderResponse::Specified {
-                    asset_no,
-                    order_id: order.order_id,
-                },
+                WaitOrderResponse::Specified { asset_no, order_id },
             );
         }
         Ok(true)
@@ -940,6 +1673,83 @@ where
         Ok(true)
     }
 
+    fn submit_orders(
+        &mut self,
+        asset_no: usize,
+        orders: Vec<OrderRequest>,
+        wait: bool,
+    ) -> Result<bool, Self::Error> {
+        let local = self.local.get_mut(asset_no).unwrap();
+        // Validates all orders before sending any of them.
+        check_order_requests(local.orders(), &orders)?;
+        let mut order_ids = Vec::with_capacity(orders.len());
+        for order in orders {
+            let order_id = order.order_id;
+            local.submit_order(order, self.cur_ts)?;
+            order_ids.push(order_id);
+        }
+
+        if wait {
+            return self.wait_order_responses(asset_no, &order_ids);
+        }
+        Ok(true)
+    }
+
+    fn cancel_orders(
+        &mut self,
+        asset_no: usize,
+        order_ids: &[OrderId],
+        wait: bool,
+    ) -> Result<bool, Self::Error> {
+        let local = self.local.get_mut(asset_no).unwrap();
+        // Validates all orders before sending any of the cancel requests.
+        check_cancel_requests(local.orders(), order_ids)?;
+        for &order_id in order_ids {
+            local.cancel(order_id, self.cur_ts)?;
+        }
+
+        if wait {
+            return self.wait_order_responses(asset_no, order_ids);
+        }
+        Ok(true)
+    }
+
+    fn cancel_all(&mut self, asset_no: usize, wait: bool) -> Result<bool, Self::Error> {
+        let mut order_ids: Vec<OrderId> = self
+            .local
+            .get(asset_no)
+            .unwrap()
+            .orders()
+            .values()
+            .filter(|order| order.cancellable())
+            .map(|order| order.order_id)
+            .collect();
+        // Sorts to send the cancel requests in a deterministic order.
+        order_ids.sort_unstable();
+        self.cancel_orders(asset_no, &order_ids, wait)
+    }
+
+    #[inline]
+    fn modify(
+        &mut self,
+        asset_no: usize,
+        order_id: OrderId,
+        price: f64,
+        qty: f64,
+        wait: bool,
+    ) -> Result<bool, Self::Error> {
+        let local = self.local.get_mut(asset_no).unwrap();
+        local.modify(order_id, price, qty, self.cur_ts)?;
+
+        if wait {
+            return self.goto::<false>(
+                UNTIL_END_OF_DATA,
+                WaitOrderResponse::Specified { asset_no, order_id },
+            );
+        }
+        Ok(true)
+    }
+
     #[inline]
     fn clear_inactive_orders(&mut self, asset_no: Option<usize>) {
         match asset_no {
@@ -1015,6 +1825,27 @@ where
         self.elapse(duration)
     }
 
+    #[inline]
+    fn set_timer(&mut self, timer_id: TimerId, timestamp: i64, interval: i64) {
+        // A timer in the past fires at the current timestamp, as the time cannot go backwards.
+        let timestamp = if self.cur_ts == i64::MAX {
+            timestamp
+        } else {
+            timestamp.max(self.cur_ts)
+        };
+        self.evs.set_timer(timer_id, timestamp, interval);
+    }
+
+    #[inline]
+    fn cancel_timer(&mut self, timer_id: TimerId) -> bool {
+        self.evs.cancel_timer(timer_id)
+    }
+
+    #[inline]
+    fn fired_timers(&self) -> &[TimerId] {
+        self.evs.fired_timers()
+    }
+
     #[inline]
     fn close(&mut self) -> Result<(), Self::Error> {
         Ok(())
@@ -1025,16 +1856,34 @@ where
         self.local.get(asset_no).unwrap().feed_latency()
     }
 
+    #[inline]
+    fn depth_latency(&self, asset_no: usize) -> Option<(i64, i64)> {
+        self.local.get(asset_no).unwrap().depth_latency()
+    }
+
     #[inline]
     fn order_latency(&self, asset_no: usize) -> Option<(i64, i64, i64)> {
         self.local.get(asset_no).unwrap().order_latency()
     }
 }
 
+impl<MD> L3Bot<MD> for Backtest<MD>
+where
+    MD: MarketDepth,
+{
+    #[inline]
+    fn queue_ahead(&self, asset_no: usize, order_id: OrderId) -> Option<f64> {
+        self.local.get(asset_no)?.queue_ahead(order_id)
+    }
+}
+
 /// `MultiAssetSingleExchangeBacktest` builder.
 pub struct MultiAssetSingleExchangeBacktestBuilder<Local, Exchange> {
     local: Vec<Local>,
     exch: Vec<Exchange>,
+    custom: Vec<Vec<Box<dyn CustomStream>>>,
+    funding: Vec<Option<Funding>>,
+    tracer: Option<Tracer>,
 }
 
 impl<Local, Exchange> MultiAssetSingleExchangeBacktestBuilder<Local, Exchange>
@@ -1047,9 +1896,19 @@ where
         let mut self_ = Self { ..self };
         self_.local.push(*asset.local);
         self_.exch.push(*asset.exch);
+        self_.custom.push(asset.custom);
+        self_.funding.push(asset.funding);
         self_
     }
 
+    /// Sets the [`Tracer`] to which the backtester and the processors of all assets write.
+    pub fn tracer(self, tracer: Tracer) -> Self {
+        Self {
+            tracer: Some(tracer),
+            ..self
+        }
+    }
+
     /// Builds [`MultiAssetSingleExchangeBacktest`].
     pub fn build(
         self,
@@ -1059,11 +1918,23 @@ where
         if self.local.len() != num_assets || self.exch.len() != num_assets {
             panic!();
         }
+        let mut local = self.local;
+        let mut exch = self.exch;
+        if let Some(tracer) = &self.tracer {
+            for (asset_no, (local, exch)) in local.iter_mut().zip(exch.iter_mut()).enumerate() {
+                local.set_tracer(tracer.clone(), asset_no);
+                exch.set_tracer(tracer.clone(), asset_no);
+            }
+        }
+        let custom = CustomStreams::new(self.custom);
         Ok(MultiAssetSingleExchangeBacktest {
             cur_ts: i64::MAX,
-            evs: EventSet::new(num_assets),
-            local: self.local,
-            exch: self.exch,
+            evs: EventSet::new(num_assets, custom.len()),
+            local,
+            exch,
+            custom,
+            funding: self.funding,
+            tracer: self.tracer,
             _md_marker: Default::default(),
         })
     }
@@ -1078,6 +1949,9 @@ pub struct MultiAssetSingleExchangeBacktest<MD, Local, Exchange> {
     evs: EventSet,
     local: Vec<Local>,
     exch: Vec<Exchange>,
+    custom: CustomStreams,
+    funding: Vec<Option<Funding>>,
+    tracer: Option<Tracer>,
     _md_marker: PhantomData<MD>,
 }
 
@@ -1091,6 +1965,9 @@ where
         MultiAssetSingleExchangeBacktestBuilder {
             local: vec![],
             exch: vec![],
+            custom: vec![],
+            funding: vec![],
+            tracer: None,
         }
     }
 
@@ -1101,9 +1978,12 @@ where
         }
         Self {
             cur_ts: i64::MAX,
-            evs: EventSet::new(num_assets),
+            evs: EventSet::new(num_assets, 0),
             local,
             exch,
+            custom: CustomStreams::new(vec![]),
+            funding: (0..num_assets).map(|_| None).collect(),
+            tracer: None,
             _md_marker: Default::default(),
         }
     }
@@ -1131,15 +2011,134 @@ where
                 }
             }
         }
+        for stream_no in 0..self.custom.len() {
+            match self.custom.get_mut(stream_no).initialize_data() {
+                Ok(ts) => self.evs.update_custom_data(stream_no, ts),
+                Err(BacktestError::EndOfData) => {
+                    self.evs.invalidate_custom_data(stream_no);
+                }
+                Err(e) => {
+                    return Err(e);
+                }
+            }
+        }
+        for (asset_no, funding) in self.funding.iter_mut().enumerate() {
+            if let Some(funding) = funding {
+                match funding.initialize_data() {
+                    Ok(ts) => self.evs.update_funding(asset_no, ts),
+                    Err(BacktestError::EndOfData) => {
+                        self.evs.invalidate_funding(asset_no);
+                    }
+                    Err(e) => {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes a [`Checkpoint`] of the current state of this backtest.
+    pub fn checkpoint(&self) -> Result<Checkpoint, BacktestError> {
+        let (evs_timestamps, evs_invalid) = self.evs.timestamps();
+        Ok(Checkpoint {
+            cur_ts: self.cur_ts,
+            evs_timestamps,
+            evs_invalid,
+            evs_timers: self.evs.timers(),
+            local: self
+                .local
+                .iter()
+                .map(|local| local.checkpoint())
+                .collect::<Result<_, _>>()?,
+            exch: self
+                .exch
+                .iter()
+                .map(|exch| exch.checkpoint())
+                .collect::<Result<_, _>>()?,
+            custom: self.custom.checkpoint()?,
+            funding: self
+                .funding
+                .iter()
+                .map(|funding| match funding {
+                    Some(funding) => funding.checkpoint(),
+                    None => Ok(Vec::new()),
+                })
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Restores the state from the [`Checkpoint`]. This backtest should be built with the same
+    /// assets, in the same order, as the backtest from which the checkpoint is taken.
+    pub fn restore(&mut self, checkpoint: &Checkpoint) -> Result<(), BacktestError> {
+        if checkpoint.local.len() != self.local.len()
+            || checkpoint.exch.len() != self.exch.len()
+            || checkpoint.custom.len() != self.custom.len()
+            || checkpoint.funding.len() != self.local.len()
+            || checkpoint.evs_timestamps.len() != self.local.len() * 5 + self.custom.len()
+        {
+            return Err(BacktestError::CheckpointError(
+                "the number of assets does not match".to_string(),
+            ));
+        }
+        for (local, state) in self.local.iter_mut().zip(checkpoint.local.iter()) {
+            local.restore(state)?;
+        }
+        for (exch, state) in self.exch.iter_mut().zip(checkpoint.exch.iter()) {
+            exch.restore(state)?;
+        }
+        self.custom.restore(&checkpoint.custom)?;
+        for (funding, state) in self.funding.iter_mut().zip(checkpoint.funding.iter()) {
+            if let Some(funding) = funding {
+                funding.restore(state)?;
+            }
+        }
+        self.evs
+            .restore(&checkpoint.evs_timestamps, checkpoint.evs_invalid);
+        self.evs.restore_timers(&checkpoint.evs_timers);
+        self.cur_ts = checkpoint.cur_ts;
         Ok(())
     }
 
+    /// Waits for the responses of the specified orders, skipping the orders that have no request
+    /// in process.
+    fn wait_order_responses(
+        &mut self,
+        asset_no: usize,
+        order_ids: &[OrderId],
+    ) -> Result<bool, BacktestError> {
+        for &order_id in order_ids {
+            let in_process = self
+                .local
+                .get(asset_no)
+                .unwrap()
+                .orders()
+                .get(&order_id)
+                .map(|order| order.req != Status::None)
+                .unwrap_or(false);
+            if in_process
+                && !self.goto::<false>(
+                    UNTIL_END_OF_DATA,
+                    WaitOrderResponse::Specified { asset_no, order_id },
+                )?
+            {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
     pub fn goto<const WAIT_NEXT_FEED: bool>(
         &mut self,
         timestamp: i64,
         wait_order_response: WaitOrderResponse,
     ) -> Result<bool, BacktestError> {
         let mut timestamp = timestamp;
+        let wait_specified_order =
+            matches!(wait_order_response, WaitOrderResponse::Specified { .. });
+        if !wait_specified_order {
+            self.evs.clear_fired_timers();
+        }
         for (asset_no, local) in self.local.iter().enumerate() {
             self.evs
                 .update_exch_order(asset_no, local.earliest_send_order_timestamp());
@@ -1153,6 +2152,9 @@ where
                         self.cur_ts = timestamp;
                         return Ok(true);
                     }
+                    if let Some(tracer) = &self.tracer {
+                        tracer.event_intent(ev.timestamp, ev.asset_no, ev.kind as usize);
+                    }
                     match ev.kind {
                         EventIntentKind::LocalData => {
                             let local = unsafe { self.local.get_unchecked_mut(ev.asset_no) };
@@ -1215,6 +2217,54 @@ where
                                 ev.asset_no,
                                 exch.earliest_recv_order_timestamp(),
                             );
+                            self.evs.update_local_order(
+                                ev.asset_no,
+                                exch.earliest_send_order_timestamp(),
+                            );
+                        }
+                        EventIntentKind::Timer => {
+                            self.evs.fire_timers(ev.timestamp);
+                            if !wait_specified_order {
+                                timestamp = ev.timestamp;
+                            }
+                        }
+                        EventIntentKind::CustomData => {
+                            match self.custom.get_mut(ev.asset_no).process_data() {
+                                Ok(next_ts) => {
+                                    self.evs.update_custom_data(ev.asset_no, next_ts);
+                                }
+                                Err(BacktestError::EndOfData) => {
+                                    self.evs.invalidate_custom_data(ev.asset_no);
+                                }
+                                Err(e) => {
+                                    return Err(e);
+                                }
+                            }
+                            if WAIT_NEXT_FEED {
+                                timestamp = ev.timestamp;
+                            }
+                        }
+                        EventIntentKind::Funding => {
+                            let funding = self.funding[ev.asset_no].as_mut().unwrap();
+                            let (settlement, next_ts) = funding.process(ev.timestamp)?;
+                            let local = &mut self.local[ev.asset_no];
+                            let exch = &mut self.exch[ev.asset_no];
+                            if let Some(mark_price) = funding.mark_price() {
+                                local.update_mark_price(mark_price);
+                                exch.on_mark_price(mark_price, ev.timestamp)?;
+                            }
+                            if let Some(funding_rate) = settlement {
+                                let (mark_price, rate) = (funding_rate.mark_px, funding_rate.rate);
+                                local.on_funding(mark_price, rate, ev.timestamp)?;
+                                exch.on_funding(mark_price, rate, ev.timestamp)?;
+                            }
+                            // The exchange can respond on its own, such as when liquidating the
+                            // position.
+                            self.evs.update_local_order(
+                                ev.asset_no,
+                                exch.earliest_send_order_timestamp(),
+                            );
+                            self.evs.update_funding(ev.asset_no, next_ts);
                         }
                     }
                 }
@@ -1277,6 +2327,14 @@ where
         }
     }
 
+    #[inline]
+    fn custom_data<D>(&self, asset_no: usize, stream_no: usize) -> Option<&D>
+    where
+        D: 'static,
+    {
+        self.custom.latest(asset_no, stream_no)
+    }
+
     #[inline]
     fn orders(&self, asset_no: usize) -> &HashMap<OrderId, Order> {
         self.local.get(asset_no).unwrap().orders()
@@ -1295,12 +2353,20 @@ where
     ) -> Result<bool, Self::Error> {
         let local = self.local.get_mut(asset_no).unwrap();
         local.submit_order(
-            order_id,
-            Side::Buy,
-            price,
-            qty,
-            order_type,
-            time_in_force,
+            OrderRequest {
+                order_id,
+                price,
+                qty,
+                side: Side::Buy,
+                time_in_force,
+                order_type,
+                self_trade_prevention: SelfTradePrevention::None,
+                trigger_price: 0.0,
+                trigger_price_type: TriggerPriceType::LastPrice,
+                oco_order_id: None,
+                expire_timestamp: 0,
+                reduce_only: false,
+            },
             self.cur_ts,
         )?;
 
@@ -1326,12 +2392,20 @@ where
     ) -> Result<bool, Self::Error> {
         let local = self.local.get_mut(asset_no).unwrap();
         local.submit_order(
-            order_id,
-            Side::Sell,
-            price,
-            qty,
-            order_type,
-            time_in_force,
+            OrderRequest {
+                order_id,
+                price,
+                qty,
+                side: Side::Sell,
+                time_in_force,
+                order_type,
+                self_trade_prevention: SelfTradePrevention::None,
+                trigger_price: 0.0,
+                trigger_price_type: TriggerPriceType::LastPrice,
+                oco_order_id: None,
+                expire_timestamp: 0,
+                reduce_only: false,
+            },
             self.cur_ts,
         )?;
 
@@ -1350,24 +2424,14 @@ where
         order: OrderRequest,
         wait: bool,
     ) -> Result<bool, Self::Error> {
+        let order_id = order.order_id;
         let local = self.local.get_mut(asset_no).unwrap();
-        local.submit_order(
-            order.order_id,
-            Side::Sell,
-            order.price,
-            order.qty,
-            order.order_type,
-            order.time_in_force,
-            self.cur_ts,
-        )?;
+        local.submit_order(order, self.cur_ts)?;
 
         if wait {
             return self.goto::<false>(
                 UNTIL_END_OF_DATA,
-                WaitOrderResponse::Specified {
-                    asset_no,
-                    order_id: order.order_id,
-                },
+                WaitOrderResponse::Specified { asset_no, order_id },
             );
         }
         Ok(true)
@@ -1392,6 +2456,83 @@ where
         Ok(true)
     }
 
+    fn submit_orders(
+        &mut self,
+        asset_no: usize,
+        orders: Vec<OrderRequest>,
+        wait: bool,
+    ) -> Result<bool, Self::Error> {
+        let local = self.local.get_mut(asset_no).unwrap();
+        // Validates all orders before sending any of them.
+        check_order_requests(local.orders(), &orders)?;
+        let mut order_ids = Vec::with_capacity(orders.len());
+        for order in orders {
+            let order_id = order.order_id;
+            local.submit_order(order, self.cur_ts)?;
+            order_ids.push(order_id);
+        }
+
+        if wait {
+            return self.wait_order_responses(asset_no, &order_ids);
+        }
+        Ok(true)
+    }
+
+    fn cancel_orders(
+        &mut self,
+        asset_no: usize,
+        order_ids: &[OrderId],
+        wait: bool,
+    ) -> Result<bool, Self::Error> {
+        let local = self.local.get_mut(asset_no).unwrap();
+        // Validates all orders before sending any of the cancel requests.
+        check_cancel_requests(local.orders(), order_ids)?;
+        for &order_id in order_ids {
+            local.cancel(order_id, self.cur_ts)?;
+        }
+
+        if wait {
+            return self.wait_order_responses(asset_no, order_ids);
+        }
+        Ok(true)
+    }
+
+    fn cancel_all(&mut self, asset_no: usize, wait: bool) -> Result<bool, Self::Error> {
+        let mut order_ids: Vec<OrderId> = self
+            .local
+            .get(asset_no)
+            .unwrap()
+            .orders()
+            .values()
+            .filter(|order| order.cancellable())
+            .map(|order| order.order_id)
+            .collect();
+        // Sorts to send the cancel requests in a deterministic order.
+        order_ids.sort_unstable();
+        self.cancel_orders(asset_no, &order_ids, wait)
+    }
+
+    #[inline]
+    fn modify(
+        &mut self,
+        asset_no: usize,
+        order_id: OrderId,
+        price: f64,
+        qty: f64,
+        wait: bool,
+    ) -> Result<bool, Self::Error> {
+        let local = self.local.get_mut(asset_no).unwrap();
+        local.modify(order_id, price, qty, self.cur_ts)?;
+
+        if wait {
+            return self.goto::<false>(
+                UNTIL_END_OF_DATA,
+                WaitOrderResponse::Specified { asset_no, order_id },
+            );
+        }
+        Ok(true)
+    }
+
     #[inline]
     fn clear_inactive_orders(&mut self, asset_no: Option<usize>) {
         match asset_no {
@@ -1466,6 +2607,27 @@ where
         self.elapse(duration)
     }
 
+    #[inline]
+    fn set_timer(&mut self, timer_id: TimerId, timestamp: i64, interval: i64) {
+        // A timer in the past fires at the current timestamp, as the time cannot go backwards.
+        let timestamp = if self.cur_ts == i64::MAX {
+            timestamp
+        } else {
+            timestamp.max(self.cur_ts)
+        };
+        self.evs.set_timer(timer_id, timestamp, interval);
+    }
+
+    #[inline]
+    fn cancel_timer(&mut self, timer_id: TimerId) -> bool {
+        self.evs.cancel_timer(timer_id)
+    }
+
+    #[inline]
+    fn fired_timers(&self) -> &[TimerId] {
+        self.evs.fired_timers()
+    }
+
     #[inline]
     fn close(&mut self) -> Result<(), Self::Error> {
         Ok(())
@@ -1476,8 +2638,1560 @@ where
         self.local.get(asset_no).unwrap().feed_latency()
     }
 
+    #[inline]
+    fn depth_latency(&self, asset_no: usize) -> Option<(i64, i64)> {
+        self.local.get(asset_no).unwrap().depth_latency()
+    }
+
     #[inline]
     fn order_latency(&self, asset_no: usize) -> Option<(i64, i64, i64)> {
         self.local.get(asset_no).unwrap().order_latency()
     }
 }
+
+impl<MD, Local, Exchange> L3Bot<MD> for MultiAssetSingleExchangeBacktest<MD, Local, Exchange>
+where
+    MD: MarketDepth,
+    Local: LocalProcessor<MD>,
+    Exchange: Processor,
+{
+    #[inline]
+    fn queue_ahead(&self, asset_no: usize, order_id: OrderId) -> Option<f64> {
+        self.local.get(asset_no)?.queue_ahead(order_id)
+    }
+}
+
+/// Checks that none of the order requests has the ID of an existing order or of another request in
+/// the batch.
+fn check_order_requests(
+    orders: &HashMap<OrderId, Order>,
+    requests: &[OrderRequest],
+) -> Result<(), BacktestError> {
+    let mut order_ids = HashSet::with_capacity(requests.len());
+    for request in requests {
+        if orders.contains_key(&request.order_id) || !order_ids.insert(request.order_id) {
+            return Err(BacktestError::OrderIdExist);
+        }
+        if request.order_id == LIQUIDATION_ORDER_ID {
+            return Err(BacktestError::InvalidOrderRequest);
+        }
+    }
+    Ok(())
+}
+
+/// Checks that all of the orders exist and have no request in process, including another cancel
+/// request in the batch.
+fn check_cancel_requests(
+    orders: &HashMap<OrderId, Order>,
+    order_ids: &[OrderId],
+) -> Result<(), BacktestError> {
+    let mut canceled = HashSet::with_capacity(order_ids.len());
+    for order_id in order_ids {
+        let order = orders.get(order_id).ok_or(BacktestError::OrderNotFound)?;
+        if order.req != Status::None || !canceled.insert(*order_id) {
+            return Err(BacktestError::OrderRequestInProcess);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backtest::{
+            assettype::{LinearAsset, SpotAsset, SpotFeeCurrency},
+            data::Data,
+            fixture::{
+                asset_builder,
+                bbo_data,
+                build,
+                build_backtest,
+                build_l3,
+                l3_data,
+                order_request,
+                test_data,
+            },
+            funding::FundingRate,
+            models::{
+                CommonFees,
+                ConstantLatency,
+                L3FIFOQueueModel,
+                L3ProRataQueueModel,
+                MarketImpact,
+                RiskAdverseQueueModel,
+                RiskLimitTier,
+                TieredMarginModel,
+                TradingValueFeeModel,
+            },
+            Backtest,
+            BacktestError,
+            DataSource,
+            ExchangeKind,
+            L1AssetBuilder,
+            L2AssetBuilder,
+            L3AssetBuilder,
+        },
+        depth::{BBOMarketDepth, MarketDepth},
+        prelude::{
+            Bot,
+            HashMapMarketDepth,
+            L3Bot,
+            OrdType,
+            OrderRequest,
+            SelfTradePrevention,
+            Side,
+            Status,
+            TimeInForce,
+            TriggerPriceType,
+        },
+        types::{
+            BuildError,
+            Event,
+            ADD_ORDER_EVENT,
+            BUY_EVENT,
+            CANCEL_ORDER_EVENT,
+            DEPTH_BBO_EVENT,
+            DEPTH_EVENT,
+            FILL_EVENT,
+            SELL_EVENT,
+            TRADE_EVENT,
+        },
+    };
+
+    #[test]
+    fn test_l1_asset_builder() {
+        let data = bbo_data(&[
+            (1_000, DEPTH_BBO_EVENT | BUY_EVENT, 100.0, 5.0),
+            (1_000, DEPTH_BBO_EVENT | SELL_EVENT, 100.2, 3.0),
+            (5_000, DEPTH_BBO_EVENT | BUY_EVENT, 100.1, 2.0),
+            (6_000, DEPTH_BBO_EVENT | BUY_EVENT, 100.0, 8.0),
+            (7_000, TRADE_EVENT | SELL_EVENT, 100.0, 3.0),
+            (8_000, TRADE_EVENT | SELL_EVENT, 100.0, 3.0),
+            (9_000, TRADE_EVENT | SELL_EVENT, 100.0, 3.0),
+            (20_000, DEPTH_BBO_EVENT | SELL_EVENT, 100.2, 3.0),
+        ]);
+        for exch_kind in [
+            ExchangeKind::NoPartialFillExchange,
+            ExchangeKind::PartialFillExchange,
+        ] {
+            let asset = L1AssetBuilder::new(0.1, 0.01)
+                .data(vec![DataSource::Data(data.clone())])
+                .latency_model(ConstantLatency::new(1000, 1000))
+                .asset_type(LinearAsset::new(1.0))
+                .fee_model(TradingValueFeeModel::new(CommonFees::new(0.0, 0.0)))
+                .exchange(exch_kind)
+                .build()
+                .unwrap();
+            let mut hbt: Backtest<BBOMarketDepth> =
+                Backtest::builder().add_asset(asset).build().unwrap();
+
+            hbt.elapse(1_000).unwrap();
+            assert_eq!(hbt.current_timestamp(), 2_000);
+            assert_eq!(hbt.depth(0).best_bid_tick, 1000);
+            assert_eq!(hbt.depth(0).best_ask_tick, 1002);
+            // The order joins the queue behind the quantity of 5 at the touch.
+            hbt.submit_buy_order(0, 1, 100.0, 1.0, TimeInForce::GTC, OrdType::Limit, false)
+                .unwrap();
+
+            hbt.elapse(3_500).unwrap();
+            assert_eq!(hbt.depth(0).best_bid_tick, 1001);
+            assert_eq!(hbt.depth(0).bid_qty_at_tick(1000), 0.0);
+
+            // The touch has moved away from the order, so the order is re-queued behind the quantity
+            // of 8 observed when the touch comes back, rather than staying behind the quantity of 5.
+            hbt.elapse(1_000).unwrap();
+            assert_eq!(hbt.depth(0).best_bid_tick, 1000);
+            assert_eq!(hbt.orders(0).get(&1).unwrap().status, Status::New);
+
+            hbt.goto_end().unwrap();
+            let order = hbt.orders(0).get(&1).unwrap();
+            assert_eq!(order.status, Status::Filled);
+            assert_eq!(order.exch_timestamp, 9_000);
+            assert_eq!(hbt.position(0), 1.0);
+        }
+    }
+
+    #[test]
+    fn test_l1_asset_builder_rejects_depth_beyond_touch() {
+        let data = bbo_data(&[
+            (1_000, DEPTH_BBO_EVENT | BUY_EVENT, 100.0, 5.0),
+            (1_000, DEPTH_BBO_EVENT | SELL_EVENT, 100.2, 3.0),
+            (2_000, DEPTH_EVENT | BUY_EVENT, 99.9, 5.0),
+        ]);
+        let result = L1AssetBuilder::new(0.1, 0.01)
+            .data(vec![DataSource::Data(data)])
+            .latency_model(ConstantLatency::new(1000, 1000))
+            .asset_type(LinearAsset::new(1.0))
+            .fee_model(TradingValueFeeModel::new(CommonFees::new(0.0, 0.0)))
+            .build();
+        assert!(matches!(result, Err(BuildError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_checkpoint_restore() {
+        let data = test_data();
+
+        let mut hbt = build_backtest(&data);
+        hbt.elapse(30_000).unwrap();
+        hbt.submit_buy_order(0, 1, 100.0, 1.0, TimeInForce::GTC, OrdType::Limit, false)
+            .unwrap();
+        // The order is still in flight when the checkpoint is taken.
+        hbt.elapse(500).unwrap();
+        let checkpoint = hbt.checkpoint().unwrap();
+
+        let mut restored = build_backtest(&data);
+        restored.restore(&checkpoint).unwrap();
+        assert_eq!(restored.current_timestamp(), hbt.current_timestamp());
+
+        hbt.goto_end().unwrap();
+        restored.goto_end().unwrap();
+
+        assert!(hbt.position(0) > 0.0);
+        assert_eq!(restored.current_timestamp(), hbt.current_timestamp());
+        assert_eq!(restored.state_values(0), hbt.state_values(0));
+        assert_eq!(restored.depth(0).best_bid_tick, hbt.depth(0).best_bid_tick);
+        assert_eq!(restored.depth(0).best_ask_tick, hbt.depth(0).best_ask_tick);
+        let order = hbt.orders(0).get(&1).unwrap();
+        let restored_order = restored.orders(0).get(&1).unwrap();
+        assert_eq!(restored_order.status, order.status);
+        assert_eq!(restored_order.exch_timestamp, order.exch_timestamp);
+    }
+
+    #[test]
+    fn test_checkpoint_restore_market_impact_and_conditional_orders() {
+        let data = test_data();
+        let builder = || asset_builder(&data).market_impact(MarketImpact::new());
+
+        let mut hbt = build(builder());
+        hbt.elapse(10_000).unwrap();
+        hbt.submit_buy_order(0, 1, 100.2, 0.5, TimeInForce::GTC, OrdType::Limit, true)
+            .unwrap();
+        // A one-cancels-other pair of a stop order and a limit order, which are accepted but whose
+        // responses are still in flight when the checkpoint is taken.
+        let order = OrderRequest {
+            trigger_price: 100.0,
+            oco_order_id: Some(3),
+            ..order_request(2, Side::Sell, OrdType::StopMarket, 0.0, 1.0)
+        };
+        hbt.submit_order(0, order, false).unwrap();
+        let order = OrderRequest {
+            oco_order_id: Some(2),
+            ..order_request(3, Side::Sell, OrdType::Limit, 100.2, 1.0)
+        };
+        hbt.submit_order(0, order, false).unwrap();
+        hbt.elapse(1_500).unwrap();
+        let checkpoint = hbt.checkpoint().unwrap();
+
+        let mut restored = build(builder());
+        restored.restore(&checkpoint).unwrap();
+
+        for hbt in [&mut hbt, &mut restored] {
+            // The sell trade at 100.0 triggers the stop order, which expires the limit order, and
+            // half of the best ask's quantity remains available.
+            hbt.submit_buy_order(0, 4, 100.2, 1.0, TimeInForce::GTC, OrdType::Limit, true)
+                .unwrap();
+            assert_eq!(hbt.orders(0).get(&2).unwrap().status, Status::Filled);
+            assert_eq!(hbt.orders(0).get(&3).unwrap().status, Status::Expired);
+            let order = hbt.orders(0).get(&4).unwrap();
+            assert_eq!(order.status, Status::Filled);
+            assert!((order.exec_price() - 100.15).abs() < 1e-9);
+            assert_eq!(hbt.position(0), 0.5);
+        }
+        assert_eq!(restored.current_timestamp(), hbt.current_timestamp());
+        assert_eq!(restored.state_values(0), hbt.state_values(0));
+    }
+
+    #[test]
+    fn test_l3_checkpoint_restore() {
+        let data = l3_data(&[
+            (1_000, ADD_ORDER_EVENT | BUY_EVENT, 100.0, 5.0, 1),
+            (2_000, ADD_ORDER_EVENT | SELL_EVENT, 100.1, 5.0, 2),
+            (9_000, ADD_ORDER_EVENT | BUY_EVENT, 100.0, 10.0, 3),
+            (10_000, FILL_EVENT | BUY_EVENT, 100.0, 4.0, 1),
+            (10_000, FILL_EVENT | BUY_EVENT, 100.0, 4.0, 3),
+            (20_000, FILL_EVENT | BUY_EVENT, 100.0, 10.0, 3),
+            (30_000, ADD_ORDER_EVENT | SELL_EVENT, 100.2, 5.0, 4),
+        ]);
+
+        for pro_rata in [false, true] {
+            let build = || {
+                if pro_rata {
+                    let queue_model = L3ProRataQueueModel::new()
+                        .top_order_priority(2.0)
+                        .size_time();
+                    build_l3(&data, ExchangeKind::PartialFillExchange, queue_model)
+                } else {
+                    build_l3(
+                        &data,
+                        ExchangeKind::PartialFillExchange,
+                        L3FIFOQueueModel::new(),
+                    )
+                }
+            };
+
+            let mut hbt = build();
+            hbt.elapse(2_500).unwrap();
+            hbt.submit_buy_order(0, 1, 100.0, 5.0, TimeInForce::GTC, OrdType::Limit, true)
+                .unwrap();
+            hbt.elapse(1_000).unwrap();
+            // The order 1 is in the queue, and the order 2 is still in flight when the checkpoint
+            // is taken.
+            hbt.submit_buy_order(0, 2, 99.9, 1.0, TimeInForce::GTC, OrdType::Limit, false)
+                .unwrap();
+            hbt.elapse(500).unwrap();
+            let checkpoint = hbt.checkpoint().unwrap();
+
+            let mut restored = build();
+            restored.restore(&checkpoint).unwrap();
+            assert_eq!(restored.current_timestamp(), hbt.current_timestamp());
+            assert_eq!(restored.depth(0).orders.len(), hbt.depth(0).orders.len());
+
+            hbt.goto_end().unwrap();
+            restored.goto_end().unwrap();
+
+            assert!(hbt.position(0) > 0.0, "{pro_rata}");
+            assert_eq!(restored.current_timestamp(), hbt.current_timestamp());
+            assert_eq!(restored.state_values(0), hbt.state_values(0));
+            assert_eq!(restored.depth(0).best_bid_tick, hbt.depth(0).best_bid_tick);
+            assert_eq!(restored.depth(0).best_ask_tick, hbt.depth(0).best_ask_tick);
+            for order_id in [1, 2] {
+                let order = hbt.orders(0).get(&order_id).unwrap();
+                let restored_order = restored.orders(0).get(&order_id).unwrap();
+                assert_eq!(restored_order.status, order.status);
+                assert_eq!(restored_order.leaves_qty, order.leaves_qty);
+                assert_eq!(restored_order.exch_timestamp, order.exch_timestamp);
+            }
+        }
+    }
+
+    #[test]
+    fn test_timers() {
+        let data = test_data();
+
+        let mut hbt = build_backtest(&data);
+        hbt.elapse(10_000).unwrap();
+        let start = hbt.current_timestamp();
+        hbt.set_timer(1, start + 2_500, 0);
+        hbt.set_timer(2, start + 1_000, 3_000);
+
+        // The elapse is woken up by the timers.
+        hbt.elapse(10_000).unwrap();
+        assert_eq!(hbt.current_timestamp(), start + 1_000);
+        assert_eq!(hbt.fired_timers(), &[2]);
+
+        hbt.wait_next_feed(false, 10_000).unwrap();
+        assert!(hbt.fired_timers().is_empty());
+
+        hbt.elapse(10_000).unwrap();
+        assert_eq!(hbt.current_timestamp(), start + 2_500);
+        assert_eq!(hbt.fired_timers(), &[1]);
+
+        hbt.elapse(10_000).unwrap();
+        assert_eq!(hbt.current_timestamp(), start + 4_000);
+        assert_eq!(hbt.fired_timers(), &[2]);
+
+        assert!(hbt.cancel_timer(2));
+        assert!(!hbt.cancel_timer(1));
+        hbt.elapse(10_000).unwrap();
+        assert_eq!(hbt.current_timestamp(), start + 14_000);
+        assert!(hbt.fired_timers().is_empty());
+    }
+
+    #[test]
+    fn test_timer_in_past() {
+        let data = test_data();
+
+        let mut hbt = build_backtest(&data);
+        hbt.elapse(10_000).unwrap();
+        let start = hbt.current_timestamp();
+        hbt.set_timer(1, start - 5_000, 0);
+        hbt.set_timer(2, start - 5_000, 3_000);
+
+        // The timers in the past fire at the current timestamp without moving the time backwards.
+        hbt.elapse(10_000).unwrap();
+        assert_eq!(hbt.current_timestamp(), start);
+        assert_eq!(hbt.fired_timers(), &[1, 2]);
+
+        hbt.elapse(10_000).unwrap();
+        assert_eq!(hbt.current_timestamp(), start + 3_000);
+        assert_eq!(hbt.fired_timers(), &[2]);
+    }
+
+    #[test]
+    fn test_custom_data() {
+        let data = test_data();
+        let signals: Vec<_> = [5_050, 10_050, 10_050, 20_050]
+            .iter()
+            .enumerate()
+            .map(|(i, &local_ts)| Event {
+                ev: 0,
+                exch_ts: 0,
+                local_ts,
+                px: 0.0,
+                qty: 0.0,
+                order_id: 0,
+                ival: 0,
+                fval: i as f64,
+            })
+            .collect();
+        let custom = Data::from_items(&signals);
+
+        let builder = || asset_builder(&data).custom_data(vec![DataSource::Data(custom.clone())]);
+        let mut hbt = build(builder());
+        let mut checkpoint = None;
+        for _ in 0..25 {
+            hbt.elapse(1_000).unwrap();
+            let cur_ts = hbt.current_timestamp();
+            // Only the items received by the local at or before the current time are visible.
+            let expected = signals
+                .iter()
+                .rposition(|signal| signal.local_ts <= cur_ts)
+                .map(|i| i as f64);
+            let latest = hbt.custom_data::<Event>(0, 0).map(|signal| signal.fval);
+            assert_eq!(latest, expected);
+            if checkpoint.is_none() && latest == Some(0.0) {
+                checkpoint = Some(hbt.checkpoint().unwrap());
+            }
+        }
+
+        let mut restored = build(builder());
+        restored.restore(&checkpoint.unwrap()).unwrap();
+        assert_eq!(restored.custom_data::<Event>(0, 0).unwrap().fval, 0.0);
+        restored
+            .elapse(hbt.current_timestamp() - restored.current_timestamp())
+            .unwrap();
+        assert_eq!(restored.custom_data::<Event>(0, 0).unwrap().fval, 3.0);
+        assert_eq!(hbt.custom_data::<Event>(0, 0).unwrap().fval, 3.0);
+    }
+
+    #[test]
+    fn test_funding() {
+        let data = test_data();
+        let rates = [FundingRate {
+            timestamp: 0,
+            rate: 0.001,
+            mark_px: 100.0,
+        }];
+        let mut hbt = build(
+            asset_builder(&data)
+                .funding(vec![DataSource::Data(Data::from_items(&rates))])
+                .funding_interval(50_000),
+        );
+
+        hbt.elapse(10_000).unwrap();
+        hbt.submit_buy_order(0, 1, 100.1, 1.0, TimeInForce::GTC, OrdType::Limit, false)
+            .unwrap();
+        hbt.elapse(30_000).unwrap();
+        assert_eq!(hbt.position(0), 1.0);
+        let balance = hbt.state_values(0).balance;
+        assert_eq!(hbt.state_values(0).funding, 0.0);
+
+        // The long position pays the funding at the settlement.
+        hbt.elapse(20_000).unwrap();
+        let state_values = hbt.state_values(0);
+        assert!((state_values.funding - 0.1).abs() < 1e-9);
+        assert!((state_values.balance - (balance - 0.1)).abs() < 1e-9);
+    }
+
+    fn build_backtest_with_margin(
+        data: &Data<Event>,
+        rates: &[FundingRate],
+        collateral: f64,
+    ) -> Backtest<HashMapMarketDepth> {
+        let margin_model =
+            TieredMarginModel::new(vec![RiskLimitTier::new(1_000_000.0, 20.0, 0.05, 0.0)], 10.0);
+        build(
+            asset_builder(data)
+                .funding(vec![DataSource::Data(Data::from_items(rates))])
+                .funding_interval(50_000)
+                .margin(margin_model, collateral),
+        )
+    }
+
+    #[test]
+    fn test_funding_liquidation() {
+        let data = test_data();
+        let rates = [FundingRate {
+            timestamp: 0,
+            rate: 0.06,
+            mark_px: 100.0,
+        }];
+        let mut hbt = build_backtest_with_margin(&data, &rates, 10.2);
+
+        hbt.elapse(10_000).unwrap();
+        hbt.submit_buy_order(0, 1, 100.1, 1.0, TimeInForce::GTC, OrdType::Limit, true)
+            .unwrap();
+        hbt.elapse(30_000).unwrap();
+        assert_eq!(hbt.position(0), 1.0);
+        assert_eq!(hbt.state_values(0).num_liquidations, 0);
+
+        // The exchange settles the funding as well, after which the collateral plus the equity
+        // falls below the maintenance margin.
+        hbt.elapse(20_000).unwrap();
+        let state_values = hbt.state_values(0);
+        assert!((state_values.funding - 6.0).abs() < 1e-9);
+        assert_eq!(state_values.position, 0.0);
+        assert_eq!(state_values.num_liquidations, 1);
+        assert!((state_values.balance - (-100.1 - 6.0 + 100.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_margin_rejection() {
+        let data = test_data();
+        let rates = [FundingRate {
+            timestamp: 0,
+            rate: 0.0,
+            mark_px: 100.0,
+        }];
+        let mut hbt = build_backtest_with_margin(&data, &rates, 15.0);
+
+        hbt.elapse(10_000).unwrap();
+        hbt.submit_buy_order(0, 1, 99.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)
+            .unwrap();
+        assert_eq!(hbt.orders(0).get(&1).unwrap().status, Status::New);
+        assert_eq!(hbt.state_values(0).initial_margin, 10.0);
+
+        // The initial margin of both orders exceeds the collateral.
+        hbt.submit_buy_order(0, 2, 99.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)
+            .unwrap();
+        assert_eq!(hbt.orders(0).get(&2).unwrap().status, Status::Expired);
+
+        // Orders on the opposite side do not increase the initial margin.
+        hbt.submit_sell_order(0, 3, 101.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)
+            .unwrap();
+        assert_eq!(hbt.orders(0).get(&3).unwrap().status, Status::New);
+        assert_eq!(hbt.state_values(0).initial_margin, 10.0);
+
+        // Modifying the order is subject to the same check.
+        hbt.modify(0, 1, 99.0, 2.0, true).unwrap();
+        let order = hbt.orders(0).get(&1).unwrap();
+        assert_eq!(order.status, Status::New);
+        assert_eq!(order.qty, 1.0);
+        assert_eq!(hbt.state_values(0).initial_margin, 10.0);
+
+        hbt.modify(0, 1, 99.0, 1.4, true).unwrap();
+        assert_eq!(hbt.orders(0).get(&1).unwrap().qty, 1.4);
+        assert!((hbt.state_values(0).initial_margin - 14.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_liquidation() {
+        let data = test_data();
+        let rates = [
+            FundingRate {
+                timestamp: 0,
+                rate: 0.0,
+                mark_px: 100.0,
+            },
+            FundingRate {
+                timestamp: 60_000,
+                rate: 0.0,
+                mark_px: 90.0,
+            },
+        ];
+        let mut hbt = build_backtest_with_margin(&data, &rates, 12.5);
+
+        hbt.elapse(10_000).unwrap();
+        hbt.submit_buy_order(0, 1, 100.1, 1.0, TimeInForce::GTC, OrdType::Limit, true)
+            .unwrap();
+        hbt.submit_buy_order(0, 2, 99.0, 0.2, TimeInForce::GTC, OrdType::Limit, true)
+            .unwrap();
+        hbt.elapse(10_000).unwrap();
+        assert_eq!(hbt.position(0), 1.0);
+        assert_eq!(hbt.state_values(0).maintenance_margin, 5.0);
+        assert_eq!(hbt.state_values(0).num_liquidations, 0);
+
+        // The mark price drops, and the collateral plus the equity falls below the maintenance
+        // margin.
+        hbt.elapse(50_000).unwrap();
+        let state_values = hbt.state_values(0);
+        assert_eq!(state_values.position, 0.0);
+        assert_eq!(state_values.num_liquidations, 1);
+        assert!((state_values.balance - (-100.1 + 90.0)).abs() < 1e-9);
+        assert_eq!(state_values.maintenance_margin, 0.0);
+        // The open orders are canceled.
+        assert_eq!(hbt.orders(0).get(&2).unwrap().status, Status::Canceled);
+
+        // The exchange has closed the position as well, so a reduce-only order has nothing to
+        // reduce.
+        let order = OrderRequest {
+            reduce_only: true,
+            ..order_request(3, Side::Sell, OrdType::Limit, 101.0, 1.0)
+        };
+        hbt.submit_order(0, order, true).unwrap();
+        assert_eq!(hbt.orders(0).get(&3).unwrap().status, Status::Expired);
+    }
+
+    #[test]
+    fn test_spot_inventory() {
+        let data = test_data();
+        let asset = L2AssetBuilder::new()
+            .data(vec![DataSource::Data(data.clone())])
+            .initial_inventory(1.0, 100.0)
+            .latency_model(ConstantLatency::new(1000, 1000))
+            .asset_type(SpotAsset::new(SpotFeeCurrency::Received))
+            .fee_model(TradingValueFeeModel::new(CommonFees::new(0.0, 0.001)))
+            .queue_model(RiskAdverseQueueModel::new())
+            .depth(|| HashMapMarketDepth::new(0.1, 0.01))
+            .build()
+            .unwrap();
+        let mut hbt = Backtest::builder().add_asset(asset).build().unwrap();
+
+        hbt.elapse(10_000).unwrap();
+        // The base inventory cannot cover the sell order.
+        hbt.submit_sell_order(0, 1, 101.0, 2.0, TimeInForce::GTC, OrdType::Limit, true)
+            .unwrap();
+        assert_eq!(hbt.orders(0).get(&1).unwrap().status, Status::Expired);
+
+        hbt.submit_sell_order(0, 2, 101.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)
+            .unwrap();
+        assert_eq!(hbt.orders(0).get(&2).unwrap().status, Status::New);
+
+        // The base inventory is reserved by the open sell order.
+        hbt.submit_sell_order(0, 3, 101.5, 0.5, TimeInForce::GTC, OrdType::Limit, true)
+            .unwrap();
+        assert_eq!(hbt.orders(0).get(&3).unwrap().status, Status::Expired);
+
+        // Nor can it cover the increased quantity, so the modification is rejected.
+        hbt.modify(0, 2, 101.0, 1.5, true).unwrap();
+        let order = hbt.orders(0).get(&2).unwrap();
+        assert_eq!(order.status, Status::New);
+        assert_eq!(order.qty, 1.0);
+
+        // The fee is deducted from the received base currency.
+        hbt.submit_buy_order(0, 4, 100.1, 0.5, TimeInForce::GTC, OrdType::Limit, true)
+            .unwrap();
+        assert_eq!(hbt.orders(0).get(&4).unwrap().status, Status::Filled);
+        assert!((hbt.position(0) - 1.4995).abs() < 1e-9);
+        assert!((hbt.state_values(0).balance - 49.95).abs() < 1e-9);
+
+        // The quote inventory cannot cover the buy order.
+        hbt.submit_buy_order(0, 5, 100.1, 0.5, TimeInForce::GTC, OrdType::Limit, true)
+            .unwrap();
+        assert_eq!(hbt.orders(0).get(&5).unwrap().status, Status::Expired);
+    }
+
+    #[test]
+    fn test_expiry() {
+        let data = test_data();
+        let mut hbt = build(asset_builder(&data).expiry(50_000, Some(100.0)));
+
+        hbt.elapse(10_000).unwrap();
+        hbt.submit_buy_order(0, 1, 100.1, 1.0, TimeInForce::GTC, OrdType::Limit, true)
+            .unwrap();
+        hbt.submit_buy_order(0, 2, 99.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)
+            .unwrap();
+        assert_eq!(hbt.position(0), 1.0);
+
+        hbt.elapse(50_000).unwrap();
+        // The position is settled at the settlement price and the open orders are expired.
+        let state_values = hbt.state_values(0);
+        assert_eq!(state_values.position, 0.0);
+        assert_eq!(state_values.settlement, 100.0);
+        assert!((state_values.balance + 0.1).abs() < 1e-9);
+        assert_eq!(hbt.orders(0).get(&2).unwrap().status, Status::Expired);
+
+        // Further orders are rejected.
+        hbt.submit_buy_order(0, 3, 99.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)
+            .unwrap();
+        assert_eq!(hbt.orders(0).get(&3).unwrap().status, Status::Expired);
+    }
+
+    #[test]
+    fn test_self_trade_prevention() {
+        // (mode, incoming qty, resting status, resting qty, incoming status, position)
+        let cases = [
+            (SelfTradePrevention::None, 1.0, Status::New, 1.0, Status::Filled, 1.0),
+            (SelfTradePrevention::CancelNewest, 1.0, Status::New, 1.0, Status::Expired, 0.0),
+            (SelfTradePrevention::CancelOldest, 1.0, Status::Expired, 1.0, Status::Filled, 1.0),
+            (SelfTradePrevention::CancelBoth, 1.0, Status::Expired, 1.0, Status::Expired, 0.0),
+            (SelfTradePrevention::Decrement, 3.0, Status::Expired, 0.0, Status::Filled, 2.0),
+            (SelfTradePrevention::Decrement, 0.5, Status::New, 0.5, Status::Expired, 0.0),
+        ];
+        for (mode, qty, resting_status, resting_qty, status, position) in cases {
+            let mut hbt = build_backtest(&test_data());
+            hbt.elapse(10_000).unwrap();
+            hbt.submit_sell_order(0, 1, 100.2, 1.0, TimeInForce::GTC, OrdType::Limit, true)
+                .unwrap();
+
+            // The buy order crosses the resting sell order as well as the best ask.
+            let order = OrderRequest {
+                self_trade_prevention: mode,
+                ..order_request(2, Side::Buy, OrdType::Limit, 100.2, qty)
+            };
+            hbt.submit_order(0, order, true).unwrap();
+            hbt.elapse(1_000).unwrap();
+
+            let resting = hbt.orders(0).get(&1).unwrap();
+            assert_eq!(resting.status, resting_status, "{mode:?}");
+            assert_eq!(resting.leaves_qty, resting_qty, "{mode:?}");
+            assert_eq!(hbt.orders(0).get(&2).unwrap().status, status, "{mode:?}");
+            assert_eq!(hbt.position(0), position, "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn test_l3_self_trade_prevention() {
+        // The market-feed orders are added every 1ms to keep the exchange processing.
+        let mut rows = vec![
+            (1_000, ADD_ORDER_EVENT | BUY_EVENT, 100.0, 5.0, 1),
+            (1_000, ADD_ORDER_EVENT | SELL_EVENT, 100.1, 5.0, 2),
+        ];
+        for i in 2..30 {
+            let order_id = i as u64 + 1;
+            rows.push((i * 1_000, ADD_ORDER_EVENT | BUY_EVENT, 99.0, 1.0, order_id));
+        }
+        let data = l3_data(&rows);
+
+        // (mode, incoming qty, resting status, resting qty, incoming status, position)
+        let cases = [
+            (SelfTradePrevention::None, 1.0, Status::New, 1.0, Status::Filled, 1.0),
+            (SelfTradePrevention::CancelNewest, 1.0, Status::New, 1.0, Status::Expired, 0.0),
+            (SelfTradePrevention::CancelOldest, 1.0, Status::Expired, 0.0, Status::Filled, 1.0),
+            (SelfTradePrevention::CancelBoth, 1.0, Status::Expired, 0.0, Status::Expired, 0.0),
+            (SelfTradePrevention::Decrement, 3.0, Status::Expired, 0.0, Status::Filled, 2.0),
+            (SelfTradePrevention::Decrement, 0.5, Status::New, 0.5, Status::Expired, 0.0),
+        ];
+        for partial_fill in [false, true] {
+            for (mode, qty, resting_status, resting_qty, status, position) in cases {
+                let exch_kind = if partial_fill {
+                    ExchangeKind::PartialFillExchange
+                } else {
+                    ExchangeKind::NoPartialFillExchange
+                };
+                let mut hbt = build_l3(&data, exch_kind, L3FIFOQueueModel::new());
+                hbt.elapse(5_000).unwrap();
+                hbt.submit_sell_order(0, 1, 100.2, 1.0, TimeInForce::GTC, OrdType::Limit, true)
+                    .unwrap();
+
+                // The buy order crosses the resting sell order as well as the best ask.
+                let order = OrderRequest {
+                    self_trade_prevention: mode,
+                    ..order_request(2, Side::Buy, OrdType::Limit, 100.2, qty)
+                };
+                hbt.submit_order(0, order, true).unwrap();
+                hbt.elapse(2_000).unwrap();
+
+                let resting = hbt.orders(0).get(&1).unwrap();
+                assert_eq!(resting.status, resting_status, "{mode:?}");
+                assert_eq!(resting.leaves_qty, resting_qty, "{mode:?}");
+                assert_eq!(hbt.orders(0).get(&2).unwrap().status, status, "{mode:?}");
+                assert_eq!(hbt.position(0), position, "{mode:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_market_impact() {
+        // (exchange, market impact, executed price of the second order)
+        let cases = [
+            (ExchangeKind::NoPartialFillExchange, false, 100.1),
+            (ExchangeKind::NoPartialFillExchange, true, 100.15),
+            (ExchangeKind::PartialFillExchange, true, 100.15),
+        ];
+        for (exch_kind, impact, exec_price) in cases {
+            let mut builder = asset_builder(&test_data()).exchange(exch_kind);
+            if impact {
+                builder = builder.market_impact(MarketImpact::new());
+            }
+            let mut hbt = build(builder);
+
+            hbt.elapse(10_000).unwrap();
+            hbt.submit_buy_order(0, 1, 100.2, 0.5, TimeInForce::GTC, OrdType::Limit, true)
+                .unwrap();
+            assert!((hbt.orders(0).get(&1).unwrap().exec_price() - 100.1).abs() < 1e-9);
+
+            // The best ask's quantity of 1 does not change, so half of it remains available.
+            hbt.submit_buy_order(0, 2, 100.2, 1.0, TimeInForce::GTC, OrdType::Limit, true)
+                .unwrap();
+            let order = hbt.orders(0).get(&2).unwrap();
+            assert_eq!(order.status, Status::Filled, "{impact}");
+            assert!((order.exec_price() - exec_price).abs() < 1e-9, "{impact}");
+            assert_eq!(hbt.position(0), 1.5);
+            let balance = -(100.1 * 0.5 + exec_price * 1.0);
+            assert!((hbt.state_values(0).balance - balance).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_sweep_book_levels() {
+        // The second ask level is 199 ticks away from the best.
+        let data = bbo_data(&[
+            (1_000, DEPTH_EVENT | BUY_EVENT, 100.0, 5.0),
+            (1_000, DEPTH_EVENT | SELL_EVENT, 100.1, 1.0),
+            (1_000, DEPTH_EVENT | SELL_EVENT, 120.0, 5.0),
+            (20_000, DEPTH_EVENT | BUY_EVENT, 99.9, 1.0),
+        ]);
+        for (order_type, price) in [(OrdType::Market, 0.0), (OrdType::Limit, 1000.0)] {
+            let cases = [
+                (ExchangeKind::NoPartialFillExchange, true),
+                (ExchangeKind::PartialFillExchange, false),
+                (ExchangeKind::PartialFillExchange, true),
+            ];
+            for (exch_kind, impact) in cases {
+                let mut builder = asset_builder(&data).exchange(exch_kind);
+                if impact {
+                    builder = builder.market_impact(MarketImpact::new());
+                }
+                let mut hbt = build(builder);
+
+                hbt.elapse(5_000).unwrap();
+                hbt.submit_buy_order(0, 1, price, 2.0, TimeInForce::IOC, order_type, true)
+                    .unwrap();
+                let order = hbt.orders(0).get(&1).unwrap();
+                assert_eq!(order.status, Status::Filled, "{order_type:?} {impact}");
+                assert_eq!(order.exec_price_tick, 1200, "{order_type:?} {impact}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_conditional_orders() {
+        let request =
+            |order_id, side, order_type, price, trigger_price, oco_order_id| OrderRequest {
+                trigger_price,
+                oco_order_id,
+                ..order_request(order_id, side, order_type, price, 1.0)
+            };
+        let mut hbt = build_backtest(&test_data());
+        hbt.elapse(11_500).unwrap();
+
+        // The last trade price is 100.1, so this would be triggered immediately.
+        let order = request(1, Side::Sell, OrdType::StopMarket, 0.0, 100.1, None);
+        hbt.submit_order(0, order, false).unwrap();
+        // A one-cancels-other pair of a stop order and a limit order.
+        let order = request(2, Side::Sell, OrdType::StopMarket, 0.0, 100.0, Some(3));
+        hbt.submit_order(0, order, false).unwrap();
+        let order = request(3, Side::Sell, OrdType::Limit, 100.2, 0.0, Some(2));
+        hbt.submit_order(0, order, false).unwrap();
+        let order = request(4, Side::Buy, OrdType::StopMarket, 0.0, 101.0, None);
+        hbt.submit_order(0, order, false).unwrap();
+
+        hbt.elapse(2_000).unwrap();
+        assert_eq!(hbt.orders(0).get(&1).unwrap().status, Status::Expired);
+        let order = hbt.orders(0).get(&2).unwrap();
+        assert_eq!(order.status, Status::New);
+        assert_eq!(order.order_type, OrdType::StopMarket);
+        assert_eq!(hbt.orders(0).get(&3).unwrap().status, Status::New);
+        assert_eq!(hbt.orders(0).get(&4).unwrap().status, Status::New);
+        hbt.cancel(0, 4, false).unwrap();
+
+        // The sell trade at 100.0 triggers the stop order, which expires the limit order.
+        hbt.elapse(3_000).unwrap();
+        let order = hbt.orders(0).get(&2).unwrap();
+        assert_eq!(order.status, Status::Filled);
+        assert_eq!(order.order_type, OrdType::Market);
+        assert_eq!(order.exec_price_tick, 1000);
+        assert_eq!(hbt.orders(0).get(&3).unwrap().status, Status::Expired);
+        assert_eq!(hbt.orders(0).get(&4).unwrap().status, Status::Canceled);
+        assert_eq!(hbt.position(0), -1.0);
+    }
+
+    #[test]
+    fn test_conditional_order_margin_rejection() {
+        let rates = [
+            FundingRate {
+                timestamp: 0,
+                rate: 0.0,
+                mark_px: 100.0,
+            },
+            FundingRate {
+                timestamp: 20_000,
+                rate: 0.0,
+                mark_px: 200.0,
+            },
+        ];
+        let margin_model =
+            TieredMarginModel::new(vec![RiskLimitTier::new(1_000_000.0, 20.0, 0.05, 0.0)], 10.0);
+        for exch_kind in [
+            ExchangeKind::NoPartialFillExchange,
+            ExchangeKind::PartialFillExchange,
+        ] {
+            let mut hbt = build(
+                asset_builder(&test_data())
+                    .exchange(exch_kind)
+                    .funding(vec![DataSource::Data(Data::from_items(&rates))])
+                    .margin(margin_model.clone(), 15.0),
+            );
+            hbt.elapse(10_000).unwrap();
+            let order = OrderRequest {
+                trigger_price: 150.0,
+                trigger_price_type: TriggerPriceType::MarkPrice,
+                ..order_request(1, Side::Buy, OrdType::StopMarket, 0.0, 1.0)
+            };
+            hbt.submit_order(0, order, true).unwrap();
+            assert_eq!(hbt.orders(0).get(&1).unwrap().status, Status::New);
+
+            // The mark price triggers the stop order, whose initial margin now exceeds the
+            // collateral, so the exchange expires the accepted order.
+            hbt.elapse(20_000).unwrap();
+            let order = hbt.orders(0).get(&1).unwrap();
+            assert_eq!(order.status, Status::Expired);
+            assert_eq!(order.req, Status::None);
+            assert_eq!(hbt.position(0), 0.0);
+        }
+    }
+
+    #[test]
+    fn test_gtd_and_reduce_only_orders() {
+        let request =
+            |order_id, side, order_type, price, qty, expire_timestamp, reduce_only| OrderRequest {
+                time_in_force: if expire_timestamp > 0 {
+                    TimeInForce::GTD
+                } else {
+                    TimeInForce::GTC
+                },
+                expire_timestamp,
+                reduce_only,
+                ..order_request(order_id, side, order_type, price, qty)
+            };
+        let mut hbt = build_backtest(&test_data());
+        hbt.elapse(10_500).unwrap();
+
+        let order = request(1, Side::Buy, OrdType::Limit, 99.0, 1.0, 15_000, false);
+        hbt.submit_order(0, order, false).unwrap();
+        // This has already expired when it is received.
+        let order = request(2, Side::Buy, OrdType::Limit, 99.0, 1.0, 5_000, false);
+        hbt.submit_order(0, order, false).unwrap();
+        // This would increase the position.
+        let order = request(3, Side::Sell, OrdType::Limit, 100.5, 1.0, 0, true);
+        hbt.submit_order(0, order, false).unwrap();
+        let order = request(4, Side::Buy, OrdType::Market, 0.0, 1.0, 0, false);
+        hbt.submit_order(0, order, false).unwrap();
+
+        hbt.elapse(2_000).unwrap();
+        assert_eq!(hbt.orders(0).get(&1).unwrap().status, Status::New);
+        assert_eq!(hbt.orders(0).get(&2).unwrap().status, Status::Expired);
+        assert_eq!(hbt.orders(0).get(&3).unwrap().status, Status::Expired);
+        assert_eq!(hbt.position(0), 1.0);
+
+        // The quantity exceeding the position is clipped.
+        let order = request(5, Side::Sell, OrdType::Market, 0.0, 2.0, 0, true);
+        hbt.submit_order(0, order, false).unwrap();
+        let order = request(6, Side::Buy, OrdType::Market, 0.0, 1.0, 0, false);
+        hbt.submit_order(0, order, false).unwrap();
+        // The resting reduce-only order is expired once the position is closed.
+        let order = request(7, Side::Sell, OrdType::Limit, 100.5, 1.0, 0, true);
+        hbt.submit_order(0, order, false).unwrap();
+        let order = request(8, Side::Sell, OrdType::Market, 0.0, 1.0, 0, false);
+        hbt.submit_order(0, order, false).unwrap();
+
+        hbt.elapse(2_000).unwrap();
+        let order = hbt.orders(0).get(&5).unwrap();
+        assert_eq!(order.status, Status::Filled);
+        assert_eq!(order.qty, 1.0);
+        assert_eq!(order.exec_qty, 1.0);
+        assert_eq!(hbt.orders(0).get(&7).unwrap().status, Status::Expired);
+        assert_eq!(hbt.orders(0).get(&8).unwrap().status, Status::Filled);
+        assert_eq!(hbt.position(0), 0.0);
+        assert_eq!(hbt.orders(0).get(&1).unwrap().status, Status::New);
+
+        // The good-till-date order is expired at 15_000.
+        hbt.elapse(2_000).unwrap();
+        let order = hbt.orders(0).get(&1).unwrap();
+        assert_eq!(order.status, Status::Expired);
+        assert_eq!(order.exch_timestamp, 15_000);
+    }
+
+    #[test]
+    fn test_batch_orders() {
+        let request =
+            |order_id, price| order_request(order_id, Side::Buy, OrdType::Limit, price, 1.0);
+        let mut hbt = build_backtest(&test_data());
+        hbt.elapse(10_500).unwrap();
+
+        let orders = (1..=4).map(|order_id| request(order_id, 99.0)).collect();
+        hbt.submit_orders(0, orders, true).unwrap();
+        // Each order goes through the order latency on its own, rather than the batch as a whole.
+        assert_eq!(hbt.current_timestamp(), 12_500);
+        assert_eq!(hbt.orders(0).len(), 4);
+        for order in hbt.orders(0).values() {
+            assert_eq!(order.status, Status::New);
+            assert_eq!(order.req, Status::None);
+            assert_eq!(order.exch_timestamp, 11_500);
+        }
+
+        hbt.cancel_orders(0, &[1, 2], true).unwrap();
+        assert_eq!(hbt.current_timestamp(), 14_500);
+        assert_eq!(hbt.orders(0).get(&1).unwrap().status, Status::Canceled);
+        assert_eq!(hbt.orders(0).get(&2).unwrap().status, Status::Canceled);
+        assert_eq!(hbt.orders(0).get(&3).unwrap().status, Status::New);
+
+        // Only the cancellable orders are canceled.
+        hbt.cancel_all(0, true).unwrap();
+        assert_eq!(hbt.current_timestamp(), 16_500);
+        for order in hbt.orders(0).values() {
+            assert_eq!(order.status, Status::Canceled);
+        }
+        assert_eq!(hbt.orders(0).get(&3).unwrap().exch_timestamp, 15_500);
+
+        // Nothing is sent if there are no cancellable orders.
+        hbt.cancel_all(0, true).unwrap();
+        assert_eq!(hbt.current_timestamp(), 16_500);
+    }
+
+    #[test]
+    fn test_batch_orders_with_invalid_entry() {
+        let request =
+            |order_id, price| order_request(order_id, Side::Buy, OrdType::Limit, price, 1.0);
+        let mut hbt = build_backtest(&test_data());
+        hbt.elapse(10_500).unwrap();
+        hbt.submit_buy_order(0, 1, 99.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)
+            .unwrap();
+
+        // The existing order ID in the middle of the batch rejects the entire batch.
+        let orders = vec![request(2, 99.0), request(1, 99.0), request(3, 99.0)];
+        assert!(matches!(
+            hbt.submit_orders(0, orders, true),
+            Err(BacktestError::OrderIdExist)
+        ));
+        // So does the duplicate order ID.
+        let orders = vec![request(2, 99.0), request(2, 99.0), request(3, 99.0)];
+        assert!(matches!(
+            hbt.submit_orders(0, orders, true),
+            Err(BacktestError::OrderIdExist)
+        ));
+        assert_eq!(hbt.orders(0).len(), 1);
+
+        let orders = vec![request(2, 99.0), request(3, 99.0)];
+        hbt.submit_orders(0, orders, true).unwrap();
+
+        // The unknown order in the middle of the batch rejects the entire batch.
+        assert!(matches!(
+            hbt.cancel_orders(0, &[1, 4, 2], true),
+            Err(BacktestError::OrderNotFound)
+        ));
+        assert!(matches!(
+            hbt.cancel_orders(0, &[1, 2, 1], true),
+            Err(BacktestError::OrderRequestInProcess)
+        ));
+        for order in hbt.orders(0).values() {
+            assert_eq!(order.status, Status::New);
+            assert_eq!(order.req, Status::None);
+        }
+    }
+
+    #[test]
+    fn test_l3_queue_ahead() {
+        let data = l3_data(&[
+            (1_000, ADD_ORDER_EVENT | BUY_EVENT, 100.0, 1.0, 1),
+            (2_000, ADD_ORDER_EVENT | BUY_EVENT, 100.0, 3.0, 2),
+            (5_000, ADD_ORDER_EVENT | BUY_EVENT, 100.0, 2.0, 3),
+            (6_000, CANCEL_ORDER_EVENT | BUY_EVENT, 100.0, 0.0, 1),
+            (20_000, ADD_ORDER_EVENT | SELL_EVENT, 100.2, 1.0, 4),
+        ]);
+        let mut hbt = build_l3(
+            &data,
+            ExchangeKind::NoPartialFillExchange,
+            L3FIFOQueueModel::new(),
+        );
+        hbt.elapse(2_000).unwrap();
+        hbt.submit_buy_order(0, 1, 100.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)
+            .unwrap();
+        assert_eq!(hbt.orders(0).get(&1).unwrap().exch_timestamp, 4_000);
+
+        // The feed orders placed before the order reaches the exchange are ahead of it.
+        assert_eq!(hbt.queue_ahead(0, 1), Some(4.0));
+        assert_eq!(hbt.queue_ahead(0, 2), None);
+
+        hbt.elapse(2_000).unwrap();
+        assert_eq!(hbt.queue_ahead(0, 1), Some(3.0));
+    }
+
+    #[test]
+    fn test_l3_partial_fill() {
+        // The backtest order is placed between the bid orders 1 and 3, and the bid order 3 is
+        // filled twice by 2.
+        let data = l3_data(&[
+            (1_000, ADD_ORDER_EVENT | BUY_EVENT, 100.0, 5.0, 1),
+            (2_000, ADD_ORDER_EVENT | SELL_EVENT, 100.1, 5.0, 2),
+            (5_000, ADD_ORDER_EVENT | BUY_EVENT, 100.0, 5.0, 3),
+            (10_000, FILL_EVENT | BUY_EVENT, 100.0, 2.0, 3),
+            (20_000, FILL_EVENT | BUY_EVENT, 100.0, 2.0, 3),
+            (30_000, ADD_ORDER_EVENT | SELL_EVENT, 100.2, 5.0, 4),
+        ]);
+
+        for (exch_kind, position) in [
+            (ExchangeKind::NoPartialFillExchange, 3.0),
+            (ExchangeKind::PartialFillExchange, 2.0),
+        ] {
+            let partial_fill = matches!(exch_kind, ExchangeKind::PartialFillExchange);
+            let mut hbt = build_l3(&data, exch_kind, L3FIFOQueueModel::new());
+
+            hbt.elapse(2_500).unwrap();
+            hbt.submit_buy_order(0, 1, 100.0, 3.0, TimeInForce::GTC, OrdType::Limit, false)
+                .unwrap();
+            hbt.elapse(8_000).unwrap();
+            assert_eq!(hbt.position(0), position);
+
+            // The partially filled order remains in the queue and is filled by the remaining
+            // quantity.
+            if partial_fill {
+                let order = hbt.orders(0).get(&1).unwrap();
+                assert_eq!(order.status, Status::PartiallyFilled);
+                assert!((order.leaves_qty - 1.0).abs() < 1e-9);
+            }
+
+            hbt.elapse(10_000).unwrap();
+            assert_eq!(hbt.position(0), 3.0);
+            assert_eq!(hbt.orders(0).get(&1).unwrap().status, Status::Filled);
+        }
+    }
+
+    #[test]
+    fn test_l3_modify_inventory() {
+        let data = l3_data(&[
+            (1_000, ADD_ORDER_EVENT | BUY_EVENT, 100.0, 5.0, 1),
+            (2_000, ADD_ORDER_EVENT | SELL_EVENT, 100.1, 5.0, 2),
+            (30_000, ADD_ORDER_EVENT | SELL_EVENT, 100.2, 5.0, 3),
+        ]);
+
+        for exch_kind in [
+            ExchangeKind::NoPartialFillExchange,
+            ExchangeKind::PartialFillExchange,
+        ] {
+            let asset = L3AssetBuilder::new()
+                .data(vec![DataSource::Data(data.clone())])
+                .initial_inventory(0.0, 200.0)
+                .latency_model(ConstantLatency::new(1000, 1000))
+                .asset_type(SpotAsset::new(SpotFeeCurrency::Received))
+                .fee_model(TradingValueFeeModel::new(CommonFees::new(0.0, 0.0)))
+                .queue_model(L3FIFOQueueModel::new())
+                .exchange(exch_kind)
+                .depth(|| HashMapMarketDepth::new(0.1, 0.1))
+                .build()
+                .unwrap();
+            let mut hbt = Backtest::builder().add_asset(asset).build().unwrap();
+
+            hbt.elapse(2_500).unwrap();
+            hbt.submit_buy_order(0, 1, 99.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)
+                .unwrap();
+            assert_eq!(hbt.orders(0).get(&1).unwrap().status, Status::New);
+
+            // The quote inventory cannot cover the increased quantity.
+            hbt.modify(0, 1, 99.0, 3.0, true).unwrap();
+            let order = hbt.orders(0).get(&1).unwrap();
+            assert_eq!(order.status, Status::New);
+            assert_eq!(order.qty, 1.0);
+
+            hbt.modify(0, 1, 99.0, 2.0, true).unwrap();
+            assert_eq!(hbt.orders(0).get(&1).unwrap().qty, 2.0);
+        }
+    }
+
+    #[test]
+    fn test_l3_taking_orders() {
+        // The ask side has 1.0 at each of 100.1, 100.2, and 100.3.
+        let data = l3_data(&[
+            (1_000, ADD_ORDER_EVENT | BUY_EVENT, 100.0, 5.0, 1),
+            (2_000, ADD_ORDER_EVENT | SELL_EVENT, 100.1, 1.0, 2),
+            (3_000, ADD_ORDER_EVENT | SELL_EVENT, 100.2, 1.0, 3),
+            (4_000, ADD_ORDER_EVENT | SELL_EVENT, 100.3, 1.0, 4),
+            (8_000, ADD_ORDER_EVENT | BUY_EVENT, 99.9, 5.0, 5),
+            (20_000, ADD_ORDER_EVENT | BUY_EVENT, 99.8, 5.0, 6),
+        ]);
+
+        // (exchange, time-in-force, price, qty, filled qty, trading value, status)
+        let cases = [
+            // The non-partial fill exchange fills the entire quantity at the best.
+            (
+                ExchangeKind::NoPartialFillExchange,
+                TimeInForce::IOC,
+                100.2,
+                2.5,
+                2.5,
+                250.25,
+                Status::Filled,
+            ),
+            (
+                ExchangeKind::NoPartialFillExchange,
+                TimeInForce::FOK,
+                100.3,
+                5.0,
+                5.0,
+                500.5,
+                Status::Filled,
+            ),
+            (
+                ExchangeKind::NoPartialFillExchange,
+                TimeInForce::GTC,
+                100.2,
+                2.5,
+                2.5,
+                250.25,
+                Status::Filled,
+            ),
+            // The partial fill exchange sweeps the book up to the limit price, and the unfilled
+            // quantity of the IOC order is expired.
+            (
+                ExchangeKind::PartialFillExchange,
+                TimeInForce::IOC,
+                100.2,
+                2.5,
+                2.0,
+                200.3,
+                Status::Expired,
+            ),
+            // The FOK order that cannot be filled in its entirety is expired without a fill.
+            (
+                ExchangeKind::PartialFillExchange,
+                TimeInForce::FOK,
+                100.3,
+                5.0,
+                0.0,
+                0.0,
+                Status::Expired,
+            ),
+            (
+                ExchangeKind::PartialFillExchange,
+                TimeInForce::FOK,
+                100.3,
+                3.0,
+                3.0,
+                300.6,
+                Status::Filled,
+            ),
+            // The unfilled quantity of the crossing GTC order is filled at its limit price.
+            (
+                ExchangeKind::PartialFillExchange,
+                TimeInForce::GTC,
+                100.2,
+                2.5,
+                2.5,
+                250.4,
+                Status::Filled,
+            ),
+        ];
+        for (exch_kind, tif, price, qty, filled_qty, trading_value, status) in cases {
+            let mut hbt = build_l3(&data, exch_kind, L3FIFOQueueModel::new());
+
+            hbt.elapse(5_000).unwrap();
+            hbt.submit_buy_order(0, 1, price, qty, tif, OrdType::Limit, false)
+                .unwrap();
+            hbt.elapse(5_000).unwrap();
+
+            assert_eq!(hbt.orders(0).get(&1).unwrap().status, status);
+            assert!((hbt.position(0) - filled_qty).abs() < 1e-9);
+            assert!((hbt.state_values(0).trading_value - trading_value).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_l3_market_order_sweep() {
+        // The second ask level is 199 ticks away from the best.
+        let data = l3_data(&[
+            (1_000, ADD_ORDER_EVENT | BUY_EVENT, 100.0, 5.0, 1),
+            (2_000, ADD_ORDER_EVENT | SELL_EVENT, 100.1, 1.0, 2),
+            (3_000, ADD_ORDER_EVENT | SELL_EVENT, 120.0, 5.0, 3),
+            (20_000, ADD_ORDER_EVENT | BUY_EVENT, 99.9, 5.0, 4),
+        ]);
+
+        let mut hbt = build_l3(
+            &data,
+            ExchangeKind::PartialFillExchange,
+            L3FIFOQueueModel::new(),
+        );
+
+        hbt.elapse(5_000).unwrap();
+        hbt.submit_buy_order(0, 1, 0.0, 2.0, TimeInForce::IOC, OrdType::Market, false)
+            .unwrap();
+        hbt.elapse(5_000).unwrap();
+
+        assert_eq!(hbt.orders(0).get(&1).unwrap().status, Status::Filled);
+        assert!((hbt.position(0) - 2.0).abs() < 1e-9);
+        assert!((hbt.state_values(0).trading_value - 220.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_l3_fill_event_without_side() {
+        // The backtest order is placed between the bid orders 1 and 3, and the fill event of the
+        // bid order 3 doesn't provide the side.
+        let data = l3_data(&[
+            (1_000, ADD_ORDER_EVENT | BUY_EVENT, 100.0, 5.0, 1),
+            (2_000, ADD_ORDER_EVENT | SELL_EVENT, 100.1, 5.0, 2),
+            (5_000, ADD_ORDER_EVENT | BUY_EVENT, 100.0, 5.0, 3),
+            (10_000, FILL_EVENT, 100.0, 5.0, 3),
+            (30_000, ADD_ORDER_EVENT | SELL_EVENT, 100.2, 5.0, 4),
+        ]);
+
+        for exch_kind in [
+            ExchangeKind::NoPartialFillExchange,
+            ExchangeKind::PartialFillExchange,
+        ] {
+            let mut hbt = build_l3(&data, exch_kind, L3FIFOQueueModel::new());
+
+            hbt.elapse(2_500).unwrap();
+            hbt.submit_buy_order(0, 1, 100.0, 3.0, TimeInForce::GTC, OrdType::Limit, false)
+                .unwrap();
+            hbt.elapse(10_000).unwrap();
+
+            assert_eq!(hbt.orders(0).get(&1).unwrap().status, Status::Filled);
+            assert_eq!(hbt.position(0), 3.0);
+        }
+    }
+
+    #[test]
+    fn test_l3_conditional_orders() {
+        let data = l3_data(&[
+            (1_000, ADD_ORDER_EVENT | BUY_EVENT, 100.0, 5.0, 1),
+            (2_000, ADD_ORDER_EVENT | SELL_EVENT, 100.1, 5.0, 2),
+            (3_000, FILL_EVENT | SELL_EVENT, 100.1, 1.0, 2),
+            (10_000, FILL_EVENT | BUY_EVENT, 100.0, 1.0, 1),
+            (30_000, ADD_ORDER_EVENT | SELL_EVENT, 100.2, 5.0, 3),
+        ]);
+        let request =
+            |order_id, side, order_type, price, trigger_price, oco_order_id| OrderRequest {
+                trigger_price,
+                oco_order_id,
+                ..order_request(order_id, side, order_type, price, 1.0)
+            };
+
+        for exch_kind in [
+            ExchangeKind::NoPartialFillExchange,
+            ExchangeKind::PartialFillExchange,
+        ] {
+            let mut hbt = build_l3(&data, exch_kind, L3FIFOQueueModel::new());
+            hbt.elapse(4_000).unwrap();
+
+            // The last fill price is 100.1, so this would be triggered immediately.
+            let order = request(1, Side::Sell, OrdType::StopMarket, 0.0, 100.1, None);
+            hbt.submit_order(0, order, false).unwrap();
+            // A one-cancels-other pair of a stop order and a limit order.
+            let order = request(2, Side::Sell, OrdType::StopMarket, 0.0, 100.0, Some(3));
+            hbt.submit_order(0, order, false).unwrap();
+            let order = request(3, Side::Sell, OrdType::Limit, 100.2, 0.0, Some(2));
+            hbt.submit_order(0, order, false).unwrap();
+            let order = request(4, Side::Buy, OrdType::StopMarket, 0.0, 101.0, None);
+            hbt.submit_order(0, order, false).unwrap();
+
+            hbt.elapse(2_000).unwrap();
+            assert_eq!(hbt.orders(0).get(&1).unwrap().status, Status::Expired);
+            let order = hbt.orders(0).get(&2).unwrap();
+            assert_eq!(order.status, Status::New);
+            assert_eq!(order.order_type, OrdType::StopMarket);
+            assert_eq!(hbt.orders(0).get(&3).unwrap().status, Status::New);
+            assert_eq!(hbt.orders(0).get(&4).unwrap().status, Status::New);
+            // Conditional orders cannot be modified.
+            hbt.modify(0, 4, 0.0, 2.0, true).unwrap();
+            assert_eq!(hbt.orders(0).get(&4).unwrap().qty, 1.0);
+            hbt.cancel(0, 4, false).unwrap();
+
+            // The fill at 100.0 triggers the stop order, which expires the limit order.
+            hbt.elapse(4_000).unwrap();
+            let order = hbt.orders(0).get(&2).unwrap();
+            assert_eq!(order.status, Status::Filled);
+            assert_eq!(order.order_type, OrdType::Market);
+            assert_eq!(order.exec_price_tick, 1000);
+            assert_eq!(hbt.orders(0).get(&3).unwrap().status, Status::Expired);
+            assert_eq!(hbt.orders(0).get(&4).unwrap().status, Status::Canceled);
+            assert_eq!(hbt.position(0), -1.0);
+        }
+    }
+
+    #[test]
+    fn test_l3_conditional_order_margin_rejection() {
+        let data = l3_data(&[
+            (1_000, ADD_ORDER_EVENT | BUY_EVENT, 100.0, 5.0, 1),
+            (2_000, ADD_ORDER_EVENT | SELL_EVENT, 100.1, 5.0, 2),
+            (30_000, ADD_ORDER_EVENT | SELL_EVENT, 100.2, 5.0, 3),
+        ]);
+        let rates = [
+            FundingRate {
+                timestamp: 0,
+                rate: 0.0,
+                mark_px: 100.0,
+            },
+            FundingRate {
+                timestamp: 20_000,
+                rate: 0.0,
+                mark_px: 200.0,
+            },
+        ];
+        let margin_model =
+            TieredMarginModel::new(vec![RiskLimitTier::new(1_000_000.0, 20.0, 0.05, 0.0)], 10.0);
+        for exch_kind in [
+            ExchangeKind::NoPartialFillExchange,
+            ExchangeKind::PartialFillExchange,
+        ] {
+            let asset = L3AssetBuilder::new()
+                .data(vec![DataSource::Data(data.clone())])
+                .latency_model(ConstantLatency::new(1000, 1000))
+                .asset_type(LinearAsset::new(1.0))
+                .fee_model(TradingValueFeeModel::new(CommonFees::new(0.0, 0.0)))
+                .queue_model(L3FIFOQueueModel::new())
+                .exchange(exch_kind)
+                .depth(|| HashMapMarketDepth::new(0.1, 0.1))
+                .funding(vec![DataSource::Data(Data::from_items(&rates))])
+                .margin(margin_model.clone(), 15.0)
+                .build()
+                .unwrap();
+            let mut hbt = Backtest::builder().add_asset(asset).build().unwrap();
+
+            hbt.elapse(5_000).unwrap();
+            let order = OrderRequest {
+                trigger_price: 150.0,
+                trigger_price_type: TriggerPriceType::MarkPrice,
+                ..order_request(1, Side::Buy, OrdType::StopMarket, 0.0, 1.0)
+            };
+            hbt.submit_order(0, order, true).unwrap();
+            assert_eq!(hbt.orders(0).get(&1).unwrap().status, Status::New);
+
+            // The mark price triggers the stop order, whose initial margin now exceeds the
+            // collateral, so the exchange expires the accepted order.
+            hbt.elapse(20_000).unwrap();
+            let order = hbt.orders(0).get(&1).unwrap();
+            assert_eq!(order.status, Status::Expired);
+            assert_eq!(order.req, Status::None);
+            assert_eq!(hbt.position(0), 0.0);
+        }
+    }
+
+    #[test]
+    fn test_l3_gtd_and_reduce_only_orders() {
+        let data = l3_data(&[
+            (1_000, ADD_ORDER_EVENT | BUY_EVENT, 100.0, 5.0, 1),
+            (2_000, ADD_ORDER_EVENT | SELL_EVENT, 100.1, 5.0, 2),
+            (15_000, ADD_ORDER_EVENT | SELL_EVENT, 100.3, 1.0, 3),
+            (30_000, ADD_ORDER_EVENT | SELL_EVENT, 100.2, 5.0, 4),
+        ]);
+        let request =
+            |order_id, side, order_type, price, qty, expire_timestamp, reduce_only| OrderRequest {
+                time_in_force: if expire_timestamp > 0 {
+                    TimeInForce::GTD
+                } else {
+                    TimeInForce::GTC
+                },
+                expire_timestamp,
+                reduce_only,
+                ..order_request(order_id, side, order_type, price, qty)
+            };
+
+        for exch_kind in [
+            ExchangeKind::NoPartialFillExchange,
+            ExchangeKind::PartialFillExchange,
+        ] {
+            let mut hbt = build_l3(&data, exch_kind, L3FIFOQueueModel::new());
+            hbt.elapse(2_500).unwrap();
+
+            let order = request(1, Side::Buy, OrdType::Limit, 99.0, 1.0, 15_000, false);
+            hbt.submit_order(0, order, false).unwrap();
+            // This has already expired when it is received.
+            let order = request(2, Side::Buy, OrdType::Limit, 99.0, 1.0, 1_000, false);
+            hbt.submit_order(0, order, false).unwrap();
+            // This would increase the position.
+            let order = request(3, Side::Sell, OrdType::Limit, 100.5, 1.0, 0, true);
+            hbt.submit_order(0, order, false).unwrap();
+            let order = request(4, Side::Buy, OrdType::Market, 0.0, 1.0, 0, false);
+            hbt.submit_order(0, order, false).unwrap();
+
+            hbt.elapse(2_000).unwrap();
+            assert_eq!(hbt.orders(0).get(&1).unwrap().status, Status::New);
+            assert_eq!(hbt.orders(0).get(&2).unwrap().status, Status::Expired);
+            assert_eq!(hbt.orders(0).get(&3).unwrap().status, Status::Expired);
+            assert_eq!(hbt.position(0), 1.0);
+
+            // The quantity exceeding the position is clipped.
+            let order = request(5, Side::Sell, OrdType::Market, 0.0, 2.0, 0, true);
+            hbt.submit_order(0, order, false).unwrap();
+            let order = request(6, Side::Buy, OrdType::Market, 0.0, 1.0, 0, false);
+            hbt.submit_order(0, order, false).unwrap();
+            // The resting reduce-only order is expired once the position is closed.
+            let order = request(7, Side::Sell, OrdType::Limit, 100.5, 1.0, 0, true);
+            hbt.submit_order(0, order, false).unwrap();
+            let order = request(8, Side::Sell, OrdType::Market, 0.0, 1.0, 0, false);
+            hbt.submit_order(0, order, false).unwrap();
+
+            hbt.elapse(2_000).unwrap();
+            let order = hbt.orders(0).get(&5).unwrap();
+            assert_eq!(order.status, Status::Filled);
+            assert_eq!(order.qty, 1.0);
+            assert_eq!(order.exec_qty, 1.0);
+            assert_eq!(hbt.orders(0).get(&7).unwrap().status, Status::Expired);
+            assert_eq!(hbt.orders(0).get(&8).unwrap().status, Status::Filled);
+            assert_eq!(hbt.position(0), 0.0);
+            assert_eq!(hbt.orders(0).get(&1).unwrap().status, Status::New);
+
+            // The good-till-date order is expired at the first event at or after 15_000.
+            hbt.elapse(12_000).unwrap();
+            let order = hbt.orders(0).get(&1).unwrap();
+            assert_eq!(order.status, Status::Expired);
+            assert_eq!(order.exch_timestamp, 15_000);
+        }
+    }
+
+    #[test]
+    fn test_l3_pro_rata() {
+        // The backtest order of 5.0 is placed between the bid order 1 of 5.0, which is the top
+        // order, and the bid order 3 of 10.0.
+        fn events(fills: &[(i64, f64, u64)]) -> Data<Event> {
+            let mut rows = vec![
+                (1_000, ADD_ORDER_EVENT | BUY_EVENT, 100.0, 5.0, 1),
+                (2_000, ADD_ORDER_EVENT | SELL_EVENT, 100.1, 5.0, 2),
+                (9_000, ADD_ORDER_EVENT | BUY_EVENT, 100.0, 10.0, 3),
+            ];
+            for &(ts, qty, order_id) in fills {
+                rows.push((ts, FILL_EVENT | BUY_EVENT, 100.0, qty, order_id));
+            }
+            rows.push((30_000, ADD_ORDER_EVENT | SELL_EVENT, 100.2, 5.0, 4));
+            l3_data(&rows)
+        }
+        let one_fill = events(&[(10_000, 8.0, 1)]);
+        // An aggressor of 8.0 matches the bid orders 1 and 3.
+        let one_aggressor = events(&[(10_000, 4.0, 1), (10_000, 4.0, 3)]);
+
+        let cases = [
+            // 8.0 * 5.0 / 20.0
+            (L3ProRataQueueModel::new(), &one_fill, 2.0),
+            // The top order is allocated 2.0 first, and then 6.0 * 5.0 / 18.0.
+            (
+                L3ProRataQueueModel::new().top_order_priority(2.0),
+                &one_fill,
+                1.6,
+            ),
+            // The top order is allocated 2.0 only once for the same aggressor. The first event
+            // allocates 2.0 * 5.0 / 18.0, and the second event 4.0 * 4.5 / 19.5.
+            (
+                L3ProRataQueueModel::new().top_order_priority(2.0),
+                &one_aggressor,
+                1.4,
+            ),
+            // The bid order 1 is allocated 4.0 in time priority, and then 4.0 * 5.0 / 16.0.
+            (L3ProRataQueueModel::new().fifo_ratio(0.5), &one_fill, 1.2),
+            // The quantities are weighted by the times in the queue, 9,000, 5,500, and 1,000, so
+            // 8.0 * 27,500 / 82,500.
+            (L3ProRataQueueModel::new().size_time(), &one_fill, 2.6),
+        ];
+        for (queue_model, data, position) in cases {
+            let mut hbt = build_l3(data, ExchangeKind::NoPartialFillExchange, queue_model);
+
+            hbt.elapse(2_500).unwrap();
+            hbt.submit_buy_order(0, 1, 100.0, 5.0, TimeInForce::GTC, OrdType::Limit, false)
+                .unwrap();
+            hbt.elapse(10_000).unwrap();
+
+            // The partially filled order remains in the queue.
+            assert!((hbt.position(0) - position).abs() < 1e-9);
+            let order = hbt.orders(0).get(&1).unwrap();
+            assert_eq!(order.status, Status::PartiallyFilled);
+            assert!((order.leaves_qty - (5.0 - position)).abs() < 1e-9);
+        }
+
+        // The order modified after a partial fill is filled only by its remaining quantity.
+        let data = events(&[(10_000, 8.0, 1), (20_000, 20.0, 3)]);
+        let mut hbt = build_l3(
+            &data,
+            ExchangeKind::NoPartialFillExchange,
+            L3ProRataQueueModel::new(),
+        );
+        hbt.elapse(2_500).unwrap();
+        hbt.submit_buy_order(0, 1, 100.0, 5.0, TimeInForce::GTC, OrdType::Limit, false)
+            .unwrap();
+        hbt.elapse(10_000).unwrap();
+        assert_eq!(hbt.position(0), 2.0);
+
+        hbt.modify(0, 1, 100.0, 3.0, false).unwrap();
+        hbt.elapse(20_000).unwrap();
+        assert_eq!(hbt.position(0), 3.0);
+        assert_eq!(hbt.orders(0).get(&1).unwrap().status, Status::Filled);
+    }
+}
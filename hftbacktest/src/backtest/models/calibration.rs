@@ -0,0 +1,690 @@
+use std::mem;
+
+use crate::{
+    backtest::{
+        data::Data,
+        models::{L3FIFOQueueModel, L3QueueModel, ProbQueueFunc, ProbQueueModel, QueueModel},
+        BacktestError,
+    },
+    depth::{
+        HashMapMarketDepth,
+        L2MarketDepth,
+        L3MarketDepth,
+        MarketDepth,
+        INVALID_MAX,
+        INVALID_MIN,
+    },
+    types::{
+        Event,
+        OrdType,
+        Order,
+        OrderId,
+        Side,
+        TimeInForce,
+        EXCH_ASK_ADD_ORDER_EVENT,
+        EXCH_ASK_DEPTH_CLEAR_EVENT,
+        EXCH_BID_ADD_ORDER_EVENT,
+        EXCH_BID_DEPTH_CLEAR_EVENT,
+        EXCH_CANCEL_ORDER_EVENT,
+        EXCH_DEPTH_CLEAR_EVENT,
+        EXCH_EVENT,
+        EXCH_FILL_EVENT,
+        EXCH_MODIFY_ORDER_EVENT,
+    },
+};
+
+/// What a Level2 queue model observes at the price level of a synthetic order.
+enum Observation {
+    /// A trade at the order's price.
+    Trade(f64),
+    /// A change in the quantity at the order's price level, from the previous quantity to the new
+    /// quantity.
+    Depth(f64, f64),
+    /// A trade through the order's price or the opposite best price crossing it, either of which
+    /// fills the order regardless of its queue position.
+    Through,
+}
+
+struct SyntheticOrder {
+    side: Side,
+    price_tick: i64,
+    front_qty: f64,
+    end_ts: i64,
+    filled_ts: Option<i64>,
+    crossed: bool,
+    observations: Vec<(i64, Observation)>,
+}
+
+/// The goodness of fit of a probability function to the actual fills.
+#[derive(Clone, Debug)]
+pub struct ProbQueueFit {
+    /// The probability function.
+    pub func: ProbQueueFunc,
+    /// The fitted exponent if the probability function is a power function.
+    pub n: Option<f64>,
+    /// The number of synthetic orders.
+    pub num_orders: usize,
+    /// The number of orders filled within the horizon according to the Level3 queue position.
+    pub num_filled: usize,
+    /// The number of orders filled within the horizon according to the probability queue model.
+    pub num_estimated_filled: usize,
+    /// The fraction of orders for which the probability queue model correctly estimates whether
+    /// the order is filled within the horizon.
+    pub fill_accuracy: f64,
+    /// The mean absolute error of the estimated fill time in nanoseconds, where an order that is
+    /// not filled is considered to be filled at the end of its horizon.
+    pub mean_abs_error: f64,
+}
+
+/// The result of [`ProbQueueCalibrator::calibrate`].
+#[derive(Clone, Debug)]
+pub struct ProbQueueCalibration {
+    /// The goodness-of-fit report of the fitted function of each function family in the
+    /// candidates, sorted by the mean absolute fill-timing error in ascending order.
+    pub fits: Vec<ProbQueueFit>,
+}
+
+impl ProbQueueCalibration {
+    /// Returns the best fit.
+    pub fn best(&self) -> &ProbQueueFit {
+        &self.fits[0]
+    }
+
+    /// Constructs a [`ProbQueueModel`] with the best fitted probability function.
+    pub fn queue_model<MD>(&self) -> ProbQueueModel<ProbQueueFunc, MD> {
+        ProbQueueModel::new(self.best().func)
+    }
+}
+
+/// Calibrates the probability queue model, [`ProbQueueModel`], from Level3 Market-By-Order data so
+/// that the fitted model can be used for the markets for which only Level2 data is available.
+///
+/// The calibrator replays the data and places a synthetic order at the best bid and the best ask
+/// at every interval while both sides of the market depth are present. The actual fill time of
+/// each order is measured by its queue position in [`L3FIFOQueueModel`], while the fill time
+/// estimated by each candidate probability function is measured from the Level2 view of the same
+/// data, which is the trades and the quantity changes at the order's price level. The synthetic
+/// orders don't affect the market, and each order is tracked until its horizon ends.
+///
+/// The fit is measured by the mean absolute error of the estimated fill time. For each power
+/// function family, the exponents of the candidates are scanned first, and then the exponent is
+/// refined by golden-section search between the neighbours of the best one. The fitted functions
+/// of the families are ranked by the error.
+pub struct ProbQueueCalibrator {
+    tick_size: f64,
+    lot_size: f64,
+    interval: i64,
+    horizon: i64,
+    candidates: Vec<ProbQueueFunc>,
+    tolerance: f64,
+}
+
+impl ProbQueueCalibrator {
+    /// Constructs a `ProbQueueCalibrator` that places synthetic orders every second and tracks
+    /// each order for a minute. By default, the candidates are [`ProbQueueFunc::Log`],
+    /// [`ProbQueueFunc::Log2`] and the power functions with the exponents from `0.5` to `6`.
+    pub fn new(tick_size: f64, lot_size: f64) -> Self {
+        let mut candidates = vec![ProbQueueFunc::Log, ProbQueueFunc::Log2];
+        for n in [0.5, 1.0, 1.5, 2.0, 3.0, 4.0, 5.0, 6.0] {
+            candidates.push(ProbQueueFunc::Power(n));
+            candidates.push(ProbQueueFunc::Power2(n));
+            candidates.push(ProbQueueFunc::Power3(n));
+        }
+        Self {
+            tick_size,
+            lot_size,
+            interval: 1_000_000_000,
+            horizon: 60_000_000_000,
+            candidates,
+            tolerance: 0.01,
+        }
+    }
+
+    /// Sets the interval in nanoseconds at which the synthetic orders are placed.
+    pub fn interval(self, interval: i64) -> Self {
+        Self { interval, ..self }
+    }
+
+    /// Sets the time in nanoseconds for which each synthetic order is tracked.
+    pub fn horizon(self, horizon: i64) -> Self {
+        Self { horizon, ..self }
+    }
+
+    /// Sets the probability functions to fit. The exponents of the power functions are the grid
+    /// from which the exponent of each family is searched.
+    pub fn candidates(self, candidates: Vec<ProbQueueFunc>) -> Self {
+        assert!(!candidates.is_empty());
+        Self { candidates, ..self }
+    }
+
+    /// Sets the width of the interval at which the exponent search stops. The default is `0.01`.
+    pub fn tolerance(self, tolerance: f64) -> Self {
+        assert!(tolerance > 0.0);
+        Self { tolerance, ..self }
+    }
+
+    /// Replays the Level3 Market-By-Order data, which must contain the exchange events, and fits
+    /// the candidates.
+    pub fn calibrate(&self, data: &Data<Event>) -> Result<ProbQueueCalibration, BacktestError> {
+        let orders = self.replay(data)?;
+        // Groups the candidates by function family, along with the exponents of the power
+        // functions.
+        let mut families: Vec<(ProbQueueFunc, Vec<f64>)> = Vec::new();
+        for func in self.candidates.iter() {
+            let family = match families
+                .iter_mut()
+                .find(|(f, _)| mem::discriminant(f) == mem::discriminant(func))
+            {
+                Some((_, grid)) => grid,
+                None => {
+                    families.push((*func, Vec::new()));
+                    &mut families.last_mut().unwrap().1
+                }
+            };
+            if let Some(n) = exponent(*func) {
+                family.push(n);
+            }
+        }
+        let mut fits: Vec<_> = families
+            .into_iter()
+            .map(|(func, grid)| self.fit(func, grid, &orders))
+            .collect();
+        fits.sort_by(|a, b| a.mean_abs_error.total_cmp(&b.mean_abs_error));
+        Ok(ProbQueueCalibration { fits })
+    }
+
+    /// Fits the exponent of the function family by scanning the grid and then refining around the
+    /// best grid point.
+    fn fit(
+        &self,
+        func: ProbQueueFunc,
+        mut grid: Vec<f64>,
+        orders: &[SyntheticOrder],
+    ) -> ProbQueueFit {
+        if grid.is_empty() {
+            return self.evaluate(func, orders);
+        }
+        grid.sort_by(f64::total_cmp);
+        grid.dedup();
+
+        let mut best: Option<(usize, ProbQueueFit)> = None;
+        for (i, &n) in grid.iter().enumerate() {
+            let fit = self.evaluate(with_exponent(func, n), orders);
+            match &best {
+                Some((_, best_fit)) if best_fit.mean_abs_error <= fit.mean_abs_error => {}
+                _ => best = Some((i, fit)),
+            }
+        }
+        let (i, mut best) = best.unwrap();
+
+        // The error is a step function of the exponent, so a point found by the search replaces
+        // the grid point only if it is strictly better.
+        let lo = if i > 0 { grid[i - 1] } else { grid[0] / 2.0 };
+        let hi = grid.get(i + 1).copied().unwrap_or(grid[i] * 2.0);
+        golden_section_search(lo, hi, self.tolerance, |n| {
+            let fit = self.evaluate(with_exponent(func, n), orders);
+            let error = fit.mean_abs_error;
+            if error < best.mean_abs_error {
+                best = fit;
+            }
+            error
+        });
+        best
+    }
+
+    fn replay(&self, data: &Data<Event>) -> Result<Vec<SyntheticOrder>, BacktestError> {
+        let mut depth = HashMapMarketDepth::new(self.tick_size, self.lot_size);
+        let mut queue = L3FIFOQueueModel::new();
+        let mut orders: Vec<SyntheticOrder> = Vec::new();
+        // The indexes of the orders that are still tracked. The order ID is the index plus one.
+        let mut active: Vec<usize> = Vec::new();
+        let mut next_place_ts = i64::MIN;
+        let mut last_ts = 0;
+
+        for row_num in 0..data.len() {
+            let ev = &data[row_num];
+            if !ev.is(EXCH_EVENT) {
+                continue;
+            }
+            let ts = ev.exch_ts;
+            last_ts = ts;
+
+            // Stops tracking the orders whose horizon has ended.
+            for &i in active.iter() {
+                if orders[i].end_ts < ts {
+                    let order_id = i as OrderId + 1;
+                    if L3QueueModel::<HashMapMarketDepth>::contains_backtest_order(&queue, order_id)
+                    {
+                        queue.cancel_backtest_order(order_id, &depth)?;
+                    }
+                }
+            }
+            active.retain(|&i| orders[i].end_ts >= ts);
+
+            let best_bid_tick = depth.best_bid_tick();
+            let best_ask_tick = depth.best_ask_tick();
+            if ts >= next_place_ts && best_bid_tick != INVALID_MIN && best_ask_tick != INVALID_MAX {
+                for (side, price_tick) in [(Side::Buy, best_bid_tick), (Side::Sell, best_ask_tick)]
+                {
+                    let order_id = orders.len() as OrderId + 1;
+                    let order = Order::new(
+                        order_id,
+                        price_tick,
+                        self.tick_size,
+                        self.lot_size,
+                        side,
+                        OrdType::Limit,
+                        TimeInForce::GTC,
+                    );
+                    queue.add_backtest_order(order, &depth)?;
+                    active.push(orders.len());
+                    orders.push(SyntheticOrder {
+                        side,
+                        price_tick,
+                        front_qty: level_qty(&depth, side, price_tick),
+                        end_ts: ts.saturating_add(self.horizon),
+                        filled_ts: None,
+                        crossed: false,
+                        observations: Vec::new(),
+                    });
+                }
+                next_place_ts = ts.saturating_add(self.interval);
+            }
+
+            // Collects the price levels whose quantity can be changed by the event, along with
+            // their quantity before the event.
+            let mut levels: Vec<(Side, i64, f64)> = Vec::new();
+            let mut filled = Vec::new();
+            if ev.is(EXCH_BID_DEPTH_CLEAR_EVENT) {
+                self.clear(&mut depth, &mut queue, &mut orders, &active, Side::Buy, ts);
+            } else if ev.is(EXCH_ASK_DEPTH_CLEAR_EVENT) {
+                self.clear(&mut depth, &mut queue, &mut orders, &active, Side::Sell, ts);
+            } else if ev.is(EXCH_DEPTH_CLEAR_EVENT) {
+                self.clear(&mut depth, &mut queue, &mut orders, &active, Side::None, ts);
+            } else if ev.is(EXCH_BID_ADD_ORDER_EVENT) {
+                let price_tick = (ev.px / self.tick_size).round() as i64;
+                levels.push((Side::Buy, price_tick, depth.bid_qty_at_tick(price_tick)));
+                let (prev_best_tick, best_tick) =
+                    depth.add_buy_order(ev.order_id, ev.px, ev.qty, ts)?;
+                queue.add_market_feed_order(ev, &depth)?;
+                if best_tick > prev_best_tick {
+                    filled = L3QueueModel::<HashMapMarketDepth>::on_best_bid_update(
+                        &mut queue,
+                        prev_best_tick,
+                        best_tick,
+                    )?;
+                }
+            } else if ev.is(EXCH_ASK_ADD_ORDER_EVENT) {
+                let price_tick = (ev.px / self.tick_size).round() as i64;
+                levels.push((Side::Sell, price_tick, depth.ask_qty_at_tick(price_tick)));
+                let (prev_best_tick, best_tick) =
+                    depth.add_sell_order(ev.order_id, ev.px, ev.qty, ts)?;
+                queue.add_market_feed_order(ev, &depth)?;
+                if best_tick < prev_best_tick {
+                    filled = L3QueueModel::<HashMapMarketDepth>::on_best_ask_update(
+                        &mut queue,
+                        prev_best_tick,
+                        best_tick,
+                    )?;
+                }
+            } else if ev.is(EXCH_MODIFY_ORDER_EVENT) {
+                if let Some(order) = depth.orders().get(&ev.order_id) {
+                    let (side, price_tick) = (order.side, order.price_tick);
+                    levels.push((side, price_tick, level_qty(&depth, side, price_tick)));
+                    let new_price_tick = (ev.px / self.tick_size).round() as i64;
+                    if new_price_tick != price_tick {
+                        levels.push((
+                            side,
+                            new_price_tick,
+                            level_qty(&depth, side, new_price_tick),
+                        ));
+                    }
+                }
+                let (side, prev_best_tick, best_tick) =
+                    depth.modify_order(ev.order_id, ev.px, ev.qty, ts)?;
+                queue.modify_market_feed_order(ev.order_id, ev, &depth)?;
+                if side == Side::Buy && best_tick > prev_best_tick {
+                    filled = L3QueueModel::<HashMapMarketDepth>::on_best_bid_update(
+                        &mut queue,
+                        prev_best_tick,
+                        best_tick,
+                    )?;
+                } else if side == Side::Sell && best_tick < prev_best_tick {
+                    filled = L3QueueModel::<HashMapMarketDepth>::on_best_ask_update(
+                        &mut queue,
+                        prev_best_tick,
+                        best_tick,
+                    )?;
+                }
+            } else if ev.is(EXCH_CANCEL_ORDER_EVENT) {
+                if let Some(order) = depth.orders().get(&ev.order_id) {
+                    let (side, price_tick) = (order.side, order.price_tick);
+                    levels.push((side, price_tick, level_qty(&depth, side, price_tick)));
+                }
+                depth.delete_order(ev.order_id, ts)?;
+                queue.cancel_market_feed_order(ev.order_id, &depth)?;
+            } else if ev.is(EXCH_FILL_EVENT) {
+                if let Some(order) = depth.orders().get(&ev.order_id) {
+                    // The fill of the resting order is a trade at its price in the Level2 view.
+                    let (side, price_tick) = (order.side, order.price_tick);
+                    for &i in active.iter() {
+                        let synthetic = &mut orders[i];
+                        if synthetic.side != side || synthetic.crossed {
+                            continue;
+                        }
+                        let through = match side {
+                            Side::Buy => synthetic.price_tick > price_tick,
+                            _ => synthetic.price_tick < price_tick,
+                        };
+                        if through {
+                            synthetic.crossed = true;
+                            synthetic.observations.push((ts, Observation::Through));
+                        } else if synthetic.price_tick == price_tick {
+                            synthetic
+                                .observations
+                                .push((ts, Observation::Trade(ev.qty)));
+                        }
+                    }
+                }
+                filled = queue.fill_market_feed_order::<false>(ev.order_id, ev, &depth)?;
+            }
+
+            for order in filled {
+                let synthetic = &mut orders[order.order_id as usize - 1];
+                if synthetic.filled_ts.is_none() && ts <= synthetic.end_ts {
+                    synthetic.filled_ts = Some(ts);
+                }
+            }
+
+            for (side, price_tick, prev_qty) in levels {
+                let new_qty = level_qty(&depth, side, price_tick);
+                if new_qty == prev_qty {
+                    continue;
+                }
+                for &i in active.iter() {
+                    let synthetic = &mut orders[i];
+                    if synthetic.side == side && synthetic.price_tick == price_tick {
+                        synthetic
+                            .observations
+                            .push((ts, Observation::Depth(prev_qty, new_qty)));
+                    }
+                }
+            }
+
+            let best_bid_tick = depth.best_bid_tick();
+            let best_ask_tick = depth.best_ask_tick();
+            for &i in active.iter() {
+                let synthetic = &mut orders[i];
+                let crossed = match synthetic.side {
+                    Side::Buy => best_ask_tick <= synthetic.price_tick,
+                    _ => best_bid_tick >= synthetic.price_tick,
+                };
+                if crossed && !synthetic.crossed {
+                    synthetic.crossed = true;
+                    synthetic.observations.push((ts, Observation::Through));
+                }
+            }
+        }
+
+        for synthetic in orders.iter_mut() {
+            synthetic.end_ts = synthetic.end_ts.min(last_ts);
+        }
+        Ok(orders)
+    }
+
+    fn clear(
+        &self,
+        depth: &mut HashMapMarketDepth,
+        queue: &mut L3FIFOQueueModel,
+        orders: &mut [SyntheticOrder],
+        active: &[usize],
+        side: Side,
+        timestamp: i64,
+    ) {
+        depth.clear_orders(side);
+        L3QueueModel::<HashMapMarketDepth>::clear_orders(queue, side);
+        // The queue positions are lost, so the orders are no longer tracked.
+        for &i in active.iter() {
+            if side == Side::None || orders[i].side == side {
+                orders[i].end_ts = orders[i].end_ts.min(timestamp);
+            }
+        }
+    }
+
+    fn evaluate(&self, func: ProbQueueFunc, orders: &[SyntheticOrder]) -> ProbQueueFit {
+        let queue_model = ProbQueueModel::<ProbQueueFunc, HashMapMarketDepth>::new(func);
+        let mut num_filled = 0;
+        let mut num_estimated_filled = 0;
+        let mut num_correct = 0;
+        let mut abs_error = 0.0;
+        for synthetic in orders {
+            // The market depth only provides the quantity ahead of the order and the lot size.
+            let mut depth = HashMapMarketDepth::new(self.tick_size, self.lot_size);
+            let price = synthetic.price_tick as f64 * self.tick_size;
+            if synthetic.side == Side::Buy {
+                depth.update_bid_depth(price, synthetic.front_qty, 0);
+            } else {
+                depth.update_ask_depth(price, synthetic.front_qty, 0);
+            }
+            let mut order = Order::new(
+                0,
+                synthetic.price_tick,
+                self.tick_size,
+                self.lot_size,
+                synthetic.side,
+                OrdType::Limit,
+                TimeInForce::GTC,
+            );
+            queue_model.new_order(&mut order, &depth);
+
+            let mut estimated_filled_ts = None;
+            for (ts, observation) in synthetic.observations.iter() {
+                if *ts > synthetic.end_ts {
+                    break;
+                }
+                match observation {
+                    Observation::Trade(qty) => {
+                        queue_model.trade(&mut order, *qty, &depth);
+                        if queue_model.is_filled(&order, &depth) > 0.0 {
+                            estimated_filled_ts = Some(*ts);
+                        }
+                    }
+                    Observation::Depth(prev_qty, new_qty) => {
+                        queue_model.depth(&mut order, *prev_qty, *new_qty, &depth);
+                    }
+                    Observation::Through => {
+                        estimated_filled_ts = Some(*ts);
+                    }
+                }
+                if estimated_filled_ts.is_some() {
+                    break;
+                }
+            }
+
+            if synthetic.filled_ts.is_some() {
+                num_filled += 1;
+            }
+            if estimated_filled_ts.is_some() {
+                num_estimated_filled += 1;
+            }
+            if synthetic.filled_ts.is_some() == estimated_filled_ts.is_some() {
+                num_correct += 1;
+            }
+            let filled_ts = synthetic.filled_ts.unwrap_or(synthetic.end_ts);
+            let estimated_filled_ts = estimated_filled_ts.unwrap_or(synthetic.end_ts);
+            abs_error += (filled_ts - estimated_filled_ts).abs() as f64;
+        }
+        let num_orders = orders.len();
+        ProbQueueFit {
+            func,
+            n: exponent(func),
+            num_orders,
+            num_filled,
+            num_estimated_filled,
+            fill_accuracy: num_correct as f64 / num_orders.max(1) as f64,
+            mean_abs_error: abs_error / num_orders.max(1) as f64,
+        }
+    }
+}
+
+fn exponent(func: ProbQueueFunc) -> Option<f64> {
+    match func {
+        ProbQueueFunc::Power(n) | ProbQueueFunc::Power2(n) | ProbQueueFunc::Power3(n) => Some(n),
+        ProbQueueFunc::Log | ProbQueueFunc::Log2 => None,
+    }
+}
+
+fn with_exponent(func: ProbQueueFunc, n: f64) -> ProbQueueFunc {
+    match func {
+        ProbQueueFunc::Power(_) => ProbQueueFunc::Power(n),
+        ProbQueueFunc::Power2(_) => ProbQueueFunc::Power2(n),
+        ProbQueueFunc::Power3(_) => ProbQueueFunc::Power3(n),
+        ProbQueueFunc::Log | ProbQueueFunc::Log2 => func,
+    }
+}
+
+/// Finds the minimum of a unimodal function in `[lo, hi]` by golden-section search, narrowing the
+/// interval until it is no wider than `tolerance`. Returns the point and the value.
+fn golden_section_search<F>(mut lo: f64, mut hi: f64, tolerance: f64, mut f: F) -> (f64, f64)
+where
+    F: FnMut(f64) -> f64,
+{
+    const INV_PHI: f64 = 0.618_033_988_749_895;
+    let mut x1 = hi - INV_PHI * (hi - lo);
+    let mut x2 = lo + INV_PHI * (hi - lo);
+    let mut f1 = f(x1);
+    let mut f2 = f(x2);
+    while hi - lo > tolerance {
+        if f1 <= f2 {
+            hi = x2;
+            x2 = x1;
+            f2 = f1;
+            x1 = hi - INV_PHI * (hi - lo);
+            f1 = f(x1);
+        } else {
+            lo = x1;
+            x1 = x2;
+            f1 = f2;
+            x2 = lo + INV_PHI * (hi - lo);
+            f2 = f(x2);
+        }
+    }
+    if f1 <= f2 {
+        (x1, f1)
+    } else {
+        (x2, f2)
+    }
+}
+
+fn level_qty(depth: &HashMapMarketDepth, side: Side, price_tick: i64) -> f64 {
+    match side {
+        Side::Buy => depth.bid_qty_at_tick(price_tick),
+        _ => depth.ask_qty_at_tick(price_tick),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backtest::{
+            data::Data,
+            models::{ProbQueueCalibrator, ProbQueueFunc},
+        },
+        types::{
+            Event,
+            EXCH_ASK_ADD_ORDER_EVENT,
+            EXCH_BID_ADD_ORDER_EVENT,
+            EXCH_CANCEL_ORDER_EVENT,
+            EXCH_FILL_EVENT,
+        },
+    };
+
+    fn event(ev: u64, ts: i64, order_id: u64, px: f64, qty: f64) -> Event {
+        Event {
+            ev,
+            exch_ts: ts,
+            local_ts: ts,
+            px,
+            qty,
+            order_id,
+            ival: 0,
+            fval: 0.0,
+        }
+    }
+
+    fn mbo_data() -> Data<Event> {
+        Data::from_items(&[
+            event(EXCH_BID_ADD_ORDER_EVENT, 1_000, 1, 100.0, 2.0),
+            event(EXCH_BID_ADD_ORDER_EVENT, 1_100, 2, 100.0, 3.0),
+            event(EXCH_ASK_ADD_ORDER_EVENT, 1_200, 100, 100.5, 1.0),
+            // The synthetic orders are placed here, and the buy order is behind the orders 1 and 2.
+            // The order 3 is behind the synthetic order, so its cancellation doesn't advance the
+            // queue position.
+            event(EXCH_BID_ADD_ORDER_EVENT, 2_000, 3, 100.0, 4.0),
+            event(EXCH_CANCEL_ORDER_EVENT, 3_000, 3, 0.0, 0.0),
+            event(EXCH_FILL_EVENT, 4_000, 1, 100.0, 2.0),
+            event(EXCH_CANCEL_ORDER_EVENT, 4_100, 1, 0.0, 0.0),
+            event(EXCH_FILL_EVENT, 5_000, 2, 100.0, 3.0),
+            event(EXCH_CANCEL_ORDER_EVENT, 5_100, 2, 0.0, 0.0),
+            event(EXCH_BID_ADD_ORDER_EVENT, 6_000, 4, 100.0, 1.0),
+            event(EXCH_FILL_EVENT, 7_000, 4, 100.0, 1.0),
+            event(EXCH_CANCEL_ORDER_EVENT, 7_100, 4, 0.0, 0.0),
+        ])
+    }
+
+    #[test]
+    fn test_calibrate() {
+        let data = mbo_data();
+        let calibration = ProbQueueCalibrator::new(0.1, 1.0)
+            .interval(1_000_000)
+            .candidates(vec![
+                ProbQueueFunc::Power(1.0),
+                ProbQueueFunc::Power3(10.0),
+                ProbQueueFunc::Log,
+            ])
+            .calibrate(&data)
+            .unwrap();
+
+        // A model that attributes most of the cancellation to the orders ahead estimates the fill
+        // when the order 2 is filled, which is too early.
+        let best = calibration.best();
+        assert_eq!(best.func, ProbQueueFunc::Power3(10.0));
+        assert_eq!(best.n, Some(10.0));
+        assert_eq!(best.num_orders, 2);
+        assert_eq!(best.num_filled, 1);
+        assert_eq!(best.num_estimated_filled, 1);
+        assert_eq!(best.fill_accuracy, 1.0);
+        assert_eq!(best.mean_abs_error, 0.0);
+        // The error is averaged over both synthetic orders, and the ask order is never filled.
+        for fit in &calibration.fits[1..] {
+            assert_eq!(fit.mean_abs_error, 1_000.0);
+        }
+        let _queue_model = calibration.queue_model::<crate::depth::HashMapMarketDepth>();
+    }
+
+    #[test]
+    fn test_calibrate_exponent() {
+        let data = mbo_data();
+
+        // Every default family is reported once.
+        let calibration = ProbQueueCalibrator::new(0.1, 1.0)
+            .interval(1_000_000)
+            .calibrate(&data)
+            .unwrap();
+        assert_eq!(calibration.fits.len(), 5);
+        assert_eq!(calibration.best().mean_abs_error, 0.0);
+
+        // The power function fits only with an exponent above 4, which the search finds between 2
+        // and 8.
+        let calibration = ProbQueueCalibrator::new(0.1, 1.0)
+            .interval(1_000_000)
+            .candidates(vec![ProbQueueFunc::Power(4.0)])
+            .calibrate(&data)
+            .unwrap();
+        let best = calibration.best();
+        let n = best.n.unwrap();
+        assert!(n > 4.0 && n < 8.0);
+        assert_eq!(best.func, ProbQueueFunc::Power(n));
+        assert_eq!(best.mean_abs_error, 0.0);
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use bincode::{Decode, Encode};
+
+use crate::types::Side;
+
+/// Tracks the liquidity consumed by your own liquidity-taking orders at each price level.
+///
+/// In backtesting based on market-data replay, the market depth does not reflect your own
+/// executions, so repeatedly taking liquidity at the same level would be unrealistically cheap.
+/// When set to the exchange model, the quantity consumed at a price level is subtracted from the
+/// quantity available to the subsequent liquidity-taking orders until the feed shows the level
+/// being replenished, that is, its quantity increasing. Optionally, the consumed quantity decays
+/// exponentially over time, modeling the liquidity that is replenished without being observed.
+#[derive(Clone, Debug, Default, Encode, Decode)]
+pub struct MarketImpact {
+    half_life: Option<i64>,
+    // key: price tick, value: (consumed quantity, timestamp at which it is updated)
+    bid_consumed: HashMap<i64, (f64, i64)>,
+    ask_consumed: HashMap<i64, (f64, i64)>,
+}
+
+impl MarketImpact {
+    /// Constructs a `MarketImpact` whose consumed quantity does not decay.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets the half-life in nanoseconds over which the consumed quantity decays by half.
+    pub fn decay(self, half_life: i64) -> Self {
+        Self {
+            half_life: Some(half_life),
+            ..self
+        }
+    }
+
+    fn consumed_qty(&self, side: Side) -> &HashMap<i64, (f64, i64)> {
+        match side {
+            Side::Buy => &self.bid_consumed,
+            _ => &self.ask_consumed,
+        }
+    }
+
+    fn decayed(&self, qty: f64, updated: i64, timestamp: i64) -> f64 {
+        match self.half_life {
+            Some(half_life) if timestamp > updated => {
+                qty * 0.5f64.powf((timestamp - updated) as f64 / half_life as f64)
+            }
+            _ => qty,
+        }
+    }
+
+    /// Returns the quantity consumed at the price level on the given side of the book, which is
+    /// [`Side::Buy`] for the bid side and [`Side::Sell`] for the ask side.
+    pub fn consumed(&self, side: Side, price_tick: i64, timestamp: i64) -> f64 {
+        self.consumed_qty(side)
+            .get(&price_tick)
+            .map(|(qty, updated)| self.decayed(*qty, *updated, timestamp))
+            .unwrap_or(0.0)
+    }
+
+    /// Returns the quantity available to a liquidity-taking order at the price level, given the
+    /// quantity in the market depth.
+    pub fn available(&self, side: Side, price_tick: i64, depth_qty: f64, timestamp: i64) -> f64 {
+        (depth_qty - self.consumed(side, price_tick, timestamp)).max(0.0)
+    }
+
+    /// Records the quantity consumed by a liquidity-taking order at the price level.
+    pub fn consume(&mut self, side: Side, price_tick: i64, qty: f64, timestamp: i64) {
+        let consumed = self.consumed(side, price_tick, timestamp) + qty;
+        let consumed_qty = match side {
+            Side::Buy => &mut self.bid_consumed,
+            _ => &mut self.ask_consumed,
+        };
+        consumed_qty.insert(price_tick, (consumed, timestamp));
+    }
+
+    /// Reduces the consumed quantity by the increase in the quantity of the price level shown by
+    /// the feed.
+    pub fn replenish(
+        &mut self,
+        side: Side,
+        price_tick: i64,
+        prev_qty: f64,
+        new_qty: f64,
+        timestamp: i64,
+    ) {
+        if new_qty <= prev_qty || !self.consumed_qty(side).contains_key(&price_tick) {
+            return;
+        }
+        let consumed = self.consumed(side, price_tick, timestamp) - (new_qty - prev_qty);
+        let consumed_qty = match side {
+            Side::Buy => &mut self.bid_consumed,
+            _ => &mut self.ask_consumed,
+        };
+        if consumed > 0.0 {
+            consumed_qty.insert(price_tick, (consumed, timestamp));
+        } else {
+            consumed_qty.remove(&price_tick);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{backtest::models::MarketImpact, types::Side};
+
+    #[test]
+    fn test_market_impact() {
+        let mut impact = MarketImpact::new();
+        impact.consume(Side::Sell, 100, 3.0, 0);
+        assert_eq!(impact.available(Side::Sell, 100, 5.0, 10), 2.0);
+        assert_eq!(impact.available(Side::Buy, 100, 5.0, 10), 5.0);
+
+        // A decrease in the quantity is not a replenishment.
+        impact.replenish(Side::Sell, 100, 5.0, 4.0, 20);
+        assert_eq!(impact.consumed(Side::Sell, 100, 20), 3.0);
+        impact.replenish(Side::Sell, 100, 4.0, 6.0, 30);
+        assert_eq!(impact.consumed(Side::Sell, 100, 30), 1.0);
+        impact.replenish(Side::Sell, 100, 6.0, 8.0, 40);
+        assert_eq!(impact.consumed(Side::Sell, 100, 40), 0.0);
+
+        let mut impact = MarketImpact::new().decay(100);
+        impact.consume(Side::Buy, 100, 4.0, 0);
+        assert_eq!(impact.consumed(Side::Buy, 100, 100), 2.0);
+        impact.consume(Side::Buy, 100, 2.0, 200);
+        assert_eq!(impact.consumed(Side::Buy, 100, 200), 3.0);
+    }
+}
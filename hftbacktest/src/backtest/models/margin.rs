@@ -0,0 +1,105 @@
+/// A risk limit tier, which determines the maximum leverage and the maintenance margin rate for
+/// positions whose notional value is up to `max_notional`.
+#[derive(Clone, Debug)]
+pub struct RiskLimitTier {
+    /// Maximum notional value of this tier.
+    pub max_notional: f64,
+    /// Maximum leverage allowed in this tier.
+    pub max_leverage: f64,
+    /// Maintenance margin rate of this tier.
+    pub maintenance_margin_rate: f64,
+    /// Amount deducted from the maintenance margin, which keeps the maintenance margin continuous
+    /// across the tiers.
+    pub maintenance_amount: f64,
+}
+
+impl RiskLimitTier {
+    /// Constructs `RiskLimitTier`.
+    pub fn new(
+        max_notional: f64,
+        max_leverage: f64,
+        maintenance_margin_rate: f64,
+        maintenance_amount: f64,
+    ) -> Self {
+        Self {
+            max_notional,
+            max_leverage,
+            maintenance_margin_rate,
+            maintenance_amount,
+        }
+    }
+}
+
+/// Provides the margin required for a position.
+pub trait MarginModel {
+    /// Calculates the initial margin required to open a position of the given notional value.
+    fn initial_margin(&self, notional: f64) -> f64;
+
+    /// Calculates the maintenance margin required to keep a position of the given notional value.
+    /// The position is liquidated if the equity falls below it.
+    fn maintenance_margin(&self, notional: f64) -> f64;
+}
+
+/// Margin tiered by the position's notional value, like the risk limits of Binance and Bybit.
+///
+/// The initial margin is the notional value divided by the leverage, which is capped by the tier's
+/// maximum leverage, and the maintenance margin is the notional value multiplied by the tier's
+/// maintenance margin rate minus the tier's maintenance amount. A notional value beyond the last
+/// tier is treated as the last tier.
+#[derive(Clone)]
+pub struct TieredMarginModel {
+    tiers: Vec<RiskLimitTier>,
+    leverage: f64,
+}
+
+impl TieredMarginModel {
+    /// Constructs `TieredMarginModel` with the risk limit tiers and the leverage in use.
+    ///
+    /// Panics if `tiers` is empty.
+    pub fn new(mut tiers: Vec<RiskLimitTier>, leverage: f64) -> Self {
+        assert!(!tiers.is_empty());
+        tiers.sort_by(|a, b| a.max_notional.total_cmp(&b.max_notional));
+        Self { tiers, leverage }
+    }
+
+    fn tier(&self, notional: f64) -> &RiskLimitTier {
+        self.tiers
+            .iter()
+            .find(|tier| notional <= tier.max_notional)
+            .unwrap_or_else(|| self.tiers.last().unwrap())
+    }
+}
+
+impl MarginModel for TieredMarginModel {
+    fn initial_margin(&self, notional: f64) -> f64 {
+        notional / self.leverage.min(self.tier(notional).max_leverage)
+    }
+
+    fn maintenance_margin(&self, notional: f64) -> f64 {
+        let tier = self.tier(notional);
+        (notional * tier.maintenance_margin_rate - tier.maintenance_amount).max(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backtest::models::{MarginModel, RiskLimitTier, TieredMarginModel};
+
+    #[test]
+    fn test_tiered_margin() {
+        let margin_model = TieredMarginModel::new(
+            vec![
+                RiskLimitTier::new(100_000.0, 50.0, 0.01, 0.0),
+                RiskLimitTier::new(500_000.0, 20.0, 0.025, 1_500.0),
+            ],
+            25.0,
+        );
+        // The leverage is capped by the tier's maximum leverage.
+        assert_eq!(margin_model.initial_margin(50_000.0), 2_000.0);
+        assert_eq!(margin_model.initial_margin(200_000.0), 10_000.0);
+        assert_eq!(margin_model.maintenance_margin(50_000.0), 500.0);
+        assert_eq!(margin_model.maintenance_margin(200_000.0), 3_500.0);
+        // Beyond the last tier.
+        assert_eq!(margin_model.maintenance_margin(1_000_000.0), 23_500.0);
+    }
+}
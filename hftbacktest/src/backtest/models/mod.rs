@@ -3,10 +3,14 @@
 //! Please find more details in the documents below.
 //! * [Latency Models](https://hftbacktest.readthedocs.io/en/latest/latency_models.html)
 //! * [Order Fill](https://hftbacktest.readthedocs.io/en/latest/order_fill.html)
+mod calibration;
 mod fee;
+mod impact;
 mod latency;
+mod margin;
 mod queue;
 
+pub use calibration::{ProbQueueCalibration, ProbQueueCalibrator, ProbQueueFit};
 pub use fee::{
     CommonFees,
     DirectionalFees,
@@ -15,18 +19,23 @@ pub use fee::{
     TradingQtyFeeModel,
     TradingValueFeeModel,
 };
+pub use impact::MarketImpact;
 pub use latency::{ConstantLatency, IntpOrderLatency, LatencyModel, OrderLatencyRow};
+pub use margin::{MarginModel, RiskLimitTier, TieredMarginModel};
 pub use queue::{
     L3FIFOQueueModel,
+    L3ProRataQueueModel,
     L3QueueModel,
     LogProbQueueFunc,
     LogProbQueueFunc2,
     PowerProbQueueFunc,
     PowerProbQueueFunc2,
     PowerProbQueueFunc3,
+    ProbQueueFunc,
     ProbQueueModel,
     Probability,
     QueueModel,
     QueuePos,
     RiskAdverseQueueModel,
+    TouchQueueModel,
 };
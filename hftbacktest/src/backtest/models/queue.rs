@@ -1,11 +1,14 @@
 use std::{
     any::Any,
+    cmp::Ordering,
     collections::{hash_map::Entry, HashMap, HashSet, VecDeque},
     marker::PhantomData,
 };
 
+use bincode::{Decode, Encode};
+
 use crate::{
-    backtest::BacktestError,
+    backtest::{checkpoint, BacktestError},
     depth::{MarketDepth, INVALID_MAX, INVALID_MIN},
     types::{
         AnyClone,
@@ -13,9 +16,11 @@ use crate::{
         OrdType,
         Order,
         OrderId,
+        SelfTradePrevention,
         Side,
         Status,
         TimeInForce,
+        TriggerPriceType,
         BUY_EVENT,
         SELL_EVENT,
     },
@@ -37,6 +42,27 @@ where
     fn depth(&self, order: &mut Order, prev_qty: f64, new_qty: f64, depth: &MD);
 
     fn is_filled(&self, order: &Order, depth: &MD) -> f64;
+
+    /// Returns the estimated quantity ahead of the order in the queue, which is written to the
+    /// [`Tracer`](crate::backtest::trace::Tracer). Returns `NaN` if it is not available.
+    fn queue_position(&self, _order: &Order) -> f64 {
+        f64::NAN
+    }
+
+    /// Encodes the estimation values held in the order to be stored in a
+    /// [`Checkpoint`](crate::backtest::checkpoint::Checkpoint).
+    fn encode_q(&self, _order: &Order) -> Result<Vec<u8>, BacktestError> {
+        Err(BacktestError::CheckpointError(
+            "the queue model does not support checkpointing".to_string(),
+        ))
+    }
+
+    /// Restores the estimation values encoded by [`QueueModel::encode_q`] into the order.
+    fn decode_q(&self, _order: &mut Order, _q: &[u8]) -> Result<(), BacktestError> {
+        Err(BacktestError::CheckpointError(
+            "the queue model does not support checkpointing".to_string(),
+        ))
+    }
 }
 
 /// Provides a conservative queue position model, where your order's queue position advances only
@@ -91,10 +117,99 @@ where
             0.0
         }
     }
+
+    fn queue_position(&self, order: &Order) -> f64 {
+        *order.q.as_any().downcast_ref::<f64>().unwrap()
+    }
+
+    fn encode_q(&self, order: &Order) -> Result<Vec<u8>, BacktestError> {
+        let front_q_qty = order.q.as_any().downcast_ref::<f64>().unwrap();
+        checkpoint::encode(front_q_qty)
+    }
+
+    fn decode_q(&self, order: &mut Order, q: &[u8]) -> Result<(), BacktestError> {
+        let front_q_qty: f64 = checkpoint::decode(q)?;
+        order.q = Box::new(front_q_qty);
+        Ok(())
+    }
+}
+
+/// Provides a conservative queue position model for Level-1 (BBO) data, where the quantity is
+/// only known at the touch.
+///
+/// An order placed at the touch starts behind the entire quantity at the touch, and an order placed
+/// inside the spread starts at the front of the queue. For an order placed behind the touch, the
+/// quantity ahead is unknown, so it is assumed that the whole quantity observed when its price level
+/// next becomes the touch is ahead of it. Afterward, like [`RiskAdverseQueueModel`], the queue
+/// position advances only when trades occur at the same price level, and it cannot be behind the
+/// quantity observed at the touch.
+pub struct TouchQueueModel<MD>(PhantomData<MD>);
+
+impl<MD> TouchQueueModel<MD> {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<MD> QueueModel<MD> for TouchQueueModel<MD>
+where
+    MD: MarketDepth,
+{
+    fn new_order(&self, order: &mut Order, depth: &MD) {
+        let front_q_qty = if order.side == Side::Buy {
+            match order.price_tick.cmp(&depth.best_bid_tick()) {
+                Ordering::Greater => 0.0,
+                Ordering::Equal => depth.bid_qty_at_tick(order.price_tick),
+                Ordering::Less => f64::INFINITY,
+            }
+        } else {
+            match order.price_tick.cmp(&depth.best_ask_tick()) {
+                Ordering::Less => 0.0,
+                Ordering::Equal => depth.ask_qty_at_tick(order.price_tick),
+                Ordering::Greater => f64::INFINITY,
+            }
+        };
+        order.q = Box::new(front_q_qty);
+    }
+
+    fn trade(&self, order: &mut Order, qty: f64, _depth: &MD) {
+        let front_q_qty = order.q.as_any_mut().downcast_mut::<f64>().unwrap();
+        *front_q_qty -= qty;
+    }
+
+    fn depth(&self, order: &mut Order, _prev_qty: f64, new_qty: f64, _depth: &MD) {
+        let front_q_qty = order.q.as_any_mut().downcast_mut::<f64>().unwrap();
+        *front_q_qty = front_q_qty.min(new_qty);
+    }
+
+    fn is_filled(&self, order: &Order, depth: &MD) -> f64 {
+        let front_q_qty = order.q.as_any().downcast_ref::<f64>().unwrap();
+        if (front_q_qty / depth.lot_size()).round() < 0.0 {
+            (-front_q_qty / depth.lot_size()).floor() * depth.lot_size()
+        } else {
+            0.0
+        }
+    }
+
+    fn queue_position(&self, order: &Order) -> f64 {
+        *order.q.as_any().downcast_ref::<f64>().unwrap()
+    }
+
+    fn encode_q(&self, order: &Order) -> Result<Vec<u8>, BacktestError> {
+        let front_q_qty = order.q.as_any().downcast_ref::<f64>().unwrap();
+        checkpoint::encode(front_q_qty)
+    }
+
+    fn decode_q(&self, order: &mut Order, q: &[u8]) -> Result<(), BacktestError> {
+        let front_q_qty: f64 = checkpoint::decode(q)?;
+        order.q = Box::new(front_q_qty);
+        Ok(())
+    }
 }
 
 /// Stores the values needed for queue position estimation and adjustment for [`ProbQueueModel`].
-#[derive(Clone)]
+#[derive(Clone, Encode, Decode)]
 pub struct QueuePos {
     front_q_qty: f64,
     cum_trade_qty: f64,
@@ -210,6 +325,26 @@ where
             0.0
         }
     }
+
+    fn queue_position(&self, order: &Order) -> f64 {
+        order
+            .q
+            .as_any()
+            .downcast_ref::<QueuePos>()
+            .unwrap()
+            .front_q_qty
+    }
+
+    fn encode_q(&self, order: &Order) -> Result<Vec<u8>, BacktestError> {
+        let q = order.q.as_any().downcast_ref::<QueuePos>().unwrap();
+        checkpoint::encode(q)
+    }
+
+    fn decode_q(&self, order: &mut Order, q: &[u8]) -> Result<(), BacktestError> {
+        let q: QueuePos = checkpoint::decode(q)?;
+        order.q = Box::new(q);
+        Ok(())
+    }
 }
 
 /// This probability model uses a power function `f(x) = x ** n` to adjust the probability which is
@@ -325,9 +460,37 @@ impl Probability for PowerProbQueueFunc3 {
     }
 }
 
+/// Selects one of the probability functions at runtime, such as the one fitted by
+/// [`ProbQueueCalibrator`](crate::backtest::models::ProbQueueCalibrator).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProbQueueFunc {
+    /// [`PowerProbQueueFunc`] with the exponent `n`.
+    Power(f64),
+    /// [`PowerProbQueueFunc2`] with the exponent `n`.
+    Power2(f64),
+    /// [`PowerProbQueueFunc3`] with the exponent `n`.
+    Power3(f64),
+    /// [`LogProbQueueFunc`].
+    Log,
+    /// [`LogProbQueueFunc2`].
+    Log2,
+}
+
+impl Probability for ProbQueueFunc {
+    fn prob(&self, front: f64, back: f64) -> f64 {
+        match *self {
+            ProbQueueFunc::Power(n) => PowerProbQueueFunc::new(n).prob(front, back),
+            ProbQueueFunc::Power2(n) => PowerProbQueueFunc2::new(n).prob(front, back),
+            ProbQueueFunc::Power3(n) => PowerProbQueueFunc3::new(n).prob(front, back),
+            ProbQueueFunc::Log => LogProbQueueFunc::new().prob(front, back),
+            ProbQueueFunc::Log2 => LogProbQueueFunc2::new().prob(front, back),
+        }
+    }
+}
+
 /// Represents the order source for the Level 3 Market-By-Order queue model, which is stored in
 /// [`order.q`](crate::types::Order::q)
-#[derive(Copy, Clone, Eq, PartialEq)]
+#[derive(Copy, Clone, Eq, PartialEq, Encode, Decode)]
 enum L3OrderSource {
     /// Represents an order originating from the market feed.
     MarketFeed,
@@ -379,8 +542,14 @@ pub trait L3QueueModel<MD> {
     /// Returns `true` if the queue contains a backtest order for the order ID.
     fn contains_backtest_order(&self, order_id: OrderId) -> bool;
 
+    /// Returns the backtest orders in the queue.
+    fn backtest_orders(&self) -> Vec<&Order>;
+
     /// Invoked when the best bid is updated.
     /// Returns the ask backtest orders that are filled by crossing the best bid.
+    ///
+    /// The returned orders have the executed quantity in [`exec_qty`](Order::exec_qty) and the
+    /// quantity before the execution in [`leaves_qty`](Order::leaves_qty).
     fn on_best_bid_update(
         &mut self,
         prev_best_tick: i64,
@@ -389,6 +558,9 @@ pub trait L3QueueModel<MD> {
 
     /// Invoked when the best ask is updated.
     /// Returns the bid backtest orders that are filled by crossing the best ask.
+    ///
+    /// The returned orders have the executed quantity in [`exec_qty`](Order::exec_qty) and the
+    /// quantity before the execution in [`leaves_qty`](Order::leaves_qty).
     fn on_best_ask_update(
         &mut self,
         prev_best_tick: i64,
@@ -441,6 +613,11 @@ pub trait L3QueueModel<MD> {
     /// subsequently. The `DELETE` constant generic is used to indicate whether the order should be
     /// deleted immediately or if it should be deleted upon receiving a delete order event, which is
     /// handled by [`cancel_market_feed_order`](L3QueueModel::cancel_market_feed_order).
+    ///
+    /// Returns the backtest orders that are filled, which have the executed quantity in
+    /// [`exec_qty`](Order::exec_qty) and the quantity before the execution in
+    /// [`leaves_qty`](Order::leaves_qty). An order whose executed quantity is less than its leaves
+    /// quantity is partially filled and remains in the queue.
     fn fill_market_feed_order<const DELETE: bool>(
         &mut self,
         order_id: OrderId,
@@ -448,6 +625,22 @@ pub trait L3QueueModel<MD> {
         depth: &MD,
     ) -> Result<Vec<Order>, BacktestError>;
 
+    /// Invoked when an order is filled from the market feed in the partial-fill exchange model,
+    /// [`L3PartialFillExchange`](crate::backtest::proc::L3PartialFillExchange).
+    ///
+    /// Unlike [`fill_market_feed_order`](L3QueueModel::fill_market_feed_order), the backtest orders
+    /// are executed only up to the fill quantity of the event, so they can be partially filled.
+    /// The returned orders follow the same convention. By default, this is the same as
+    /// [`fill_market_feed_order`](L3QueueModel::fill_market_feed_order).
+    fn partially_fill_market_feed_order<const DELETE: bool>(
+        &mut self,
+        order_id: OrderId,
+        order: &Event,
+        depth: &MD,
+    ) -> Result<Vec<Order>, BacktestError> {
+        self.fill_market_feed_order::<DELETE>(order_id, order, depth)
+    }
+
     /// Invoked when a clear order message is received. Returns the expired orders due to the clear
     /// message.
     ///
@@ -464,6 +657,41 @@ pub trait L3QueueModel<MD> {
     /// Due to these challenges, HftBacktest opts to clear all backtest orders upon receiving a
     /// clear message, even though this may differ from the exchange's actual behavior.
     fn clear_orders(&mut self, side: Side) -> Vec<Order>;
+
+    /// Returns the encoded state of the queues, including both the backtest orders and the market
+    /// feed orders in their queue positions, to be stored in a
+    /// [`Checkpoint`](crate::backtest::checkpoint::Checkpoint).
+    fn checkpoint(&self) -> Result<Vec<u8>, BacktestError> {
+        Err(BacktestError::CheckpointError(
+            "the queue model does not support checkpointing".to_string(),
+        ))
+    }
+
+    /// Restores the state returned by [`L3QueueModel::checkpoint`]. The queue model should be
+    /// constructed in the same way as the queue model from which the state is taken.
+    fn restore(&mut self, _state: &[u8]) -> Result<(), BacktestError> {
+        Err(BacktestError::CheckpointError(
+            "the queue model does not support checkpointing".to_string(),
+        ))
+    }
+}
+
+/// The state of [`L3FIFOQueueModel`] stored in a checkpoint.
+#[derive(Encode, Decode)]
+struct L3FIFOQueueState {
+    // The orders in each queue by price in ticks, in queue order, along with their sources.
+    bid_queues: Vec<(i64, Vec<(Order, L3OrderSource)>)>,
+    ask_queues: Vec<(i64, Vec<(Order, L3OrderSource)>)>,
+}
+
+/// The state of [`L3ProRataQueueModel`] stored in a checkpoint.
+#[derive(Encode, Decode)]
+struct L3ProRataQueueState {
+    queue: L3FIFOQueueState,
+    bid_top_order: Option<(i64, L3OrderSource, OrderId)>,
+    ask_top_order: Option<(i64, L3OrderSource, OrderId)>,
+    bid_top_order_alloc: (i64, f64),
+    ask_top_order_alloc: (i64, f64),
 }
 
 /// This provides a Level 3 Market-By-Order queue model for backtesting in a FIFO manner. This means
@@ -472,7 +700,8 @@ pub trait L3QueueModel<MD> {
 /// when the market order, order from the market feed, behind the backtest order is executed.
 /// Exchanges may have different matching algorithms, such as Pro-Rata, and may have exotic order
 /// types that aren't executed in a FIFO manner. Therefore, you should carefully choose the queue
-/// model, even when dealing with a Level 3 Market-By-Order feed.
+/// model, even when dealing with a Level 3 Market-By-Order feed. For pro-rata matching, see
+/// [`L3ProRataQueueModel`].
 #[derive(Default)]
 pub struct L3FIFOQueueModel {
     // Stores the location of the queue that holds the order by (side, price in ticks).
@@ -490,6 +719,52 @@ impl L3FIFOQueueModel {
         Default::default()
     }
 
+    fn state(&self) -> L3FIFOQueueState {
+        let encode = |queues: &HashMap<i64, VecDeque<Order>>| {
+            queues
+                .iter()
+                .map(|(price_tick, queue)| {
+                    let orders = queue
+                        .iter()
+                        .map(|order| (order.clone(), order.order_source()))
+                        .collect();
+                    (*price_tick, orders)
+                })
+                .collect()
+        };
+        L3FIFOQueueState {
+            bid_queues: encode(&self.bid_queue),
+            ask_queues: encode(&self.ask_queue),
+        }
+    }
+
+    fn restore_state(&mut self, state: L3FIFOQueueState) {
+        self.backtest_orders.clear();
+        self.mkt_feed_orders.clear();
+        self.bid_queue.clear();
+        self.ask_queue.clear();
+        for (side, queues) in [
+            (Side::Buy, state.bid_queues),
+            (Side::Sell, state.ask_queues),
+        ] {
+            for (price_tick, orders) in queues {
+                let queue = match side {
+                    Side::Buy => self.bid_queue.entry(price_tick).or_default(),
+                    _ => self.ask_queue.entry(price_tick).or_default(),
+                };
+                for (mut order, source) in orders {
+                    let locations = match source {
+                        L3OrderSource::Backtest => &mut self.backtest_orders,
+                        L3OrderSource::MarketFeed => &mut self.mkt_feed_orders,
+                    };
+                    locations.insert(order.order_id, (side, price_tick));
+                    order.q = Box::new(source);
+                    queue.push_back(order);
+                }
+            }
+        }
+    }
+
     fn fill_bid_between<const INVALID_FROM: bool>(
         &mut self,
         from_tick: i64,
@@ -534,6 +809,9 @@ impl L3FIFOQueueModel {
                 }
             }
         }
+        for order in filled.iter_mut() {
+            order.exec_qty = order.leaves_qty;
+        }
         filled
     }
 
@@ -581,6 +859,9 @@ impl L3FIFOQueueModel {
                 }
             }
         }
+        for order in filled.iter_mut() {
+            order.exec_qty = order.leaves_qty;
+        }
         filled
     }
 }
@@ -593,6 +874,24 @@ where
         self.backtest_orders.contains_key(&order_id)
     }
 
+    fn backtest_orders(&self) -> Vec<&Order> {
+        self.backtest_orders
+            .iter()
+            .filter_map(|(order_id, (side, order_price_tick))| {
+                let queue = match side {
+                    Side::Buy => self.bid_queue.get(order_price_tick),
+                    Side::Sell => self.ask_queue.get(order_price_tick),
+                    Side::None | Side::Unsupported => unreachable!(),
+                };
+                queue.and_then(|queue| {
+                    queue
+                        .iter()
+                        .find(|order| order.is_backtest_order() && order.order_id == *order_id)
+                })
+            })
+            .collect()
+    }
+
     fn on_best_bid_update(
         &mut self,
         prev_best_tick: i64,
@@ -675,6 +974,15 @@ where
             req: Status::None,
             status: Status::None,
             time_in_force: TimeInForce::GTC,
+            self_trade_prevention: SelfTradePrevention::None,
+            avg_exec_price: 0.0,
+            trigger_price_tick: 0,
+            oco_order_id: 0,
+            trigger_price_type: TriggerPriceType::LastPrice,
+            oco: false,
+            expire_timestamp: 0,
+            reduce_only: false,
+            exch_order_id: 0,
         });
 
         match self.mkt_feed_orders.entry(order_id) {
@@ -1012,7 +1320,8 @@ where
                         }
                     }
                 }
-                for order in &filled {
+                for order in filled.iter_mut() {
+                    order.exec_qty = order.leaves_qty;
                     self.backtest_orders.remove(&order.order_id);
                 }
                 Ok(filled)
@@ -1053,7 +1362,8 @@ where
                         }
                     }
                 }
-                for order in &filled {
+                for order in filled.iter_mut() {
+                    order.exec_qty = order.leaves_qty;
                     self.backtest_orders.remove(&order.order_id);
                 }
                 Ok(filled)
@@ -1062,6 +1372,81 @@ where
         }
     }
 
+    fn partially_fill_market_feed_order<const DELETE: bool>(
+        &mut self,
+        order_id: OrderId,
+        order: &Event,
+        depth: &MD,
+    ) -> Result<Vec<Order>, BacktestError> {
+        let (side, order_price_tick) = if DELETE {
+            self.mkt_feed_orders
+                .remove(&order_id)
+                .ok_or(BacktestError::OrderNotFound)?
+        } else {
+            *self
+                .mkt_feed_orders
+                .get(&order_id)
+                .ok_or(BacktestError::OrderNotFound)?
+        };
+        let exec_price_tick = (order.px / depth.tick_size()).round() as i64;
+        let lot_size = depth.lot_size();
+
+        // The backtest orders at prices better than the price of the filled market-feed order are
+        // filled.
+        // The fill event should occur before the cancel event which may update the best price.
+        let mut filled = match side {
+            Side::Buy if exec_price_tick < depth.best_bid_tick() => {
+                self.fill_bid_between::<false>(depth.best_bid_tick(), exec_price_tick + 1)
+            }
+            Side::Sell if exec_price_tick > depth.best_ask_tick() => {
+                self.fill_ask_between::<false>(depth.best_ask_tick(), exec_price_tick - 1)
+            }
+            _ => Vec::new(),
+        };
+
+        let queue = match side {
+            Side::Buy => self.bid_queue.get_mut(&order_price_tick).unwrap(),
+            Side::Sell => self.ask_queue.get_mut(&order_price_tick).unwrap(),
+            Side::None | Side::Unsupported => unreachable!(),
+        };
+
+        // The fill quantity is allocated in time priority to the backtest orders in the queue,
+        // placed before the filled market-feed order.
+        let mut fill_qty = order.qty;
+        let mut i = 0;
+        while i < queue.len() {
+            let order_in_q = queue.get_mut(i).unwrap();
+            match order_in_q.order_source() {
+                L3OrderSource::MarketFeed if order_in_q.order_id == order_id => {
+                    if DELETE {
+                        queue.remove(i);
+                    }
+                    break;
+                }
+                L3OrderSource::MarketFeed => {
+                    i += 1;
+                }
+                L3OrderSource::Backtest if (fill_qty / lot_size).round() <= 0.0 => {
+                    i += 1;
+                }
+                L3OrderSource::Backtest => {
+                    let mut filled_order = order_in_q.clone();
+                    filled_order.exec_qty = order_in_q.leaves_qty.min(fill_qty);
+                    fill_qty -= filled_order.exec_qty;
+                    order_in_q.leaves_qty -= filled_order.exec_qty;
+                    if (order_in_q.leaves_qty / lot_size).round() > 0.0 {
+                        i += 1;
+                    } else {
+                        queue.remove(i);
+                        self.backtest_orders.remove(&filled_order.order_id);
+                    }
+                    filled.push(filled_order);
+                }
+            }
+        }
+        Ok(filled)
+    }
+
     fn clear_orders(&mut self, side: Side) -> Vec<Order> {
         match side {
             Side::Buy => {
@@ -1115,21 +1500,477 @@ where
             }
         }
     }
+
+    fn checkpoint(&self) -> Result<Vec<u8>, BacktestError> {
+        checkpoint::encode(&self.state())
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<(), BacktestError> {
+        let state: L3FIFOQueueState = checkpoint::decode(state)?;
+        self.restore_state(state);
+        Ok(())
+    }
+}
+
+/// This provides a Level 3 Market-By-Order queue model for backtesting in a pro-rata manner, which
+/// is used by some exchanges, such as CME, for certain products. The quantity executed at a price
+/// level is allocated to the orders at the level in proportion to their quantities rather than in
+/// time priority, so backtest orders can be partially filled.
+///
+/// Each fill event from the market feed is regarded as the quantity executed at the price level,
+/// and each backtest order at the level is allocated its pro-rata share of it, rounded down to the
+/// lot size, based on the quantities of all orders in the queue at that time. The remainder left
+/// by rounding is allocated in time priority. As in [`L3FIFOQueueModel`], backtest orders at
+/// prices better than the execution price, or crossed by the best price on the opposite side, are
+/// fully filled.
+///
+/// The allocation can be adjusted to match the exchange's matching algorithm:
+///
+/// * [`top_order_priority`](L3ProRataQueueModel::top_order_priority) allocates to the top order,
+///   the order that established a better best price, before any other allocation.
+/// * [`fifo_ratio`](L3ProRataQueueModel::fifo_ratio) allocates a part of the executed quantity in
+///   time priority before the pro-rata allocation, which is known as a FIFO/pro-rata split.
+/// * [`size_time`](L3ProRataQueueModel::size_time) allocates in proportion to the quantity
+///   multiplied by the time in the queue rather than the quantity alone.
+///
+/// An aggressor that matches several orders generates a fill event for each of them, and the
+/// events are allocated one by one. The fill events at the same exchange timestamp are regarded as
+/// coming from the same aggressor, so the top order is allocated up to its maximum quantity across
+/// them. The pro-rata allocations of the events add up to that of the aggressor's entire quantity,
+/// except for the rounding to the lot size.
+///
+/// Since the market feed reports the fills of the actual queue, which doesn't include the backtest
+/// orders, the allocation is an estimation. The quantities of the market feed orders are not
+/// reduced by the quantities allocated to the backtest orders.
+#[derive(Default)]
+pub struct L3ProRataQueueModel {
+    queue: L3FIFOQueueModel,
+    top_order_max_qty: Option<f64>,
+    fifo_ratio: f64,
+    size_time: bool,
+    // Stores the top order by (price in ticks, order source, order ID).
+    bid_top_order: Option<(i64, L3OrderSource, OrderId)>,
+    ask_top_order: Option<(i64, L3OrderSource, OrderId)>,
+    // Stores the quantity allocated to the top order by the latest aggressor by (exchange
+    // timestamp, quantity).
+    bid_top_order_alloc: (i64, f64),
+    ask_top_order_alloc: (i64, f64),
+}
+
+impl L3ProRataQueueModel {
+    /// Constructs an instance of `L3ProRataQueueModel` that allocates the executed quantity purely
+    /// pro-rata.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Gives priority to the top order, which is the first order that established a better best
+    /// price. The top order is allocated up to `max_qty` of the quantity executed at its price level
+    /// before the other orders. It loses the priority when it is modified or canceled, or when
+    /// another order establishes a better price.
+    pub fn top_order_priority(self, max_qty: f64) -> Self {
+        Self {
+            top_order_max_qty: Some(max_qty),
+            ..self
+        }
+    }
+
+    /// Allocates the given ratio of the executed quantity, rounded down to the lot size, in time
+    /// priority before allocating the rest pro-rata. The ratio must be between 0 and 1, and 1 makes
+    /// the allocation FIFO.
+    pub fn fifo_ratio(self, ratio: f64) -> Self {
+        assert!((0.0..=1.0).contains(&ratio));
+        Self {
+            fifo_ratio: ratio,
+            ..self
+        }
+    }
+
+    /// Allocates the quantity left after the top order and FIFO allocations in proportion to each
+    /// order's quantity multiplied by the time it has been in the queue until the fill, which is
+    /// known as time pro-rata. If every order entered the queue at the time of the fill, it is
+    /// allocated in proportion to the quantity.
+    pub fn size_time(self) -> Self {
+        Self {
+            size_time: true,
+            ..self
+        }
+    }
+
+    fn top_order(&mut self, side: Side) -> &mut Option<(i64, L3OrderSource, OrderId)> {
+        match side {
+            Side::Buy => &mut self.bid_top_order,
+            Side::Sell => &mut self.ask_top_order,
+            Side::None | Side::Unsupported => unreachable!(),
+        }
+    }
+
+    fn update_top_order(
+        &mut self,
+        side: Side,
+        order_price_tick: i64,
+        source: L3OrderSource,
+        order_id: OrderId,
+        best_tick: i64,
+    ) {
+        // The order establishes a better best price if it is the first order at the price level
+        // and its price is not worse than the best price.
+        let (queue, better) = match side {
+            Side::Buy => (
+                self.queue.bid_queue.get(&order_price_tick),
+                order_price_tick >= best_tick,
+            ),
+            Side::Sell => (
+                self.queue.ask_queue.get(&order_price_tick),
+                order_price_tick <= best_tick,
+            ),
+            Side::None | Side::Unsupported => unreachable!(),
+        };
+        if better && queue.map(|queue| queue.is_empty()).unwrap_or(true) {
+            *self.top_order(side) = Some((order_price_tick, source, order_id));
+        }
+    }
+
+    fn remove_top_order(&mut self, source: L3OrderSource, order_id: OrderId) {
+        for top_order in [&mut self.bid_top_order, &mut self.ask_top_order] {
+            if matches!(top_order, Some((_, s, id)) if *s == source && *id == order_id) {
+                *top_order = None;
+            }
+        }
+    }
+}
+
+/// Allocates the quantity to the orders in the queue in time priority, and returns the quantity
+/// left after all orders are fully allocated.
+fn allocate_fifo(queue: &VecDeque<Order>, allocated: &mut [f64], mut qty: f64) -> f64 {
+    for (order, allocated) in queue.iter().zip(allocated.iter_mut()) {
+        if qty <= 0.0 {
+            break;
+        }
+        let alloc_qty = (order.leaves_qty - *allocated).min(qty);
+        *allocated += alloc_qty;
+        qty -= alloc_qty;
+    }
+    qty
+}
+
+/// Returns the quantity allocated to each order in the queue out of the executed quantity.
+/// `top_order` is the position of the top order in the queue and the maximum quantity allocated to
+/// it. If `size_time_at` is given, the pro-rata allocation is weighted by the time from each
+/// order's entry into the queue to that timestamp.
+fn allocate_pro_rata(
+    queue: &VecDeque<Order>,
+    top_order: Option<(usize, f64)>,
+    fifo_ratio: f64,
+    size_time_at: Option<i64>,
+    exec_qty: f64,
+    lot_size: f64,
+) -> Vec<f64> {
+    let round_down = |qty: f64| (qty / lot_size + 1e-9).floor() * lot_size;
+    let mut allocated = vec![0.0; queue.len()];
+    let mut remaining = exec_qty;
+
+    if let Some((i, max_qty)) = top_order {
+        allocated[i] = remaining.min(max_qty).min(queue[i].leaves_qty);
+        remaining -= allocated[i];
+    }
+
+    let fifo_qty = round_down(remaining * fifo_ratio);
+    remaining += allocate_fifo(queue, &mut allocated, fifo_qty) - fifo_qty;
+
+    let mut weights: Vec<f64> = queue
+        .iter()
+        .zip(allocated.iter())
+        .map(|(order, allocated)| order.leaves_qty - allocated)
+        .collect();
+    if let Some(timestamp) = size_time_at {
+        let size_time_weights: Vec<f64> = queue
+            .iter()
+            .zip(weights.iter())
+            .map(|(order, qty)| qty * (timestamp - order.exch_timestamp).max(0) as f64)
+            .collect();
+        if size_time_weights.iter().sum::<f64>() > 0.0 {
+            weights = size_time_weights;
+        }
+    }
+    let total_weight: f64 = weights.iter().sum();
+    if remaining > 0.0 && total_weight > 0.0 {
+        let pro_rata_qty = remaining;
+        for ((order, allocated), weight) in queue.iter().zip(allocated.iter_mut()).zip(weights) {
+            let alloc_qty = round_down(
+                (pro_rata_qty * weight / total_weight).min(order.leaves_qty - *allocated),
+            );
+            *allocated += alloc_qty;
+            remaining -= alloc_qty;
+        }
+        // The remainder left by rounding is allocated in time priority.
+        let residual_qty = (remaining / lot_size).round() * lot_size;
+        allocate_fifo(queue, &mut allocated, residual_qty);
+    }
+    allocated
+}
+
+impl<MD> L3QueueModel<MD> for L3ProRataQueueModel
+where
+    MD: MarketDepth,
+{
+    fn contains_backtest_order(&self, order_id: OrderId) -> bool {
+        L3QueueModel::<MD>::contains_backtest_order(&self.queue, order_id)
+    }
+
+    fn backtest_orders(&self) -> Vec<&Order> {
+        L3QueueModel::<MD>::backtest_orders(&self.queue)
+    }
+
+    fn on_best_bid_update(
+        &mut self,
+        prev_best_tick: i64,
+        new_best_tick: i64,
+    ) -> Result<Vec<Order>, BacktestError> {
+        L3QueueModel::<MD>::on_best_bid_update(&mut self.queue, prev_best_tick, new_best_tick)
+    }
+
+    fn on_best_ask_update(
+        &mut self,
+        prev_best_tick: i64,
+        new_best_tick: i64,
+    ) -> Result<Vec<Order>, BacktestError> {
+        L3QueueModel::<MD>::on_best_ask_update(&mut self.queue, prev_best_tick, new_best_tick)
+    }
+
+    fn add_backtest_order(&mut self, order: Order, depth: &MD) -> Result<(), BacktestError> {
+        let best_tick = match order.side {
+            Side::Buy => depth.best_bid_tick(),
+            Side::Sell => depth.best_ask_tick(),
+            Side::None | Side::Unsupported => unreachable!(),
+        };
+        self.update_top_order(
+            order.side,
+            order.price_tick,
+            L3OrderSource::Backtest,
+            order.order_id,
+            best_tick,
+        );
+        self.queue.add_backtest_order(order, depth)
+    }
+
+    fn add_market_feed_order(&mut self, order: &Event, depth: &MD) -> Result<(), BacktestError> {
+        let order_price_tick = (order.px / depth.tick_size()).round() as i64;
+        if order.is(BUY_EVENT) {
+            self.update_top_order(
+                Side::Buy,
+                order_price_tick,
+                L3OrderSource::MarketFeed,
+                order.order_id,
+                depth.best_bid_tick(),
+            );
+        } else if order.is(SELL_EVENT) {
+            self.update_top_order(
+                Side::Sell,
+                order_price_tick,
+                L3OrderSource::MarketFeed,
+                order.order_id,
+                depth.best_ask_tick(),
+            );
+        }
+        self.queue.add_market_feed_order(order, depth)
+    }
+
+    fn cancel_backtest_order(
+        &mut self,
+        order_id: OrderId,
+        depth: &MD,
+    ) -> Result<Order, BacktestError> {
+        self.remove_top_order(L3OrderSource::Backtest, order_id);
+        self.queue.cancel_backtest_order(order_id, depth)
+    }
+
+    fn cancel_market_feed_order(
+        &mut self,
+        order_id: OrderId,
+        depth: &MD,
+    ) -> Result<(), BacktestError> {
+        self.remove_top_order(L3OrderSource::MarketFeed, order_id);
+        self.queue.cancel_market_feed_order(order_id, depth)
+    }
+
+    fn modify_backtest_order(
+        &mut self,
+        order_id: OrderId,
+        order: Order,
+        depth: &MD,
+    ) -> Result<(), BacktestError> {
+        self.remove_top_order(L3OrderSource::Backtest, order_id);
+        self.queue.modify_backtest_order(order_id, order, depth)
+    }
+
+    fn modify_market_feed_order(
+        &mut self,
+        order_id: OrderId,
+        order: &Event,
+        depth: &MD,
+    ) -> Result<(), BacktestError> {
+        self.remove_top_order(L3OrderSource::MarketFeed, order_id);
+        self.queue.modify_market_feed_order(order_id, order, depth)
+    }
+
+    fn fill_market_feed_order<const DELETE: bool>(
+        &mut self,
+        order_id: OrderId,
+        order: &Event,
+        depth: &MD,
+    ) -> Result<Vec<Order>, BacktestError> {
+        let (side, order_price_tick) = *self
+            .queue
+            .mkt_feed_orders
+            .get(&order_id)
+            .ok_or(BacktestError::OrderNotFound)?;
+        let exec_price_tick = (order.px / depth.tick_size()).round() as i64;
+        let lot_size = depth.lot_size();
+
+        // The backtest orders at prices better than the price of the filled market-feed order are
+        // filled.
+        // The fill event should occur before the cancel event which may update the best price.
+        let (mut filled, top_order) = match side {
+            Side::Buy if exec_price_tick < depth.best_bid_tick() => (
+                self.queue
+                    .fill_bid_between::<false>(depth.best_bid_tick(), exec_price_tick + 1),
+                self.bid_top_order,
+            ),
+            Side::Sell if exec_price_tick > depth.best_ask_tick() => (
+                self.queue
+                    .fill_ask_between::<false>(depth.best_ask_tick(), exec_price_tick - 1),
+                self.ask_top_order,
+            ),
+            Side::Buy => (Vec::new(), self.bid_top_order),
+            Side::Sell => (Vec::new(), self.ask_top_order),
+            Side::None | Side::Unsupported => unreachable!(),
+        };
+
+        let queue = match side {
+            Side::Buy => self.queue.bid_queue.get_mut(&order_price_tick).unwrap(),
+            _ => self.queue.ask_queue.get_mut(&order_price_tick).unwrap(),
+        };
+
+        // The top order is allocated up to the maximum quantity per aggressor, so the quantity
+        // already allocated to it by the fill events of the same aggressor is deducted.
+        let top_order_alloc = match side {
+            Side::Buy => &mut self.bid_top_order_alloc,
+            _ => &mut self.ask_top_order_alloc,
+        };
+        if top_order_alloc.0 != order.exch_ts {
+            *top_order_alloc = (order.exch_ts, 0.0);
+        }
+
+        // The executed quantity at the price level is allocated to the orders in the queue.
+        let top_order = match (top_order, self.top_order_max_qty) {
+            (Some((top_price_tick, source, top_order_id)), Some(max_qty))
+                if top_price_tick == order_price_tick =>
+            {
+                queue
+                    .iter()
+                    .position(|order| {
+                        order.order_source() == source && order.order_id == top_order_id
+                    })
+                    .map(|i| (i, (max_qty - top_order_alloc.1).max(0.0)))
+            }
+            _ => None,
+        };
+        let size_time_at = if self.size_time {
+            Some(order.exch_ts)
+        } else {
+            None
+        };
+        let allocated = allocate_pro_rata(
+            queue,
+            top_order,
+            self.fifo_ratio,
+            size_time_at,
+            order.qty,
+            lot_size,
+        );
+        if let Some((i, _)) = top_order {
+            top_order_alloc.1 += allocated[i];
+        }
+
+        let mut allocated = allocated.into_iter();
+        queue.retain_mut(|order_in_q| {
+            let alloc_qty = allocated.next().unwrap();
+            if order_in_q.is_backtest_order() && alloc_qty > 0.0 {
+                let mut order = order_in_q.clone();
+                order.exec_qty = alloc_qty;
+                filled.push(order);
+
+                order_in_q.leaves_qty -= alloc_qty;
+                if (order_in_q.leaves_qty / lot_size).round() > 0.0 {
+                    true
+                } else {
+                    self.queue.backtest_orders.remove(&order_in_q.order_id);
+                    false
+                }
+            } else {
+                !(DELETE && order_in_q.is_market_feed_order() && order_in_q.order_id == order_id)
+            }
+        });
+        if DELETE {
+            self.queue.mkt_feed_orders.remove(&order_id);
+            self.remove_top_order(L3OrderSource::MarketFeed, order_id);
+        }
+        Ok(filled)
+    }
+
+    fn clear_orders(&mut self, side: Side) -> Vec<Order> {
+        match side {
+            Side::Buy => self.bid_top_order = None,
+            Side::Sell => self.ask_top_order = None,
+            Side::None => {
+                self.bid_top_order = None;
+                self.ask_top_order = None;
+            }
+            Side::Unsupported => unreachable!(),
+        }
+        L3QueueModel::<MD>::clear_orders(&mut self.queue, side)
+    }
+
+    fn checkpoint(&self) -> Result<Vec<u8>, BacktestError> {
+        checkpoint::encode(&L3ProRataQueueState {
+            queue: self.queue.state(),
+            bid_top_order: self.bid_top_order,
+            ask_top_order: self.ask_top_order,
+            bid_top_order_alloc: self.bid_top_order_alloc,
+            ask_top_order_alloc: self.ask_top_order_alloc,
+        })
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<(), BacktestError> {
+        let state: L3ProRataQueueState = checkpoint::decode(state)?;
+        self.queue.restore_state(state.queue);
+        self.bid_top_order = state.bid_top_order;
+        self.ask_top_order = state.ask_top_order;
+        self.bid_top_order_alloc = state.bid_top_order_alloc;
+        self.ask_top_order_alloc = state.ask_top_order_alloc;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod l3_tests {
     use crate::{
-        backtest::{models::L3FIFOQueueModel, L3QueueModel},
+        backtest::{
+            models::{queue::L3Order as _, L3FIFOQueueModel, L3ProRataQueueModel},
+            L3QueueModel,
+        },
         prelude::{
             Event,
             HashMapMarketDepth,
             L3MarketDepth,
             OrdType,
             Order,
+            SelfTradePrevention,
             Side,
             Status,
             TimeInForce,
+            TriggerPriceType,
         },
         types::{ADD_ORDER_EVENT, BUY_EVENT, EXCH_EVENT, FILL_EVENT, SELL_EVENT},
     };
@@ -1189,6 +2030,15 @@ mod l3_tests {
                 status: Status::None,
                 side: Side::Buy,
                 time_in_force: TimeInForce::GTC,
+                self_trade_prevention: SelfTradePrevention::None,
+                avg_exec_price: 0.0,
+                trigger_price_tick: 0,
+                oco_order_id: 0,
+                trigger_price_type: TriggerPriceType::LastPrice,
+                oco: false,
+                expire_timestamp: 0,
+                reduce_only: false,
+                exch_order_id: 0,
             },
             &depth,
         )
@@ -1223,6 +2073,15 @@ mod l3_tests {
                 status: Status::None,
                 side: Side::Sell,
                 time_in_force: TimeInForce::GTC,
+                self_trade_prevention: SelfTradePrevention::None,
+                avg_exec_price: 0.0,
+                trigger_price_tick: 0,
+                oco_order_id: 0,
+                trigger_price_type: TriggerPriceType::LastPrice,
+                oco: false,
+                expire_timestamp: 0,
+                reduce_only: false,
+                exch_order_id: 0,
             },
             &depth,
         )
@@ -1279,6 +2138,15 @@ mod l3_tests {
                 status: Status::None,
                 side: Side::Buy,
                 time_in_force: TimeInForce::GTC,
+                self_trade_prevention: SelfTradePrevention::None,
+                avg_exec_price: 0.0,
+                trigger_price_tick: 0,
+                oco_order_id: 0,
+                trigger_price_type: TriggerPriceType::LastPrice,
+                oco: false,
+                expire_timestamp: 0,
+                reduce_only: false,
+                exch_order_id: 0,
             },
             &depth,
         )
@@ -1353,4 +2221,304 @@ mod l3_tests {
             )
         );
     }
+
+    #[test]
+    fn modify_backtest_order_priority() {
+        let mut depth = HashMapMarketDepth::new(1.0, 1.0);
+        let mut qm = L3FIFOQueueModel::new();
+
+        let order = Order {
+            qty: 2.0,
+            leaves_qty: 2.0,
+            exec_qty: 0.0,
+            exec_price_tick: 0,
+            price_tick: 100,
+            tick_size: 1.0,
+            exch_timestamp: 0,
+            local_timestamp: 0,
+            order_id: 1,
+            q: Box::new(()),
+            maker: false,
+            order_type: OrdType::Limit,
+            req: Status::None,
+            status: Status::New,
+            side: Side::Buy,
+            time_in_force: TimeInForce::GTC,
+            self_trade_prevention: SelfTradePrevention::None,
+            avg_exec_price: 0.0,
+            trigger_price_tick: 0,
+            oco_order_id: 0,
+            trigger_price_type: TriggerPriceType::LastPrice,
+            oco: false,
+            expire_timestamp: 0,
+            reduce_only: false,
+            exch_order_id: 0,
+        };
+        qm.add_backtest_order(order.clone(), &depth).unwrap();
+
+        for order_id in [1, 2] {
+            let ev = Event {
+                ev: EXCH_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                exch_ts: 0,
+                local_ts: 0,
+                px: 100.0,
+                qty: 1.0,
+                order_id,
+                ival: 0,
+                fval: 0.0,
+            };
+
+            depth
+                .add_buy_order(ev.order_id, ev.px, ev.qty, ev.exch_ts)
+                .unwrap();
+            qm.add_market_feed_order(&ev, &depth).unwrap();
+        }
+
+        // Decreasing the quantity retains the queue priority.
+        let mut modified = order.clone();
+        modified.qty = 1.0;
+        modified.leaves_qty = 1.0;
+        qm.modify_backtest_order(1, modified, &depth).unwrap();
+        let queue = qm.bid_queue.get(&100).unwrap();
+        assert!(queue[0].is_backtest_order());
+        assert_eq!(queue[0].leaves_qty, 1.0);
+
+        // Increasing the quantity loses the queue priority, so the backtest order is now behind
+        // the market feed order 2.
+        let mut modified = order.clone();
+        modified.qty = 3.0;
+        modified.leaves_qty = 3.0;
+        qm.modify_backtest_order(1, modified, &depth).unwrap();
+
+        let ev = Event {
+            ev: EXCH_EVENT | BUY_EVENT | FILL_EVENT,
+            exch_ts: 0,
+            local_ts: 0,
+            px: 100.0,
+            qty: 1.0,
+            order_id: 2,
+            ival: 0,
+            fval: 0.0,
+        };
+
+        depth.delete_order(1, 0).unwrap();
+        depth.delete_order(2, 0).unwrap();
+        let filled = qm.fill_market_feed_order::<false>(2, &ev, &depth).unwrap();
+        assert_eq!(filled.len(), 0);
+        assert!(
+            <L3FIFOQueueModel as L3QueueModel<HashMapMarketDepth>>::contains_backtest_order(&qm, 1)
+        );
+
+        // A price change also loses the queue priority.
+        let mut modified = order.clone();
+        modified.price_tick = 99;
+        qm.modify_backtest_order(1, modified, &depth).unwrap();
+        let filled = <L3FIFOQueueModel as L3QueueModel<HashMapMarketDepth>>::on_best_ask_update(
+            &mut qm, 101, 99,
+        )
+        .unwrap();
+        assert_eq!(filled.len(), 1);
+        assert_eq!(filled[0].price_tick, 99);
+    }
+
+    fn backtest_order(qty: f64) -> Order {
+        Order {
+            qty,
+            leaves_qty: qty,
+            exec_qty: 0.0,
+            exec_price_tick: 0,
+            price_tick: 100,
+            tick_size: 1.0,
+            exch_timestamp: 0,
+            local_timestamp: 0,
+            order_id: 1,
+            q: Box::new(()),
+            maker: false,
+            order_type: OrdType::Limit,
+            req: Status::None,
+            status: Status::None,
+            side: Side::Buy,
+            time_in_force: TimeInForce::GTC,
+            self_trade_prevention: SelfTradePrevention::None,
+            avg_exec_price: 0.0,
+            trigger_price_tick: 0,
+            oco_order_id: 0,
+            trigger_price_type: TriggerPriceType::LastPrice,
+            oco: false,
+            expire_timestamp: 0,
+            reduce_only: false,
+            exch_order_id: 0,
+        }
+    }
+
+    fn event(ev: u64, qty: f64) -> Event {
+        Event {
+            ev: EXCH_EVENT | BUY_EVENT | ev,
+            exch_ts: 0,
+            local_ts: 0,
+            px: 100.0,
+            qty,
+            order_id: 1,
+            ival: 0,
+            fval: 0.0,
+        }
+    }
+
+    #[test]
+    fn fill_pro_rata() {
+        // Pure pro-rata
+        let mut depth = HashMapMarketDepth::new(1.0, 1.0);
+        let mut qm = L3ProRataQueueModel::new();
+        let ev = event(ADD_ORDER_EVENT, 6.0);
+        depth
+            .add_buy_order(ev.order_id, ev.px, ev.qty, ev.exch_ts)
+            .unwrap();
+        qm.add_market_feed_order(&ev, &depth).unwrap();
+        qm.add_backtest_order(backtest_order(4.0), &depth).unwrap();
+
+        // 5 out of 10 is allocated pro-rata.
+        let filled = qm
+            .fill_market_feed_order::<false>(1, &event(FILL_EVENT, 5.0), &depth)
+            .unwrap();
+        assert_eq!(filled.len(), 1);
+        assert_eq!(filled[0].exec_qty, 2.0);
+        assert_eq!(filled[0].leaves_qty, 4.0);
+
+        // 5 out of 8 is allocated pro-rata, and the remainder left by rounding down is allocated
+        // to the market feed order ahead.
+        let filled = qm
+            .fill_market_feed_order::<false>(1, &event(FILL_EVENT, 5.0), &depth)
+            .unwrap();
+        assert_eq!(filled[0].exec_qty, 1.0);
+        assert_eq!(filled[0].leaves_qty, 2.0);
+        assert!(
+            <L3ProRataQueueModel as L3QueueModel<HashMapMarketDepth>>::contains_backtest_order(
+                &qm, 1
+            )
+        );
+
+        // Pro-rata with top-order priority
+        let mut depth = HashMapMarketDepth::new(1.0, 1.0);
+        let mut qm = L3ProRataQueueModel::new().top_order_priority(f64::INFINITY);
+        qm.add_backtest_order(backtest_order(2.0), &depth).unwrap();
+        let ev = event(ADD_ORDER_EVENT, 8.0);
+        depth
+            .add_buy_order(ev.order_id, ev.px, ev.qty, ev.exch_ts)
+            .unwrap();
+        qm.add_market_feed_order(&ev, &depth).unwrap();
+
+        let filled = qm
+            .fill_market_feed_order::<false>(1, &event(FILL_EVENT, 4.0), &depth)
+            .unwrap();
+        assert_eq!(filled[0].exec_qty, 2.0);
+        assert!(
+            !<L3ProRataQueueModel as L3QueueModel<HashMapMarketDepth>>::contains_backtest_order(
+                &qm, 1
+            )
+        );
+
+        // FIFO/pro-rata split
+        let mut depth = HashMapMarketDepth::new(1.0, 1.0);
+        let mut qm = L3ProRataQueueModel::new().fifo_ratio(0.5);
+        let ev = event(ADD_ORDER_EVENT, 4.0);
+        depth
+            .add_buy_order(ev.order_id, ev.px, ev.qty, ev.exch_ts)
+            .unwrap();
+        qm.add_market_feed_order(&ev, &depth).unwrap();
+        qm.add_backtest_order(backtest_order(4.0), &depth).unwrap();
+
+        // 3 is allocated to the market feed order ahead in FIFO, then 3 out of 5 is allocated
+        // pro-rata.
+        let filled = qm
+            .fill_market_feed_order::<false>(1, &event(FILL_EVENT, 6.0), &depth)
+            .unwrap();
+        assert_eq!(filled[0].exec_qty, 2.0);
+    }
+
+    #[test]
+    fn checkpoint_restore() {
+        let mut depth = HashMapMarketDepth::new(1.0, 1.0);
+        let new_qm = || L3ProRataQueueModel::new().top_order_priority(3.0);
+        let mut qm = new_qm();
+        qm.add_backtest_order(backtest_order(10.0), &depth).unwrap();
+        let ev = event(ADD_ORDER_EVENT, 10.0);
+        depth
+            .add_buy_order(ev.order_id, ev.px, ev.qty, ev.exch_ts)
+            .unwrap();
+        qm.add_market_feed_order(&ev, &depth).unwrap();
+
+        // The first fill event of the aggressor allocates 2 to the top order.
+        let filled = qm
+            .fill_market_feed_order::<false>(1, &event(FILL_EVENT, 2.0), &depth)
+            .unwrap();
+        assert_eq!(filled[0].exec_qty, 2.0);
+
+        let state =
+            <L3ProRataQueueModel as L3QueueModel<HashMapMarketDepth>>::checkpoint(&qm).unwrap();
+        let mut restored = new_qm();
+        <L3ProRataQueueModel as L3QueueModel<HashMapMarketDepth>>::restore(&mut restored, &state)
+            .unwrap();
+
+        // The top order is allocated the remaining 1 out of its maximum of 3, then 3 out of 9
+        // pro-rata, and the remainder of 1 left by rounding down in time priority.
+        for qm in [&mut qm, &mut restored] {
+            let filled = qm
+                .fill_market_feed_order::<false>(1, &event(FILL_EVENT, 10.0), &depth)
+                .unwrap();
+            assert_eq!(filled.len(), 1);
+            assert_eq!(filled[0].exec_qty, 5.0);
+            assert_eq!(filled[0].leaves_qty, 8.0);
+        }
+    }
+
+    #[test]
+    fn fill_partially() {
+        let mut depth = HashMapMarketDepth::new(1.0, 1.0);
+        let mut qm = L3FIFOQueueModel::new();
+
+        for (order_id, qty) in [(1, 1.0), (2, 5.0)] {
+            let ev = Event {
+                order_id,
+                ..event(ADD_ORDER_EVENT, qty)
+            };
+            depth
+                .add_buy_order(ev.order_id, ev.px, ev.qty, ev.exch_ts)
+                .unwrap();
+            qm.add_market_feed_order(&ev, &depth).unwrap();
+            if order_id == 1 {
+                qm.add_backtest_order(backtest_order(3.0), &depth).unwrap();
+            }
+        }
+
+        // The fill quantity of the market feed order behind is allocated to the backtest order.
+        let ev = Event {
+            order_id: 2,
+            ..event(FILL_EVENT, 2.0)
+        };
+        let filled = qm
+            .partially_fill_market_feed_order::<false>(2, &ev, &depth)
+            .unwrap();
+        assert_eq!(filled.len(), 1);
+        assert_eq!(filled[0].exec_qty, 2.0);
+        assert_eq!(filled[0].leaves_qty, 3.0);
+        assert!(
+            <L3FIFOQueueModel as L3QueueModel<HashMapMarketDepth>>::contains_backtest_order(&qm, 1)
+        );
+
+        // The fill quantity of the market feed order ahead is not.
+        let filled = qm
+            .partially_fill_market_feed_order::<false>(1, &event(FILL_EVENT, 1.0), &depth)
+            .unwrap();
+        assert!(filled.is_empty());
+
+        let filled = qm
+            .partially_fill_market_feed_order::<false>(2, &ev, &depth)
+            .unwrap();
+        assert_eq!(filled[0].exec_qty, 1.0);
+        assert!(
+            !<L3FIFOQueueModel as L3QueueModel<HashMapMarketDepth>>::contains_backtest_order(
+                &qm, 1
+            )
+        );
+    }
 }
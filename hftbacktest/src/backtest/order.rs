@@ -46,6 +46,12 @@ impl OrderBus {
         unsafe { &mut *self.order_list.get() }.push_back((order, timestamp));
     }
 
+    /// Returns a copy of the orders in the bus along with their timestamps, in the order they are
+    /// to be received.
+    pub fn to_vec(&self) -> Vec<(Order, i64)> {
+        unsafe { &*self.order_list.get() }.iter().cloned().collect()
+    }
+
     /// Resets this to clear it.
     pub fn reset(&mut self) {
         unsafe { &mut *self.order_list.get() }.clear();
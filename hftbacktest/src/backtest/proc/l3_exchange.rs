@@ -0,0 +1,1318 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    mem,
+};
+
+use bincode::{Decode, Encode};
+
+use crate::{
+    backtest::{
+        assettype::AssetType,
+        checkpoint,
+        data::{Data, Reader},
+        expiry::Expiry,
+        models::{FeeModel, L3QueueModel, LatencyModel},
+        order::OrderBus,
+        proc::{
+            l3_depth_levels,
+            l3_depth_snapshot,
+            liquidation_order,
+            mark_price,
+            prevent_self_trade,
+            restore_data,
+            restore_l3_depth,
+            restore_order_bus,
+            Processor,
+            SelfTradeAction,
+        },
+        state::{Margin, State},
+        trace::Tracer,
+        BacktestError,
+    },
+    depth::{L3MarketDepth, L3Order},
+    prelude::OrdType,
+    types::{
+        Event,
+        Order,
+        OrderId,
+        Side,
+        StateValues,
+        Status,
+        TimeInForce,
+        TriggerPriceType,
+        BUY_EVENT,
+        EXCH_ASK_ADD_ORDER_EVENT,
+        EXCH_ASK_DEPTH_CLEAR_EVENT,
+        EXCH_BID_ADD_ORDER_EVENT,
+        EXCH_BID_DEPTH_CLEAR_EVENT,
+        EXCH_CANCEL_ORDER_EVENT,
+        EXCH_DEPTH_CLEAR_EVENT,
+        EXCH_EVENT,
+        EXCH_FILL_EVENT,
+        EXCH_MODIFY_ORDER_EVENT,
+        SELL_EVENT,
+    },
+};
+
+/// The Level3 exchange processor's state stored in a checkpoint.
+#[derive(Encode, Decode)]
+struct L3ExchangeState {
+    data_pos: usize,
+    row_num: usize,
+    // The encoded state of the queue model, which holds the orders.
+    queue: Vec<u8>,
+    orders_to: Vec<(Order, i64)>,
+    depth: Vec<L3Order>,
+    state_values: StateValues,
+    expiry: Expiry,
+    conditional_orders: HashMap<OrderId, Order>,
+    oco_expired: HashMap<OrderId, OrderId>,
+    last_price: f64,
+    mark_price: f64,
+}
+
+/// The exchange model for Level 3 Market-By-Order data, which is used through
+/// [`L3NoPartialFillExchange`] or [`L3PartialFillExchange`]. `PARTIAL_FILL` determines whether
+/// liquidity-taking orders and the market-feed fills are executed partially.
+///
+/// **Conditional Orders**
+///
+/// Stop and take-profit orders wait on the exchange side, outside the queues, until the last fill
+/// price of the market feed or the mark price reaches the trigger price. They are rejected if they
+/// would be triggered immediately upon receipt. Once triggered, they turn into market or limit
+/// orders and are processed as new orders. Until the mark price is updated by the funding rate
+/// data, the mid price is used as the mark price. Conditional orders cannot be modified.
+///
+/// Once either order of a one-cancels-other pair is executed or triggered, the other is expired.
+/// Canceling either order does not affect the other.
+///
+/// **Good-Till-Date and Reduce-Only Orders**
+///
+/// A [`TimeInForce::GTD`] order is expired at the first market data event at or after its expiry
+/// timestamp, and is rejected if it has already expired upon receipt. The quantity of a
+/// reduce-only order is reduced to the position when the order is accepted or executed, and the
+/// order is expired if it would increase the position, including when the position is closed or
+/// reversed while the order rests in the queue.
+pub struct L3Exchange<AT, LM, QM, MD, FM, const PARTIAL_FILL: bool>
+where
+    AT: AssetType,
+    LM: LatencyModel,
+    QM: L3QueueModel<MD>,
+    MD: L3MarketDepth,
+    FM: FeeModel,
+{
+    reader: Reader<Event>,
+    data: Data<Event>,
+    row_num: usize,
+    orders_to: OrderBus,
+    orders_from: OrderBus,
+
+    depth: MD,
+    state: State<AT, FM>,
+    order_latency: LM,
+    queue_model: QM,
+
+    tracer: Option<Tracer>,
+    asset_no: usize,
+
+    expiry: Expiry,
+
+    // key: order_id, value: conditional order waiting to be triggered
+    conditional_orders: HashMap<OrderId, Order>,
+    // The one-cancels-other orders to expire along with the order IDs of their counterparts,
+    // which have been executed or triggered.
+    oco_to_expire: Vec<(OrderId, OrderId)>,
+    // key: order_id of a one-cancels-other order expired before it is received, value: order_id
+    // of its counterpart
+    oco_expired: HashMap<OrderId, OrderId>,
+    last_price: f64,
+    mark_price: f64,
+    // (expiry timestamp, order_id) of the good-till-date orders, which may include the orders
+    // that are no longer open
+    gtd_orders: BTreeSet<(i64, OrderId)>,
+    // order_ids of the resting reduce-only orders, which may include the orders that are no longer
+    // open
+    reduce_only_orders: BTreeSet<OrderId>,
+    // Whether the position has changed since the reduce-only orders were last checked.
+    position_changed: bool,
+    margin: Option<Margin>,
+}
+
+/// The exchange model without partial fills for Level 3 Market-By-Order data.
+///
+/// Support order types: [OrdType::Limit](crate::types::OrdType::Limit)
+/// Support time-in-force: [`TimeInForce::GTC`], [`TimeInForce::GTX`], [`TimeInForce::GTD`]
+///
+/// **Conditions for Full Execution**
+///
+/// Buy order in the order book
+///
+/// - Your order price >= the best ask price
+/// - Your order price > sell trade price
+/// - Your order is at the front of the queue and your order price == sell trade price
+///
+/// Sell order in the order book
+///
+/// - Your order price <= the best bid price
+/// - Your order price < buy trade price
+/// - Your order is at the front of the queue && your order price == buy trade price
+///
+/// **Queue Allocation**
+///
+/// Orders in the queue are executed by the quantity that the queue model allocates to them.
+/// [`L3FIFOQueueModel`](crate::backtest::models::L3FIFOQueueModel) always allocates the full
+/// quantity, whereas pro-rata allocation by
+/// [`L3ProRataQueueModel`](crate::backtest::models::L3ProRataQueueModel) can partially fill an
+/// order, which then remains in the queue.
+///
+/// **Liquidity-Taking Order**
+///
+/// Regardless of the quantity at the best, liquidity-taking orders will be fully executed at the
+/// best. Be aware that this may cause unrealistic fill simulations if you attempt to execute a
+/// large quantity.
+///
+pub type L3NoPartialFillExchange<AT, LM, QM, MD, FM> = L3Exchange<AT, LM, QM, MD, FM, false>;
+
+/// The exchange model with partial fills for Level 3 Market-By-Order data.
+///
+/// * Support order types: [OrdType::Limit](crate::types::OrdType::Limit),
+///   [OrdType::Market](crate::types::OrdType::Market)
+/// * Support time-in-force: [`TimeInForce::GTC`], [`TimeInForce::FOK`], [`TimeInForce::IOC`],
+///   [`TimeInForce::GTX`], [`TimeInForce::GTD`]
+///
+/// **Conditions for Full Execution**
+///
+/// Buy order in the order book
+///
+/// - Your order price >= the best ask price
+/// - Your order price > sell trade price
+///
+/// Sell order in the order book
+///
+/// - Your order price <= the best bid price
+/// - Your order price < buy trade price
+///
+/// **Conditions for Partial Execution**
+///
+/// When a market-feed order is filled, the fill quantity is allocated by the queue model through
+/// [`L3QueueModel::partially_fill_market_feed_order`]. With
+/// [`L3FIFOQueueModel`](crate::backtest::models::L3FIFOQueueModel), it is allocated in time
+/// priority to the backtest orders placed before the filled market-feed order, so that your order
+/// is filled by (remaining) fill quantity rather than in its entirety. The backtest orders behind
+/// the filled market-feed order are filled once a market-feed order behind them is filled.
+///
+/// **Liquidity-Taking Order**
+///
+/// Liquidity-taking orders will be executed based on the quantity of the order book, even though
+/// the best price and quantity do not change due to your execution. Be aware that this may cause
+/// unrealistic fill simulations if you attempt to execute a large quantity.
+///
+pub type L3PartialFillExchange<AT, LM, QM, MD, FM> = L3Exchange<AT, LM, QM, MD, FM, true>;
+
+impl<AT, LM, QM, MD, FM, const PARTIAL_FILL: bool> L3Exchange<AT, LM, QM, MD, FM, PARTIAL_FILL>
+where
+    AT: AssetType,
+    LM: LatencyModel,
+    QM: L3QueueModel<MD>,
+    MD: L3MarketDepth,
+    FM: FeeModel,
+    BacktestError: From<<MD as L3MarketDepth>::Error>,
+{
+    /// Constructs an instance of the exchange model.
+    pub fn new(
+        reader: Reader<Event>,
+        depth: MD,
+        state: State<AT, FM>,
+        order_latency: LM,
+        queue_model: QM,
+        orders_to: OrderBus,
+        orders_from: OrderBus,
+    ) -> Self {
+        Self {
+            reader,
+            data: Data::empty(),
+            row_num: 0,
+            orders_to,
+            orders_from,
+            depth,
+            state,
+            order_latency,
+            queue_model,
+            tracer: None,
+            asset_no: 0,
+            expiry: Default::default(),
+            conditional_orders: HashMap::new(),
+            oco_to_expire: Vec::new(),
+            oco_expired: HashMap::new(),
+            last_price: f64::NAN,
+            mark_price: f64::NAN,
+            gtd_orders: BTreeSet::new(),
+            reduce_only_orders: BTreeSet::new(),
+            position_changed: false,
+            margin: None,
+        }
+    }
+
+    /// Sets the expiry of the contract. At the expiry, the open orders are expired, the position
+    /// is settled, and further orders are rejected.
+    pub fn set_expiry(&mut self, expiry: Expiry) {
+        self.expiry = expiry;
+    }
+
+    /// Sets the margin, based on which the orders are rejected and the position is liquidated. See
+    /// [`NoPartialFillExchange`](crate::backtest::proc::NoPartialFillExchange) for details.
+    pub fn set_margin(&mut self, margin: Margin) {
+        self.margin = Some(margin);
+    }
+
+    /// Expires the open orders, settles the position at the settlement price, and rejects further
+    /// orders from now on.
+    fn expire(&mut self, timestamp: i64) -> Result<(), BacktestError> {
+        let mid_price = (self.depth.best_bid() + self.depth.best_ask()) / 2.0;
+        let settlement_price = self.expiry.expire(mid_price);
+        self.state.apply_settlement(settlement_price);
+        self.close_open_orders(Status::Expired, timestamp)
+    }
+
+    /// Returns `true` if both the inventory and the margin can cover the new or modified order on
+    /// top of the other open orders.
+    fn is_covered(&self, order: &Order) -> bool {
+        let other_orders = || {
+            self.queue_model
+                .backtest_orders()
+                .into_iter()
+                .filter(|open_order| open_order.order_id != order.order_id)
+        };
+        if !self.state.has_inventory(order, other_orders()) {
+            return false;
+        }
+        match &self.margin {
+            Some(margin) => margin.is_sufficient(
+                &self.state,
+                mark_price(self.mark_price, &self.depth),
+                other_orders()
+                    .chain(self.conditional_orders.values())
+                    .chain([order]),
+            ),
+            None => true,
+        }
+    }
+
+    /// Liquidates the position at the mark price and cancels the open orders if the collateral
+    /// plus the equity falls below the maintenance margin.
+    fn check_margin(&mut self, timestamp: i64) -> Result<(), BacktestError> {
+        let mark_price = mark_price(self.mark_price, &self.depth);
+        if !self
+            .margin
+            .as_ref()
+            .is_some_and(|margin| margin.requires_liquidation(&self.state, mark_price))
+        {
+            return Ok(());
+        }
+        let order = liquidation_order(
+            self.state.values().position,
+            mark_price,
+            self.depth.tick_size(),
+            timestamp,
+        );
+        self.state.apply_liquidation(&order);
+        let local_recv_timestamp = timestamp + self.order_latency.response(timestamp, &order);
+        self.orders_to.append(order, local_recv_timestamp);
+        self.close_open_orders(Status::Canceled, timestamp)
+    }
+
+    /// Removes all the open orders from the queues, along with the conditional orders, and reports
+    /// them with the given status.
+    fn close_open_orders(&mut self, status: Status, timestamp: i64) -> Result<(), BacktestError> {
+        self.gtd_orders.clear();
+        let mut order_ids: Vec<_> = self
+            .queue_model
+            .backtest_orders()
+            .iter()
+            .map(|order| order.order_id)
+            .chain(self.conditional_orders.keys().copied())
+            .collect();
+        order_ids.sort();
+        for order_id in order_ids {
+            let mut order = match self.conditional_orders.remove(&order_id) {
+                Some(order) => order,
+                None => self
+                    .queue_model
+                    .cancel_backtest_order(order_id, &self.depth)?,
+            };
+            if status == Status::Expired {
+                self.expired(order, timestamp)?;
+            } else {
+                order.status = status;
+                order.exch_timestamp = timestamp;
+                let local_recv_timestamp =
+                    timestamp + self.order_latency.response(timestamp, &order);
+                self.orders_to.append(order, local_recv_timestamp);
+            }
+        }
+        Ok(())
+    }
+
+    fn process_recv_order_(
+        &mut self,
+        mut order: Order,
+        recv_timestamp: i64,
+    ) -> Result<(), BacktestError> {
+        if let Some(tracer) = &self.tracer {
+            tracer.order_request(self.asset_no, &order);
+        }
+        // Processes a new order.
+        if order.req == Status::New {
+            order.req = Status::None;
+            self.ack_new(order, recv_timestamp)?;
+        }
+        // Processes a cancel order.
+        else if order.req == Status::Canceled {
+            order.req = Status::None;
+            self.ack_cancel(order, recv_timestamp)?;
+        }
+        // Processes a modify order.
+        else if order.req == Status::Replaced {
+            order.req = Status::None;
+            self.ack_modify(order, recv_timestamp)?;
+        } else {
+            return Err(BacktestError::InvalidOrderRequest);
+        }
+        self.expire_oco_orders(recv_timestamp)?;
+        self.expire_reduce_only_orders(recv_timestamp)?;
+        Ok(())
+    }
+
+    fn expired(&mut self, mut order: Order, timestamp: i64) -> Result<(), BacktestError> {
+        order.exec_qty = 0.0;
+        order.leaves_qty = 0.0;
+        order.status = Status::Expired;
+        order.exch_timestamp = timestamp;
+        let local_recv_timestamp =
+            order.exch_timestamp + self.order_latency.response(timestamp, &order);
+
+        self.orders_to.append(order, local_recv_timestamp);
+        Ok(())
+    }
+
+    /// Removes the resting order from the queue and expires it.
+    fn expire_resting_order(
+        &mut self,
+        order_id: OrderId,
+        timestamp: i64,
+    ) -> Result<(), BacktestError> {
+        let order = self
+            .queue_model
+            .cancel_backtest_order(order_id, &self.depth)?;
+        self.expired(order, timestamp)
+    }
+
+    /// Returns the price against which the trigger price of a conditional order is evaluated.
+    fn reference_price(&self, trigger_price_type: TriggerPriceType) -> f64 {
+        match trigger_price_type {
+            TriggerPriceType::MarkPrice => mark_price(self.mark_price, &self.depth),
+            _ => self.last_price,
+        }
+    }
+
+    /// Accepts the conditional order unless it would be triggered immediately.
+    fn ack_conditional(&mut self, mut order: Order, timestamp: i64) {
+        if order.is_triggered_at(self.reference_price(order.trigger_price_type)) {
+            order.req = Status::Rejected;
+        } else {
+            order.status = Status::New;
+        }
+        order.exch_timestamp = timestamp;
+        let local_recv_timestamp = timestamp + self.order_latency.response(timestamp, &order);
+        self.orders_to.append(order.clone(), local_recv_timestamp);
+        if order.status == Status::New {
+            self.conditional_orders.insert(order.order_id, order);
+        }
+    }
+
+    /// Triggers the conditional orders whose trigger price is reached, processing them as new
+    /// market or limit orders.
+    fn trigger_orders(&mut self, timestamp: i64) -> Result<(), BacktestError> {
+        if self.conditional_orders.is_empty() {
+            return Ok(());
+        }
+        let mut triggered: Vec<_> = self
+            .conditional_orders
+            .values()
+            .filter(|order| order.is_triggered_at(self.reference_price(order.trigger_price_type)))
+            .map(|order| order.order_id)
+            .collect();
+        triggered.sort();
+        for order_id in triggered {
+            let mut order = self.conditional_orders.remove(&order_id).unwrap();
+            order.order_type = order.order_type.triggered();
+            if order.oco {
+                self.oco_to_expire
+                    .push((order.oco_order_id, order.order_id));
+            }
+            self.ack_new(order, timestamp)?;
+        }
+        Ok(())
+    }
+
+    /// Expires the other orders of the one-cancels-other pairs whose orders have been executed or
+    /// triggered.
+    fn expire_oco_orders(&mut self, timestamp: i64) -> Result<(), BacktestError> {
+        if self.oco_to_expire.is_empty() {
+            return Ok(());
+        }
+        for (order_id, counterpart_id) in mem::take(&mut self.oco_to_expire) {
+            let is_counterpart = |order: &Order| order.oco && order.oco_order_id == counterpart_id;
+            let resting = self
+                .queue_model
+                .backtest_orders()
+                .into_iter()
+                .find(|order| order.order_id == order_id)
+                .map(is_counterpart);
+            if self
+                .conditional_orders
+                .get(&order_id)
+                .is_some_and(is_counterpart)
+            {
+                let order = self.conditional_orders.remove(&order_id).unwrap();
+                self.expired(order, timestamp)?;
+            } else if resting == Some(true) {
+                self.expire_resting_order(order_id, timestamp)?;
+            } else if resting.is_none() && !self.conditional_orders.contains_key(&order_id) {
+                // The order may not be received yet.
+                self.oco_expired.insert(order_id, counterpart_id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Expires the good-till-date orders whose expiry timestamp has been reached.
+    fn expire_gtd_orders(&mut self, timestamp: i64) -> Result<(), BacktestError> {
+        while let Some(&(expire_timestamp, order_id)) = self.gtd_orders.first() {
+            if expire_timestamp > timestamp {
+                break;
+            }
+            self.gtd_orders.pop_first();
+            let is_due = |order: &Order| {
+                order.time_in_force == TimeInForce::GTD
+                    && order.expire_timestamp == expire_timestamp
+            };
+            if self.conditional_orders.get(&order_id).is_some_and(is_due) {
+                let order = self.conditional_orders.remove(&order_id).unwrap();
+                self.expired(order, timestamp)?;
+            } else if self
+                .queue_model
+                .backtest_orders()
+                .into_iter()
+                .any(|order| order.order_id == order_id && is_due(order))
+            {
+                self.expire_resting_order(order_id, timestamp)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reduces the quantity of the reduce-only order so that it does not exceed the position.
+    /// Returns `false` if the order would increase the position.
+    fn clip_reduce_only(&self, order: &mut Order) -> bool {
+        let reducible_qty = self.state.reducible_qty(order.side);
+        if (reducible_qty / self.depth.lot_size()).round() <= 0.0 {
+            return false;
+        }
+        if order.leaves_qty > reducible_qty {
+            order.qty -= order.leaves_qty - reducible_qty;
+            order.leaves_qty = reducible_qty;
+        }
+        true
+    }
+
+    /// Expires the resting reduce-only orders that would increase the position since the position
+    /// has been closed or reversed. The orders are checked only if the position has changed since
+    /// the last check.
+    fn expire_reduce_only_orders(&mut self, timestamp: i64) -> Result<(), BacktestError> {
+        if !mem::take(&mut self.position_changed) || self.reduce_only_orders.is_empty() {
+            return Ok(());
+        }
+        let lot_size = self.depth.lot_size();
+        let orders = self.queue_model.backtest_orders();
+        let mut expired = Vec::new();
+        self.reduce_only_orders.retain(|order_id| {
+            match orders.iter().find(|order| order.order_id == *order_id) {
+                Some(order) if order.reduce_only => {
+                    if (self.state.reducible_qty(order.side) / lot_size).round() <= 0.0 {
+                        expired.push(*order_id);
+                        false
+                    } else {
+                        true
+                    }
+                }
+                _ => false,
+            }
+        });
+        for order_id in expired {
+            self.expire_resting_order(order_id, timestamp)?;
+        }
+        Ok(())
+    }
+
+    /// Applies the self-trade prevention of the incoming order against the resting backtest orders
+    /// on the other side that it would match. Returns `false` if the incoming order is expired.
+    fn prevent_self_trade(
+        &mut self,
+        order: &mut Order,
+        timestamp: i64,
+    ) -> Result<bool, BacktestError> {
+        let lot_size = self.depth.lot_size();
+        let (actions, expired) = prevent_self_trade(
+            order,
+            self.queue_model.backtest_orders().into_iter(),
+            lot_size,
+        );
+        for action in actions {
+            let (order_id, qty) = match action {
+                SelfTradeAction::Expire(order_id) => (order_id, None),
+                SelfTradeAction::Decrement(order_id, qty) => (order_id, Some(qty)),
+            };
+            let mut resting = self
+                .queue_model
+                .backtest_orders()
+                .into_iter()
+                .find(|resting| resting.order_id == order_id)
+                .unwrap()
+                .clone();
+            if let Some(qty) = qty {
+                resting.qty -= qty;
+                resting.leaves_qty -= qty;
+            }
+            if qty.is_some() && (resting.leaves_qty / lot_size).round() > 0.0 {
+                // The decremented order retains its queue position.
+                self.queue_model
+                    .modify_backtest_order(order_id, resting.clone(), &self.depth)?;
+
+                resting.exec_qty = 0.0;
+                resting.status = if ((resting.qty - resting.leaves_qty) / lot_size).round() > 0.0 {
+                    Status::PartiallyFilled
+                } else {
+                    Status::New
+                };
+                resting.exch_timestamp = timestamp;
+                let local_recv_timestamp =
+                    timestamp + self.order_latency.response(timestamp, &resting);
+                self.orders_to.append(resting, local_recv_timestamp);
+            } else {
+                self.queue_model
+                    .cancel_backtest_order(order_id, &self.depth)?;
+                self.expired(resting, timestamp)?;
+            }
+        }
+        if expired {
+            self.expired(order.clone(), timestamp)?;
+        }
+        Ok(!expired)
+    }
+
+    fn fill(
+        &mut self,
+        order: &mut Order,
+        timestamp: i64,
+        maker: bool,
+        exec_price_tick: i64,
+        exec_qty: f64,
+    ) -> Result<(), BacktestError> {
+        if order.status == Status::Expired
+            || order.status == Status::Canceled
+            || order.status == Status::Filled
+        {
+            return Err(BacktestError::InvalidOrderStatus);
+        }
+
+        if order.reduce_only && exec_qty > self.state.reducible_qty(order.side) {
+            let in_queue = self.queue_model.contains_backtest_order(order.order_id);
+            if !self.clip_reduce_only(order) {
+                // The position has been closed or reversed by the preceding fills.
+                if in_queue {
+                    return self.expire_resting_order(order.order_id, timestamp);
+                }
+                return self.expired(order.clone(), timestamp);
+            }
+            // The order is filled by the clipped quantity, so the rest no longer remains in the
+            // queue.
+            if in_queue {
+                self.queue_model
+                    .cancel_backtest_order(order.order_id, &self.depth)?;
+            }
+        }
+
+        order.maker = maker;
+        if maker {
+            order.exec_price_tick = order.price_tick;
+        } else {
+            order.exec_price_tick = exec_price_tick;
+        }
+
+        order.exec_qty = exec_qty.min(order.leaves_qty);
+        // The counterpart of a one-cancels-other order is expired upon the first execution.
+        if order.oco && ((order.qty - order.leaves_qty) / self.depth.lot_size()).round() <= 0.0 {
+            self.oco_to_expire
+                .push((order.oco_order_id, order.order_id));
+        }
+        order.leaves_qty -= order.exec_qty;
+        if (order.leaves_qty / self.depth.lot_size()).round() > 0f64 {
+            order.status = Status::PartiallyFilled;
+        } else {
+            order.status = Status::Filled;
+        }
+        order.exch_timestamp = timestamp;
+        let local_recv_timestamp =
+            order.exch_timestamp + self.order_latency.response(timestamp, order);
+
+        self.state.apply_fill(order);
+        self.position_changed = true;
+        if let Some(tracer) = &self.tracer {
+            tracer.fill(self.asset_no, order);
+        }
+        self.orders_to.append(order.clone(), local_recv_timestamp);
+        Ok(())
+    }
+
+    /// Sweeps the price levels on the other side of the book from the best, up to and including
+    /// `limit_tick` if given, for up to `qty`. Returns the quantity taken at each price level.
+    fn sweep(&self, side: Side, limit_tick: Option<i64>, qty: f64) -> Vec<(i64, f64)> {
+        let book_side = if side == Side::Buy {
+            Side::Sell
+        } else {
+            Side::Buy
+        };
+        let lot_size = self.depth.lot_size();
+        let mut levels = Vec::new();
+        let mut leaves_qty = qty;
+        for t in l3_depth_levels(&self.depth, book_side, limit_tick) {
+            if (leaves_qty / lot_size).round() <= 0.0 {
+                break;
+            }
+            let available = match side {
+                Side::Buy => self.depth.ask_qty_at_tick(t),
+                _ => self.depth.bid_qty_at_tick(t),
+            };
+            if available > 0.0 {
+                let exec_qty = available.min(leaves_qty);
+                levels.push((t, exec_qty));
+                leaves_qty -= exec_qty;
+            }
+        }
+        levels
+    }
+
+    /// Executes the liquidity-taking order across the price levels, making a fill at each price
+    /// level.
+    fn fill_levels(
+        &mut self,
+        order: &mut Order,
+        timestamp: i64,
+        levels: &[(i64, f64)],
+    ) -> Result<(), BacktestError> {
+        for (t, qty) in levels {
+            self.fill(order, timestamp, false, *t, *qty)?;
+        }
+        Ok(())
+    }
+
+    /// Executes the liquidity-taking order up to the limit price, or across the book if
+    /// `limit_price_tick` is `None`. See [`L3NoPartialFillExchange`] and [`L3PartialFillExchange`]
+    /// for how the order is executed.
+    fn take_market(
+        &mut self,
+        order: &mut Order,
+        limit_price_tick: Option<i64>,
+        timestamp: i64,
+    ) -> Result<(), BacktestError> {
+        if !PARTIAL_FILL {
+            let best_tick = if order.side == Side::Buy {
+                self.depth.best_ask_tick()
+            } else {
+                self.depth.best_bid_tick()
+            };
+            let exec_qty = order.leaves_qty;
+            return self.fill(order, timestamp, false, best_tick, exec_qty);
+        }
+
+        let lot_size = self.depth.lot_size();
+        let levels = self.sweep(order.side, limit_price_tick, order.leaves_qty);
+        let cum_qty: f64 = levels.iter().map(|(_, qty)| qty).sum();
+        let leaves_qty = order.leaves_qty - cum_qty;
+
+        match order.time_in_force {
+            TimeInForce::FOK if (leaves_qty / lot_size).round() > 0.0 => {
+                // The order must be executed immediately in its entirety; otherwise, the entire
+                // order will be cancelled.
+                self.expired(order.clone(), timestamp)
+            }
+            TimeInForce::GTC | TimeInForce::GTD if limit_price_tick.is_some() => {
+                // The order cannot remain in the opposite side of the book, as it cannot affect
+                // the market depth during backtesting based on market-data replay. So, even though
+                // it simulates partial fill, if the order size is not small enough, it introduces
+                // unreality.
+                let mut levels = levels;
+                let price_tick = limit_price_tick.unwrap();
+                if (leaves_qty / lot_size).round() > 0.0 {
+                    match levels.last_mut() {
+                        Some((t, qty)) if *t == price_tick => *qty += leaves_qty,
+                        _ => levels.push((price_tick, leaves_qty)),
+                    }
+                }
+                self.fill_levels(order, timestamp, &levels)
+            }
+            _ => {
+                // The unfilled quantity of IOC and market orders is expired.
+                self.fill_levels(order, timestamp, &levels)?;
+                if order.status != Status::Filled {
+                    self.expired(order.clone(), timestamp)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn fill_ask_orders_by_crossing(
+        &mut self,
+        prev_best_tick: i64,
+        new_best_tick: i64,
+        timestamp: i64,
+    ) -> Result<(), BacktestError> {
+        let filled = self
+            .queue_model
+            .on_best_bid_update(prev_best_tick, new_best_tick)?;
+        for mut order in filled {
+            let price_tick = order.price_tick;
+            let exec_qty = order.exec_qty;
+            self.fill(&mut order, timestamp, true, price_tick, exec_qty)?;
+        }
+        Ok(())
+    }
+
+    fn fill_bid_orders_by_crossing(
+        &mut self,
+        prev_best_tick: i64,
+        new_best_tick: i64,
+        timestamp: i64,
+    ) -> Result<(), BacktestError> {
+        let filled = self
+            .queue_model
+            .on_best_ask_update(prev_best_tick, new_best_tick)?;
+        for mut order in filled {
+            let price_tick = order.price_tick;
+            let exec_qty = order.exec_qty;
+            self.fill(&mut order, timestamp, true, price_tick, exec_qty)?;
+        }
+        Ok(())
+    }
+
+    fn ack_new(&mut self, mut order: Order, timestamp: i64) -> Result<(), BacktestError> {
+        if self.queue_model.contains_backtest_order(order.order_id)
+            || self.conditional_orders.contains_key(&order.order_id)
+        {
+            return Err(BacktestError::OrderIdExist);
+        }
+
+        if self.expiry.is_expired()
+            || (order.time_in_force == TimeInForce::GTD && order.expire_timestamp <= timestamp)
+            || !self.is_covered(&order)
+        {
+            // Rejects the order as the contract or the good-till-date order has expired, or due to
+            // insufficient inventory or margin.
+            if order.status == Status::New {
+                // The triggered conditional order has already been accepted, so it is expired
+                // instead.
+                return self.expired(order, timestamp);
+            }
+            order.req = Status::Rejected;
+            order.exch_timestamp = timestamp;
+            let local_recv_timestamp = timestamp + self.order_latency.response(timestamp, &order);
+            self.orders_to.append(order, local_recv_timestamp);
+            return Ok(());
+        }
+
+        if order.oco && self.oco_expired.get(&order.order_id) == Some(&order.oco_order_id) {
+            // The counterpart of this one-cancels-other order has already been executed or
+            // triggered.
+            self.oco_expired.remove(&order.order_id);
+            return self.expired(order, timestamp);
+        }
+
+        if order.time_in_force == TimeInForce::GTD {
+            self.gtd_orders
+                .insert((order.expire_timestamp, order.order_id));
+        }
+
+        if order.order_type.is_conditional() {
+            self.ack_conditional(order, timestamp);
+            return Ok(());
+        }
+
+        if order.reduce_only {
+            if !self.clip_reduce_only(&mut order) {
+                // The reduce-only order would increase the position.
+                return self.expired(order, timestamp);
+            }
+            self.reduce_only_orders.insert(order.order_id);
+        }
+
+        if !self.prevent_self_trade(&mut order, timestamp)? {
+            return Ok(());
+        }
+
+        match order.order_type {
+            OrdType::Limit => {
+                let crossed = if order.side == Side::Buy {
+                    // Checks if the buy order price is greater than or equal to the current best
+                    // ask.
+                    order.price_tick >= self.depth.best_ask_tick()
+                } else {
+                    // Checks if the sell order price is less than or equal to the current best
+                    // bid.
+                    order.price_tick <= self.depth.best_bid_tick()
+                };
+                if crossed {
+                    match order.time_in_force {
+                        TimeInForce::GTX => {
+                            order.status = Status::Expired;
+
+                            order.exch_timestamp = timestamp;
+                            let local_recv_timestamp =
+                                timestamp + self.order_latency.response(timestamp, &order);
+                            self.orders_to.append(order.clone(), local_recv_timestamp);
+                            Ok(())
+                        }
+                        TimeInForce::GTC
+                        | TimeInForce::GTD
+                        | TimeInForce::FOK
+                        | TimeInForce::IOC => {
+                            // Takes the market.
+                            let price_tick = order.price_tick;
+                            self.take_market(&mut order, Some(price_tick), timestamp)
+                        }
+                        TimeInForce::Unsupported => Err(BacktestError::InvalidOrderRequest),
+                    }
+                } else {
+                    match order.time_in_force {
+                        TimeInForce::GTC | TimeInForce::GTD | TimeInForce::GTX => {
+                            // Initializes the order's queue position.
+                            order.status = Status::New;
+                            order.exch_timestamp = timestamp;
+
+                            self.queue_model
+                                .add_backtest_order(order.clone(), &self.depth)?;
+
+                            let local_recv_timestamp =
+                                timestamp + self.order_latency.response(timestamp, &order);
+                            self.orders_to.append(order, local_recv_timestamp);
+                            Ok(())
+                        }
+                        TimeInForce::FOK | TimeInForce::IOC => {
+                            order.status = Status::Expired;
+
+                            order.exch_timestamp = timestamp;
+                            let local_recv_timestamp =
+                                timestamp + self.order_latency.response(timestamp, &order);
+                            self.orders_to.append(order.clone(), local_recv_timestamp);
+                            Ok(())
+                        }
+                        TimeInForce::Unsupported => Err(BacktestError::InvalidOrderRequest),
+                    }
+                }
+            }
+            OrdType::Market => {
+                // Takes the market.
+                self.take_market(&mut order, None, timestamp)
+            }
+            _ => Err(BacktestError::InvalidOrderRequest),
+        }
+    }
+
+    fn ack_cancel(&mut self, mut order: Order, timestamp: i64) -> Result<(), BacktestError> {
+        if let Some(mut exch_order) = self.conditional_orders.remove(&order.order_id) {
+            exch_order.status = Status::Canceled;
+            exch_order.exch_timestamp = timestamp;
+            let local_recv_timestamp =
+                timestamp + self.order_latency.response(timestamp, &exch_order);
+            self.orders_to.append(exch_order, local_recv_timestamp);
+            return Ok(());
+        }
+
+        match self
+            .queue_model
+            .cancel_backtest_order(order.order_id, &self.depth)
+        {
+            Ok(mut exch_order) => {
+                // Makes the response.
+                exch_order.status = Status::Canceled;
+                exch_order.exch_timestamp = timestamp;
+                let local_recv_timestamp =
+                    timestamp + self.order_latency.response(timestamp, &exch_order);
+                self.orders_to
+                    .append(exch_order.clone(), local_recv_timestamp);
+                Ok(())
+            }
+            Err(BacktestError::OrderNotFound) => {
+                order.req = Status::Rejected;
+                order.exch_timestamp = timestamp;
+                let local_recv_timestamp =
+                    timestamp + self.order_latency.response(timestamp, &order);
+                self.orders_to.append(order, local_recv_timestamp);
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn ack_modify(&mut self, mut order: Order, timestamp: i64) -> Result<(), BacktestError> {
+        // The order can be already deleted due to fill or expiration. Conditional orders cannot be
+        // modified.
+        if !self.queue_model.contains_backtest_order(order.order_id) {
+            order.req = Status::Rejected;
+            order.exch_timestamp = timestamp;
+            let local_recv_timestamp = timestamp + self.order_latency.response(timestamp, &order);
+            self.orders_to.append(order, local_recv_timestamp);
+            return Ok(());
+        }
+
+        // The modified quantity should be greater than the quantity already executed, and the
+        // inventory and the margin should cover the modified order as they would a new order.
+        let filled_qty = self
+            .queue_model
+            .backtest_orders()
+            .into_iter()
+            .find(|exch_order| exch_order.order_id == order.order_id)
+            .map(|exch_order| exch_order.qty - exch_order.leaves_qty)
+            .unwrap_or(0.0);
+        let mut modified = order.clone();
+        modified.leaves_qty = order.qty - filled_qty;
+        if ((order.qty - filled_qty) / self.depth.lot_size()).round() <= 0.0
+            || !self.is_covered(&modified)
+        {
+            order.req = Status::Rejected;
+            order.exch_timestamp = timestamp;
+            let local_recv_timestamp = timestamp + self.order_latency.response(timestamp, &order);
+            self.orders_to.append(order, local_recv_timestamp);
+            return Ok(());
+        }
+        order.leaves_qty = order.qty - filled_qty;
+        order.exch_timestamp = timestamp;
+
+        let crossed = if order.side == Side::Buy {
+            // Checks if the buy order price is greater than or equal to the current best ask.
+            order.price_tick >= self.depth.best_ask_tick()
+        } else {
+            // Checks if the sell order price is less than or equal to the current best bid.
+            order.price_tick <= self.depth.best_bid_tick()
+        };
+
+        if crossed {
+            self.queue_model
+                .cancel_backtest_order(order.order_id, &self.depth)?;
+
+            if order.time_in_force == TimeInForce::GTX {
+                order.status = Status::Expired;
+
+                let local_recv_timestamp =
+                    timestamp + self.order_latency.response(timestamp, &order);
+                self.orders_to.append(order, local_recv_timestamp);
+                Ok(())
+            } else if !self.prevent_self_trade(&mut order, timestamp)? {
+                Ok(())
+            } else {
+                // Takes the market.
+                let price_tick = order.price_tick;
+                self.take_market(&mut order, Some(price_tick), timestamp)
+            }
+        } else {
+            // The queue model determines whether the order retains its queue priority.
+            order.exec_qty = 0.0;
+            order.status = if filled_qty > 0.0 {
+                Status::PartiallyFilled
+            } else {
+                Status::New
+            };
+            self.queue_model
+                .modify_backtest_order(order.order_id, order.clone(), &self.depth)?;
+
+            let local_recv_timestamp = timestamp + self.order_latency.response(timestamp, &order);
+            self.orders_to.append(order, local_recv_timestamp);
+            Ok(())
+        }
+    }
+}
+
+impl<AT, LM, QM, MD, FM, const PARTIAL_FILL: bool> Processor
+    for L3Exchange<AT, LM, QM, MD, FM, PARTIAL_FILL>
+where
+    AT: AssetType,
+    LM: LatencyModel,
+    QM: L3QueueModel<MD>,
+    MD: L3MarketDepth,
+    FM: FeeModel,
+    BacktestError: From<<MD as L3MarketDepth>::Error>,
+{
+    fn initialize_data(&mut self) -> Result<i64, BacktestError> {
+        self.data = self.reader.next_data()?;
+        for rn in 0..self.data.len() {
+            if self.data[rn].is(EXCH_EVENT) {
+                self.row_num = rn;
+                return Ok(self.expiry.next_timestamp(self.data[rn].exch_ts));
+            }
+        }
+        Err(BacktestError::EndOfData)
+    }
+
+    fn process_data(&mut self) -> Result<(i64, i64), BacktestError> {
+        let row_num = self.row_num;
+        if self.expiry.is_due(self.data[row_num].exch_ts) {
+            self.expire(self.expiry.timestamp())?;
+            return Ok((self.data[row_num].exch_ts, i64::MAX));
+        }
+        self.expire_gtd_orders(self.data[row_num].exch_ts)?;
+        if self.data[row_num].is(EXCH_BID_DEPTH_CLEAR_EVENT) {
+            self.depth.clear_orders(Side::Buy);
+            let expired = self.queue_model.clear_orders(Side::Buy);
+            for order in expired {
+                self.expired(order, self.data[row_num].exch_ts)?;
+            }
+        } else if self.data[row_num].is(EXCH_ASK_DEPTH_CLEAR_EVENT) {
+            self.depth.clear_orders(Side::Sell);
+            let expired = self.queue_model.clear_orders(Side::Sell);
+            for order in expired {
+                self.expired(order, self.data[row_num].exch_ts)?;
+            }
+        } else if self.data[row_num].is(EXCH_DEPTH_CLEAR_EVENT) {
+            self.depth.clear_orders(Side::None);
+            let expired = self.queue_model.clear_orders(Side::None);
+            for order in expired {
+                self.expired(order, self.data[row_num].exch_ts)?;
+            }
+        } else if self.data[row_num].is(EXCH_BID_ADD_ORDER_EVENT) {
+            let (prev_best_bid_tick, best_bid_tick) = self.depth.add_buy_order(
+                self.data[row_num].order_id,
+                self.data[row_num].px,
+                self.data[row_num].qty,
+                self.data[row_num].exch_ts,
+            )?;
+            self.queue_model
+                .add_market_feed_order(&self.data[row_num], &self.depth)?;
+            if best_bid_tick > prev_best_bid_tick {
+                self.fill_ask_orders_by_crossing(
+                    prev_best_bid_tick,
+                    best_bid_tick,
+                    self.data[row_num].exch_ts,
+                )?;
+            }
+        } else if self.data[row_num].is(EXCH_ASK_ADD_ORDER_EVENT) {
+            let (prev_best_ask_tick, best_ask_tick) = self.depth.add_sell_order(
+                self.data[row_num].order_id,
+                self.data[row_num].px,
+                self.data[row_num].qty,
+                self.data[row_num].exch_ts,
+            )?;
+            self.queue_model
+                .add_market_feed_order(&self.data[row_num], &self.depth)?;
+            if best_ask_tick < prev_best_ask_tick {
+                self.fill_bid_orders_by_crossing(
+                    prev_best_ask_tick,
+                    best_ask_tick,
+                    self.data[row_num].exch_ts,
+                )?;
+            }
+        } else if self.data[row_num].is(EXCH_MODIFY_ORDER_EVENT) {
+            let (side, prev_best_tick, best_tick) = self.depth.modify_order(
+                self.data[row_num].order_id,
+                self.data[row_num].px,
+                self.data[row_num].qty,
+                self.data[row_num].exch_ts,
+            )?;
+            self.queue_model.modify_market_feed_order(
+                self.data[row_num].order_id,
+                &self.data[row_num],
+                &self.depth,
+            )?;
+            if side == Side::Buy {
+                if best_tick > prev_best_tick {
+                    self.fill_ask_orders_by_crossing(
+                        prev_best_tick,
+                        best_tick,
+                        self.data[row_num].exch_ts,
+                    )?;
+                }
+            } else if best_tick < prev_best_tick {
+                self.fill_bid_orders_by_crossing(
+                    prev_best_tick,
+                    best_tick,
+                    self.data[row_num].exch_ts,
+                )?;
+            }
+        } else if self.data[row_num].is(EXCH_CANCEL_ORDER_EVENT) {
+            let order_id = self.data[row_num].order_id;
+            self.depth
+                .delete_order(order_id, self.data[row_num].exch_ts)?;
+            self.queue_model
+                .cancel_market_feed_order(self.data[row_num].order_id, &self.depth)?;
+        } else if self.data[row_num].is(EXCH_FILL_EVENT) {
+            // Resolves the side of the filled order from the market depth if the event doesn't
+            // provide it.
+            let mut event = self.data[row_num].clone();
+            if !event.is(BUY_EVENT) && !event.is(SELL_EVENT) {
+                let side = self
+                    .depth
+                    .orders()
+                    .get(&event.order_id)
+                    .map(|order| order.side)
+                    .ok_or(BacktestError::OrderNotFound)?;
+                event.ev |= if side == Side::Buy {
+                    BUY_EVENT
+                } else {
+                    SELL_EVENT
+                };
+            }
+            let filled = if PARTIAL_FILL {
+                self.queue_model.partially_fill_market_feed_order::<false>(
+                    event.order_id,
+                    &event,
+                    &self.depth,
+                )?
+            } else {
+                self.queue_model.fill_market_feed_order::<false>(
+                    event.order_id,
+                    &event,
+                    &self.depth,
+                )?
+            };
+            for mut order in filled {
+                let price_tick = order.price_tick;
+                let exec_qty = order.exec_qty;
+                self.fill(&mut order, event.exch_ts, true, price_tick, exec_qty)?;
+            }
+        }
+        if self.data[row_num].is(EXCH_FILL_EVENT) {
+            self.last_price = self.data[row_num].px;
+        }
+        self.trigger_orders(self.data[row_num].exch_ts)?;
+        self.expire_oco_orders(self.data[row_num].exch_ts)?;
+        self.expire_reduce_only_orders(self.data[row_num].exch_ts)?;
+        self.check_margin(self.data[row_num].exch_ts)?;
+
+        // Checks
+        let mut next_ts = 0;
+        for rn in (self.row_num + 1)..self.data.len() {
+            if self.data[rn].is(EXCH_EVENT) {
+                self.row_num = rn;
+                next_ts = self.data[rn].exch_ts;
+                break;
+            }
+        }
+
+        if next_ts <= 0 {
+            let next_data = self.reader.next_data()?;
+            let next_row = &next_data[0];
+            next_ts = next_row.exch_ts;
+            let data = mem::replace(&mut self.data, next_data);
+            self.reader.release(data);
+            self.row_num = 0;
+        }
+        Ok((self.expiry.next_timestamp(next_ts), i64::MAX))
+    }
+
+    fn process_recv_order(
+        &mut self,
+        timestamp: i64,
+        _wait_resp_order_id: Option<OrderId>,
+    ) -> Result<bool, BacktestError> {
+        // Processes the order part.
+        while !self.orders_from.is_empty() {
+            let recv_timestamp = self.orders_from.earliest_timestamp().unwrap();
+            if timestamp == recv_timestamp {
+                let (order, _) = self.orders_from.pop_front().unwrap();
+                self.process_recv_order_(order, recv_timestamp)?;
+            } else {
+                assert!(recv_timestamp > timestamp);
+                break;
+            }
+        }
+        self.check_margin(timestamp)?;
+        Ok(false)
+    }
+
+    fn earliest_recv_order_timestamp(&self) -> i64 {
+        self.orders_from.earliest_timestamp().unwrap_or(i64::MAX)
+    }
+
+    fn earliest_send_order_timestamp(&self) -> i64 {
+        self.orders_to.earliest_timestamp().unwrap_or(i64::MAX)
+    }
+
+    fn checkpoint(&self) -> Result<Vec<u8>, BacktestError> {
+        checkpoint::encode(&L3ExchangeState {
+            data_pos: self.reader.position(),
+            row_num: self.row_num,
+            queue: self.queue_model.checkpoint()?,
+            orders_to: self.orders_to.to_vec(),
+            depth: l3_depth_snapshot(&self.depth),
+            state_values: self.state.values().clone(),
+            expiry: self.expiry,
+            conditional_orders: self.conditional_orders.clone(),
+            oco_expired: self.oco_expired.clone(),
+            last_price: self.last_price,
+            mark_price: self.mark_price,
+        })
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<(), BacktestError> {
+        let state: L3ExchangeState = checkpoint::decode(state)?;
+        restore_data(&mut self.reader, &mut self.data, state.data_pos)?;
+        self.row_num = state.row_num;
+        self.queue_model.restore(&state.queue)?;
+        restore_order_bus(&mut self.orders_to, state.orders_to);
+        restore_l3_depth(&mut self.depth, &state.depth)?;
+        self.state.state_values = state.state_values;
+        self.expiry = state.expiry;
+        self.conditional_orders = state.conditional_orders;
+        self.oco_expired = state.oco_expired;
+        self.last_price = state.last_price;
+        self.mark_price = state.mark_price;
+        let orders = self.queue_model.backtest_orders();
+        self.gtd_orders = orders
+            .iter()
+            .copied()
+            .chain(self.conditional_orders.values())
+            .filter(|order| order.time_in_force == TimeInForce::GTD)
+            .map(|order| (order.expire_timestamp, order.order_id))
+            .collect();
+        self.reduce_only_orders = orders
+            .iter()
+            .filter(|order| order.reduce_only)
+            .map(|order| order.order_id)
+            .collect();
+        self.position_changed = true;
+        Ok(())
+    }
+
+    fn on_mark_price(&mut self, mark_price: f64, timestamp: i64) -> Result<(), BacktestError> {
+        self.mark_price = mark_price;
+        self.trigger_orders(timestamp)?;
+        self.expire_oco_orders(timestamp)?;
+        self.expire_reduce_only_orders(timestamp)?;
+        self.check_margin(timestamp)
+    }
+
+    fn on_funding(
+        &mut self,
+        mark_price: f64,
+        funding_rate: f64,
+        timestamp: i64,
+    ) -> Result<(), BacktestError> {
+        self.state.apply_funding(mark_price, funding_rate);
+        self.check_margin(timestamp)
+    }
+
+    fn set_tracer(&mut self, tracer: Tracer, asset_no: usize) {
+        self.tracer = Some(tracer);
+        self.asset_no = asset_no;
+    }
+}
@@ -3,26 +3,40 @@ use std::{
     mem,
 };
 
+use bincode::{Decode, Encode};
+
 use crate::{
     backtest::{
         assettype::AssetType,
+        checkpoint,
         data::{Data, Reader},
+        expiry::Expiry,
         models::{FeeModel, LatencyModel},
         order::OrderBus,
-        proc::{LocalProcessor, Processor},
-        state::State,
+        proc::{
+            l3_depth_snapshot,
+            mark_price,
+            restore_data,
+            restore_l3_depth,
+            restore_order_bus,
+            update_local_margin,
+            LocalProcessor,
+            Processor,
+            LIQUIDATION_ORDER_ID,
+        },
+        state::{Margin, State},
+        trace::Tracer,
         BacktestError,
     },
-    depth::L3MarketDepth,
+    depth::{qty_ahead, L3MarketDepth, L3Order},
     types::{
         Event,
-        OrdType,
         Order,
         OrderId,
+        OrderRequest,
         Side,
         StateValues,
         Status,
-        TimeInForce,
         LOCAL_ASK_ADD_ORDER_EVENT,
         LOCAL_ASK_DEPTH_CLEAR_EVENT,
         LOCAL_BID_ADD_ORDER_EVENT,
@@ -35,7 +49,28 @@ use crate::{
     },
 };
 
+/// The Level3 local processor's state stored in a checkpoint.
+#[derive(Encode, Decode)]
+struct L3LocalState {
+    data_pos: usize,
+    row_num: usize,
+    orders: HashMap<OrderId, Order>,
+    orders_to: Vec<(Order, i64)>,
+    depth: Vec<L3Order>,
+    state_values: StateValues,
+    trades: Vec<Event>,
+    last_feed_latency: Option<(i64, i64)>,
+    last_depth_latency: Option<(i64, i64)>,
+    last_order_latency: Option<(i64, i64, i64)>,
+    mark_price: f64,
+    expiry: Expiry,
+}
+
 /// The Level3 Market-By-Order local model.
+///
+/// The orders in the market depth are timestamped with the exchange timestamp of the feed, so
+/// that they can be compared with the exchange timestamp of the backtest orders to find the
+/// queue position, as a [`LiveBot`](crate::live::LiveBot) does.
 pub struct L3Local<AT, LM, MD, FM>
 where
     AT: AssetType,
@@ -54,7 +89,13 @@ where
     order_latency: LM,
     trades: Vec<Event>,
     last_feed_latency: Option<(i64, i64)>,
+    last_depth_latency: Option<(i64, i64)>,
     last_order_latency: Option<(i64, i64, i64)>,
+    tracer: Option<Tracer>,
+    asset_no: usize,
+    margin: Option<Margin>,
+    mark_price: f64,
+    expiry: Expiry,
 }
 
 impl<AT, LM, MD, FM> L3Local<AT, LM, MD, FM>
@@ -86,13 +127,65 @@ where
             order_latency,
             trades: Vec::with_capacity(trade_len),
             last_feed_latency: None,
+            last_depth_latency: None,
             last_order_latency: None,
+            tracer: None,
+            asset_no: 0,
+            margin: None,
+            mark_price: f64::NAN,
+            expiry: Default::default(),
         }
     }
 
+    /// Sets the margin, which is reported in the state values. The exchange rejects the orders
+    /// and liquidates the position based on the same margin.
+    pub fn set_margin(&mut self, margin: Margin) {
+        self.margin = Some(margin);
+    }
+
+    /// Sets the expiry of the contract. At the expiry, the position is settled, and the open
+    /// orders are expired by the exchange.
+    pub fn set_expiry(&mut self, expiry: Expiry) {
+        self.expiry = expiry;
+    }
+
+    /// Settles the position at the settlement price.
+    fn expire(&mut self) {
+        let mid_price = (self.depth.best_bid() + self.depth.best_ask()) / 2.0;
+        let settlement_price = self.expiry.expire(mid_price);
+        self.state.apply_settlement(settlement_price);
+    }
+
+    /// Updates the margin at the mark price.
+    fn update_margin(&mut self) {
+        let mark_price = mark_price(self.mark_price, &self.depth);
+        update_local_margin(
+            self.margin.as_ref(),
+            &mut self.state,
+            mark_price,
+            &self.orders,
+        );
+    }
+
     fn process_recv_order_(&mut self, order: Order) -> Result<(), BacktestError> {
-        if order.status == Status::Filled {
+        if let Some(tracer) = &self.tracer {
+            tracer.order_response(self.asset_no, &order);
+        }
+        if order.order_id == LIQUIDATION_ORDER_ID {
+            // The exchange has liquidated the position and canceled the open orders.
+            self.state.apply_liquidation(&order);
+            return Ok(());
+        }
+        // Each partial fill is reported with its executed quantity.
+        if order.status == Status::Filled
+            || (order.status == Status::PartiallyFilled && order.exec_qty > 0.0)
+        {
             self.state.apply_fill(&order);
+            // The orders filled before the expiry are settled upon receipt.
+            if self.expiry.is_expired() {
+                self.state
+                    .apply_settlement(self.expiry.settlement_price().unwrap());
+            }
         }
         // Applies the received order response to the local orders.
         match self.orders.entry(order.order_id) {
@@ -131,28 +224,35 @@ where
 {
     fn submit_order(
         &mut self,
-        order_id: OrderId,
-        side: Side,
-        price: f64,
-        qty: f64,
-        order_type: OrdType,
-        time_in_force: TimeInForce,
+        request: OrderRequest,
         current_timestamp: i64,
     ) -> Result<(), BacktestError> {
-        if self.orders.contains_key(&order_id) {
+        if self.orders.contains_key(&request.order_id) {
             return Err(BacktestError::OrderIdExist);
         }
+        if request.order_id == LIQUIDATION_ORDER_ID {
+            return Err(BacktestError::InvalidOrderRequest);
+        }
 
-        let price_tick = (price / self.depth.tick_size()).round() as i64;
+        let price_tick = (request.price / self.depth.tick_size()).round() as i64;
         let mut order = Order::new(
-            order_id,
+            request.order_id,
             price_tick,
             self.depth.tick_size(),
-            qty,
-            side,
-            order_type,
-            time_in_force,
+            request.qty,
+            request.side,
+            request.order_type,
+            request.time_in_force,
         );
+        order.self_trade_prevention = request.self_trade_prevention;
+        order.trigger_price_tick = (request.trigger_price / self.depth.tick_size()).round() as i64;
+        order.trigger_price_type = request.trigger_price_type;
+        if let Some(oco_order_id) = request.oco_order_id {
+            order.oco = true;
+            order.oco_order_id = oco_order_id;
+        }
+        order.expire_timestamp = request.expire_timestamp;
+        order.reduce_only = request.reduce_only;
         order.req = Status::New;
         order.local_timestamp = current_timestamp;
         self.orders.insert(order.order_id, order.clone());
@@ -201,6 +301,45 @@ where
         Ok(())
     }
 
+    fn modify(
+        &mut self,
+        order_id: OrderId,
+        price: f64,
+        qty: f64,
+        current_timestamp: i64,
+    ) -> Result<(), BacktestError> {
+        let price_tick = (price / self.depth.tick_size()).round() as i64;
+        let order = self
+            .orders
+            .get_mut(&order_id)
+            .ok_or(BacktestError::OrderNotFound)?;
+
+        if order.req != Status::None {
+            return Err(BacktestError::OrderRequestInProcess);
+        }
+
+        // The local order retains its current price and quantity until the exchange accepts the
+        // modification.
+        order.req = Status::Replaced;
+        let mut order_ = order.clone();
+        order_.price_tick = price_tick;
+        order_.qty = qty;
+        let order_entry_latency = self.order_latency.entry(current_timestamp, &order_);
+        // Negative latency indicates that the order is rejected for technical reasons, and its
+        // value represents the latency that the local experiences when receiving the rejection
+        // notification.
+        if order_entry_latency < 0 {
+            // Rejects the order.
+            order_.req = Status::Rejected;
+            let rej_recv_timestamp = current_timestamp - order_entry_latency;
+            self.orders_from.append(order_, rej_recv_timestamp);
+        } else {
+            let exch_recv_timestamp = current_timestamp + order_entry_latency;
+            self.orders_to.append(order_, exch_recv_timestamp);
+        }
+        Ok(())
+    }
+
     fn clear_inactive_orders(&mut self) {
         self.orders.retain(|_, order| {
             order.status != Status::Expired
@@ -217,6 +356,10 @@ where
         self.state.values()
     }
 
+    fn update_mark_price(&mut self, mark_price: f64) {
+        self.mark_price = mark_price;
+    }
+
     fn depth(&self) -> &MD {
         &self.depth
     }
@@ -237,6 +380,25 @@ where
         self.last_feed_latency
     }
 
+    fn depth_latency(&self) -> Option<(i64, i64)> {
+        self.last_depth_latency
+    }
+
+    fn queue_ahead(&self, order_id: OrderId) -> Option<f64> {
+        // The backtest order isn't in the feed, so the feed orders placed at the exchange before
+        // the backtest order are ahead of it.
+        let order = self.orders.get(&order_id)?;
+        if !order.active() {
+            return None;
+        }
+        Some(qty_ahead(
+            &self.depth,
+            order.side,
+            order.price_tick,
+            order.exch_timestamp,
+        ))
+    }
+
     fn order_latency(&self) -> Option<(i64, i64, i64)> {
         self.last_order_latency
     }
@@ -256,13 +418,19 @@ where
             if self.data[rn].is(LOCAL_EVENT) {
                 self.row_num = rn;
                 let tmp = self.data[rn].local_ts;
-                return Ok(tmp);
+                return Ok(self.expiry.next_timestamp(tmp));
             }
         }
         Err(BacktestError::EndOfData)
     }
 
     fn process_data(&mut self) -> Result<(i64, i64), BacktestError> {
+        let local_ts = self.data[self.row_num].local_ts;
+        if self.expiry.is_due(local_ts) {
+            self.expire();
+            return Ok((local_ts, i64::MAX));
+        }
+
         let ev = &self.data[self.row_num];
         // Processes a depth event
         if ev.is(LOCAL_BID_DEPTH_CLEAR_EVENT) {
@@ -273,15 +441,15 @@ where
             self.depth.clear_orders(Side::None);
         } else if ev.is(LOCAL_BID_ADD_ORDER_EVENT) {
             self.depth
-                .add_buy_order(ev.order_id, ev.px, ev.qty, ev.local_ts)?;
+                .add_buy_order(ev.order_id, ev.px, ev.qty, ev.exch_ts)?;
         } else if ev.is(LOCAL_ASK_ADD_ORDER_EVENT) {
             self.depth
-                .add_sell_order(ev.order_id, ev.px, ev.qty, ev.local_ts)?;
+                .add_sell_order(ev.order_id, ev.px, ev.qty, ev.exch_ts)?;
         } else if ev.is(LOCAL_MODIFY_ORDER_EVENT) {
             self.depth
-                .modify_order(ev.order_id, ev.px, ev.qty, ev.local_ts)?;
+                .modify_order(ev.order_id, ev.px, ev.qty, ev.exch_ts)?;
         } else if ev.is(LOCAL_CANCEL_ORDER_EVENT) {
-            self.depth.delete_order(ev.order_id, ev.local_ts)?;
+            self.depth.delete_order(ev.order_id, ev.exch_ts)?;
         }
         // Processes a trade event
         else if ev.is(LOCAL_TRADE_EVENT) && self.trades.capacity() > 0 {
@@ -290,6 +458,10 @@ where
 
         // Stores the current feed latency
         self.last_feed_latency = Some((ev.exch_ts, ev.local_ts));
+        if !ev.is(LOCAL_TRADE_EVENT) {
+            self.last_depth_latency = self.last_feed_latency;
+        }
+        self.update_margin();
 
         // Checks
         let mut next_ts = 0;
@@ -310,7 +482,7 @@ where
             self.row_num = 0;
         }
 
-        Ok((next_ts, i64::MAX))
+        Ok((self.expiry.next_timestamp(next_ts), i64::MAX))
     }
 
     fn process_recv_order(
@@ -327,8 +499,9 @@ where
 
                 // Updates the order latency only if it has a valid exchange timestamp. When the
                 // order is rejected before it reaches the matching engine, it has no exchange
-                // timestamp. This situation occurs in crypto exchanges.
-                if order.exch_timestamp > 0 {
+                // timestamp. This situation occurs in crypto exchanges. The liquidation report
+                // doesn't respond to any order request.
+                if order.exch_timestamp > 0 && order.order_id != LIQUIDATION_ORDER_ID {
                     self.last_order_latency =
                         Some((order.local_timestamp, order.exch_timestamp, recv_timestamp));
                 }
@@ -345,6 +518,7 @@ where
                 break;
             }
         }
+        self.update_margin();
         Ok(wait_resp_order_received)
     }
 
@@ -355,4 +529,54 @@ where
     fn earliest_send_order_timestamp(&self) -> i64 {
         self.orders_to.earliest_timestamp().unwrap_or(i64::MAX)
     }
+
+    fn checkpoint(&self) -> Result<Vec<u8>, BacktestError> {
+        checkpoint::encode(&L3LocalState {
+            data_pos: self.reader.position(),
+            row_num: self.row_num,
+            orders: self.orders.clone(),
+            orders_to: self.orders_to.to_vec(),
+            depth: l3_depth_snapshot(&self.depth),
+            state_values: self.state.values().clone(),
+            trades: self.trades.clone(),
+            last_feed_latency: self.last_feed_latency,
+            last_depth_latency: self.last_depth_latency,
+            last_order_latency: self.last_order_latency,
+            mark_price: self.mark_price,
+            expiry: self.expiry,
+        })
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<(), BacktestError> {
+        let state: L3LocalState = checkpoint::decode(state)?;
+        restore_data(&mut self.reader, &mut self.data, state.data_pos)?;
+        self.row_num = state.row_num;
+        self.orders = state.orders;
+        restore_order_bus(&mut self.orders_to, state.orders_to);
+        restore_l3_depth(&mut self.depth, &state.depth)?;
+        self.state.state_values = state.state_values;
+        self.trades.clear();
+        self.trades.extend(state.trades);
+        self.last_feed_latency = state.last_feed_latency;
+        self.last_depth_latency = state.last_depth_latency;
+        self.last_order_latency = state.last_order_latency;
+        self.mark_price = state.mark_price;
+        self.expiry = state.expiry;
+        Ok(())
+    }
+
+    fn on_funding(
+        &mut self,
+        mark_price: f64,
+        funding_rate: f64,
+        _timestamp: i64,
+    ) -> Result<(), BacktestError> {
+        self.state.apply_funding(mark_price, funding_rate);
+        Ok(())
+    }
+
+    fn set_tracer(&mut self, tracer: Tracer, asset_no: usize) {
+        self.tracer = Some(tracer);
+        self.asset_no = asset_no;
+    }
 }
@@ -3,29 +3,44 @@ use std::{
     mem,
 };
 
+use bincode::{Decode, Encode};
+
 use crate::{
     backtest::{
         assettype::AssetType,
+        checkpoint,
         data::{Data, Reader},
+        expiry::Expiry,
         models::{FeeModel, LatencyModel},
         order::OrderBus,
-        proc::{LocalProcessor, Processor},
-        state::State,
+        proc::{
+            mark_price,
+            restore_data,
+            restore_depth,
+            restore_order_bus,
+            update_local_margin,
+            LocalProcessor,
+            Processor,
+            LIQUIDATION_ORDER_ID,
+        },
+        state::{Margin, State},
+        trace::Tracer,
         BacktestError,
     },
-    depth::{L2MarketDepth, MarketDepth},
+    depth::{update_best_ask, update_best_bid, ApplySnapshot, L2MarketDepth, MarketDepth},
     types::{
         Event,
-        OrdType,
         Order,
         OrderId,
+        OrderRequest,
         Side,
         StateValues,
         Status,
-        TimeInForce,
+        LOCAL_ASK_DEPTH_BBO_EVENT,
         LOCAL_ASK_DEPTH_CLEAR_EVENT,
         LOCAL_ASK_DEPTH_EVENT,
         LOCAL_ASK_DEPTH_SNAPSHOT_EVENT,
+        LOCAL_BID_DEPTH_BBO_EVENT,
         LOCAL_BID_DEPTH_CLEAR_EVENT,
         LOCAL_BID_DEPTH_EVENT,
         LOCAL_BID_DEPTH_SNAPSHOT_EVENT,
@@ -35,6 +50,23 @@ use crate::{
     },
 };
 
+/// The local processor's state stored in a checkpoint.
+#[derive(Encode, Decode)]
+struct LocalState {
+    data_pos: usize,
+    row_num: usize,
+    orders: HashMap<OrderId, Order>,
+    orders_to: Vec<(Order, i64)>,
+    depth: Vec<Event>,
+    state_values: StateValues,
+    trades: Vec<Event>,
+    last_feed_latency: Option<(i64, i64)>,
+    last_depth_latency: Option<(i64, i64)>,
+    last_order_latency: Option<(i64, i64, i64)>,
+    mark_price: f64,
+    expiry: Expiry,
+}
+
 /// The local model.
 pub struct Local<AT, LM, MD, FM>
 where
@@ -54,7 +86,13 @@ where
     order_latency: LM,
     trades: Vec<Event>,
     last_feed_latency: Option<(i64, i64)>,
+    last_depth_latency: Option<(i64, i64)>,
     last_order_latency: Option<(i64, i64, i64)>,
+    tracer: Option<Tracer>,
+    asset_no: usize,
+    margin: Option<Margin>,
+    mark_price: f64,
+    expiry: Expiry,
 }
 
 impl<AT, LM, MD, FM> Local<AT, LM, MD, FM>
@@ -86,13 +124,62 @@ where
             order_latency,
             trades: Vec::with_capacity(last_trades_cap),
             last_feed_latency: None,
+            last_depth_latency: None,
             last_order_latency: None,
+            tracer: None,
+            asset_no: 0,
+            margin: None,
+            mark_price: f64::NAN,
+            expiry: Default::default(),
         }
     }
 
+    /// Sets the margin, which is reported in the state values. The exchange rejects the orders
+    /// and liquidates the position based on the same margin.
+    pub fn set_margin(&mut self, margin: Margin) {
+        self.margin = Some(margin);
+    }
+
+    /// Sets the expiry of the contract. At the expiry, the position is settled, and the open
+    /// orders are expired by the exchange.
+    pub fn set_expiry(&mut self, expiry: Expiry) {
+        self.expiry = expiry;
+    }
+
+    /// Settles the position at the settlement price.
+    fn expire(&mut self) {
+        let mid_price = (self.depth.best_bid() + self.depth.best_ask()) / 2.0;
+        let settlement_price = self.expiry.expire(mid_price);
+        self.state.apply_settlement(settlement_price);
+    }
+
+    /// Updates the margin at the mark price.
+    fn update_margin(&mut self) {
+        let mark_price = mark_price(self.mark_price, &self.depth);
+        update_local_margin(
+            self.margin.as_ref(),
+            &mut self.state,
+            mark_price,
+            &self.orders,
+        );
+    }
+
     fn process_recv_order_(&mut self, order: Order) -> Result<(), BacktestError> {
+        if let Some(tracer) = &self.tracer {
+            tracer.order_response(self.asset_no, &order);
+        }
+        if order.order_id == LIQUIDATION_ORDER_ID {
+            // The exchange has liquidated the position and canceled the open orders.
+            self.state.apply_liquidation(&order);
+            return Ok(());
+        }
         if order.status == Status::Filled {
             self.state.apply_fill(&order);
+            // The orders filled before the expiry are settled upon receipt.
+            if self.expiry.is_expired() {
+                self.state
+                    .apply_settlement(self.expiry.settlement_price().unwrap());
+            }
         }
         // Applies the received order response to the local orders.
         match self.orders.entry(order.order_id) {
@@ -125,33 +212,40 @@ impl<AT, LM, MD, FM> LocalProcessor<MD> for Local<AT, LM, MD, FM>
 where
     AT: AssetType,
     LM: LatencyModel,
-    MD: MarketDepth + L2MarketDepth,
+    MD: MarketDepth + L2MarketDepth + ApplySnapshot,
     FM: FeeModel,
 {
     fn submit_order(
         &mut self,
-        order_id: OrderId,
-        side: Side,
-        price: f64,
-        qty: f64,
-        order_type: OrdType,
-        time_in_force: TimeInForce,
+        request: OrderRequest,
         current_timestamp: i64,
     ) -> Result<(), BacktestError> {
-        if self.orders.contains_key(&order_id) {
+        if self.orders.contains_key(&request.order_id) {
             return Err(BacktestError::OrderIdExist);
         }
+        if request.order_id == LIQUIDATION_ORDER_ID {
+            return Err(BacktestError::InvalidOrderRequest);
+        }
 
-        let price_tick = (price / self.depth.tick_size()).round() as i64;
+        let price_tick = (request.price / self.depth.tick_size()).round() as i64;
         let mut order = Order::new(
-            order_id,
+            request.order_id,
             price_tick,
             self.depth.tick_size(),
-            qty,
-            side,
-            order_type,
-            time_in_force,
+            request.qty,
+            request.side,
+            request.order_type,
+            request.time_in_force,
         );
+        order.self_trade_prevention = request.self_trade_prevention;
+        order.trigger_price_tick = (request.trigger_price / self.depth.tick_size()).round() as i64;
+        order.trigger_price_type = request.trigger_price_type;
+        if let Some(oco_order_id) = request.oco_order_id {
+            order.oco = true;
+            order.oco_order_id = oco_order_id;
+        }
+        order.expire_timestamp = request.expire_timestamp;
+        order.reduce_only = request.reduce_only;
         order.req = Status::New;
         order.local_timestamp = current_timestamp;
         self.orders.insert(order.order_id, order.clone());
@@ -200,6 +294,45 @@ where
         Ok(())
     }
 
+    fn modify(
+        &mut self,
+        order_id: OrderId,
+        price: f64,
+        qty: f64,
+        current_timestamp: i64,
+    ) -> Result<(), BacktestError> {
+        let price_tick = (price / self.depth.tick_size()).round() as i64;
+        let order = self
+            .orders
+            .get_mut(&order_id)
+            .ok_or(BacktestError::OrderNotFound)?;
+
+        if order.req != Status::None {
+            return Err(BacktestError::OrderRequestInProcess);
+        }
+
+        // The local order retains its current price and quantity until the exchange accepts the
+        // modification.
+        order.req = Status::Replaced;
+        let mut order_ = order.clone();
+        order_.price_tick = price_tick;
+        order_.qty = qty;
+        let order_entry_latency = self.order_latency.entry(current_timestamp, &order_);
+        // Negative latency indicates that the order is rejected for technical reasons, and its
+        // value represents the latency that the local experiences when receiving the rejection
+        // notification.
+        if order_entry_latency < 0 {
+            // Rejects the order.
+            order_.req = Status::Rejected;
+            let rej_recv_timestamp = current_timestamp - order_entry_latency;
+            self.orders_from.append(order_, rej_recv_timestamp);
+        } else {
+            let exch_recv_timestamp = current_timestamp + order_entry_latency;
+            self.orders_to.append(order_, exch_recv_timestamp);
+        }
+        Ok(())
+    }
+
     fn clear_inactive_orders(&mut self) {
         self.orders.retain(|_, order| {
             order.status != Status::Expired
@@ -216,6 +349,10 @@ where
         self.state.values()
     }
 
+    fn update_mark_price(&mut self, mark_price: f64) {
+        self.mark_price = mark_price;
+    }
+
     fn depth(&self) -> &MD {
         &self.depth
     }
@@ -236,6 +373,14 @@ where
         self.last_feed_latency
     }
 
+    fn depth_latency(&self) -> Option<(i64, i64)> {
+        self.last_depth_latency
+    }
+
+    fn queue_ahead(&self, _order_id: OrderId) -> Option<f64> {
+        None
+    }
+
     fn order_latency(&self) -> Option<(i64, i64, i64)> {
         self.last_order_latency
     }
@@ -245,7 +390,7 @@ impl<AT, LM, MD, FM> Processor for Local<AT, LM, MD, FM>
 where
     AT: AssetType,
     LM: LatencyModel,
-    MD: MarketDepth + L2MarketDepth,
+    MD: MarketDepth + L2MarketDepth + ApplySnapshot,
     FM: FeeModel,
 {
     fn initialize_data(&mut self) -> Result<i64, BacktestError> {
@@ -254,13 +399,19 @@ where
             if self.data[rn].is(LOCAL_EVENT) {
                 self.row_num = rn;
                 let tmp = self.data[rn].local_ts;
-                return Ok(tmp);
+                return Ok(self.expiry.next_timestamp(tmp));
             }
         }
         Err(BacktestError::EndOfData)
     }
 
     fn process_data(&mut self) -> Result<(i64, i64), BacktestError> {
+        let local_ts = self.data[self.row_num].local_ts;
+        if self.expiry.is_due(local_ts) {
+            self.expire();
+            return Ok((local_ts, i64::MAX));
+        }
+
         let ev = &self.data[self.row_num];
         // Processes a depth event
         if ev.is(LOCAL_BID_DEPTH_CLEAR_EVENT) {
@@ -273,6 +424,10 @@ where
             self.depth.update_bid_depth(ev.px, ev.qty, ev.local_ts);
         } else if ev.is(LOCAL_ASK_DEPTH_EVENT) || ev.is(LOCAL_ASK_DEPTH_SNAPSHOT_EVENT) {
             self.depth.update_ask_depth(ev.px, ev.qty, ev.local_ts);
+        } else if ev.is(LOCAL_BID_DEPTH_BBO_EVENT) {
+            update_best_bid(&mut self.depth, ev.px, ev.qty, ev.local_ts);
+        } else if ev.is(LOCAL_ASK_DEPTH_BBO_EVENT) {
+            update_best_ask(&mut self.depth, ev.px, ev.qty, ev.local_ts);
         }
         // Processes a trade event
         else if ev.is(LOCAL_TRADE_EVENT) && self.trades.capacity() > 0 {
@@ -281,6 +436,10 @@ where
 
         // Stores the current feed latency
         self.last_feed_latency = Some((ev.exch_ts, ev.local_ts));
+        if !ev.is(LOCAL_TRADE_EVENT) {
+            self.last_depth_latency = self.last_feed_latency;
+        }
+        self.update_margin();
 
         // Checks
         let mut next_ts = 0;
@@ -301,7 +460,7 @@ where
             self.row_num = 0;
         }
 
-        Ok((next_ts, i64::MAX))
+        Ok((self.expiry.next_timestamp(next_ts), i64::MAX))
     }
 
     fn process_recv_order(
@@ -318,8 +477,9 @@ where
 
                 // Updates the order latency only if it has a valid exchange timestamp. When the
                 // order is rejected before it reaches the matching engine, it has no exchange
-                // timestamp. This situation occurs in crypto exchanges.
-                if order.exch_timestamp > 0 {
+                // timestamp. This situation occurs in crypto exchanges. The liquidation report
+                // doesn't respond to any order request.
+                if order.exch_timestamp > 0 && order.order_id != LIQUIDATION_ORDER_ID {
                     self.last_order_latency =
                         Some((order.local_timestamp, order.exch_timestamp, recv_timestamp));
                 }
@@ -336,6 +496,7 @@ where
                 break;
             }
         }
+        self.update_margin();
         Ok(wait_resp_order_received)
     }
 
@@ -346,4 +507,54 @@ where
     fn earliest_send_order_timestamp(&self) -> i64 {
         self.orders_to.earliest_timestamp().unwrap_or(i64::MAX)
     }
+
+    fn checkpoint(&self) -> Result<Vec<u8>, BacktestError> {
+        checkpoint::encode(&LocalState {
+            data_pos: self.reader.position(),
+            row_num: self.row_num,
+            orders: self.orders.clone(),
+            orders_to: self.orders_to.to_vec(),
+            depth: self.depth.snapshot(),
+            state_values: self.state.values().clone(),
+            trades: self.trades.clone(),
+            last_feed_latency: self.last_feed_latency,
+            last_depth_latency: self.last_depth_latency,
+            last_order_latency: self.last_order_latency,
+            mark_price: self.mark_price,
+            expiry: self.expiry,
+        })
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<(), BacktestError> {
+        let state: LocalState = checkpoint::decode(state)?;
+        restore_data(&mut self.reader, &mut self.data, state.data_pos)?;
+        self.row_num = state.row_num;
+        self.orders = state.orders;
+        restore_order_bus(&mut self.orders_to, state.orders_to);
+        restore_depth(&mut self.depth, &state.depth);
+        self.state.state_values = state.state_values;
+        self.trades.clear();
+        self.trades.extend(state.trades);
+        self.last_feed_latency = state.last_feed_latency;
+        self.last_depth_latency = state.last_depth_latency;
+        self.last_order_latency = state.last_order_latency;
+        self.mark_price = state.mark_price;
+        self.expiry = state.expiry;
+        Ok(())
+    }
+
+    fn on_funding(
+        &mut self,
+        mark_price: f64,
+        funding_rate: f64,
+        _timestamp: i64,
+    ) -> Result<(), BacktestError> {
+        self.state.apply_funding(mark_price, funding_rate);
+        Ok(())
+    }
+
+    fn set_tracer(&mut self, tracer: Tracer, asset_no: usize) {
+        self.tracer = Some(tracer);
+        self.asset_no = asset_no;
+    }
 }
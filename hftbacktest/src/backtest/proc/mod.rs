@@ -2,25 +2,51 @@ mod local;
 mod nopartialfillexchange;
 mod partialfillexchange;
 
-use std::collections::HashMap;
+use std::{collections::HashMap, mem};
+
+use bincode::{Decode, Encode};
 
 pub use local::Local;
 pub use nopartialfillexchange::NoPartialFillExchange;
 pub use partialfillexchange::PartialFillExchange;
 
+mod l3_exchange;
 mod l3_local;
 
-mod l3_nopartialfillexchange;
-
+pub use l3_exchange::{L3Exchange, L3NoPartialFillExchange, L3PartialFillExchange};
 pub use l3_local::L3Local;
-pub use l3_nopartialfillexchange::L3NoPartialFillExchange;
 
 use crate::{
-    backtest::BacktestError,
-    depth::MarketDepth,
-    prelude::{Event, OrdType, Order, OrderId, Side, StateValues, TimeInForce},
+    backtest::{
+        assettype::AssetType,
+        data::{Data, Reader},
+        expiry::Expiry,
+        models::{FeeModel, MarketImpact},
+        order::OrderBus,
+        state::{Margin, State},
+        trace::Tracer,
+        BacktestError,
+    },
+    depth::{ApplySnapshot, L2MarketDepth, L3MarketDepth, L3Order, MarketDepth},
+    prelude::{
+        Event,
+        OrdType,
+        Order,
+        OrderId,
+        OrderRequest,
+        SelfTradePrevention,
+        Side,
+        StateValues,
+        Status,
+        TimeInForce,
+    },
+    types::BUY_EVENT,
 };
 
+/// The order ID reserved for the report of a forced liquidation, which the exchange sends to the
+/// local when it liquidates the position. Orders cannot be submitted with this order ID.
+pub const LIQUIDATION_ORDER_ID: OrderId = OrderId::MAX;
+
 /// Provides local-specific interaction.
 pub trait LocalProcessor<MD>: Processor
 where
@@ -28,24 +54,13 @@ where
 {
     /// Submits a new order.
     ///
-    /// * `order_id` - The unique order ID; there should not be any existing order with the same ID
-    ///                on both local and exchange sides.
-    /// * `price` - Order price.
-    /// * `qty` - Quantity to buy.
-    /// * `order_type` - Available [`OrdType`] options vary depending on the exchange model. See to
-    ///                   the exchange model for details.
-    /// * `time_in_force` - Available [`TimeInForce`] options vary depending on the exchange model.
-    ///                     See to the exchange model for details.
+    /// * `request` - The order to submit; there should not be any existing order with the same ID
+    ///   on both local and exchange sides. Available [`OrdType`] and [`TimeInForce`] options vary
+    ///   depending on the exchange model. See the exchange model for details.
     /// * `current_timestamp` - The current backtesting timestamp.
-    #[allow(clippy::too_many_arguments)]
     fn submit_order(
         &mut self,
-        order_id: OrderId,
-        side: Side,
-        price: f64,
-        qty: f64,
-        order_type: OrdType,
-        time_in_force: TimeInForce,
+        request: OrderRequest,
         current_timestamp: i64,
     ) -> Result<(), BacktestError>;
 
@@ -55,6 +70,20 @@ where
     /// * `current_timestamp` - The current backtesting timestamp.
     fn cancel(&mut self, order_id: OrderId, current_timestamp: i64) -> Result<(), BacktestError>;
 
+    /// Modifies the price and quantity of the specified order.
+    ///
+    /// * `order_id` - Order ID to modify.
+    /// * `price` - New order price.
+    /// * `qty` - New order quantity.
+    /// * `current_timestamp` - The current backtesting timestamp.
+    fn modify(
+        &mut self,
+        order_id: OrderId,
+        price: f64,
+        qty: f64,
+        current_timestamp: i64,
+    ) -> Result<(), BacktestError>;
+
     /// Clears inactive orders from the local orders whose status is neither
     /// [`Status::New`](crate::types::Status::New) nor
     /// [`Status::PartiallyFilled`](crate::types::Status::PartiallyFilled).
@@ -66,6 +95,10 @@ where
     /// Returns the state's values such as balance, fee, and so on.
     fn state_values(&self) -> &StateValues;
 
+    /// Updates the mark price at which the margin is evaluated. Until it is updated, the mid price
+    /// is used as the mark price.
+    fn update_mark_price(&mut self, mark_price: f64);
+
     /// Returns the [`MarketDepth`].
     fn depth(&self) -> &MD;
 
@@ -81,9 +114,17 @@ where
     /// Returns the last feed's exchange timestamp and local receipt timestamp.
     fn feed_latency(&self) -> Option<(i64, i64)>;
 
+    /// Returns the last market depth feed's exchange timestamp and local receipt timestamp. Every
+    /// feed other than a trade is a market depth feed.
+    fn depth_latency(&self) -> Option<(i64, i64)>;
+
     /// Returns the last order's request timestamp, exchange timestamp, and response receipt
     /// timestamp.
     fn order_latency(&self) -> Option<(i64, i64, i64)>;
+
+    /// Returns the quantity ahead of the order in the queue at its price level in the Level3
+    /// Market-By-Order feed, or `None` if it is unknown. See [`L3Bot`](crate::types::L3Bot).
+    fn queue_ahead(&self, order_id: OrderId) -> Option<f64>;
 }
 
 /// Processes the historical feed data and the order interaction.
@@ -112,4 +153,319 @@ pub trait Processor {
     /// Returns the foremost timestamp at which an order sent by this processor is to be received by
     /// the corresponding processor.
     fn earliest_send_order_timestamp(&self) -> i64;
+
+    /// Returns the encoded state of this processor to be stored in a
+    /// [`Checkpoint`](crate::backtest::checkpoint::Checkpoint).
+    ///
+    /// The orders that this processor sends and that are not yet received by the corresponding
+    /// processor are part of the state.
+    fn checkpoint(&self) -> Result<Vec<u8>, BacktestError> {
+        Err(BacktestError::CheckpointError(
+            "the processor does not support checkpointing".to_string(),
+        ))
+    }
+
+    /// Restores the state returned by [`Processor::checkpoint`]. The processor should be
+    /// constructed with the same data and models as the processor from which the state is taken.
+    fn restore(&mut self, _state: &[u8]) -> Result<(), BacktestError> {
+        Err(BacktestError::CheckpointError(
+            "the processor does not support checkpointing".to_string(),
+        ))
+    }
+
+    /// Updates the mark price against which the conditional orders are triggered. This is invoked
+    /// when the mark price is updated by the funding rate data.
+    fn on_mark_price(&mut self, _mark_price: f64, _timestamp: i64) -> Result<(), BacktestError> {
+        Ok(())
+    }
+
+    /// Settles the funding for the current position. This is invoked on both the local and the
+    /// exchange at every funding interval.
+    ///
+    /// * `mark_price` - Mark price at which the position is valued.
+    /// * `funding_rate` - Funding rate; long positions pay short positions if it is positive.
+    /// * `timestamp` - The current backtesting timestamp.
+    fn on_funding(
+        &mut self,
+        _mark_price: f64,
+        _funding_rate: f64,
+        _timestamp: i64,
+    ) -> Result<(), BacktestError> {
+        Ok(())
+    }
+
+    /// Sets the [`Tracer`] to which this processor writes, along with the asset number of this
+    /// processor. By default, the processor does not write to the tracer.
+    fn set_tracer(&mut self, _tracer: Tracer, _asset_no: usize) {}
+}
+
+/// The exchange processor's state stored in a checkpoint.
+#[derive(Encode, Decode)]
+struct ExchangeState {
+    data_pos: usize,
+    row_num: usize,
+    // The orders with their encoded queue position estimation values.
+    orders: Vec<(Order, Vec<u8>)>,
+    orders_to: Vec<(Order, i64)>,
+    depth: Vec<Event>,
+    state_values: StateValues,
+    expiry: Expiry,
+    market_impact: Option<MarketImpact>,
+    conditional_orders: HashMap<OrderId, Order>,
+    oco_expired: HashMap<OrderId, OrderId>,
+    last_price: f64,
+    mark_price: f64,
+}
+
+/// Reloads the data that was being processed at the time of the checkpoint. `data_pos` is the
+/// reader's position at that time; zero means that no data had been read yet.
+fn restore_data(
+    reader: &mut Reader<Event>,
+    data: &mut Data<Event>,
+    data_pos: usize,
+) -> Result<(), BacktestError> {
+    if data_pos > 0 {
+        reader.seek(data_pos - 1);
+        let restored = reader.next_data()?;
+        let released = mem::replace(data, restored);
+        reader.release(released);
+    }
+    Ok(())
+}
+
+/// Restores the market depth from the snapshot events.
+fn restore_depth<MD>(depth: &mut MD, events: &[Event])
+where
+    MD: L2MarketDepth + ApplySnapshot,
+{
+    depth.clear_depth(Side::None, 0.0);
+    depth.apply_snapshot(&Data::from_items(events));
+}
+
+/// Returns the orders in the Level3 market depth to be stored in a checkpoint.
+fn l3_depth_snapshot<MD: L3MarketDepth>(depth: &MD) -> Vec<L3Order> {
+    let mut orders: Vec<_> = depth.orders().values().cloned().collect();
+    orders.sort_by_key(|order| (order.timestamp, order.order_id));
+    orders
+}
+
+/// Restores the Level3 market depth from the orders.
+fn restore_l3_depth<MD>(depth: &mut MD, orders: &[L3Order]) -> Result<(), BacktestError>
+where
+    MD: L3MarketDepth,
+    BacktestError: From<<MD as L3MarketDepth>::Error>,
+{
+    depth.clear_orders(Side::None);
+    for order in orders {
+        let px = order.price_tick as f64 * depth.tick_size();
+        if order.side == Side::Buy {
+            depth.add_buy_order(order.order_id, px, order.qty, order.timestamp)?;
+        } else {
+            depth.add_sell_order(order.order_id, px, order.qty, order.timestamp)?;
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the orders in the bus with the given orders.
+fn restore_order_bus(order_bus: &mut OrderBus, orders: Vec<(Order, i64)>) {
+    order_bus.reset();
+    for (order, timestamp) in orders {
+        order_bus.append(order, timestamp);
+    }
+}
+
+/// The action that the self-trade prevention of an incoming order takes on a resting order.
+enum SelfTradeAction {
+    /// Expires the resting order.
+    Expire(OrderId),
+    /// Decrements both the quantity and the leaves quantity of the resting order by the given
+    /// quantity, and expires it if nothing is left.
+    Decrement(OrderId, f64),
+}
+
+/// Applies the self-trade prevention of the incoming order against the resting orders on the other
+/// side that it would match, in price-time priority. The incoming order is decremented in place
+/// for [`SelfTradePrevention::Decrement`]. Returns the actions that the exchange should take on the
+/// resting orders, in order, and whether the incoming order should be expired.
+fn prevent_self_trade<'a>(
+    order: &mut Order,
+    resting_orders: impl Iterator<Item = &'a Order>,
+    lot_size: f64,
+) -> (Vec<SelfTradeAction>, bool) {
+    // A post-only order never takes liquidity.
+    if order.self_trade_prevention == SelfTradePrevention::None
+        || order.time_in_force == TimeInForce::GTX
+    {
+        return (Vec::new(), false);
+    }
+    let mut matched: Vec<_> = resting_orders
+        .filter(|resting| {
+            resting.side != order.side
+                && (order.order_type == OrdType::Market
+                    || (order.side == Side::Buy && resting.price_tick <= order.price_tick)
+                    || (order.side == Side::Sell && resting.price_tick >= order.price_tick))
+        })
+        .map(|resting| {
+            let price_priority = if resting.side == Side::Sell {
+                resting.price_tick
+            } else {
+                -resting.price_tick
+            };
+            (
+                price_priority,
+                resting.exch_timestamp,
+                resting.order_id,
+                resting.leaves_qty,
+            )
+        })
+        .collect();
+    if matched.is_empty() {
+        return (Vec::new(), false);
+    }
+    matched.sort_by_key(|&(price_priority, timestamp, order_id, _)| {
+        (price_priority, timestamp, order_id)
+    });
+
+    match order.self_trade_prevention {
+        SelfTradePrevention::CancelNewest => (Vec::new(), true),
+        SelfTradePrevention::CancelOldest => (
+            matched
+                .into_iter()
+                .map(|(_, _, order_id, _)| SelfTradeAction::Expire(order_id))
+                .collect(),
+            false,
+        ),
+        SelfTradePrevention::CancelBoth => (
+            matched
+                .into_iter()
+                .map(|(_, _, order_id, _)| SelfTradeAction::Expire(order_id))
+                .collect(),
+            true,
+        ),
+        SelfTradePrevention::Decrement => {
+            let mut actions = Vec::new();
+            for (_, _, order_id, leaves_qty) in matched {
+                let qty = leaves_qty.min(order.leaves_qty);
+                order.qty -= qty;
+                order.leaves_qty -= qty;
+                actions.push(SelfTradeAction::Decrement(order_id, qty));
+                if (order.leaves_qty / lot_size).round() <= 0.0 {
+                    return (actions, true);
+                }
+            }
+            (actions, false)
+        }
+        SelfTradePrevention::None | SelfTradePrevention::Unsupported => (Vec::new(), false),
+    }
+}
+
+/// Returns the price levels in ticks on the `side` of the Level2 market depth that a
+/// liquidity-taking order can sweep, from the best to the worst, up to and including `limit_tick`
+/// if given.
+fn depth_levels<MD>(depth: &MD, side: Side, limit_tick: Option<i64>) -> Vec<i64>
+where
+    MD: MarketDepth + ApplySnapshot,
+{
+    let tick_size = depth.tick_size();
+    let is_bid = side == Side::Buy;
+    sort_levels(
+        depth
+            .snapshot()
+            .iter()
+            .filter(|event| event.is(BUY_EVENT) == is_bid && event.qty > 0.0)
+            .map(|event| (event.px / tick_size).round() as i64),
+        side,
+        limit_tick,
+    )
+}
+
+/// Returns the price levels in ticks on the `side` of the Level3 market depth, in the same way as
+/// [`depth_levels`].
+fn l3_depth_levels<MD: L3MarketDepth>(depth: &MD, side: Side, limit_tick: Option<i64>) -> Vec<i64> {
+    sort_levels(
+        depth
+            .orders()
+            .values()
+            .filter(|order| order.side == side)
+            .map(|order| order.price_tick),
+        side,
+        limit_tick,
+    )
+}
+
+fn sort_levels(
+    price_ticks: impl Iterator<Item = i64>,
+    side: Side,
+    limit_tick: Option<i64>,
+) -> Vec<i64> {
+    let mut price_ticks: Vec<_> = price_ticks
+        .filter(|&t| match (side, limit_tick) {
+            (_, None) => true,
+            (Side::Buy, Some(limit_tick)) => t >= limit_tick,
+            (_, Some(limit_tick)) => t <= limit_tick,
+        })
+        .collect();
+    if side == Side::Buy {
+        price_ticks.sort_unstable_by(|a, b| b.cmp(a));
+    } else {
+        price_ticks.sort_unstable();
+    }
+    price_ticks.dedup();
+    price_ticks
+}
+
+/// Returns the mark price, or the mid price if the mark price has not been updated.
+fn mark_price<MD: MarketDepth>(mark_price: f64, depth: &MD) -> f64 {
+    if mark_price.is_nan() {
+        (depth.best_bid() + depth.best_ask()) / 2.0
+    } else {
+        mark_price
+    }
+}
+
+/// Returns the report of the forced liquidation that closes the position at the mark price. Both
+/// the exchange and the local apply it through [`State::apply_liquidation`].
+fn liquidation_order(position: f64, mark_price: f64, tick_size: f64, timestamp: i64) -> Order {
+    let side = if position > 0.0 {
+        Side::Sell
+    } else {
+        Side::Buy
+    };
+    let price_tick = (mark_price / tick_size).round() as i64;
+    let mut order = Order::new(
+        LIQUIDATION_ORDER_ID,
+        price_tick,
+        tick_size,
+        position.abs(),
+        side,
+        OrdType::Market,
+        TimeInForce::IOC,
+    );
+    order.leaves_qty = 0.0;
+    order.exec_qty = position.abs();
+    order.exec_price_tick = price_tick;
+    order.avg_exec_price = mark_price;
+    order.status = Status::Filled;
+    order.exch_timestamp = timestamp;
+    order
+}
+
+/// Updates the margin reported in the local's state values, counting the local orders that are
+/// open or waiting for the exchange to accept them.
+fn update_local_margin<AT, FM>(
+    margin: Option<&Margin>,
+    state: &mut State<AT, FM>,
+    mark_price: f64,
+    orders: &HashMap<OrderId, Order>,
+) where
+    AT: AssetType,
+    FM: FeeModel,
+{
+    if let Some(margin) = margin {
+        let open_orders = orders
+            .values()
+            .filter(|order| order.req == Status::New || order.active());
+        margin.update(state, mark_price, open_orders);
+    }
 }
@@ -1,7 +1,7 @@
 use std::{
     cell::RefCell,
     cmp::Ordering,
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap, HashSet},
     mem,
     rc::Rc,
 };
@@ -9,14 +9,36 @@ use std::{
 use crate::{
     backtest::{
         assettype::AssetType,
+        checkpoint,
         data::{Data, Reader},
-        models::{FeeModel, LatencyModel, QueueModel},
+        expiry::Expiry,
+        models::{FeeModel, LatencyModel, MarketImpact, QueueModel},
         order::OrderBus,
-        proc::Processor,
-        state::State,
+        proc::{
+            depth_levels,
+            liquidation_order,
+            mark_price,
+            prevent_self_trade,
+            restore_data,
+            restore_depth,
+            restore_order_bus,
+            ExchangeState,
+            Processor,
+            SelfTradeAction,
+        },
+        state::{Margin, State},
+        trace::Tracer,
         BacktestError,
     },
-    depth::{L2MarketDepth, MarketDepth, INVALID_MAX, INVALID_MIN},
+    depth::{
+        update_best_ask,
+        update_best_bid,
+        ApplySnapshot,
+        L2MarketDepth,
+        MarketDepth,
+        INVALID_MAX,
+        INVALID_MIN,
+    },
     prelude::OrdType,
     types::{
         Event,
@@ -25,9 +47,12 @@ use crate::{
         Side,
         Status,
         TimeInForce,
+        TriggerPriceType,
+        EXCH_ASK_DEPTH_BBO_EVENT,
         EXCH_ASK_DEPTH_CLEAR_EVENT,
         EXCH_ASK_DEPTH_EVENT,
         EXCH_ASK_DEPTH_SNAPSHOT_EVENT,
+        EXCH_BID_DEPTH_BBO_EVENT,
         EXCH_BID_DEPTH_CLEAR_EVENT,
         EXCH_BID_DEPTH_EVENT,
         EXCH_BID_DEPTH_SNAPSHOT_EVENT,
@@ -41,7 +66,7 @@ use crate::{
 /// The exchange model without partial fills.
 ///
 /// Support order types: [OrdType::Limit](crate::types::OrdType::Limit)
-/// Support time-in-force: [`TimeInForce::GTC`], [`TimeInForce::GTX`]
+/// Support time-in-force: [`TimeInForce::GTC`], [`TimeInForce::GTX`], [`TimeInForce::GTD`]
 ///
 /// **Conditions for Full Execution**
 ///
@@ -63,6 +88,45 @@ use crate::{
 /// best. Be aware that this may cause unrealistic fill simulations if you attempt to execute a
 /// large quantity.
 ///
+/// If a [`MarketImpact`] is set, liquidity-taking orders instead sweep the price levels in the
+/// book up to the order price, or the entire book for a market order, taking the quantity not yet
+/// consumed by your previous liquidity-taking orders. The quantity left is executed at the order
+/// price, or at the worst price in the book for a market order. The order is still fully executed
+/// in a single fill, at the volume-weighted average price.
+///
+/// **Self-Trade Prevention**
+///
+/// When an incoming order, other than a post-only order, would match resting orders on the other
+/// side, its [`SelfTradePrevention`] mode is applied to them in price-time priority before it
+/// takes liquidity. Expired orders are reported with [`Status::Expired`].
+///
+/// **Conditional Orders**
+///
+/// Stop and take-profit orders wait on the exchange side, outside the order book, until the last
+/// trade price or the mark price reaches the trigger price. They are rejected if they would be
+/// triggered immediately upon receipt. Once triggered, they turn into market or limit orders and
+/// are processed as new orders. Until the mark price is updated by the funding rate data, the mid
+/// price is used as the mark price. Conditional orders cannot be modified.
+///
+/// Once either order of a one-cancels-other pair is executed or triggered, the other is expired.
+/// Canceling either order does not affect the other.
+///
+/// **Good-Till-Date and Reduce-Only Orders**
+///
+/// A [`TimeInForce::GTD`] order is expired at the first market data event at or after its expiry
+/// timestamp, and is rejected if it has already expired upon receipt. The quantity of a
+/// reduce-only order is reduced to the position when the order is accepted or executed, and the
+/// order is expired if it would increase the position, including when the position is closed or
+/// reversed while the order rests in the order book.
+///
+/// **Margin**
+///
+/// If a [`Margin`] is set, a new order is rejected if the collateral plus the equity cannot cover
+/// the initial margin of the position and the open orders including the new one. Once the
+/// collateral plus the equity falls below the maintenance margin, the position is liquidated at
+/// the mark price and the open orders are canceled. The liquidation is reported to the local as a
+/// filled order with [`LIQUIDATION_ORDER_ID`](crate::backtest::proc::LIQUIDATION_ORDER_ID).
+///
 pub struct NoPartialFillExchange<AT, LM, QM, MD, FM>
 where
     AT: AssetType,
@@ -90,6 +154,32 @@ where
     queue_model: QM,
 
     filled_orders: Vec<OrderId>,
+
+    tracer: Option<Tracer>,
+    asset_no: usize,
+
+    expiry: Expiry,
+    market_impact: Option<MarketImpact>,
+
+    // key: order_id, value: conditional order waiting to be triggered
+    conditional_orders: HashMap<OrderId, Order>,
+    // The one-cancels-other orders to expire along with the order IDs of their counterparts,
+    // which have been executed or triggered.
+    oco_to_expire: Vec<(OrderId, OrderId)>,
+    // key: order_id of a one-cancels-other order expired before it is received, value: order_id
+    // of its counterpart
+    oco_expired: HashMap<OrderId, OrderId>,
+    last_price: f64,
+    mark_price: f64,
+    // (expiry timestamp, order_id) of the good-till-date orders, which may include the orders
+    // that are no longer open
+    gtd_orders: BTreeSet<(i64, OrderId)>,
+    // order_ids of the resting reduce-only orders, which may include the orders that are no longer
+    // open
+    reduce_only_orders: BTreeSet<OrderId>,
+    // Whether the position has changed since the reduce-only orders were last checked.
+    position_changed: bool,
+    margin: Option<Margin>,
 }
 
 impl<AT, LM, QM, MD, FM> NoPartialFillExchange<AT, LM, QM, MD, FM>
@@ -97,7 +187,7 @@ where
     AT: AssetType,
     LM: LatencyModel,
     QM: QueueModel<MD>,
-    MD: MarketDepth,
+    MD: MarketDepth + ApplySnapshot,
     FM: FeeModel,
 {
     /// Constructs an instance of `NoPartialFillExchange`.
@@ -124,6 +214,316 @@ where
             order_latency,
             queue_model,
             filled_orders: Default::default(),
+            tracer: None,
+            asset_no: 0,
+            expiry: Default::default(),
+            market_impact: None,
+            conditional_orders: HashMap::new(),
+            oco_to_expire: Vec::new(),
+            oco_expired: HashMap::new(),
+            last_price: f64::NAN,
+            mark_price: f64::NAN,
+            gtd_orders: BTreeSet::new(),
+            reduce_only_orders: BTreeSet::new(),
+            position_changed: false,
+            margin: None,
+        }
+    }
+
+    /// Sets the expiry of the contract. At the expiry, the open orders are expired, the position
+    /// is settled, and further orders are rejected.
+    pub fn set_expiry(&mut self, expiry: Expiry) {
+        self.expiry = expiry;
+    }
+
+    /// Sets the market impact model, which makes liquidity-taking orders sweep the price levels,
+    /// taking the quantity not yet consumed by the previous liquidity-taking orders.
+    pub fn set_market_impact(&mut self, market_impact: MarketImpact) {
+        self.market_impact = Some(market_impact);
+    }
+
+    /// Sets the margin, based on which the orders are rejected and the position is liquidated.
+    pub fn set_margin(&mut self, margin: Margin) {
+        self.margin = Some(margin);
+    }
+
+    /// Expires the open orders, settles the position at the settlement price, and rejects further
+    /// orders from now on.
+    fn expire(&mut self, timestamp: i64) {
+        let mid_price = (self.depth.best_bid() + self.depth.best_ask()) / 2.0;
+        let settlement_price = self.expiry.expire(mid_price);
+        self.state.apply_settlement(settlement_price);
+        self.close_open_orders(Status::Expired, timestamp);
+    }
+
+    /// Returns `true` if both the inventory and the margin can cover the new or modified order on
+    /// top of the other open orders.
+    fn is_covered(&self, order: &Order) -> bool {
+        let orders = self.orders.borrow();
+        let other_orders = || {
+            orders
+                .values()
+                .filter(|open_order| open_order.order_id != order.order_id)
+        };
+        if !self.state.has_inventory(order, other_orders()) {
+            return false;
+        }
+        match &self.margin {
+            Some(margin) => margin.is_sufficient(
+                &self.state,
+                mark_price(self.mark_price, &self.depth),
+                other_orders()
+                    .chain(self.conditional_orders.values())
+                    .chain([order]),
+            ),
+            None => true,
+        }
+    }
+
+    /// Liquidates the position at the mark price and cancels the open orders if the collateral
+    /// plus the equity falls below the maintenance margin.
+    fn check_margin(&mut self, timestamp: i64) {
+        let mark_price = mark_price(self.mark_price, &self.depth);
+        if !self
+            .margin
+            .as_ref()
+            .is_some_and(|margin| margin.requires_liquidation(&self.state, mark_price))
+        {
+            return;
+        }
+        let order = liquidation_order(
+            self.state.values().position,
+            mark_price,
+            self.depth.tick_size(),
+            timestamp,
+        );
+        self.state.apply_liquidation(&order);
+        let local_recv_timestamp = timestamp + self.order_latency.response(timestamp, &order);
+        self.orders_to.append(order, local_recv_timestamp);
+        self.close_open_orders(Status::Canceled, timestamp);
+    }
+
+    /// Removes all the open orders, including the conditional orders, and reports them with the
+    /// given status.
+    fn close_open_orders(&mut self, status: Status, timestamp: i64) {
+        self.buy_orders.clear();
+        self.sell_orders.clear();
+        self.gtd_orders.clear();
+        let mut orders: Vec<_> = self
+            .orders
+            .borrow_mut()
+            .drain()
+            .map(|(_, order)| order)
+            .collect();
+        orders.extend(self.conditional_orders.drain().map(|(_, order)| order));
+        orders.sort_by_key(|order| order.order_id);
+        for mut order in orders {
+            order.status = status;
+            order.exch_timestamp = timestamp;
+            let local_recv_timestamp = timestamp + self.order_latency.response(timestamp, &order);
+            self.orders_to.append(order, local_recv_timestamp);
+        }
+    }
+
+    /// Applies the self-trade prevention of the incoming order against the resting orders on the
+    /// other side that it would match. Returns `false` if the incoming order is expired.
+    fn prevent_self_trade(&mut self, order: &mut Order, timestamp: i64) -> bool {
+        let lot_size = self.depth.lot_size();
+        let (actions, expired) = prevent_self_trade(order, self.orders.borrow().values(), lot_size);
+        for action in actions {
+            match action {
+                SelfTradeAction::Expire(order_id) => {
+                    self.expire_resting_order(order_id, timestamp);
+                }
+                SelfTradeAction::Decrement(order_id, qty) => {
+                    let resting = {
+                        let mut orders = self.orders.borrow_mut();
+                        let resting = orders.get_mut(&order_id).unwrap();
+                        resting.qty -= qty;
+                        resting.leaves_qty -= qty;
+                        resting.exch_timestamp = timestamp;
+                        ((resting.leaves_qty / lot_size).round() > 0.0).then(|| resting.clone())
+                    };
+                    match resting {
+                        Some(resting) => {
+                            let local_recv_timestamp =
+                                timestamp + self.order_latency.response(timestamp, &resting);
+                            self.orders_to.append(resting, local_recv_timestamp);
+                        }
+                        None => self.expire_resting_order(order_id, timestamp),
+                    }
+                }
+            }
+        }
+        if expired {
+            self.expire_order(order, timestamp);
+        }
+        !expired
+    }
+
+    /// Expires the order that is not resting in the order book.
+    fn expire_order(&mut self, order: &mut Order, timestamp: i64) {
+        order.status = Status::Expired;
+        order.exch_timestamp = timestamp;
+        let local_recv_timestamp = timestamp + self.order_latency.response(timestamp, order);
+        self.orders_to.append(order.clone(), local_recv_timestamp);
+    }
+
+    /// Removes the resting order from the order book and expires it.
+    fn expire_resting_order(&mut self, order_id: OrderId, timestamp: i64) {
+        let mut order = self.orders.borrow_mut().remove(&order_id).unwrap();
+        if order.side == Side::Buy {
+            self.buy_orders
+                .get_mut(&order.price_tick)
+                .unwrap()
+                .remove(&order_id);
+        } else {
+            self.sell_orders
+                .get_mut(&order.price_tick)
+                .unwrap()
+                .remove(&order_id);
+        }
+        self.expire_order(&mut order, timestamp);
+    }
+
+    /// Returns the price against which the trigger price of a conditional order is evaluated.
+    fn reference_price(&self, trigger_price_type: TriggerPriceType) -> f64 {
+        match trigger_price_type {
+            TriggerPriceType::MarkPrice => mark_price(self.mark_price, &self.depth),
+            _ => self.last_price,
+        }
+    }
+
+    /// Accepts the conditional order unless it would be triggered immediately.
+    fn ack_conditional(&mut self, mut order: Order, timestamp: i64) {
+        if order.is_triggered_at(self.reference_price(order.trigger_price_type)) {
+            order.req = Status::Rejected;
+        } else {
+            order.status = Status::New;
+        }
+        order.exch_timestamp = timestamp;
+        let local_recv_timestamp = timestamp + self.order_latency.response(timestamp, &order);
+        self.orders_to.append(order.clone(), local_recv_timestamp);
+        if order.status == Status::New {
+            self.conditional_orders.insert(order.order_id, order);
+        }
+    }
+
+    /// Triggers the conditional orders whose trigger price is reached, processing them as new
+    /// market or limit orders.
+    fn trigger_orders(&mut self, timestamp: i64) -> Result<(), BacktestError> {
+        if self.conditional_orders.is_empty() {
+            return Ok(());
+        }
+        let mut triggered: Vec<_> = self
+            .conditional_orders
+            .values()
+            .filter(|order| order.is_triggered_at(self.reference_price(order.trigger_price_type)))
+            .map(|order| order.order_id)
+            .collect();
+        triggered.sort();
+        for order_id in triggered {
+            let mut order = self.conditional_orders.remove(&order_id).unwrap();
+            order.order_type = order.order_type.triggered();
+            if order.oco {
+                self.oco_to_expire
+                    .push((order.oco_order_id, order.order_id));
+            }
+            self.ack_new(order, timestamp)?;
+        }
+        Ok(())
+    }
+
+    /// Expires the other orders of the one-cancels-other pairs whose orders have been executed or
+    /// triggered.
+    fn expire_oco_orders(&mut self, timestamp: i64) {
+        if self.oco_to_expire.is_empty() {
+            return;
+        }
+        for (order_id, counterpart_id) in mem::take(&mut self.oco_to_expire) {
+            let is_counterpart = |order: &Order| order.oco && order.oco_order_id == counterpart_id;
+            if self
+                .conditional_orders
+                .get(&order_id)
+                .is_some_and(is_counterpart)
+            {
+                let mut order = self.conditional_orders.remove(&order_id).unwrap();
+                self.expire_order(&mut order, timestamp);
+            } else if self
+                .orders
+                .borrow()
+                .get(&order_id)
+                .is_some_and(is_counterpart)
+            {
+                self.expire_resting_order(order_id, timestamp);
+            } else if !self.conditional_orders.contains_key(&order_id)
+                && !self.orders.borrow().contains_key(&order_id)
+            {
+                // The order may not be received yet.
+                self.oco_expired.insert(order_id, counterpart_id);
+            }
+        }
+    }
+
+    /// Expires the good-till-date orders whose expiry timestamp has been reached.
+    fn expire_gtd_orders(&mut self, timestamp: i64) {
+        while let Some(&(expire_timestamp, order_id)) = self.gtd_orders.first() {
+            if expire_timestamp > timestamp {
+                break;
+            }
+            self.gtd_orders.pop_first();
+            let is_due = |order: &Order| {
+                order.time_in_force == TimeInForce::GTD
+                    && order.expire_timestamp == expire_timestamp
+            };
+            if self.conditional_orders.get(&order_id).is_some_and(is_due) {
+                let mut order = self.conditional_orders.remove(&order_id).unwrap();
+                self.expire_order(&mut order, timestamp);
+            } else if self.orders.borrow().get(&order_id).is_some_and(is_due) {
+                self.expire_resting_order(order_id, timestamp);
+            }
+        }
+    }
+
+    /// Reduces the quantity of the reduce-only order so that it does not exceed the position.
+    /// Returns `false` if the order would increase the position.
+    fn clip_reduce_only(&self, order: &mut Order) -> bool {
+        let reducible_qty = self.state.reducible_qty(order.side);
+        if (reducible_qty / self.depth.lot_size()).round() <= 0.0 {
+            return false;
+        }
+        if order.leaves_qty > reducible_qty {
+            order.qty -= order.leaves_qty - reducible_qty;
+            order.leaves_qty = reducible_qty;
+        }
+        true
+    }
+
+    /// Expires the resting reduce-only orders that would increase the position since the position
+    /// has been closed or reversed. The orders are checked only if the position has changed since
+    /// the last check.
+    fn expire_reduce_only_orders(&mut self, timestamp: i64) {
+        if !mem::take(&mut self.position_changed) || self.reduce_only_orders.is_empty() {
+            return;
+        }
+        let orders = self.orders.borrow();
+        let mut expired = Vec::new();
+        self.reduce_only_orders
+            .retain(|order_id| match orders.get(order_id) {
+                Some(order) if order.reduce_only => {
+                    if (self.state.reducible_qty(order.side) / self.depth.lot_size()).round() <= 0.0
+                    {
+                        expired.push(*order_id);
+                        false
+                    } else {
+                        true
+                    }
+                }
+                _ => false,
+            });
+        drop(orders);
+        for order_id in expired {
+            self.expire_resting_order(order_id, timestamp);
         }
     }
 
@@ -132,6 +532,9 @@ where
         mut order: Order,
         recv_timestamp: i64,
     ) -> Result<(), BacktestError> {
+        if let Some(tracer) = &self.tracer {
+            tracer.order_request(self.asset_no, &order);
+        }
         // Processes a new order.
         if order.req == Status::New {
             order.req = Status::None;
@@ -141,9 +544,16 @@ where
         else if order.req == Status::Canceled {
             order.req = Status::None;
             self.ack_cancel(order, recv_timestamp)?;
+        }
+        // Processes a modify order.
+        else if order.req == Status::Replaced {
+            order.req = Status::None;
+            self.ack_modify(order, recv_timestamp)?;
         } else {
             return Err(BacktestError::InvalidOrderRequest);
         }
+        self.expire_oco_orders(recv_timestamp);
+        self.expire_reduce_only_orders(recv_timestamp);
         Ok(())
     }
 
@@ -163,6 +573,7 @@ where
             Ordering::Equal => {
                 // Updates the order's queue position.
                 self.queue_model.trade(order, qty, &self.depth);
+                self.trace_queue_position(order);
                 if self.queue_model.is_filled(order, &self.depth) > 0.0 {
                     self.filled_orders.push(order.order_id);
                     return self.fill(order, timestamp, true, order.price_tick);
@@ -188,6 +599,7 @@ where
             Ordering::Equal => {
                 // Updates the order's queue position.
                 self.queue_model.trade(order, qty, &self.depth);
+                self.trace_queue_position(order);
                 if self.queue_model.is_filled(order, &self.depth) > 0.0 {
                     self.filled_orders.push(order.order_id);
                     return self.fill(order, timestamp, true, order.price_tick);
@@ -197,6 +609,12 @@ where
         Ok(())
     }
 
+    fn trace_queue_position(&self, order: &Order) {
+        if let Some(tracer) = &self.tracer {
+            tracer.queue_position(self.asset_no, order, self.queue_model.queue_position(order));
+        }
+    }
+
     fn fill(
         &mut self,
         order: &mut Order,
@@ -211,9 +629,16 @@ where
             return Err(BacktestError::InvalidOrderStatus);
         }
 
+        if order.reduce_only && !self.clip_reduce_only(order) {
+            // The position has been closed or reversed by the preceding fills.
+            self.expire_order(order, timestamp);
+            return Ok(());
+        }
+
         order.maker = maker;
         if maker {
             order.exec_price_tick = order.price_tick;
+            order.avg_exec_price = 0.0;
         } else {
             order.exec_price_tick = exec_price_tick;
         }
@@ -225,11 +650,122 @@ where
         let local_recv_timestamp =
             order.exch_timestamp + self.order_latency.response(timestamp, order);
 
+        if order.oco {
+            self.oco_to_expire
+                .push((order.oco_order_id, order.order_id));
+        }
+
         self.state.apply_fill(order);
+        self.position_changed = true;
+        if let Some(tracer) = &self.tracer {
+            tracer.fill(self.asset_no, order);
+        }
         self.orders_to.append(order.clone(), local_recv_timestamp);
         Ok(())
     }
 
+    /// Returns the quantity that a liquidity-taking order on the `side` can take at the price level
+    /// on the other side of the book.
+    fn available_qty(&self, side: Side, price_tick: i64, timestamp: i64) -> f64 {
+        let (book_side, depth_qty) = match side {
+            Side::Buy => (Side::Sell, self.depth.ask_qty_at_tick(price_tick)),
+            _ => (Side::Buy, self.depth.bid_qty_at_tick(price_tick)),
+        };
+        match &self.market_impact {
+            Some(market_impact) => {
+                let lot_size = self.depth.lot_size();
+                let available =
+                    market_impact.available(book_side, price_tick, depth_qty, timestamp);
+                (available / lot_size).round() * lot_size
+            }
+            None => depth_qty,
+        }
+    }
+
+    /// Sweeps the price levels on the other side of the book from the best, up to and including
+    /// `limit_tick` if given, for up to `qty`. Returns the quantity taken at each price level.
+    fn sweep(
+        &self,
+        side: Side,
+        limit_tick: Option<i64>,
+        qty: f64,
+        timestamp: i64,
+    ) -> Vec<(i64, f64)> {
+        let book_side = if side == Side::Buy {
+            Side::Sell
+        } else {
+            Side::Buy
+        };
+        let lot_size = self.depth.lot_size();
+        let mut levels = Vec::new();
+        let mut leaves_qty = qty;
+        for t in depth_levels(&self.depth, book_side, limit_tick) {
+            if (leaves_qty / lot_size).round() <= 0.0 {
+                break;
+            }
+            let available = self.available_qty(side, t, timestamp);
+            if available > 0.0 {
+                let exec_qty = available.min(leaves_qty);
+                levels.push((t, exec_qty));
+                leaves_qty -= exec_qty;
+            }
+        }
+        levels
+    }
+
+    /// Takes the market. See [`NoPartialFillExchange`] for how the liquidity-taking order is
+    /// executed.
+    fn take(&mut self, order: &mut Order, timestamp: i64) -> Result<(), BacktestError> {
+        let best_tick = if order.side == Side::Buy {
+            self.depth.best_ask_tick()
+        } else {
+            self.depth.best_bid_tick()
+        };
+        if self.market_impact.is_none() {
+            return self.fill(order, timestamp, false, best_tick);
+        }
+
+        // A market order sweeps the entire book.
+        let limit_tick = (order.order_type != OrdType::Market).then_some(order.price_tick);
+        let mut levels = self.sweep(order.side, limit_tick, order.leaves_qty, timestamp);
+        let leaves_qty = order.leaves_qty - levels.iter().map(|(_, qty)| qty).sum::<f64>();
+        if (leaves_qty / self.depth.lot_size()).round() > 0.0 {
+            let remainder_tick =
+                limit_tick.unwrap_or_else(|| levels.last().map(|(t, _)| *t).unwrap_or(best_tick));
+            levels.push((remainder_tick, leaves_qty));
+        }
+        self.fill_levels(order, timestamp, &levels)
+    }
+
+    /// Executes the liquidity-taking order across the price levels in a single fill at the
+    /// volume-weighted average price, and records the consumed liquidity.
+    fn fill_levels(
+        &mut self,
+        order: &mut Order,
+        timestamp: i64,
+        levels: &[(i64, f64)],
+    ) -> Result<(), BacktestError> {
+        let exec_price_tick = levels.last().unwrap().0;
+        if let Some(market_impact) = self.market_impact.as_mut() {
+            let book_side = if order.side == Side::Buy {
+                Side::Sell
+            } else {
+                Side::Buy
+            };
+            for (t, qty) in levels {
+                market_impact.consume(book_side, *t, *qty, timestamp);
+            }
+        }
+        order.avg_exec_price = if levels.iter().any(|(t, _)| *t != exec_price_tick) {
+            let exec_qty: f64 = levels.iter().map(|(_, qty)| qty).sum();
+            let exec_value: f64 = levels.iter().map(|(t, qty)| *t as f64 * qty).sum();
+            exec_value / exec_qty * order.tick_size
+        } else {
+            0.0
+        };
+        self.fill(order, timestamp, false, exec_price_tick)
+    }
+
     fn remove_filled_orders(&mut self) {
         if !self.filled_orders.is_empty() {
             let mut orders = self.orders.borrow_mut();
@@ -250,7 +786,37 @@ where
         }
     }
 
-    fn on_bid_qty_chg(&mut self, price_tick: i64, prev_qty: f64, new_qty: f64) {
+    /// Re-queues the orders on the side priced between the previous and the new best, excluding
+    /// the new best, when the best bid or offer moves. Since a best bid and offer feed carries the
+    /// quantity at the touch only, the quantity ahead of the orders that the touch has moved away
+    /// from is no longer known, so their queue positions are estimated again by the queue model.
+    fn requeue_orders(&mut self, side: Side, prev_best_tick: i64, new_best_tick: i64) {
+        if prev_best_tick == new_best_tick {
+            return;
+        }
+        let (low_tick, high_tick) = if prev_best_tick < new_best_tick {
+            (prev_best_tick, new_best_tick)
+        } else {
+            (new_best_tick, prev_best_tick)
+        };
+        let orders = self.orders.clone();
+        let mut orders_borrowed = orders.borrow_mut();
+        for order in orders_borrowed.values_mut() {
+            if order.side == side
+                && order.price_tick != new_best_tick
+                && order.price_tick >= low_tick
+                && order.price_tick <= high_tick
+            {
+                self.queue_model.new_order(order, &self.depth);
+                self.trace_queue_position(order);
+            }
+        }
+    }
+
+    fn on_bid_qty_chg(&mut self, price_tick: i64, prev_qty: f64, new_qty: f64, timestamp: i64) {
+        if let Some(market_impact) = self.market_impact.as_mut() {
+            market_impact.replenish(Side::Buy, price_tick, prev_qty, new_qty, timestamp);
+        }
         let orders = self.orders.clone();
         if let Some(order_ids) = self.buy_orders.get(&price_tick) {
             for order_id in order_ids.iter() {
@@ -258,11 +824,15 @@ where
                 let order = orders_borrowed.get_mut(order_id).unwrap();
                 self.queue_model
                     .depth(order, prev_qty, new_qty, &self.depth);
+                self.trace_queue_position(order);
             }
         }
     }
 
-    fn on_ask_qty_chg(&mut self, price_tick: i64, prev_qty: f64, new_qty: f64) {
+    fn on_ask_qty_chg(&mut self, price_tick: i64, prev_qty: f64, new_qty: f64, timestamp: i64) {
+        if let Some(market_impact) = self.market_impact.as_mut() {
+            market_impact.replenish(Side::Sell, price_tick, prev_qty, new_qty, timestamp);
+        }
         let orders = self.orders.clone();
         if let Some(order_ids) = self.sell_orders.get(&price_tick) {
             for order_id in order_ids.iter() {
@@ -270,6 +840,7 @@ where
                 let order = orders_borrowed.get_mut(order_id).unwrap();
                 self.queue_model
                     .depth(order, prev_qty, new_qty, &self.depth);
+                self.trace_queue_position(order);
             }
         }
     }
@@ -351,6 +922,56 @@ where
             return Err(BacktestError::OrderIdExist);
         }
 
+        if self.expiry.is_expired()
+            || (order.time_in_force == TimeInForce::GTD && order.expire_timestamp <= timestamp)
+            || !self.is_covered(&order)
+        {
+            // Rejects the order as the contract or the good-till-date order has expired, or due to
+            // insufficient inventory or margin.
+            if order.status == Status::New {
+                // The triggered conditional order has already been accepted, so it is expired
+                // instead.
+                self.expire_order(&mut order, timestamp);
+                return Ok(());
+            }
+            order.req = Status::Rejected;
+            order.exch_timestamp = timestamp;
+            let local_recv_timestamp = timestamp + self.order_latency.response(timestamp, &order);
+            self.orders_to.append(order, local_recv_timestamp);
+            return Ok(());
+        }
+
+        if order.oco && self.oco_expired.get(&order.order_id) == Some(&order.oco_order_id) {
+            // The counterpart of this one-cancels-other order has already been executed or
+            // triggered.
+            self.oco_expired.remove(&order.order_id);
+            self.expire_order(&mut order, timestamp);
+            return Ok(());
+        }
+
+        if order.time_in_force == TimeInForce::GTD {
+            self.gtd_orders
+                .insert((order.expire_timestamp, order.order_id));
+        }
+
+        if order.order_type.is_conditional() {
+            self.ack_conditional(order, timestamp);
+            return Ok(());
+        }
+
+        if order.reduce_only {
+            if !self.clip_reduce_only(&mut order) {
+                // The reduce-only order would increase the position.
+                self.expire_order(&mut order, timestamp);
+                return Ok(());
+            }
+            self.reduce_only_orders.insert(order.order_id);
+        }
+
+        if !self.prevent_self_trade(&mut order, timestamp) {
+            return Ok(());
+        }
+
         if order.side == Side::Buy {
             match order.order_type {
                 OrdType::Limit => {
@@ -366,19 +987,23 @@ where
                                 self.orders_to.append(order.clone(), local_recv_timestamp);
                                 Ok(())
                             }
-                            TimeInForce::GTC | TimeInForce::FOK | TimeInForce::IOC => {
+                            TimeInForce::GTC
+                            | TimeInForce::GTD
+                            | TimeInForce::FOK
+                            | TimeInForce::IOC => {
                                 // Since this always fills the full quantity, both FOK and IOC
                                 // orders are also fully filled at the best price.
                                 // Takes the market.
-                                self.fill(&mut order, timestamp, false, self.depth.best_ask_tick())
+                                self.take(&mut order, timestamp)
                             }
                             TimeInForce::Unsupported => Err(BacktestError::InvalidOrderRequest),
                         }
                     } else {
                         match order.time_in_force {
-                            TimeInForce::GTC | TimeInForce::GTX => {
+                            TimeInForce::GTC | TimeInForce::GTD | TimeInForce::GTX => {
                                 // Initializes the order's queue position.
                                 self.queue_model.new_order(&mut order, &self.depth);
+                                self.trace_queue_position(&order);
                                 order.status = Status::New;
                                 // The exchange accepts this order.
                                 self.buy_orders
@@ -408,9 +1033,9 @@ where
                 }
                 OrdType::Market => {
                     // Takes the market.
-                    self.fill(&mut order, timestamp, false, self.depth.best_ask_tick())
+                    self.take(&mut order, timestamp)
                 }
-                OrdType::Unsupported => Err(BacktestError::InvalidOrderRequest),
+                _ => Err(BacktestError::InvalidOrderRequest),
             }
         } else {
             match order.order_type {
@@ -427,19 +1052,23 @@ where
                                 self.orders_to.append(order.clone(), local_recv_timestamp);
                                 Ok(())
                             }
-                            TimeInForce::GTC | TimeInForce::FOK | TimeInForce::IOC => {
+                            TimeInForce::GTC
+                            | TimeInForce::GTD
+                            | TimeInForce::FOK
+                            | TimeInForce::IOC => {
                                 // Since this always fills the full quantity, both FOK and IOC
                                 // orders are also fully filled at the best price.
                                 // Takes the market.
-                                self.fill(&mut order, timestamp, false, self.depth.best_bid_tick())
+                                self.take(&mut order, timestamp)
                             }
                             TimeInForce::Unsupported => Err(BacktestError::InvalidOrderRequest),
                         }
                     } else {
                         match order.time_in_force {
-                            TimeInForce::GTC | TimeInForce::GTX => {
+                            TimeInForce::GTC | TimeInForce::GTD | TimeInForce::GTX => {
                                 // Initializes the order's queue position.
                                 self.queue_model.new_order(&mut order, &self.depth);
+                                self.trace_queue_position(&order);
                                 order.status = Status::New;
                                 // The exchange accepts this order.
                                 self.sell_orders
@@ -469,14 +1098,23 @@ where
                 }
                 OrdType::Market => {
                     // Takes the market.
-                    self.fill(&mut order, timestamp, false, self.depth.best_bid_tick())
+                    self.take(&mut order, timestamp)
                 }
-                OrdType::Unsupported => Err(BacktestError::InvalidOrderRequest),
+                _ => Err(BacktestError::InvalidOrderRequest),
             }
         }
     }
 
     fn ack_cancel(&mut self, mut order: Order, timestamp: i64) -> Result<(), BacktestError> {
+        if let Some(mut exch_order) = self.conditional_orders.remove(&order.order_id) {
+            exch_order.status = Status::Canceled;
+            exch_order.exch_timestamp = timestamp;
+            let local_recv_timestamp =
+                timestamp + self.order_latency.response(timestamp, &exch_order);
+            self.orders_to.append(exch_order, local_recv_timestamp);
+            return Ok(());
+        }
+
         let exch_order = {
             let mut order_borrowed = self.orders.borrow_mut();
             order_borrowed.remove(&order.order_id)
@@ -514,6 +1152,15 @@ where
     }
 
     fn ack_modify(&mut self, mut order: Order, timestamp: i64) -> Result<(), BacktestError> {
+        if self.conditional_orders.contains_key(&order.order_id) {
+            // Conditional orders cannot be modified.
+            order.req = Status::Rejected;
+            order.exch_timestamp = timestamp;
+            let local_recv_timestamp = timestamp + self.order_latency.response(timestamp, &order);
+            self.orders_to.append(order, local_recv_timestamp);
+            return Ok(());
+        }
+
         let mut exch_order = {
             let mut order_borrowed = self.orders.borrow_mut();
             let exch_order = order_borrowed.remove(&order.order_id);
@@ -531,14 +1178,31 @@ where
             exch_order.unwrap()
         };
 
+        // The inventory and the margin should cover the modified order as they would a new order.
+        let mut modified = order.clone();
+        modified.leaves_qty = order.qty;
+        if !self.is_covered(&modified) {
+            self.orders
+                .borrow_mut()
+                .insert(exch_order.order_id, exch_order);
+
+            order.req = Status::Rejected;
+            order.exch_timestamp = timestamp;
+            let local_recv_timestamp = timestamp + self.order_latency.response(timestamp, &order);
+            self.orders_to.append(order, local_recv_timestamp);
+            return Ok(());
+        }
+
         let prev_price_tick = exch_order.price_tick;
+        let prev_qty = exch_order.qty;
         exch_order.price_tick = order.price_tick;
         // No partial fill occurs.
         exch_order.qty = order.qty;
-        // The initialization of the order queue position may not occur when the modified quantity
-        // is smaller than the previous quantity, depending on the exchanges. It may need to
-        // implement exchange-specific specialization.
-        let init_q_pos = true;
+        exch_order.leaves_qty = order.qty;
+        // The order loses its queue priority if its quantity increases, but retains it if its
+        // quantity decreases.
+        let init_q_pos = (exch_order.qty / self.depth.lot_size()).round()
+            > (prev_qty / self.depth.lot_size()).round();
 
         if exch_order.side == Side::Buy {
             // Checks if the buy order price is greater than or equal to the current best ask.
@@ -550,14 +1214,11 @@ where
 
                 if exch_order.time_in_force == TimeInForce::GTX {
                     exch_order.status = Status::Expired;
+                } else if !self.prevent_self_trade(&mut exch_order, timestamp) {
+                    return Ok(());
                 } else {
                     // Takes the market.
-                    return self.fill(
-                        &mut exch_order,
-                        timestamp,
-                        false,
-                        self.depth.best_ask_tick(),
-                    );
+                    return self.take(&mut exch_order, timestamp);
                 }
 
                 exch_order.exch_timestamp = timestamp;
@@ -581,6 +1242,7 @@ where
                 if init_q_pos || prev_price_tick != exch_order.price_tick {
                     // Initializes the order's queue position.
                     self.queue_model.new_order(&mut exch_order, &self.depth);
+                    self.trace_queue_position(&exch_order);
                 }
                 exch_order.status = Status::New;
 
@@ -605,14 +1267,11 @@ where
 
                 if exch_order.time_in_force == TimeInForce::GTX {
                     exch_order.status = Status::Expired;
+                } else if !self.prevent_self_trade(&mut exch_order, timestamp) {
+                    return Ok(());
                 } else {
                     // Takes the market.
-                    return self.fill(
-                        &mut exch_order,
-                        timestamp,
-                        false,
-                        self.depth.best_bid_tick(),
-                    );
+                    return self.take(&mut exch_order, timestamp);
                 }
 
                 exch_order.exch_timestamp = timestamp;
@@ -636,6 +1295,7 @@ where
                 if init_q_pos || prev_price_tick != exch_order.price_tick {
                     // Initialize the order's queue position.
                     self.queue_model.new_order(&mut exch_order, &self.depth);
+                    self.trace_queue_position(&exch_order);
                 }
                 exch_order.status = Status::New;
 
@@ -659,7 +1319,7 @@ where
     AT: AssetType,
     LM: LatencyModel,
     QM: QueueModel<MD>,
-    MD: MarketDepth + L2MarketDepth,
+    MD: MarketDepth + L2MarketDepth + ApplySnapshot,
     FM: FeeModel,
 {
     fn initialize_data(&mut self) -> Result<i64, BacktestError> {
@@ -667,7 +1327,7 @@ where
         for rn in 0..self.data.len() {
             if self.data[rn].is(EXCH_EVENT) {
                 self.row_num = rn;
-                return Ok(self.data[rn].exch_ts);
+                return Ok(self.expiry.next_timestamp(self.data[rn].exch_ts));
             }
         }
         Err(BacktestError::EndOfData)
@@ -675,6 +1335,11 @@ where
 
     fn process_data(&mut self) -> Result<(i64, i64), BacktestError> {
         let row_num = self.row_num;
+        if self.expiry.is_due(self.data[row_num].exch_ts) {
+            self.expire(self.expiry.timestamp());
+            return Ok((self.data[row_num].exch_ts, i64::MAX));
+        }
+        self.expire_gtd_orders(self.data[row_num].exch_ts);
         if self.data[row_num].is(EXCH_BID_DEPTH_CLEAR_EVENT) {
             self.depth.clear_depth(Side::Buy, self.data[row_num].px);
         } else if self.data[row_num].is(EXCH_ASK_DEPTH_CLEAR_EVENT) {
@@ -690,7 +1355,7 @@ where
                     self.data[row_num].qty,
                     self.data[row_num].exch_ts,
                 );
-            self.on_bid_qty_chg(price_tick, prev_qty, new_qty);
+            self.on_bid_qty_chg(price_tick, prev_qty, new_qty, timestamp);
             if best_bid_tick > prev_best_bid_tick {
                 self.on_best_bid_update(prev_best_bid_tick, best_bid_tick, timestamp)?;
             }
@@ -703,7 +1368,33 @@ where
                     self.data[row_num].qty,
                     self.data[row_num].exch_ts,
                 );
-            self.on_ask_qty_chg(price_tick, prev_qty, new_qty);
+            self.on_ask_qty_chg(price_tick, prev_qty, new_qty, timestamp);
+            if best_ask_tick < prev_best_ask_tick {
+                self.on_best_ask_update(prev_best_ask_tick, best_ask_tick, timestamp)?;
+            }
+        } else if self.data[row_num].is(EXCH_BID_DEPTH_BBO_EVENT) {
+            let (price_tick, prev_best_bid_tick, best_bid_tick, prev_qty, new_qty, timestamp) =
+                update_best_bid(
+                    &mut self.depth,
+                    self.data[row_num].px,
+                    self.data[row_num].qty,
+                    self.data[row_num].exch_ts,
+                );
+            self.on_bid_qty_chg(price_tick, prev_qty, new_qty, timestamp);
+            self.requeue_orders(Side::Buy, prev_best_bid_tick, best_bid_tick);
+            if best_bid_tick > prev_best_bid_tick {
+                self.on_best_bid_update(prev_best_bid_tick, best_bid_tick, timestamp)?;
+            }
+        } else if self.data[row_num].is(EXCH_ASK_DEPTH_BBO_EVENT) {
+            let (price_tick, prev_best_ask_tick, best_ask_tick, prev_qty, new_qty, timestamp) =
+                update_best_ask(
+                    &mut self.depth,
+                    self.data[row_num].px,
+                    self.data[row_num].qty,
+                    self.data[row_num].exch_ts,
+                );
+            self.on_ask_qty_chg(price_tick, prev_qty, new_qty, timestamp);
+            self.requeue_orders(Side::Sell, prev_best_ask_tick, best_ask_tick);
             if best_ask_tick < prev_best_ask_tick {
                 self.on_best_ask_update(prev_best_ask_tick, best_ask_tick, timestamp)?;
             }
@@ -781,6 +1472,16 @@ where
             self.remove_filled_orders();
         }
 
+        if self.data[row_num].is(EXCH_BUY_TRADE_EVENT)
+            || self.data[row_num].is(EXCH_SELL_TRADE_EVENT)
+        {
+            self.last_price = self.data[row_num].px;
+        }
+        self.trigger_orders(self.data[row_num].exch_ts)?;
+        self.expire_oco_orders(self.data[row_num].exch_ts);
+        self.expire_reduce_only_orders(self.data[row_num].exch_ts);
+        self.check_margin(self.data[row_num].exch_ts);
+
         // Checks
         let mut next_ts = 0;
         for rn in (self.row_num + 1)..self.data.len() {
@@ -799,7 +1500,7 @@ where
             self.reader.release(data);
             self.row_num = 0;
         }
-        Ok((next_ts, i64::MAX))
+        Ok((self.expiry.next_timestamp(next_ts), i64::MAX))
     }
 
     fn process_recv_order(
@@ -818,6 +1519,7 @@ where
                 break;
             }
         }
+        self.check_margin(timestamp);
         Ok(false)
     }
 
@@ -828,4 +1530,98 @@ where
     fn earliest_send_order_timestamp(&self) -> i64 {
         self.orders_to.earliest_timestamp().unwrap_or(i64::MAX)
     }
+
+    fn checkpoint(&self) -> Result<Vec<u8>, BacktestError> {
+        let mut orders = Vec::new();
+        for order in self.orders.borrow().values() {
+            orders.push((order.clone(), self.queue_model.encode_q(order)?));
+        }
+        checkpoint::encode(&ExchangeState {
+            data_pos: self.reader.position(),
+            row_num: self.row_num,
+            orders,
+            orders_to: self.orders_to.to_vec(),
+            depth: self.depth.snapshot(),
+            state_values: self.state.values().clone(),
+            expiry: self.expiry,
+            market_impact: self.market_impact.clone(),
+            conditional_orders: self.conditional_orders.clone(),
+            oco_expired: self.oco_expired.clone(),
+            last_price: self.last_price,
+            mark_price: self.mark_price,
+        })
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<(), BacktestError> {
+        let state: ExchangeState = checkpoint::decode(state)?;
+        restore_data(&mut self.reader, &mut self.data, state.data_pos)?;
+        self.row_num = state.row_num;
+
+        let mut orders = self.orders.borrow_mut();
+        orders.clear();
+        self.buy_orders.clear();
+        self.sell_orders.clear();
+        for (mut order, q) in state.orders {
+            self.queue_model.decode_q(&mut order, &q)?;
+            if order.side == Side::Buy {
+                self.buy_orders
+                    .entry(order.price_tick)
+                    .or_default()
+                    .insert(order.order_id);
+            } else {
+                self.sell_orders
+                    .entry(order.price_tick)
+                    .or_default()
+                    .insert(order.order_id);
+            }
+            orders.insert(order.order_id, order);
+        }
+        restore_order_bus(&mut self.orders_to, state.orders_to);
+        restore_depth(&mut self.depth, &state.depth);
+        self.state.state_values = state.state_values;
+        self.expiry = state.expiry;
+        self.market_impact = state.market_impact;
+        self.conditional_orders = state.conditional_orders;
+        self.oco_expired = state.oco_expired;
+        self.last_price = state.last_price;
+        self.mark_price = state.mark_price;
+        self.gtd_orders = orders
+            .values()
+            .chain(self.conditional_orders.values())
+            .filter(|order| order.time_in_force == TimeInForce::GTD)
+            .map(|order| (order.expire_timestamp, order.order_id))
+            .collect();
+        self.reduce_only_orders = orders
+            .values()
+            .filter(|order| order.reduce_only)
+            .map(|order| order.order_id)
+            .collect();
+        self.position_changed = true;
+        Ok(())
+    }
+
+    fn on_mark_price(&mut self, mark_price: f64, timestamp: i64) -> Result<(), BacktestError> {
+        self.mark_price = mark_price;
+        self.trigger_orders(timestamp)?;
+        self.expire_oco_orders(timestamp);
+        self.expire_reduce_only_orders(timestamp);
+        self.check_margin(timestamp);
+        Ok(())
+    }
+
+    fn on_funding(
+        &mut self,
+        mark_price: f64,
+        funding_rate: f64,
+        timestamp: i64,
+    ) -> Result<(), BacktestError> {
+        self.state.apply_funding(mark_price, funding_rate);
+        self.check_margin(timestamp);
+        Ok(())
+    }
+
+    fn set_tracer(&mut self, tracer: Tracer, asset_no: usize) {
+        self.tracer = Some(tracer);
+        self.asset_no = asset_no;
+    }
 }
@@ -24,6 +24,7 @@ struct Record {
     num_trades: i64,
     trading_volume: f64,
     trading_value: f64,
+    funding: f64,
 }
 
 unsafe impl POD for Record {}
@@ -57,6 +58,7 @@ impl Recorder for BacktestRecorder {
                 trading_volume: state_values.trading_volume,
                 trading_value: state_values.trading_value,
                 num_trades: state_values.num_trades,
+                funding: state_values.funding,
             });
         }
         Ok(())
@@ -83,8 +85,10 @@ impl BacktestRecorder {
 
     /// Saves record data into a CSV file at the specified path. It creates a separate CSV file for
     /// each asset, with the filename `{prefix}_{asset_no}.csv`.
-    /// The columns are `timestamp`, `mid`, `balance`, `position`, `fee`, `trade_num`,
-    /// `trade_amount`, `trade_qty`.
+    /// The columns are `timestamp`, `balance`, `position`, `fee`, `trading_volume`,
+    /// `trading_value`, `num_trades`, `price`, `funding`. The funding payment is included in the
+    /// balance, and `funding` is recorded separately so that it can be reported apart from the
+    /// trading P&L.
     pub fn to_csv<Prefix, P>(&self, prefix: Prefix, path: P) -> Result<(), Error>
     where
         Prefix: AsRef<str>,
@@ -96,7 +100,7 @@ impl BacktestRecorder {
             let mut file = File::create(file_path)?;
             writeln!(
                 file,
-                "timestamp,balance,position,fee,trading_volume,trading_value,num_trades,price",
+                "timestamp,balance,position,fee,trading_volume,trading_value,num_trades,price,funding",
             )?;
             for Record {
                 timestamp,
@@ -107,11 +111,12 @@ impl BacktestRecorder {
                 trading_value,
                 num_trades,
                 price: mid_price,
+                funding,
             } in values
             {
                 writeln!(
                     file,
-                    "{},{},{},{},{},{},{},{}",
+                    "{},{},{},{},{},{},{},{},{}",
                     timestamp,
                     balance,
                     position,
@@ -120,6 +125,7 @@ impl BacktestRecorder {
                     trading_value,
                     num_trades,
                     mid_price,
+                    funding,
                 )?;
             }
         }
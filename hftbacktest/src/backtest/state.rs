@@ -1,6 +1,11 @@
+use std::rc::Rc;
+
 use crate::{
-    backtest::{assettype::AssetType, models::FeeModel},
-    types::{Order, StateValues},
+    backtest::{
+        assettype::AssetType,
+        models::{FeeModel, MarginModel},
+    },
+    types::{Order, Side, StateValues},
 };
 
 #[derive(Debug)]
@@ -28,21 +33,134 @@ where
                 num_trades: 0,
                 trading_volume: 0.0,
                 trading_value: 0.0,
+                funding: 0.0,
+                initial_margin: 0.0,
+                maintenance_margin: 0.0,
+                num_liquidations: 0,
+                settlement: 0.0,
             },
             fee_model,
             asset_type,
         }
     }
 
+    /// Sets the starting inventories, which are the position and the balance.
+    pub fn with_inventory(mut self, position: f64, balance: f64) -> Self {
+        self.state_values.position = position;
+        self.state_values.balance = balance;
+        self
+    }
+
     #[inline]
     pub fn apply_fill(&mut self, order: &Order) {
+        let amount = self.asset_type.amount(order.exec_price(), order.exec_qty);
+        let fee = self.fee_model.amount(order, amount);
+        let (position_fee, balance_fee) =
+            self.asset_type
+                .fee_deduction(order.side, order.exec_price(), fee);
+        self.state_values.position +=
+            order.exec_qty * AsRef::<f64>::as_ref(&order.side) - position_fee;
+        self.state_values.balance -= amount * AsRef::<f64>::as_ref(&order.side) + balance_fee;
+        self.state_values.fee += fee;
+        self.state_values.num_trades += 1;
+        self.state_values.trading_volume += order.exec_qty;
+        self.state_values.trading_value += amount;
+    }
+
+    /// Settles the funding for the current position at the mark price.
+    #[inline]
+    pub fn apply_funding(&mut self, mark_price: f64, funding_rate: f64) {
+        let position = self.state_values.position;
+        self.state_values.balance += self.asset_type.funding(mark_price, position, funding_rate);
+        self.state_values.funding += self.asset_type.amount(mark_price, position) * funding_rate;
+    }
+
+    /// Calculates the initial margin required for the position and the open orders at the mark
+    /// price. The larger of the buy side and sell side exposures is taken, as only one side can be
+    /// filled in the end.
+    pub fn initial_margin<'a>(
+        &self,
+        margin_model: &dyn MarginModel,
+        mark_price: f64,
+        open_orders: impl Iterator<Item = &'a Order>,
+    ) -> f64 {
+        let mut buy_qty = self.state_values.position;
+        let mut sell_qty = -self.state_values.position;
+        for order in open_orders {
+            match order.side {
+                Side::Buy => buy_qty += order.leaves_qty,
+                Side::Sell => sell_qty += order.leaves_qty,
+                _ => {}
+            }
+        }
+        let qty = buy_qty.max(sell_qty).max(0.0);
+        margin_model.initial_margin(self.asset_type.amount(mark_price, qty))
+    }
+
+    /// Calculates the maintenance margin required for the position at the mark price.
+    pub fn maintenance_margin(&self, margin_model: &dyn MarginModel, mark_price: f64) -> f64 {
+        let notional = self
+            .asset_type
+            .amount(mark_price, self.state_values.position.abs());
+        margin_model.maintenance_margin(notional)
+    }
+
+    /// Returns `true` if the inventories can cover the new order on top of the open orders, which
+    /// is the position for sell orders and the balance for buy orders. This always holds if the
+    /// asset type doesn't require inventories.
+    pub fn has_inventory<'a>(
+        &self,
+        order: &Order,
+        open_orders: impl Iterator<Item = &'a Order>,
+    ) -> bool {
+        if !self.asset_type.requires_inventory() {
+            return true;
+        }
+        let required = |order: &Order| match order.side {
+            Side::Sell => order.leaves_qty,
+            _ => self.asset_type.amount(order.price(), order.leaves_qty),
+        };
+        let inventory = match order.side {
+            Side::Sell => self.state_values.position,
+            _ => self.state_values.balance,
+        };
+        let reserved: f64 = open_orders
+            .filter(|open_order| open_order.side == order.side)
+            .map(required)
+            .sum();
+        required(order) + reserved <= inventory
+    }
+
+    /// Returns the quantity that an order on the given side can execute without increasing the
+    /// position, which is the size of the opposite position.
+    pub fn reducible_qty(&self, side: Side) -> f64 {
+        let position = self.state_values.position;
+        match side {
+            Side::Buy => (-position).max(0.0),
+            Side::Sell => position.max(0.0),
+            Side::None | Side::Unsupported => 0.0,
+        }
+    }
+
+    /// Closes the position by the forced liquidation that the order reports, which is executed at
+    /// the mark price without fees.
+    pub fn apply_liquidation(&mut self, order: &Order) {
         let amount = self.asset_type.amount(order.exec_price(), order.exec_qty);
         self.state_values.position += order.exec_qty * AsRef::<f64>::as_ref(&order.side);
         self.state_values.balance -= amount * AsRef::<f64>::as_ref(&order.side);
-        self.state_values.fee += self.fee_model.amount(order, amount);
         self.state_values.num_trades += 1;
         self.state_values.trading_volume += order.exec_qty;
         self.state_values.trading_value += amount;
+        self.state_values.num_liquidations += 1;
+    }
+
+    /// Settles the position at the settlement price when the contract expires.
+    pub fn apply_settlement(&mut self, settlement_price: f64) {
+        let position = self.state_values.position;
+        let amount = self.asset_type.amount(settlement_price, position.abs()) * position.signum();
+        self.state_values.position = 0.0;
+        self.state_values.balance += amount;
+        self.state_values.settlement += amount;
     }
 
     #[inline]
@@ -60,3 +178,74 @@ where
         &self.state_values
     }
 }
+
+/// The margin account of an asset, which consists of the margin model and the collateral
+/// deposited in addition to the balance.
+///
+/// The exchange processors reject the orders that the collateral plus the equity cannot cover and
+/// liquidate the position that falls below the maintenance margin, while the local processors
+/// report the margin in the [`StateValues`].
+#[derive(Clone)]
+pub struct Margin {
+    margin_model: Rc<dyn MarginModel>,
+    collateral: f64,
+}
+
+impl Margin {
+    pub fn new(margin_model: Rc<dyn MarginModel>, collateral: f64) -> Self {
+        Self {
+            margin_model,
+            collateral,
+        }
+    }
+
+    /// Returns `true` if the collateral plus the equity can cover the initial margin of the
+    /// position and the open orders at the mark price. This always holds if the mark price is not
+    /// available yet.
+    pub fn is_sufficient<'a, AT, FM>(
+        &self,
+        state: &State<AT, FM>,
+        mark_price: f64,
+        open_orders: impl Iterator<Item = &'a Order>,
+    ) -> bool
+    where
+        AT: AssetType,
+        FM: FeeModel,
+    {
+        !mark_price.is_finite()
+            || state.initial_margin(self.margin_model.as_ref(), mark_price, open_orders)
+                <= self.collateral + state.equity(mark_price)
+    }
+
+    /// Returns `true` if the position needs to be liquidated, which happens when the collateral
+    /// plus the equity falls below the maintenance margin at the mark price.
+    pub fn requires_liquidation<AT, FM>(&self, state: &State<AT, FM>, mark_price: f64) -> bool
+    where
+        AT: AssetType,
+        FM: FeeModel,
+    {
+        mark_price.is_finite()
+            && state.values().position != 0.0
+            && self.collateral + state.equity(mark_price)
+                < state.maintenance_margin(self.margin_model.as_ref(), mark_price)
+    }
+
+    /// Updates the initial margin and the maintenance margin in the state values at the mark price.
+    pub fn update<'a, AT, FM>(
+        &self,
+        state: &mut State<AT, FM>,
+        mark_price: f64,
+        open_orders: impl Iterator<Item = &'a Order>,
+    ) where
+        AT: AssetType,
+        FM: FeeModel,
+    {
+        if !mark_price.is_finite() {
+            return;
+        }
+        state.state_values.initial_margin =
+            state.initial_margin(self.margin_model.as_ref(), mark_price, open_orders);
+        state.state_values.maintenance_margin =
+            state.maintenance_margin(self.margin_model.as_ref(), mark_price);
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    num::NonZeroUsize,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use crate::backtest::recorder::BacktestRecorder;
+
+/// Runs a backtest for each parameter in a grid concurrently and collects the
+/// [`BacktestRecorder`] results.
+///
+/// Since a backtest itself is bound to the thread on which it is built, the given closure builds
+/// and runs the backtest for a parameter on a worker thread. To avoid every backtest loading and
+/// decompressing the same data, load the data once as
+/// [`SharedData`](`crate::backtest::data::SharedData`) and build each backtest from
+/// [`SharedData::to_data`](`crate::backtest::data::SharedData::to_data`), so that all backtests
+/// read the same copy of the data. The data is copied only for a backtest whose reader
+/// preprocesses it.
+///
+/// **Example**
+/// ```ignore
+/// let data: Vec<SharedData<Event>> = files
+///     .iter()
+///     .map(|file| read_npz_file(file, "data").map(SharedData::from))
+///     .collect::<Result<_, _>>()?;
+///
+/// let results = ParameterSweep::new(vec![(0.0005, 10), (0.001, 10), (0.001, 20)])
+///     .num_threads(4)
+///     .run(|&(half_spread, grid_num)| {
+///         let data = data.iter().map(|d| DataSource::Data(d.to_data())).collect();
+///         let mut hbt = prepare_backtest(data);
+///         let mut recorder = BacktestRecorder::new(&hbt);
+///         gridtrading(&mut hbt, &mut recorder, half_spread, grid_num)?;
+///         hbt.close()?;
+///         Ok(recorder)
+///     });
+/// ```
+pub struct ParameterSweep<P> {
+    params: Vec<P>,
+    num_threads: usize,
+}
+
+impl<P> ParameterSweep<P>
+where
+    P: Sync,
+{
+    /// Constructs a `ParameterSweep` over the given parameters. By default, the number of threads
+    /// is the available parallelism of the system.
+    pub fn new(params: Vec<P>) -> Self {
+        Self {
+            params,
+            num_threads: thread::available_parallelism()
+                .map(NonZeroUsize::get)
+                .unwrap_or(1),
+        }
+    }
+
+    /// Sets the maximum number of backtests to run concurrently.
+    pub fn num_threads(self, num_threads: usize) -> Self {
+        Self {
+            num_threads: num_threads.max(1),
+            ..self
+        }
+    }
+
+    /// Runs the backtest for each parameter using the given closure, which builds and runs a
+    /// backtest for the parameter and returns its [`BacktestRecorder`].
+    ///
+    /// The results are returned paired with their parameters in the order of the given parameters.
+    pub fn run<F, E>(self, f: F) -> Vec<(P, Result<BacktestRecorder, E>)>
+    where
+        F: Fn(&P) -> Result<BacktestRecorder, E> + Sync,
+        E: Send,
+    {
+        let next = AtomicUsize::new(0);
+        let num_threads = self.num_threads.min(self.params.len());
+        let params = &self.params;
+        let mut results: Vec<_> = thread::scope(|s| {
+            let workers: Vec<_> = (0..num_threads)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut results = Vec::new();
+                        loop {
+                            let i = next.fetch_add(1, Ordering::Relaxed);
+                            if i >= params.len() {
+                                break;
+                            }
+                            results.push((i, f(&params[i])));
+                        }
+                        results
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect()
+        });
+        results.sort_by_key(|(i, _)| *i);
+        self.params
+            .into_iter()
+            .zip(results)
+            .map(|(param, (_, result))| (param, result))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backtest::{
+            data::{DataSource, FeedLatencyAdjustment, Reader, SharedData},
+            fixture::{asset_builder, test_data},
+            recorder::BacktestRecorder,
+            sweep::ParameterSweep,
+            Backtest,
+        },
+        prelude::{Bot, HashMapMarketDepth, OrdType, Recorder, TimeInForce},
+        types::Event,
+    };
+
+    #[test]
+    fn test_shared_data() {
+        let data = test_data();
+        let addr = &data[0] as *const Event;
+        let shared = SharedData::from(data);
+        // The buffer is moved into the shared data without copying.
+        assert_eq!(&shared[0] as *const Event, addr);
+
+        // Every backtest reads the same buffer.
+        let view1 = shared.to_data();
+        let view2 = shared.to_data();
+        assert!(view1.is_shared());
+        assert_eq!(view1.len(), shared.len());
+        assert_eq!(&view1[10] as *const Event, &shared[10] as *const Event);
+        assert_eq!(&view2[10] as *const Event, &shared[10] as *const Event);
+
+        // Modifying requires an owned copy, which leaves the shared buffer untouched.
+        let mut copy = view1.deep_copy();
+        assert!(!copy.is_shared());
+        copy[10].px += 1.0;
+        assert_ne!(copy[10].px, shared[10].px);
+        assert_eq!(view2[10].px, shared[10].px);
+    }
+
+    #[test]
+    fn test_shared_data_preprocessed_copy() {
+        let shared = SharedData::from(test_data());
+
+        // Without a preprocessor, the reader reads the shared buffer.
+        let mut reader = Reader::builder()
+            .data(vec![DataSource::Data(shared.to_data())])
+            .build()
+            .unwrap();
+        let data = reader.next_data().unwrap();
+        assert_eq!(&data[10] as *const Event, &shared[10] as *const Event);
+
+        // Only the backtest whose data is preprocessed gets its own copy.
+        let mut reader = Reader::builder()
+            .data(vec![DataSource::Data(shared.to_data())])
+            .preprocessor(FeedLatencyAdjustment::new(100))
+            .build()
+            .unwrap();
+        let data = reader.next_data().unwrap();
+        assert!(!data.is_shared());
+        assert_eq!(data[10].local_ts, shared[10].local_ts + 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "Shared data is read-only.")]
+    fn test_shared_data_is_read_only() {
+        let shared = SharedData::from(test_data());
+        let mut view = shared.to_data();
+        view[10].px += 1.0;
+    }
+
+    #[test]
+    fn test_parameter_sweep() {
+        let data = SharedData::from(test_data());
+        let qtys = vec![1.0, 2.0, 3.0, 4.0, 5.0];
+
+        let results = ParameterSweep::new(qtys.clone())
+            .num_threads(3)
+            .run(|&qty| {
+                let asset = asset_builder(&data.to_data()).build()?;
+                let mut hbt: Backtest<HashMapMarketDepth> =
+                    Backtest::builder().add_asset(asset).build()?;
+                let mut recorder = BacktestRecorder::new(&hbt);
+                hbt.elapse(10_000)?;
+                hbt.submit_buy_order(0, 1, 100.1, qty, TimeInForce::GTC, OrdType::Limit, true)?;
+                hbt.goto_end()?;
+                recorder.record(&mut hbt)?;
+                assert_eq!(hbt.position(0), qty);
+                hbt.close()?;
+                Ok::<_, anyhow::Error>(recorder)
+            });
+
+        assert_eq!(results.len(), qtys.len());
+        for ((qty, result), expected) in results.into_iter().zip(qtys) {
+            assert_eq!(qty, expected);
+            assert!(result.is_ok());
+        }
+    }
+}
@@ -0,0 +1,455 @@
+use std::{
+    cell::RefCell,
+    fs::File,
+    io::{Error as IoError, ErrorKind, Seek, SeekFrom, Write},
+    path::Path,
+    rc::Rc,
+};
+
+use hftbacktest_derive::NpyDTyped;
+
+use crate::{
+    backtest::data::{read_npy_file, write_npy_data, write_npy_header, POD},
+    types::{Order, OrderId},
+};
+
+/// Indicates that the record is an event processed by the backtester. `ival` is the kind of the
+/// event: `0` local data, `1` local order response, `2` exchange data, `3` exchange order request,
+/// `4` timer, `5` custom data, and `6` funding settlement.
+pub const TRACE_EVENT_INTENT: u64 = 0;
+
+/// Indicates that the record is an order request received by the exchange. `local_ts` is the
+/// timestamp at which the request was sent, `px` and `qty` are the requested price and quantity,
+/// and `ival` is the request [`Status`](crate::types::Status).
+pub const TRACE_ORDER_REQUEST: u64 = 1;
+
+/// Indicates that the record is an order response received by the local. `local_ts` and `exch_ts`
+/// are the order's timestamps, `px` is the order price, `qty` is the leaves quantity, `ival` is the
+/// order [`Status`](crate::types::Status), and `fval` is the executed quantity.
+pub const TRACE_ORDER_RESPONSE: u64 = 2;
+
+/// Indicates that the record is an order's queue position updated by the
+/// [`QueueModel`](crate::backtest::models::QueueModel). `px` is the order price, `qty` is the
+/// leaves quantity, and `fval` is the queue position reported by
+/// [`QueueModel::queue_position`](crate::backtest::models::QueueModel::queue_position).
+pub const TRACE_QUEUE_POSITION: u64 = 3;
+
+/// Indicates that the record is a fill on the exchange. `exch_ts` is the execution timestamp, `px`
+/// is the executed price, `qty` is the executed quantity, and `ival` is `1` if the order is
+/// executed as a maker, or `0` otherwise.
+pub const TRACE_FILL: u64 = 4;
+
+/// A trace record written by [`Tracer`].
+///
+/// The meaning of the fields varies depending on the `kind`; see the `TRACE_*` constants.
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq, NpyDTyped)]
+pub struct TraceRecord {
+    /// Record kind, which is one of the `TRACE_*` constants.
+    pub kind: u64,
+    /// Backtesting timestamp at which the record is written.
+    pub timestamp: i64,
+    pub asset_no: u64,
+    pub order_id: u64,
+    pub local_ts: i64,
+    pub exch_ts: i64,
+    pub px: f64,
+    pub qty: f64,
+    pub ival: i64,
+    pub fval: f64,
+}
+
+unsafe impl POD for TraceRecord {}
+
+/// The number of records buffered in memory before they are written to the file.
+const CHUNK_SIZE: usize = 8192;
+
+struct TraceWriter {
+    file: File,
+    timestamp: i64,
+    chunk: Vec<TraceRecord>,
+    len: usize,
+    error: Option<IoError>,
+}
+
+impl TraceWriter {
+    fn push(&mut self, record: TraceRecord) {
+        self.chunk.push(record);
+        if self.chunk.len() >= CHUNK_SIZE {
+            self.write_chunk();
+        }
+    }
+
+    fn write_chunk(&mut self) {
+        // Once writing fails, the file is no longer consistent, so the subsequent records are
+        // discarded and the error is reported by `flush`.
+        if self.error.is_none() {
+            match write_npy_data(&mut self.file, &self.chunk) {
+                Ok(()) => self.len += self.chunk.len(),
+                Err(error) => self.error = Some(error),
+            }
+        }
+        self.chunk.clear();
+    }
+
+    fn flush(&mut self) -> Result<(), IoError> {
+        self.write_chunk();
+        if let Some(error) = &self.error {
+            return Err(IoError::new(error.kind(), error.to_string()));
+        }
+        self.file.seek(SeekFrom::Start(0))?;
+        write_npy_header::<_, TraceRecord>(&mut self.file, self.len)?;
+        self.file.seek(SeekFrom::End(0))?;
+        self.file.flush()
+    }
+}
+
+impl Drop for TraceWriter {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// Records what the backtester does, including every processed event, every order request and
+/// response, every queue position update, and every fill, in processing order.
+///
+/// It is opt-in and is enabled by setting it on the backtest builder, which shares it with the
+/// backtester and the processors of all assets. Since the backtest is deterministic, running the
+/// same backtest produces the same trace, which can be compared against a live trading log.
+/// Level3 exchanges do not write the queue position records.
+///
+/// The records are streamed to a `npy` file in chunks of 8192 records, so the memory
+/// usage does not grow with the length of the backtest. The file can be loaded by
+/// [`TraceReader::load`] after [`Tracer::flush`] is called or the tracer is dropped.
+///
+/// **Example**
+/// ```ignore
+/// let tracer = Tracer::new("trace.npy")?;
+/// let mut hbt = Backtest::builder()
+///     .add_asset(asset)
+///     .tracer(tracer.clone())
+///     .build()?;
+/// // Runs the strategy.
+/// tracer.flush()?;
+///
+/// let trace = TraceReader::load("trace.npy")?;
+/// for record in trace.order(0, order_id) {
+///     println!("{record:?}");
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Tracer(Rc<RefCell<TraceWriter>>);
+
+impl Tracer {
+    /// Constructs an instance of `Tracer` that writes the records to the `npy` file at the path.
+    pub fn new<P>(path: P) -> Result<Self, IoError>
+    where
+        P: AsRef<Path>,
+    {
+        let mut file = File::create(path)?;
+        write_npy_header::<_, TraceRecord>(&mut file, 0)?;
+        Ok(Self(Rc::new(RefCell::new(TraceWriter {
+            file,
+            timestamp: 0,
+            chunk: Vec::with_capacity(CHUNK_SIZE),
+            len: 0,
+            error: None,
+        }))))
+    }
+
+    /// Records an event processed by the backtester and sets the current timestamp for the
+    /// subsequent records.
+    pub fn event_intent(&self, timestamp: i64, asset_no: usize, kind: usize) {
+        let mut writer = self.0.borrow_mut();
+        writer.timestamp = timestamp;
+        writer.push(TraceRecord {
+            kind: TRACE_EVENT_INTENT,
+            timestamp,
+            asset_no: asset_no as u64,
+            order_id: 0,
+            local_ts: 0,
+            exch_ts: 0,
+            px: 0.0,
+            qty: 0.0,
+            ival: kind as i64,
+            fval: 0.0,
+        });
+    }
+
+    /// Records an order request received by the exchange.
+    pub fn order_request(&self, asset_no: usize, order: &Order) {
+        self.push(TRACE_ORDER_REQUEST, asset_no, order, |record| {
+            record.px = order.price();
+            record.qty = order.qty;
+            record.ival = order.req as i64;
+        });
+    }
+
+    /// Records an order response received by the local.
+    pub fn order_response(&self, asset_no: usize, order: &Order) {
+        self.push(TRACE_ORDER_RESPONSE, asset_no, order, |record| {
+            record.px = order.price();
+            record.qty = order.leaves_qty;
+            record.ival = order.status as i64;
+            record.fval = order.exec_qty;
+        });
+    }
+
+    /// Records an order's queue position.
+    pub fn queue_position(&self, asset_no: usize, order: &Order, queue_position: f64) {
+        self.push(TRACE_QUEUE_POSITION, asset_no, order, |record| {
+            record.px = order.price();
+            record.qty = order.leaves_qty;
+            record.fval = queue_position;
+        });
+    }
+
+    /// Records a fill on the exchange.
+    pub fn fill(&self, asset_no: usize, order: &Order) {
+        self.push(TRACE_FILL, asset_no, order, |record| {
+            record.px = order.exec_price();
+            record.qty = order.exec_qty;
+            record.ival = order.maker as i64;
+        });
+    }
+
+    fn push<F>(&self, kind: u64, asset_no: usize, order: &Order, f: F)
+    where
+        F: FnOnce(&mut TraceRecord),
+    {
+        let mut writer = self.0.borrow_mut();
+        let mut record = TraceRecord {
+            kind,
+            timestamp: writer.timestamp,
+            asset_no: asset_no as u64,
+            order_id: order.order_id,
+            local_ts: order.local_timestamp,
+            exch_ts: order.exch_timestamp,
+            px: 0.0,
+            qty: 0.0,
+            ival: 0,
+            fval: 0.0,
+        };
+        f(&mut record);
+        writer.push(record);
+    }
+
+    /// Returns the number of records.
+    pub fn len(&self) -> usize {
+        let writer = self.0.borrow();
+        writer.len + writer.chunk.len()
+    }
+
+    /// Returns `true` if there are no records.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Writes the buffered records to the file and updates the number of records in its header,
+    /// after which the file can be loaded by [`TraceReader::load`]. The tracer can continue
+    /// writing afterward. Returns the error if writing any of the records has failed.
+    pub fn flush(&self) -> Result<(), IoError> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+/// Provides queries over the records written by [`Tracer`].
+pub struct TraceReader {
+    records: Vec<TraceRecord>,
+}
+
+impl TraceReader {
+    /// Loads the records from the `npy` file written by [`Tracer`].
+    pub fn load<P>(path: P) -> Result<Self, IoError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path
+            .as_ref()
+            .to_str()
+            .ok_or_else(|| IoError::new(ErrorKind::InvalidInput, "path is not valid UTF-8"))?;
+        let data = read_npy_file::<TraceRecord>(path)?;
+        let records = (0..data.len()).map(|i| data[i].clone()).collect();
+        Ok(Self { records })
+    }
+
+    /// Returns all records in processing order.
+    pub fn records(&self) -> &[TraceRecord] {
+        &self.records
+    }
+
+    /// Returns the records related to the order, which are the order requests, the order
+    /// responses, the queue position updates, and the fills.
+    pub fn order(&self, asset_no: usize, order_id: OrderId) -> Vec<&TraceRecord> {
+        self.records
+            .iter()
+            .filter(|record| {
+                record.kind != TRACE_EVENT_INTENT
+                    && record.asset_no == asset_no as u64
+                    && record.order_id == order_id
+            })
+            .collect()
+    }
+
+    /// Returns the records written within the time window, `start <= timestamp < end`.
+    pub fn window(&self, start: i64, end: i64) -> &[TraceRecord] {
+        // Records are written in processing order, so the timestamps are non-decreasing.
+        let from = self
+            .records
+            .partition_point(|record| record.timestamp < start);
+        let to = self
+            .records
+            .partition_point(|record| record.timestamp < end);
+        &self.records[from..to.max(from)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        backtest::{
+            assettype::LinearAsset,
+            data::{Data, DataSource},
+            fixture::{asset_builder, feed_events, l3_data},
+            models::{CommonFees, ConstantLatency, L3FIFOQueueModel, TradingValueFeeModel},
+            trace::{
+                TraceReader,
+                Tracer,
+                CHUNK_SIZE,
+                TRACE_EVENT_INTENT,
+                TRACE_FILL,
+                TRACE_ORDER_REQUEST,
+                TRACE_ORDER_RESPONSE,
+            },
+            Backtest,
+            ExchangeKind,
+            L3AssetBuilder,
+        },
+        depth::HashMapMarketDepth,
+        types::{Bot, OrdType, Status, TimeInForce, ADD_ORDER_EVENT, BUY_EVENT, SELL_EVENT},
+    };
+
+    fn trace_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("hftbacktest_test_{name}.npy"))
+    }
+
+    fn load(path: &PathBuf) -> TraceReader {
+        let reader = TraceReader::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        reader
+    }
+
+    fn run_backtest(tracer: Tracer, n: i64) {
+        let asset = asset_builder(&Data::from_items(&feed_events(n, false)))
+            .latency_model(ConstantLatency::new(50, 50))
+            .build()
+            .unwrap();
+        let mut hbt: Backtest<HashMapMarketDepth> = Backtest::builder()
+            .add_asset(asset)
+            .tracer(tracer)
+            .build()
+            .unwrap();
+        hbt.elapse(10_000).unwrap();
+        hbt.submit_buy_order(0, 1, 100.1, 1.0, TimeInForce::GTC, OrdType::Limit, false)
+            .unwrap();
+        hbt.goto_end().unwrap();
+        assert_eq!(hbt.position(0), 1.0);
+    }
+
+    #[test]
+    fn test_tracer() {
+        let path = trace_path("tracer");
+        let tracer = Tracer::new(&path).unwrap();
+        run_backtest(tracer.clone(), 100);
+        tracer.flush().unwrap();
+        let reader = load(&path);
+        assert_eq!(reader.records().len(), tracer.len());
+
+        let kinds: Vec<_> = reader.order(0, 1).iter().map(|r| r.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![TRACE_ORDER_REQUEST, TRACE_FILL, TRACE_ORDER_RESPONSE]
+        );
+        let response = reader.order(0, 1)[2];
+        assert_eq!(response.ival, Status::Filled as i64);
+        assert_eq!(response.fval, 1.0);
+
+        let window = reader.window(20_000, 30_000);
+        assert!(!window.is_empty());
+        assert!(window
+            .iter()
+            .all(|r| r.timestamp >= 20_000 && r.timestamp < 30_000));
+        assert!(window.iter().all(|r| r.kind == TRACE_EVENT_INTENT));
+
+        // The backtest is deterministic, so the same backtest produces the same trace. The file is
+        // completed when the last clone of the tracer is dropped.
+        let other_path = trace_path("tracer_other");
+        run_backtest(Tracer::new(&other_path).unwrap(), 100);
+        assert_eq!(load(&other_path).records(), reader.records());
+    }
+
+    #[test]
+    fn test_tracer_chunks() {
+        let path = trace_path("tracer_chunks");
+        let tracer = Tracer::new(&path).unwrap();
+        run_backtest(tracer.clone(), 10_000);
+        assert!(tracer.len() > 2 * CHUNK_SIZE);
+        // Only the records since the last full chunk are held in memory.
+        assert!(tracer.0.borrow().chunk.len() < CHUNK_SIZE);
+        assert_eq!(tracer.0.borrow().len % CHUNK_SIZE, 0);
+        tracer.flush().unwrap();
+
+        let reader = load(&path);
+        assert_eq!(reader.records().len(), tracer.len());
+        assert!(reader
+            .records()
+            .windows(2)
+            .all(|r| r[0].timestamp <= r[1].timestamp));
+        assert_eq!(reader.order(0, 1).len(), 3);
+    }
+
+    #[test]
+    fn test_l3_tracer() {
+        let data = l3_data(&[
+            (1_000, ADD_ORDER_EVENT | BUY_EVENT, 100.0, 5.0, 1),
+            (2_000, ADD_ORDER_EVENT | SELL_EVENT, 100.1, 5.0, 2),
+            (10_000, ADD_ORDER_EVENT | SELL_EVENT, 100.2, 5.0, 3),
+        ]);
+        let path = trace_path("l3_tracer");
+        let tracer = Tracer::new(&path).unwrap();
+        let asset = L3AssetBuilder::new()
+            .data(vec![DataSource::Data(data)])
+            .latency_model(ConstantLatency::new(1000, 1000))
+            .asset_type(LinearAsset::new(1.0))
+            .fee_model(TradingValueFeeModel::new(CommonFees::new(0.0, 0.0)))
+            .queue_model(L3FIFOQueueModel::new())
+            .exchange(ExchangeKind::NoPartialFillExchange)
+            .depth(|| HashMapMarketDepth::new(0.1, 0.1))
+            .build()
+            .unwrap();
+        let mut hbt: Backtest<HashMapMarketDepth> = Backtest::builder()
+            .add_asset(asset)
+            .tracer(tracer.clone())
+            .build()
+            .unwrap();
+        hbt.elapse(5_000).unwrap();
+        hbt.submit_buy_order(0, 1, 100.1, 1.0, TimeInForce::GTC, OrdType::Limit, false)
+            .unwrap();
+        hbt.goto_end().unwrap();
+        assert_eq!(hbt.position(0), 1.0);
+        tracer.flush().unwrap();
+
+        let reader = load(&path);
+        let kinds: Vec<_> = reader.order(0, 1).iter().map(|r| r.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![TRACE_ORDER_REQUEST, TRACE_FILL, TRACE_ORDER_RESPONSE]
+        );
+        let fill = reader.order(0, 1)[1];
+        assert!((fill.px - 100.1).abs() < 1e-9);
+        assert_eq!(fill.qty, 1.0);
+        assert_eq!(fill.ival, 0);
+    }
+}
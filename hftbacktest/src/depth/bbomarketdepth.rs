@@ -0,0 +1,384 @@
+use super::{ApplySnapshot, L1MarketDepth, L2MarketDepth, MarketDepth, INVALID_MAX, INVALID_MIN};
+use crate::{
+    backtest::data::Data,
+    prelude::Side,
+    types::{Event, BUY_EVENT, DEPTH_SNAPSHOT_EVENT, EXCH_EVENT, LOCAL_EVENT, SELL_EVENT},
+};
+
+/// L1 Market depth implementation that only keeps the best bid and offer.
+///
+/// This is intended for backtesting with bookTicker-style feeds that only provide the best bid and
+/// ask. Since nothing beyond the touch is known, quantities at any other price level are reported
+/// as zero.
+///
+/// It also implements [`L2MarketDepth`] so that it can be fed by L2 events. In that case, only
+/// updates that set a new touch or change the quantity at the touch are reflected; updates deeper
+/// in the book are ignored. If the quantity at the touch is removed, that side becomes empty until
+/// the next update since the next best level is unknown.
+#[derive(Debug)]
+pub struct BBOMarketDepth {
+    pub tick_size: f64,
+    pub lot_size: f64,
+    pub timestamp: i64,
+    pub best_bid_tick: i64,
+    pub best_ask_tick: i64,
+    pub best_bid_qty: f64,
+    pub best_ask_qty: f64,
+}
+
+impl BBOMarketDepth {
+    /// Constructs an instance of `BBOMarketDepth`.
+    pub fn new(tick_size: f64, lot_size: f64) -> Self {
+        Self {
+            tick_size,
+            lot_size,
+            timestamp: 0,
+            best_bid_tick: INVALID_MIN,
+            best_ask_tick: INVALID_MAX,
+            best_bid_qty: 0.0,
+            best_ask_qty: 0.0,
+        }
+    }
+
+    fn clear_bid(&mut self) {
+        self.best_bid_tick = INVALID_MIN;
+        self.best_bid_qty = 0.0;
+    }
+
+    fn clear_ask(&mut self) {
+        self.best_ask_tick = INVALID_MAX;
+        self.best_ask_qty = 0.0;
+    }
+}
+
+impl L1MarketDepth for BBOMarketDepth {
+    fn update_best_bid(
+        &mut self,
+        px: f64,
+        qty: f64,
+        timestamp: i64,
+    ) -> (i64, i64, i64, f64, f64, i64) {
+        let price_tick = (px / self.tick_size).round() as i64;
+        let qty_lot = (qty / self.lot_size).round() as i64;
+        let prev_best_bid_tick = self.best_bid_tick;
+        let prev_qty = self.bid_qty_at_tick(price_tick);
+
+        if qty_lot > 0 {
+            self.best_bid_tick = price_tick;
+            self.best_bid_qty = qty;
+            // The new best bid cannot cross the best ask; the stale best ask is removed.
+            if self.best_bid_tick >= self.best_ask_tick {
+                self.clear_ask();
+            }
+        } else {
+            self.clear_bid();
+        }
+        self.timestamp = timestamp;
+        (
+            price_tick,
+            prev_best_bid_tick,
+            self.best_bid_tick,
+            prev_qty,
+            qty,
+            timestamp,
+        )
+    }
+
+    fn update_best_ask(
+        &mut self,
+        px: f64,
+        qty: f64,
+        timestamp: i64,
+    ) -> (i64, i64, i64, f64, f64, i64) {
+        let price_tick = (px / self.tick_size).round() as i64;
+        let qty_lot = (qty / self.lot_size).round() as i64;
+        let prev_best_ask_tick = self.best_ask_tick;
+        let prev_qty = self.ask_qty_at_tick(price_tick);
+
+        if qty_lot > 0 {
+            self.best_ask_tick = price_tick;
+            self.best_ask_qty = qty;
+            // The new best ask cannot cross the best bid; the stale best bid is removed.
+            if self.best_bid_tick >= self.best_ask_tick {
+                self.clear_bid();
+            }
+        } else {
+            self.clear_ask();
+        }
+        self.timestamp = timestamp;
+        (
+            price_tick,
+            prev_best_ask_tick,
+            self.best_ask_tick,
+            prev_qty,
+            qty,
+            timestamp,
+        )
+    }
+}
+
+impl L2MarketDepth for BBOMarketDepth {
+    fn update_bid_depth(
+        &mut self,
+        price: f64,
+        qty: f64,
+        timestamp: i64,
+    ) -> (i64, i64, i64, f64, f64, i64) {
+        let price_tick = (price / self.tick_size).round() as i64;
+        let qty_lot = (qty / self.lot_size).round() as i64;
+        if (price_tick > self.best_bid_tick && qty_lot > 0) || price_tick == self.best_bid_tick {
+            self.update_best_bid(price, qty, timestamp)
+        } else {
+            (
+                price_tick,
+                self.best_bid_tick,
+                self.best_bid_tick,
+                0.0,
+                qty,
+                timestamp,
+            )
+        }
+    }
+
+    fn update_ask_depth(
+        &mut self,
+        price: f64,
+        qty: f64,
+        timestamp: i64,
+    ) -> (i64, i64, i64, f64, f64, i64) {
+        let price_tick = (price / self.tick_size).round() as i64;
+        let qty_lot = (qty / self.lot_size).round() as i64;
+        if (price_tick < self.best_ask_tick && qty_lot > 0) || price_tick == self.best_ask_tick {
+            self.update_best_ask(price, qty, timestamp)
+        } else {
+            (
+                price_tick,
+                self.best_ask_tick,
+                self.best_ask_tick,
+                0.0,
+                qty,
+                timestamp,
+            )
+        }
+    }
+
+    fn clear_depth(&mut self, side: Side, clear_upto_price: f64) {
+        match side {
+            Side::Buy => {
+                if clear_upto_price.is_finite() {
+                    let clear_upto = (clear_upto_price / self.tick_size).round() as i64;
+                    if self.best_bid_tick >= clear_upto {
+                        self.clear_bid();
+                    }
+                } else {
+                    self.clear_bid();
+                }
+            }
+            Side::Sell => {
+                if clear_upto_price.is_finite() {
+                    let clear_upto = (clear_upto_price / self.tick_size).round() as i64;
+                    if self.best_ask_tick <= clear_upto {
+                        self.clear_ask();
+                    }
+                } else {
+                    self.clear_ask();
+                }
+            }
+            Side::None => {
+                self.clear_bid();
+                self.clear_ask();
+            }
+            Side::Unsupported => {
+                unreachable!();
+            }
+        }
+    }
+}
+
+impl MarketDepth for BBOMarketDepth {
+    #[inline(always)]
+    fn best_bid(&self) -> f64 {
+        if self.best_bid_tick == INVALID_MIN {
+            f64::NAN
+        } else {
+            self.best_bid_tick as f64 * self.tick_size
+        }
+    }
+
+    #[inline(always)]
+    fn best_ask(&self) -> f64 {
+        if self.best_ask_tick == INVALID_MAX {
+            f64::NAN
+        } else {
+            self.best_ask_tick as f64 * self.tick_size
+        }
+    }
+
+    #[inline(always)]
+    fn best_bid_tick(&self) -> i64 {
+        self.best_bid_tick
+    }
+
+    #[inline(always)]
+    fn best_ask_tick(&self) -> i64 {
+        self.best_ask_tick
+    }
+
+    #[inline(always)]
+    fn tick_size(&self) -> f64 {
+        self.tick_size
+    }
+
+    #[inline(always)]
+    fn lot_size(&self) -> f64 {
+        self.lot_size
+    }
+
+    #[inline(always)]
+    fn bid_qty_at_tick(&self, price_tick: i64) -> f64 {
+        if price_tick == self.best_bid_tick {
+            self.best_bid_qty
+        } else {
+            0.0
+        }
+    }
+
+    #[inline(always)]
+    fn ask_qty_at_tick(&self, price_tick: i64) -> f64 {
+        if price_tick == self.best_ask_tick {
+            self.best_ask_qty
+        } else {
+            0.0
+        }
+    }
+}
+
+impl ApplySnapshot for BBOMarketDepth {
+    fn apply_snapshot(&mut self, data: &Data<Event>) {
+        self.clear_bid();
+        self.clear_ask();
+        for row_num in 0..data.len() {
+            let price = data[row_num].px;
+            let qty = data[row_num].qty;
+
+            let price_tick = (price / self.tick_size).round() as i64;
+            if data[row_num].ev & BUY_EVENT == BUY_EVENT {
+                if price_tick > self.best_bid_tick {
+                    self.best_bid_tick = price_tick;
+                    self.best_bid_qty = qty;
+                }
+            } else if data[row_num].ev & SELL_EVENT == SELL_EVENT && price_tick < self.best_ask_tick
+            {
+                self.best_ask_tick = price_tick;
+                self.best_ask_qty = qty;
+            }
+        }
+    }
+
+    fn snapshot(&self) -> Vec<Event> {
+        let mut events = Vec::new();
+        if self.best_bid_tick != INVALID_MIN {
+            events.push(Event {
+                ev: EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | DEPTH_SNAPSHOT_EVENT,
+                exch_ts: self.timestamp,
+                local_ts: self.timestamp,
+                px: self.best_bid_tick as f64 * self.tick_size,
+                qty: self.best_bid_qty,
+                order_id: 0,
+                ival: 0,
+                fval: 0.0,
+            });
+        }
+        if self.best_ask_tick != INVALID_MAX {
+            events.push(Event {
+                ev: EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | DEPTH_SNAPSHOT_EVENT,
+                exch_ts: self.timestamp,
+                local_ts: self.timestamp,
+                px: self.best_ask_tick as f64 * self.tick_size,
+                qty: self.best_ask_qty,
+                order_id: 0,
+                ival: 0,
+                fval: 0.0,
+            });
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        depth::{
+            BBOMarketDepth,
+            L1MarketDepth,
+            L2MarketDepth,
+            MarketDepth,
+            INVALID_MAX,
+            INVALID_MIN,
+        },
+        types::Side,
+    };
+
+    #[test]
+    fn test_update_best_bid_ask() {
+        let mut depth = BBOMarketDepth::new(0.1, 0.001);
+
+        let (price_tick, prev_best, best, prev_qty, qty, _) = depth.update_best_bid(500.1, 1.0, 1);
+        assert_eq!(price_tick, 5001);
+        assert_eq!(prev_best, INVALID_MIN);
+        assert_eq!(best, 5001);
+        assert_eq!(prev_qty, 0.0);
+        assert_eq!(qty, 1.0);
+
+        let (_, prev_best, best, prev_qty, _, _) = depth.update_best_bid(500.1, 2.0, 2);
+        assert_eq!(prev_best, 5001);
+        assert_eq!(best, 5001);
+        assert_eq!(prev_qty, 1.0);
+        assert_eq!(depth.bid_qty_at_tick(5001), 2.0);
+
+        let (_, prev_best, best, _, _, _) = depth.update_best_ask(500.3, 1.0, 3);
+        assert_eq!(prev_best, INVALID_MAX);
+        assert_eq!(best, 5003);
+
+        // The best bid can move away from the touch; the previous level is no longer known.
+        let (_, prev_best, best, prev_qty, _, _) = depth.update_best_bid(500.0, 3.0, 4);
+        assert_eq!(prev_best, 5001);
+        assert_eq!(best, 5000);
+        assert_eq!(prev_qty, 0.0);
+        assert_eq!(depth.bid_qty_at_tick(5001), 0.0);
+
+        // A crossing best bid removes the stale best ask.
+        depth.update_best_bid(500.3, 1.0, 5);
+        assert_eq!(depth.best_bid_tick(), 5003);
+        assert_eq!(depth.best_ask_tick(), INVALID_MAX);
+        assert!(depth.best_ask().is_nan());
+    }
+
+    #[test]
+    fn test_l2_update() {
+        let mut depth = BBOMarketDepth::new(0.1, 0.001);
+        depth.update_bid_depth(500.1, 1.0, 0);
+        depth.update_ask_depth(500.3, 1.0, 0);
+
+        // Updates behind the touch are ignored.
+        let (_, prev_best, best, prev_qty, _, _) = depth.update_bid_depth(500.0, 5.0, 0);
+        assert_eq!(prev_best, 5001);
+        assert_eq!(best, 5001);
+        assert_eq!(prev_qty, 0.0);
+        assert_eq!(depth.bid_qty_at_tick(5000), 0.0);
+
+        // Improves the touch.
+        depth.update_ask_depth(500.2, 2.0, 0);
+        assert_eq!(depth.best_ask_tick(), 5002);
+        assert_eq!(depth.ask_qty_at_tick(5002), 2.0);
+
+        // Removing the touch leaves the side empty.
+        depth.update_ask_depth(500.2, 0.0, 0);
+        assert_eq!(depth.best_ask_tick(), INVALID_MAX);
+
+        depth.clear_depth(Side::Buy, 500.2);
+        assert_eq!(depth.best_bid_tick(), 5001);
+        depth.clear_depth(Side::Buy, 500.1);
+        assert_eq!(depth.best_bid_tick(), INVALID_MIN);
+    }
+}
@@ -607,7 +607,16 @@ impl L3MarketDepth for HashMapMarketDepth {
 #[cfg(test)]
 mod tests {
     use crate::{
-        depth::{HashMapMarketDepth, L3MarketDepth, MarketDepth, INVALID_MAX, INVALID_MIN},
+        depth::{
+            update_best_ask,
+            update_best_bid,
+            HashMapMarketDepth,
+            L2MarketDepth,
+            L3MarketDepth,
+            MarketDepth,
+            INVALID_MAX,
+            INVALID_MIN,
+        },
         types::Side,
     };
 
@@ -831,4 +840,37 @@ mod tests {
         assert_eq_qty!(depth.ask_qty_at_tick(4981), 0.0, lot_size);
         assert_eq_qty!(depth.ask_qty_at_tick(5002), 0.002, lot_size);
     }
+
+    #[test]
+    fn test_l1_update_best_bid_ask() {
+        let lot_size = 0.001;
+        let mut depth = HashMapMarketDepth::new(0.1, lot_size);
+        depth.update_bid_depth(500.3, 0.001, 0);
+        depth.update_bid_depth(500.2, 0.002, 0);
+        depth.update_bid_depth(500.1, 0.003, 0);
+        depth.update_ask_depth(500.5, 0.001, 0);
+        depth.update_ask_depth(500.6, 0.002, 0);
+
+        // The levels above the new best bid are removed.
+        let (price_tick, prev_best, best, prev_qty, qty, _) =
+            update_best_bid(&mut depth, 500.2, 0.005, 0);
+        assert_eq!(price_tick, 5002);
+        assert_eq!(prev_best, 5003);
+        assert_eq!(best, 5002);
+        assert_eq_qty!(prev_qty, 0.002, lot_size);
+        assert_eq_qty!(qty, 0.005, lot_size);
+        assert_eq_qty!(depth.bid_qty_at_tick(5003), 0.0, lot_size);
+        assert_eq_qty!(depth.bid_qty_at_tick(5001), 0.003, lot_size);
+
+        // The levels below the new best ask are removed.
+        let (_, prev_best, best, _, _, _) = update_best_ask(&mut depth, 500.6, 0.004, 0);
+        assert_eq!(prev_best, 5005);
+        assert_eq!(best, 5006);
+        assert_eq_qty!(depth.ask_qty_at_tick(5005), 0.0, lot_size);
+
+        // Crossing the best ask moves it.
+        let (_, _, best, _, _, _) = update_best_bid(&mut depth, 500.6, 0.001, 0);
+        assert_eq!(best, 5006);
+        assert_eq!(depth.best_ask_tick(), INVALID_MAX);
+    }
 }
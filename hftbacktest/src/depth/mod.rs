@@ -1,11 +1,15 @@
 use std::collections::HashMap;
 
+use bincode::{Decode, Encode};
+
+pub use bbomarketdepth::BBOMarketDepth;
 pub use btreemarketdepth::BTreeMarketDepth;
 pub use hashmapmarketdepth::HashMapMarketDepth;
 pub use roivectormarketdepth::ROIVectorMarketDepth;
 
 use crate::prelude::Side;
 
+mod bbomarketdepth;
 mod btreemarketdepth;
 mod hashmapmarketdepth;
 mod roivectormarketdepth;
@@ -102,7 +106,7 @@ pub trait ApplySnapshot {
 }
 
 /// Level3 order from the market feed.
-#[derive(Debug)]
+#[derive(Clone, Debug, Encode, Decode)]
 pub struct L3Order {
     pub order_id: OrderId,
     pub side: Side,
@@ -111,6 +115,25 @@ pub struct L3Order {
     pub timestamp: i64,
 }
 
+/// Returns the total quantity of the orders in the Level3 market depth that are on the side at the
+/// price in ticks and have been placed before the timestamp, which is the quantity ahead of an
+/// order placed at the timestamp in a FIFO queue.
+pub fn qty_ahead<MD: L3MarketDepth>(
+    depth: &MD,
+    side: Side,
+    price_tick: i64,
+    timestamp: i64,
+) -> f64 {
+    depth
+        .orders()
+        .values()
+        .filter(|order| {
+            order.side == side && order.price_tick == price_tick && order.timestamp < timestamp
+        })
+        .map(|order| order.qty)
+        .sum()
+}
+
 /// Provides Level3-specific market depth functions.
 pub trait L3MarketDepth: MarketDepth {
     type Error;
@@ -181,3 +204,61 @@ pub trait L1MarketDepth {
         timestamp: i64,
     ) -> (i64, i64, i64, f64, f64, i64);
 }
+
+/// Updates the best bid of the Level2 market depth from a best bid and offer feed event, which
+/// also removes the bid levels above the new best bid as they no longer exist. Returns the same
+/// tuple as [`L2MarketDepth::update_bid_depth`].
+pub fn update_best_bid<MD>(
+    depth: &mut MD,
+    px: f64,
+    qty: f64,
+    timestamp: i64,
+) -> (i64, i64, i64, f64, f64, i64)
+where
+    MD: MarketDepth + L2MarketDepth,
+{
+    let price_tick = (px / depth.tick_size()).round() as i64;
+    let prev_best_bid_tick = depth.best_bid_tick();
+    if prev_best_bid_tick != INVALID_MIN && price_tick < prev_best_bid_tick {
+        depth.clear_depth(Side::Buy, (price_tick + 1) as f64 * depth.tick_size());
+    }
+    let prev_qty = depth.bid_qty_at_tick(price_tick);
+    let (_, _, best_bid_tick, _, _, _) = depth.update_bid_depth(px, qty, timestamp);
+    (
+        price_tick,
+        prev_best_bid_tick,
+        best_bid_tick,
+        prev_qty,
+        qty,
+        timestamp,
+    )
+}
+
+/// Updates the best ask of the Level2 market depth from a best bid and offer feed event, which
+/// also removes the ask levels below the new best ask as they no longer exist. Returns the same
+/// tuple as [`L2MarketDepth::update_ask_depth`].
+pub fn update_best_ask<MD>(
+    depth: &mut MD,
+    px: f64,
+    qty: f64,
+    timestamp: i64,
+) -> (i64, i64, i64, f64, f64, i64)
+where
+    MD: MarketDepth + L2MarketDepth,
+{
+    let price_tick = (px / depth.tick_size()).round() as i64;
+    let prev_best_ask_tick = depth.best_ask_tick();
+    if prev_best_ask_tick != INVALID_MAX && price_tick > prev_best_ask_tick {
+        depth.clear_depth(Side::Sell, (price_tick - 1) as f64 * depth.tick_size());
+    }
+    let prev_qty = depth.ask_qty_at_tick(price_tick);
+    let (_, _, best_ask_tick, _, _, _) = depth.update_ask_depth(px, qty, timestamp);
+    (
+        price_tick,
+        prev_best_ask_tick,
+        best_ask_tick,
+        prev_qty,
+        qty,
+        timestamp,
+    )
+}
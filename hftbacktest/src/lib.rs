@@ -43,6 +43,12 @@ pub mod live;
 /// Defines HftBacktest types.
 pub mod types;
 
+/// Provides an event-driven strategy driver.
+pub mod strategy;
+
+/// Provides a pre-trade risk check layer that wraps a bot.
+pub mod risk;
+
 /// Provides common types.
 pub mod prelude;
 
@@ -1,20 +1,20 @@
 use std::{
-    collections::{hash_map::Entry, HashMap},
-    time::{Duration, Instant},
+    collections::{hash_map::Entry, HashMap, HashSet},
+    time::Duration,
 };
 
-use chrono::Utc;
 use rand::Rng;
 use thiserror::Error;
 use tracing::{debug, error, info};
 
 use crate::{
-    depth::{L2MarketDepth, MarketDepth},
-    live::{ipc::Channel, Instrument},
+    depth::{qty_ahead, L3MarketDepth},
+    live::{ipc::Channel, Instrument, LiveMarketDepth},
     types::{
         Bot,
         BuildError,
         Event,
+        L3Bot,
         LiveError,
         LiveEvent,
         LiveRequest,
@@ -22,15 +22,17 @@ use crate::{
         Order,
         OrderId,
         OrderRequest,
+        SelfTradePrevention,
         Side,
         StateValues,
         Status,
         TimeInForce,
+        TimerId,
+        TriggerPriceType,
         WaitOrderResponse,
-        LOCAL_ASK_DEPTH_EVENT,
-        LOCAL_BID_DEPTH_EVENT,
         LOCAL_BUY_TRADE_EVENT,
         LOCAL_SELL_TRADE_EVENT,
+        LOCAL_TRADE_EVENT,
     },
 };
 
@@ -55,6 +57,12 @@ pub enum BotError {
 pub type ErrorHandler = Box<dyn Fn(LiveError) -> Result<(), BotError>>;
 pub type OrderRecvHook = Box<dyn Fn(&Order, &Order) -> Result<(), BotError>>;
 
+struct Timer {
+    timer_id: TimerId,
+    timestamp: i64,
+    interval: i64,
+}
+
 fn generate_random_id() -> u64 {
     // Initialize the random number generator
     let mut rng = rand::thread_rng();
@@ -129,11 +137,22 @@ impl<MD> LiveBotBuilder<MD> {
 
     /// Builds a live [`LiveBot`] based on the registered connectors and assets.
     pub fn build<CH>(self) -> Result<LiveBot<CH, MD>, BuildError>
+    where
+        CH: Channel,
+    {
+        let channel = CH::build(&self.instruments)?;
+        self.build_with_channel(channel)
+    }
+
+    /// Builds a live [`LiveBot`] that communicates through the given [`Channel`] instead of one
+    /// built from the registered assets, such as
+    /// [`RecordingChannel`](`crate::live::ipc::replay::RecordingChannel`) or
+    /// [`ReplayChannel`](`crate::live::ipc::replay::ReplayChannel`).
+    pub fn build_with_channel<CH>(self, mut channel: CH) -> Result<LiveBot<CH, MD>, BuildError>
     where
         CH: Channel,
     {
         let id = self.id;
-        let mut channel = CH::build(&self.instruments)?;
 
         // Requests to prepare a given asset for trading.
         // The Connector will send the current orders on this asset.
@@ -162,6 +181,8 @@ impl<MD> LiveBotBuilder<MD> {
             instruments: self.instruments,
             error_handler: self.error_handler,
             order_hook: self.order_hook,
+            timers: Vec::new(),
+            fired_timers: Vec::new(),
         })
     }
 }
@@ -194,12 +215,14 @@ pub struct LiveBot<CH, MD> {
     instruments: Vec<Instrument<MD>>,
     error_handler: Option<ErrorHandler>,
     order_hook: Option<OrderRecvHook>,
+    timers: Vec<Timer>,
+    fired_timers: Vec<TimerId>,
 }
 
 impl<CH, MD> LiveBot<CH, MD>
 where
     CH: Channel,
-    MD: MarketDepth + L2MarketDepth,
+    MD: LiveMarketDepth,
 {
     fn process_event<const WAIT_NEXT_FEED: bool>(
         &mut self,
@@ -211,15 +234,11 @@ where
             LiveEvent::Feed { event, .. } => {
                 let instrument = unsafe { self.instruments.get_unchecked_mut(inst_no) };
                 instrument.last_feed_latency = Some((event.exch_ts, event.local_ts));
-                if event.is(LOCAL_BID_DEPTH_EVENT) {
-                    instrument
-                        .depth
-                        .update_bid_depth(event.px, event.qty, event.exch_ts);
-                } else if event.is(LOCAL_ASK_DEPTH_EVENT) {
-                    instrument
-                        .depth
-                        .update_ask_depth(event.px, event.qty, event.exch_ts);
-                } else if (event.is(LOCAL_BUY_TRADE_EVENT) || event.is(LOCAL_SELL_TRADE_EVENT))
+                if !event.is(LOCAL_TRADE_EVENT) {
+                    instrument.last_depth_latency = instrument.last_feed_latency;
+                }
+                instrument.depth.apply_feed(&event);
+                if (event.is(LOCAL_BUY_TRADE_EVENT) || event.is(LOCAL_SELL_TRADE_EVENT))
                     && instrument.last_trades.capacity() > 0
                 {
                     instrument.last_trades.push(event);
@@ -235,12 +254,10 @@ where
                     } if wait_order_id == order.order_id && wait_order_asset_no == inst_no => true,
                     _ => false,
                 };
+                let now = self.channel.now();
                 let instrument = unsafe { self.instruments.get_unchecked_mut(inst_no) };
-                instrument.last_order_latency = Some((
-                    order.local_timestamp,
-                    order.exch_timestamp,
-                    Utc::now().timestamp_nanos_opt().unwrap(),
-                ));
+                instrument.last_order_latency =
+                    Some((order.local_timestamp, order.exch_timestamp, now));
                 match instrument.orders.entry(order.order_id) {
                     Entry::Occupied(mut entry) => {
                         let ex_order = entry.get_mut();
@@ -283,19 +300,66 @@ where
         Ok(false)
     }
 
+    /// Fires the timers that are due. One-shot timers are removed, and periodic timers are
+    /// rescheduled to their next timestamp. Returns `true` if any timer fires.
+    fn fire_timers(&mut self) -> bool {
+        let now = self.channel.now();
+        let num_fired = self.fired_timers.len();
+        for timer in self.timers.iter_mut() {
+            if timer.timestamp <= now {
+                self.fired_timers.push(timer.timer_id);
+                if timer.interval > 0 {
+                    while timer.timestamp <= now {
+                        timer.timestamp += timer.interval;
+                    }
+                }
+            }
+        }
+        self.timers
+            .retain(|timer| timer.interval > 0 || timer.timestamp > now);
+        self.fired_timers.len() > num_fired
+    }
+
+    /// Returns the duration until the earliest timer fires.
+    fn next_timer_duration(&self) -> Option<Duration> {
+        let now = self.channel.now();
+        self.timers
+            .iter()
+            .map(|timer| timer.timestamp)
+            .min()
+            .map(|timestamp| Duration::from_nanos((timestamp - now).max(0) as u64))
+    }
+
     fn elapse_<const WAIT_NEXT_FEED: bool>(
         &mut self,
         duration: i64,
         wait_order_response: WaitOrderResponse,
     ) -> Result<bool, BotError> {
-        let instant = Instant::now();
+        // The elapsed time is measured by the channel's clock so that a replayed session, which
+        // runs on a virtual clock, behaves the same as the recorded one.
+        let start = self.channel.now();
+        let since_start =
+            |channel: &CH| Duration::from_nanos((channel.now() - start).max(0) as u64);
         let duration = Duration::from_nanos(duration as u64);
         let mut remaining_duration = duration;
         let mut batch_mode = false;
         let mut wait_resp_received = false;
+        // Waiting for a specific order's response is not woken up by timers.
+        let wait_specified_order =
+            matches!(wait_order_response, WaitOrderResponse::Specified { .. });
+        if !wait_specified_order {
+            self.fired_timers.clear();
+        }
 
         loop {
-            match self.channel.recv_timeout(self.id, remaining_duration) {
+            if !batch_mode && self.fire_timers() && !wait_specified_order {
+                return Ok(true);
+            }
+            let timeout = match self.next_timer_duration() {
+                Some(timer_duration) if !batch_mode => remaining_duration.min(timer_duration),
+                _ => remaining_duration,
+            };
+            match self.channel.recv_timeout(self.id, timeout) {
                 Ok((_, LiveEvent::BatchStart)) => {
                     batch_mode = true;
                 }
@@ -314,7 +378,12 @@ where
                     }
                 }
                 Err(BotError::Timeout) => {
-                    return Ok(true);
+                    // The timeout may be caused by a timer, which is handled at the beginning of
+                    // the loop.
+                    if since_start(&self.channel) >= duration {
+                        self.fire_timers();
+                        return Ok(true);
+                    }
                 }
                 Err(BotError::Interrupted) => {
                     return Ok(false);
@@ -324,7 +393,7 @@ where
                 }
             }
             if !batch_mode {
-                let elapsed = instant.elapsed();
+                let elapsed = since_start(&self.channel);
                 if elapsed > duration {
                     return Ok(true);
                 }
@@ -333,55 +402,83 @@ where
         }
     }
 
-    #[allow(clippy::too_many_arguments)]
-    fn submit_order(
+    fn send_order(
         &mut self,
         asset_no: usize,
-        order_id: u64,
-        price: f64,
-        qty: f64,
-        time_in_force: TimeInForce,
-        order_type: OrdType,
+        order: OrderRequest,
         wait: bool,
-        side: Side,
     ) -> Result<bool, BotError> {
         let instrument = self
             .instruments
             .get_mut(asset_no)
             .ok_or(BotError::InstrumentNotFound)?;
-        if instrument.orders.contains_key(&order_id) {
+        if instrument.orders.contains_key(&order.order_id) {
             return Err(BotError::OrderIdExist);
         }
         let symbol = instrument.symbol.clone();
-        let tick_size = instrument.tick_size;
-        let order = Order {
-            order_id,
-            price_tick: (price / tick_size).round() as i64,
-            qty,
-            leaves_qty: qty,
+        let order = Self::new_order(&order, instrument.tick_size, self.channel.now());
+        let order_id = order.order_id;
+        instrument.orders.insert(order_id, order.clone());
+
+        self.channel
+            .send(self.id, asset_no, LiveRequest::Order { symbol, order })?;
+
+        if wait {
+            // fixme: timeout should be specified by the argument.
+            return self.wait_order_response(asset_no, order_id, 60_000_000_000);
+        }
+        Ok(true)
+    }
+
+    fn new_order(order: &OrderRequest, tick_size: f64, local_timestamp: i64) -> Order {
+        Order {
+            order_id: order.order_id,
+            price_tick: (order.price / tick_size).round() as i64,
+            qty: order.qty,
+            leaves_qty: order.qty,
             tick_size,
-            side,
-            time_in_force,
-            order_type,
+            side: order.side,
+            time_in_force: order.time_in_force,
+            order_type: order.order_type,
             status: Status::New,
-            local_timestamp: Utc::now().timestamp_nanos_opt().unwrap(),
+            local_timestamp,
             req: Status::New,
             exec_price_tick: 0,
             exch_timestamp: 0,
             exec_qty: 0.0,
+            self_trade_prevention: order.self_trade_prevention,
+            avg_exec_price: 0.0,
+            trigger_price_tick: (order.trigger_price / tick_size).round() as i64,
+            oco_order_id: order.oco_order_id.unwrap_or(0),
+            trigger_price_type: order.trigger_price_type,
+            oco: order.oco_order_id.is_some(),
+            expire_timestamp: order.expire_timestamp,
+            reduce_only: order.reduce_only,
+            exch_order_id: 0,
             // Invalid information
             q: Box::new(()),
             maker: false,
-        };
-        let order_id = order.order_id;
-        instrument.orders.insert(order_id, order.clone());
-
-        self.channel
-            .send(self.id, asset_no, LiveRequest::Order { symbol, order })?;
+        }
+    }
 
-        if wait {
+    /// Waits for the responses of the specified orders, skipping the orders that have no request
+    /// in process.
+    fn wait_order_responses(
+        &mut self,
+        asset_no: usize,
+        order_ids: &[OrderId],
+    ) -> Result<bool, BotError> {
+        for &order_id in order_ids {
+            let in_process = self
+                .instruments
+                .get(asset_no)
+                .and_then(|instrument| instrument.orders.get(&order_id))
+                .map(|order| order.req != Status::None)
+                .unwrap_or(false);
             // fixme: timeout should be specified by the argument.
-            return self.wait_order_response(asset_no, order_id, 60_000_000_000);
+            if in_process && !self.wait_order_response(asset_no, order_id, 60_000_000_000)? {
+                return Ok(false);
+            }
         }
         Ok(true)
     }
@@ -390,13 +487,13 @@ where
 impl<CH, MD> Bot<MD> for LiveBot<CH, MD>
 where
     CH: Channel,
-    MD: MarketDepth + L2MarketDepth,
+    MD: LiveMarketDepth,
 {
     type Error = BotError;
 
     #[inline]
     fn current_timestamp(&self) -> i64 {
-        Utc::now().timestamp_nanos_opt().unwrap()
+        self.channel.now()
     }
 
     #[inline]
@@ -451,6 +548,14 @@ where
         }
     }
 
+    #[inline]
+    fn custom_data<D>(&self, _asset_no: usize, _stream_no: usize) -> Option<&D>
+    where
+        D: 'static,
+    {
+        None
+    }
+
     #[inline]
     fn orders(&self, asset_no: usize) -> &HashMap<OrderId, Order> {
         &self.instruments.get(asset_no).unwrap().orders
@@ -467,15 +572,23 @@ where
         order_type: OrdType,
         wait: bool,
     ) -> Result<bool, Self::Error> {
-        self.submit_order(
+        self.send_order(
             asset_no,
-            order_id,
-            price,
-            qty,
-            time_in_force,
-            order_type,
+            OrderRequest {
+                order_id,
+                price,
+                qty,
+                side: Side::Buy,
+                time_in_force,
+                order_type,
+                self_trade_prevention: SelfTradePrevention::None,
+                trigger_price: 0.0,
+                trigger_price_type: TriggerPriceType::LastPrice,
+                oco_order_id: None,
+                expire_timestamp: 0,
+                reduce_only: false,
+            },
             wait,
-            Side::Buy,
         )
     }
 
@@ -490,15 +603,23 @@ where
         order_type: OrdType,
         wait: bool,
     ) -> Result<bool, Self::Error> {
-        self.submit_order(
+        self.send_order(
             asset_no,
-            order_id,
-            price,
-            qty,
-            time_in_force,
-            order_type,
+            OrderRequest {
+                order_id,
+                price,
+                qty,
+                side: Side::Sell,
+                time_in_force,
+                order_type,
+                self_trade_prevention: SelfTradePrevention::None,
+                trigger_price: 0.0,
+                trigger_price_type: TriggerPriceType::LastPrice,
+                oco_order_id: None,
+                expire_timestamp: 0,
+                reduce_only: false,
+            },
             wait,
-            Side::Sell,
         )
     }
 
@@ -508,16 +629,7 @@ where
         order: OrderRequest,
         wait: bool,
     ) -> Result<bool, Self::Error> {
-        self.submit_order(
-            asset_no,
-            order.order_id,
-            order.price,
-            order.qty,
-            order.time_in_force,
-            order.order_type,
-            wait,
-            order.side,
-        )
+        self.send_order(asset_no, order, wait)
     }
 
     #[inline]
@@ -540,7 +652,7 @@ where
             return Err(BotError::InvalidOrderStatus);
         }
         order.req = Status::Canceled;
-        order.local_timestamp = Utc::now().timestamp_nanos_opt().unwrap();
+        order.local_timestamp = self.channel.now();
 
         self.channel.send(
             self.id,
@@ -558,6 +670,162 @@ where
         Ok(true)
     }
 
+    fn submit_orders(
+        &mut self,
+        asset_no: usize,
+        orders: Vec<OrderRequest>,
+        wait: bool,
+    ) -> Result<bool, Self::Error> {
+        let instrument = self
+            .instruments
+            .get_mut(asset_no)
+            .ok_or(BotError::InstrumentNotFound)?;
+        // Validates all orders before sending any of them.
+        let mut order_ids = HashSet::with_capacity(orders.len());
+        for order in orders.iter() {
+            if instrument.orders.contains_key(&order.order_id) || !order_ids.insert(order.order_id)
+            {
+                return Err(BotError::OrderIdExist);
+            }
+        }
+        if orders.is_empty() {
+            return Ok(true);
+        }
+        let symbol = instrument.symbol.clone();
+        let local_timestamp = self.channel.now();
+        let orders: Vec<Order> = orders
+            .iter()
+            .map(|order| Self::new_order(order, instrument.tick_size, local_timestamp))
+            .collect();
+        let order_ids: Vec<OrderId> = orders.iter().map(|order| order.order_id).collect();
+        for order in orders.iter() {
+            instrument.orders.insert(order.order_id, order.clone());
+        }
+
+        self.channel
+            .send(self.id, asset_no, LiveRequest::Orders { symbol, orders })?;
+
+        if wait {
+            return self.wait_order_responses(asset_no, &order_ids);
+        }
+        Ok(true)
+    }
+
+    fn cancel_orders(
+        &mut self,
+        asset_no: usize,
+        order_ids: &[OrderId],
+        wait: bool,
+    ) -> Result<bool, Self::Error> {
+        let instrument = self
+            .instruments
+            .get_mut(asset_no)
+            .ok_or(BotError::InstrumentNotFound)?;
+        // Validates all orders before sending any of the cancel requests.
+        for order_id in order_ids {
+            let order = instrument
+                .orders
+                .get(order_id)
+                .ok_or(BotError::OrderNotFound)?;
+            if !order.cancellable() {
+                return Err(BotError::InvalidOrderStatus);
+            }
+        }
+        if order_ids.is_empty() {
+            return Ok(true);
+        }
+        let symbol = instrument.symbol.clone();
+        let local_timestamp = self.channel.now();
+        let mut orders = Vec::with_capacity(order_ids.len());
+        for order_id in order_ids {
+            let order = instrument.orders.get_mut(order_id).unwrap();
+            order.req = Status::Canceled;
+            order.local_timestamp = local_timestamp;
+            orders.push(order.clone());
+        }
+
+        self.channel
+            .send(self.id, asset_no, LiveRequest::Orders { symbol, orders })?;
+
+        if wait {
+            return self.wait_order_responses(asset_no, order_ids);
+        }
+        Ok(true)
+    }
+
+    fn cancel_all(&mut self, asset_no: usize, wait: bool) -> Result<bool, Self::Error> {
+        let instrument = self
+            .instruments
+            .get_mut(asset_no)
+            .ok_or(BotError::InstrumentNotFound)?;
+        let symbol = instrument.symbol.clone();
+        let local_timestamp = self.channel.now();
+        let mut order_ids = Vec::new();
+        for order in instrument.orders.values_mut() {
+            if order.cancellable() {
+                order.req = Status::Canceled;
+                order.local_timestamp = local_timestamp;
+                order_ids.push(order.order_id);
+            }
+        }
+
+        // Sends the request even if there is no cancellable order locally, as the exchange may
+        // have open orders that the bot is unaware of.
+        self.channel
+            .send(self.id, asset_no, LiveRequest::CancelAll { symbol })?;
+
+        if wait {
+            return self.wait_order_responses(asset_no, &order_ids);
+        }
+        Ok(true)
+    }
+
+    fn modify(
+        &mut self,
+        asset_no: usize,
+        order_id: OrderId,
+        price: f64,
+        qty: f64,
+        wait: bool,
+    ) -> Result<bool, Self::Error> {
+        let instrument = self
+            .instruments
+            .get_mut(asset_no)
+            .ok_or(BotError::InstrumentNotFound)?;
+        let symbol = instrument.symbol.clone();
+        let tick_size = instrument.tick_size;
+        let order = instrument
+            .orders
+            .get_mut(&order_id)
+            .ok_or(BotError::OrderNotFound)?;
+        if !order.cancellable() {
+            return Err(BotError::InvalidOrderStatus);
+        }
+        // The local order retains its current price and quantity until the connector responds to
+        // the modification.
+        order.req = Status::Replaced;
+        order.local_timestamp = self.channel.now();
+
+        let mut order_ = order.clone();
+        order_.price_tick = (price / tick_size).round() as i64;
+        order_.qty = qty;
+
+        self.channel.send(
+            self.id,
+            asset_no,
+            LiveRequest::Order {
+                symbol,
+                order: order_,
+            },
+        )?;
+
+        if wait {
+            // fixme: timeout should be specified by the argument.
+            return self.wait_order_response(asset_no, order_id, 60_000_000_000);
+        }
+        Ok(true)
+    }
+
     #[inline]
     fn clear_inactive_orders(&mut self, asset_no: Option<usize>) {
         match asset_no {
@@ -607,6 +875,26 @@ where
         Ok(true)
     }
 
+    fn set_timer(&mut self, timer_id: TimerId, timestamp: i64, interval: i64) {
+        self.cancel_timer(timer_id);
+        self.timers.push(Timer {
+            timer_id,
+            timestamp,
+            interval,
+        });
+    }
+
+    fn cancel_timer(&mut self, timer_id: TimerId) -> bool {
+        let len = self.timers.len();
+        self.timers.retain(|timer| timer.timer_id != timer_id);
+        self.timers.len() != len
+    }
+
+    #[inline]
+    fn fired_timers(&self) -> &[TimerId] {
+        &self.fired_timers
+    }
+
     fn close(&mut self) -> Result<(), Self::Error> {
         Ok(())
     }
@@ -615,7 +903,35 @@ where
         self.instruments.get(asset_no).unwrap().last_feed_latency
     }
 
+    fn depth_latency(&self, asset_no: usize) -> Option<(i64, i64)> {
+        self.instruments.get(asset_no).unwrap().last_depth_latency
+    }
+
     fn order_latency(&self, asset_no: usize) -> Option<(i64, i64, i64)> {
         self.instruments.get(asset_no).unwrap().last_order_latency
     }
 }
+
+impl<CH, MD> L3Bot<MD> for LiveBot<CH, MD>
+where
+    CH: Channel,
+    MD: LiveMarketDepth + L3MarketDepth,
+{
+    /// The order is identified in the Level3 Market-By-Order feed by its
+    /// [`exch_order_id`](Order::exch_order_id), so `None` is returned until the order appears in
+    /// the feed.
+    fn queue_ahead(&self, asset_no: usize, order_id: OrderId) -> Option<f64> {
+        let instrument = self.instruments.get(asset_no)?;
+        let exch_order_id = instrument.orders.get(&order_id)?.exch_order_id;
+        if exch_order_id == 0 {
+            return None;
+        }
+        let own = instrument.depth.orders().get(&exch_order_id)?;
+        Some(qty_ahead(
+            &instrument.depth,
+            own.side,
+            own.price_tick,
+            own.timestamp,
+        ))
+    }
+}
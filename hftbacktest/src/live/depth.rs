@@ -0,0 +1,316 @@
+use tracing::error;
+
+#[cfg(any(feature = "unstable_fuse", doc))]
+use crate::depth::FusedHashMapMarketDepth;
+use crate::{
+    depth::{
+        BBOMarketDepth,
+        BTreeMarketDepth,
+        HashMapMarketDepth,
+        L2MarketDepth,
+        L3MarketDepth,
+        MarketDepth,
+        ROIVectorMarketDepth,
+    },
+    types::{
+        Event,
+        Side,
+        LOCAL_ASK_ADD_ORDER_EVENT,
+        LOCAL_ASK_DEPTH_CLEAR_EVENT,
+        LOCAL_ASK_DEPTH_EVENT,
+        LOCAL_BID_ADD_ORDER_EVENT,
+        LOCAL_BID_DEPTH_CLEAR_EVENT,
+        LOCAL_BID_DEPTH_EVENT,
+        LOCAL_CANCEL_ORDER_EVENT,
+        LOCAL_DEPTH_CLEAR_EVENT,
+        LOCAL_FILL_EVENT,
+        LOCAL_MODIFY_ORDER_EVENT,
+    },
+};
+
+/// Applies the market feed events received by a [`LiveBot`](crate::live::LiveBot) to the market
+/// depth.
+///
+/// A Level2 market depth applies the depth events and the depth clear events, which clear the depth
+/// up to the given price, in the same way as [`Local`](crate::backtest::proc::Local) does in
+/// backtesting. A market depth that also supports Level3 Market-By-Order applies the add, cancel,
+/// and modify order events in the same way as [`L3Local`](crate::backtest::proc::L3Local) does, and
+/// once it holds Market-By-Order orders, the depth clear events clear the orders on the side, so
+/// the same strategy runs in both. A fill event reduces the quantity of the filled order by the
+/// executed quantity and removes the order once it is fully filled. A cancel event for an order
+/// that has already been removed this way is ignored.
+pub trait LiveMarketDepth: MarketDepth {
+    /// Applies the market feed event to the market depth.
+    fn apply_feed(&mut self, event: &Event);
+}
+
+fn apply_l2_feed<MD: L2MarketDepth>(depth: &mut MD, event: &Event) {
+    if event.is(LOCAL_BID_DEPTH_EVENT) {
+        depth.update_bid_depth(event.px, event.qty, event.exch_ts);
+    } else if event.is(LOCAL_ASK_DEPTH_EVENT) {
+        depth.update_ask_depth(event.px, event.qty, event.exch_ts);
+    } else if event.is(LOCAL_BID_DEPTH_CLEAR_EVENT) {
+        depth.clear_depth(Side::Buy, event.px);
+    } else if event.is(LOCAL_ASK_DEPTH_CLEAR_EVENT) {
+        depth.clear_depth(Side::Sell, event.px);
+    } else if event.is(LOCAL_DEPTH_CLEAR_EVENT) {
+        depth.clear_depth(Side::None, 0.0);
+    }
+}
+
+fn apply_l3_feed<MD>(depth: &mut MD, event: &Event)
+where
+    MD: L2MarketDepth + L3MarketDepth,
+    MD::Error: std::fmt::Debug,
+{
+    // The depth clear events of a Level2 feed clear the depth up to the given price as in
+    // `Local`, while those of a Market-By-Order feed clear the orders on the side as in `L3Local`.
+    let is_l3 = !depth.orders().is_empty();
+    let result = if is_l3 && event.is(LOCAL_BID_DEPTH_CLEAR_EVENT) {
+        depth.clear_orders(Side::Buy);
+        Ok(())
+    } else if is_l3 && event.is(LOCAL_ASK_DEPTH_CLEAR_EVENT) {
+        depth.clear_orders(Side::Sell);
+        Ok(())
+    } else if is_l3 && event.is(LOCAL_DEPTH_CLEAR_EVENT) {
+        depth.clear_orders(Side::None);
+        Ok(())
+    } else if event.is(LOCAL_BID_ADD_ORDER_EVENT) {
+        depth
+            .add_buy_order(event.order_id, event.px, event.qty, event.exch_ts)
+            .map(|_| ())
+    } else if event.is(LOCAL_ASK_ADD_ORDER_EVENT) {
+        depth
+            .add_sell_order(event.order_id, event.px, event.qty, event.exch_ts)
+            .map(|_| ())
+    } else if event.is(LOCAL_MODIFY_ORDER_EVENT) {
+        depth
+            .modify_order(event.order_id, event.px, event.qty, event.exch_ts)
+            .map(|_| ())
+    } else if event.is(LOCAL_FILL_EVENT) {
+        let order = depth
+            .orders()
+            .get(&event.order_id)
+            .map(|order| (order.price_tick, order.qty));
+        match order {
+            Some((price_tick, qty)) => {
+                let qty = qty - event.qty;
+                if (qty / depth.lot_size()).round() > 0.0 {
+                    let px = price_tick as f64 * depth.tick_size();
+                    depth
+                        .modify_order(event.order_id, px, qty, event.exch_ts)
+                        .map(|_| ())
+                } else {
+                    depth
+                        .delete_order(event.order_id, event.exch_ts)
+                        .map(|_| ())
+                }
+            }
+            None => Ok(()),
+        }
+    } else if event.is(LOCAL_CANCEL_ORDER_EVENT) {
+        if depth.orders().contains_key(&event.order_id) {
+            depth
+                .delete_order(event.order_id, event.exch_ts)
+                .map(|_| ())
+        } else {
+            Ok(())
+        }
+    } else {
+        apply_l2_feed(depth, event);
+        Ok(())
+    };
+    if let Err(error) = result {
+        error!(?error, ?event, "Couldn't apply the Market-By-Order event.");
+    }
+}
+
+impl LiveMarketDepth for HashMapMarketDepth {
+    fn apply_feed(&mut self, event: &Event) {
+        apply_l3_feed(self, event);
+    }
+}
+
+impl LiveMarketDepth for BTreeMarketDepth {
+    fn apply_feed(&mut self, event: &Event) {
+        apply_l3_feed(self, event);
+    }
+}
+
+impl LiveMarketDepth for ROIVectorMarketDepth {
+    fn apply_feed(&mut self, event: &Event) {
+        apply_l3_feed(self, event);
+    }
+}
+
+impl LiveMarketDepth for BBOMarketDepth {
+    fn apply_feed(&mut self, event: &Event) {
+        apply_l2_feed(self, event);
+    }
+}
+
+#[cfg(any(feature = "unstable_fuse", doc))]
+impl LiveMarketDepth for FusedHashMapMarketDepth {
+    fn apply_feed(&mut self, event: &Event) {
+        apply_l2_feed(self, event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        depth::{HashMapMarketDepth, L3MarketDepth, MarketDepth},
+        live::{
+            ipc::replay::{ReplayChannel, ReplayMode, SessionRecord},
+            Instrument,
+            LiveBotBuilder,
+        },
+        types::{
+            Bot,
+            Event,
+            L3Bot,
+            LiveEvent,
+            OrdType,
+            Order,
+            Side,
+            Status,
+            TimeInForce,
+            LOCAL_ASK_ADD_ORDER_EVENT,
+            LOCAL_ASK_DEPTH_EVENT,
+            LOCAL_BID_ADD_ORDER_EVENT,
+            LOCAL_BID_DEPTH_CLEAR_EVENT,
+            LOCAL_BID_DEPTH_EVENT,
+            LOCAL_CANCEL_ORDER_EVENT,
+            LOCAL_FILL_EVENT,
+            LOCAL_MODIFY_ORDER_EVENT,
+        },
+    };
+
+    fn feed(timestamp: i64, ev: u64, order_id: u64, px: f64, qty: f64) -> SessionRecord {
+        SessionRecord::Recv {
+            timestamp,
+            inst_no: 0,
+            event: LiveEvent::Feed {
+                symbol: "BTCUSDT".to_string(),
+                event: Event {
+                    ev,
+                    exch_ts: timestamp - 100,
+                    local_ts: timestamp,
+                    px,
+                    qty,
+                    order_id,
+                    ival: 0,
+                    fval: 0.0,
+                },
+            },
+        }
+    }
+
+    #[test]
+    fn test_market_by_order_feed() {
+        let mut order = Order::new(
+            1,
+            1000,
+            0.1,
+            2.0,
+            Side::Buy,
+            OrdType::Limit,
+            TimeInForce::GTC,
+        );
+        order.status = Status::New;
+        order.exch_timestamp = 2_900;
+        order.exch_order_id = 103;
+        let records = vec![
+            feed(1_000, LOCAL_BID_ADD_ORDER_EVENT, 101, 100.0, 1.0),
+            feed(2_000, LOCAL_BID_ADD_ORDER_EVENT, 102, 100.0, 3.0),
+            feed(3_000, LOCAL_BID_ADD_ORDER_EVENT, 103, 100.0, 2.0),
+            SessionRecord::Recv {
+                timestamp: 3_500,
+                inst_no: 0,
+                event: LiveEvent::Order {
+                    symbol: "BTCUSDT".to_string(),
+                    order,
+                },
+            },
+            feed(4_000, LOCAL_BID_ADD_ORDER_EVENT, 104, 100.0, 5.0),
+            feed(5_000, LOCAL_ASK_ADD_ORDER_EVENT, 201, 100.2, 1.0),
+            feed(6_000, LOCAL_FILL_EVENT, 101, 100.0, 0.4),
+            feed(7_000, LOCAL_FILL_EVENT, 101, 100.0, 0.6),
+            feed(7_500, LOCAL_CANCEL_ORDER_EVENT, 101, 0.0, 0.0),
+            feed(8_000, LOCAL_MODIFY_ORDER_EVENT, 102, 100.0, 1.5),
+            feed(8_500, LOCAL_CANCEL_ORDER_EVENT, 104, 0.0, 0.0),
+            feed(3_000_000_000, LOCAL_ASK_ADD_ORDER_EVENT, 202, 100.3, 1.0),
+        ];
+
+        let mut hbt = LiveBotBuilder::new()
+            .register(Instrument::new(
+                "connector",
+                "BTCUSDT",
+                0.1,
+                0.001,
+                HashMapMarketDepth::new(0.1, 0.001),
+                0,
+            ))
+            .build_with_channel(ReplayChannel::new(records, ReplayMode::Virtual))
+            .unwrap();
+        while hbt.current_timestamp() < 5_000 {
+            assert!(hbt.elapse(1_000).unwrap());
+        }
+        assert_eq!(hbt.depth(0).best_bid_tick(), 1000);
+        assert_eq!(hbt.depth(0).best_ask_tick(), 1002);
+        assert_eq!(hbt.depth(0).bid_qty_at_tick(1000), 11.0);
+        // The orders added before the bot's own order are ahead of it in the queue.
+        assert_eq!(hbt.queue_ahead(0, 1), Some(4.0));
+        assert_eq!(hbt.queue_ahead(0, 2), None);
+
+        while hbt.current_timestamp() < 6_000 {
+            assert!(hbt.elapse(1_000).unwrap());
+        }
+        // The partial fill reduces the quantity of the order.
+        assert_eq!(hbt.depth(0).orders().get(&101).unwrap().qty, 0.6);
+        assert_eq!(hbt.depth(0).bid_qty_at_tick(1000), 10.6);
+        assert_eq!(hbt.queue_ahead(0, 1), Some(3.6));
+
+        while hbt.current_timestamp() < 9_000 {
+            assert!(hbt.elapse(1_000).unwrap());
+        }
+        // The full fill removes the order, and the subsequent cancel event of the filled order is
+        // ignored.
+        assert!(!hbt.depth(0).orders().contains_key(&101));
+        assert!(!hbt.depth(0).orders().contains_key(&104));
+        assert_eq!(hbt.depth(0).bid_qty_at_tick(1000), 3.5);
+        assert_eq!(hbt.queue_ahead(0, 1), Some(1.5));
+    }
+
+    #[test]
+    fn test_depth_clear_feed() {
+        let records = vec![
+            feed(1_000, LOCAL_BID_DEPTH_EVENT, 0, 100.0, 1.0),
+            feed(1_100, LOCAL_BID_DEPTH_EVENT, 0, 99.9, 2.0),
+            feed(1_200, LOCAL_BID_DEPTH_EVENT, 0, 99.8, 3.0),
+            feed(1_300, LOCAL_ASK_DEPTH_EVENT, 0, 100.1, 1.0),
+            feed(2_000, LOCAL_BID_DEPTH_CLEAR_EVENT, 0, 99.9, 0.0),
+            feed(3_000_000_000, LOCAL_ASK_DEPTH_EVENT, 0, 100.2, 1.0),
+        ];
+
+        let mut hbt = LiveBotBuilder::new()
+            .register(Instrument::new(
+                "connector",
+                "BTCUSDT",
+                0.1,
+                0.001,
+                HashMapMarketDepth::new(0.1, 0.001),
+                0,
+            ))
+            .build_with_channel(ReplayChannel::new(records, ReplayMode::Virtual))
+            .unwrap();
+        while hbt.current_timestamp() < 2_000 {
+            assert!(hbt.elapse(1_000).unwrap());
+        }
+        // The Level2 depth clear event clears the bid side only up to the given price.
+        assert_eq!(hbt.depth(0).best_bid_tick(), 998);
+        assert_eq!(hbt.depth(0).bid_qty_at_tick(998), 3.0);
+        assert_eq!(hbt.depth(0).bid_qty_at_tick(999), 0.0);
+        assert_eq!(hbt.depth(0).best_ask_tick(), 1001);
+    }
+}
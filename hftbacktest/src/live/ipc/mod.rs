@@ -1,5 +1,7 @@
 use std::time::Duration;
 
+use chrono::Utc;
+
 use crate::{
     live::{BotError, Instrument},
     prelude::BuildError,
@@ -7,6 +9,7 @@ use crate::{
 };
 
 pub mod iceoryx;
+pub mod replay;
 
 pub const TO_ALL: u64 = 0;
 
@@ -27,4 +30,10 @@ pub trait Channel {
 
     /// Sends a [`LiveRequest`] to the connector corresponding to the `inst_no`.
     fn send(&mut self, id: u64, inst_no: usize, request: LiveRequest) -> Result<(), BotError>;
+
+    /// Returns the current timestamp in nanoseconds, which is used as the clock of the
+    /// [`LiveBot`](`crate::live::LiveBot`) using this channel. By default, it is the system time.
+    fn now(&self) -> i64 {
+        Utc::now().timestamp_nanos_opt().unwrap()
+    }
 }
@@ -0,0 +1,446 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufWriter, Error as IoError, ErrorKind, Write},
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use bincode::{config, error::DecodeError, Decode, Encode};
+
+use crate::{
+    live::{ipc::Channel, BotError, Instrument},
+    types::{BuildError, LiveEvent, LiveRequest},
+};
+
+/// A record of a live session written by [`RecordingChannel`].
+#[derive(Clone, Debug, Encode, Decode)]
+pub enum SessionRecord {
+    /// A [`LiveEvent`] received by the bot, with the timestamp at which it is received.
+    Recv {
+        timestamp: i64,
+        inst_no: usize,
+        event: LiveEvent,
+    },
+    /// A [`LiveRequest`] sent by the bot, with the timestamp at which it is sent.
+    Send {
+        timestamp: i64,
+        inst_no: usize,
+        request: LiveRequest,
+    },
+}
+
+/// Reads the records of a live session written by [`RecordingChannel`].
+///
+/// If the session is terminated abruptly and the last record is incomplete, the records up to the
+/// incomplete one are returned.
+pub fn read_session<P>(path: P) -> Result<Vec<SessionRecord>, IoError>
+where
+    P: AsRef<Path>,
+{
+    let bytes = std::fs::read(path)?;
+    let mut records = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        match bincode::decode_from_slice::<SessionRecord, _>(&bytes[offset..], config::standard()) {
+            Ok((record, len)) => {
+                records.push(record);
+                offset += len;
+            }
+            Err(DecodeError::UnexpectedEnd { .. }) => break,
+            Err(error) => return Err(IoError::new(ErrorKind::InvalidData, error.to_string())),
+        }
+    }
+    Ok(records)
+}
+
+/// A [`Channel`] that records every [`LiveEvent`] received through the wrapped channel and every
+/// [`LiveRequest`] sent through it into a file, which can be replayed by [`ReplayChannel`].
+///
+/// Since the file path cannot be derived from the assets, it should be built by
+/// [`LiveBotBuilder::build_with_channel`](`crate::live::LiveBotBuilder::build_with_channel`).
+///
+/// **Example**
+/// ```ignore
+/// let channel = RecordingChannel::new(
+///     IceoryxUnifiedChannel::build(&instruments)?,
+///     "session.bin",
+/// )?;
+/// let mut hbt = LiveBotBuilder::new()
+///     .register(instrument)
+///     .build_with_channel(channel)?;
+/// ```
+pub struct RecordingChannel<CH> {
+    channel: CH,
+    writer: BufWriter<File>,
+}
+
+impl<CH> RecordingChannel<CH>
+where
+    CH: Channel,
+{
+    /// Constructs a `RecordingChannel` that wraps the given channel and records into the file at
+    /// the given path.
+    pub fn new<P>(channel: CH, path: P) -> Result<Self, IoError>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Self {
+            channel,
+            writer: BufWriter::new(File::create(path)?),
+        })
+    }
+
+    fn write(&mut self, record: &SessionRecord) -> Result<(), BotError> {
+        bincode::encode_into_std_write(record, &mut self.writer, config::standard())
+            .map_err(|error| BotError::Custom(error.to_string()))?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), BotError> {
+        self.writer
+            .flush()
+            .map_err(|error| BotError::Custom(error.to_string()))
+    }
+}
+
+impl<CH> Channel for RecordingChannel<CH>
+where
+    CH: Channel,
+{
+    fn build<MD>(_instruments: &[Instrument<MD>]) -> Result<Self, BuildError>
+    where
+        Self: Sized,
+    {
+        Err(BuildError::InvalidArgument(
+            "`RecordingChannel` must be constructed by `RecordingChannel::new`",
+        ))
+    }
+
+    fn recv_timeout(&mut self, id: u64, timeout: Duration) -> Result<(usize, LiveEvent), BotError> {
+        match self.channel.recv_timeout(id, timeout) {
+            Ok((inst_no, event)) => {
+                let timestamp = self.channel.now();
+                self.write(&SessionRecord::Recv {
+                    timestamp,
+                    inst_no,
+                    event: event.clone(),
+                })?;
+                Ok((inst_no, event))
+            }
+            Err(error) => {
+                // Flushes while idle so that the file stays close to up to date without flushing
+                // on every event.
+                if matches!(error, BotError::Timeout | BotError::Interrupted) {
+                    self.flush()?;
+                }
+                Err(error)
+            }
+        }
+    }
+
+    fn send(&mut self, id: u64, inst_no: usize, request: LiveRequest) -> Result<(), BotError> {
+        let timestamp = self.channel.now();
+        self.write(&SessionRecord::Send {
+            timestamp,
+            inst_no,
+            request: request.clone(),
+        })?;
+        self.flush()?;
+        self.channel.send(id, inst_no, request)
+    }
+
+    fn now(&self) -> i64 {
+        self.channel.now()
+    }
+}
+
+/// Determines how [`ReplayChannel`] advances its clock.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ReplayMode {
+    /// The clock jumps to the timestamp of the next event, or to the end of the timeout if there
+    /// is no event before it, so the session is replayed as fast as possible.
+    Virtual,
+    /// The clock follows the system clock from the timestamp of the first record, so the session
+    /// is replayed at the recorded pace.
+    RealTime,
+}
+
+/// A [`Channel`] that replays a live session recorded by [`RecordingChannel`] into
+/// [`LiveBot`](`crate::live::LiveBot`) without any connector.
+///
+/// The recorded events are delivered at their recorded receive timestamps, and the bot's clock
+/// is the replay clock, so the bot observes the same sequence of events and timeouts as in the
+/// recorded session. Once all events are delivered, it reports [`BotError::Interrupted`], which
+/// ends the bot's waiting functions with `false`. The requests sent by the bot are not forwarded
+/// anywhere but are kept to be compared with the recorded requests.
+///
+/// **Example**
+/// ```ignore
+/// let channel = ReplayChannel::open("session.bin", ReplayMode::Virtual)?;
+/// let mut hbt = LiveBotBuilder::new()
+///     .register(instrument)
+///     .build_with_channel(channel)?;
+/// ```
+pub struct ReplayChannel {
+    events: VecDeque<(i64, usize, LiveEvent)>,
+    recorded_requests: Vec<(i64, usize, LiveRequest)>,
+    sent_requests: Vec<(i64, usize, LiveRequest)>,
+    mode: ReplayMode,
+    start_ts: i64,
+    start_instant: Instant,
+    clock: i64,
+}
+
+impl ReplayChannel {
+    /// Constructs a `ReplayChannel` that replays the given records.
+    pub fn new(records: Vec<SessionRecord>, mode: ReplayMode) -> Self {
+        let start_ts = match records.first() {
+            Some(SessionRecord::Recv { timestamp, .. })
+            | Some(SessionRecord::Send { timestamp, .. }) => *timestamp,
+            None => 0,
+        };
+        let mut events = VecDeque::new();
+        let mut recorded_requests = Vec::new();
+        for record in records {
+            match record {
+                SessionRecord::Recv {
+                    timestamp,
+                    inst_no,
+                    event,
+                } => events.push_back((timestamp, inst_no, event)),
+                SessionRecord::Send {
+                    timestamp,
+                    inst_no,
+                    request,
+                } => recorded_requests.push((timestamp, inst_no, request)),
+            }
+        }
+        Self {
+            events,
+            recorded_requests,
+            sent_requests: Vec::new(),
+            mode,
+            start_ts,
+            start_instant: Instant::now(),
+            clock: start_ts,
+        }
+    }
+
+    /// Constructs a `ReplayChannel` that replays the session recorded in the file at the given
+    /// path.
+    pub fn open<P>(path: P, mode: ReplayMode) -> Result<Self, IoError>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Self::new(read_session(path)?, mode))
+    }
+
+    /// Returns the requests sent by the bot in the recorded session, with the timestamps at which
+    /// they were sent and the instrument numbers.
+    pub fn recorded_requests(&self) -> &[(i64, usize, LiveRequest)] {
+        &self.recorded_requests
+    }
+
+    /// Returns the requests sent by the bot during the replay, with the timestamps at which they
+    /// are sent and the instrument numbers.
+    pub fn sent_requests(&self) -> &[(i64, usize, LiveRequest)] {
+        &self.sent_requests
+    }
+
+    /// Returns the number of the events that are not replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.events.len()
+    }
+}
+
+impl Channel for ReplayChannel {
+    fn build<MD>(_instruments: &[Instrument<MD>]) -> Result<Self, BuildError>
+    where
+        Self: Sized,
+    {
+        Err(BuildError::InvalidArgument(
+            "`ReplayChannel` must be constructed by `ReplayChannel::new` or `ReplayChannel::open`",
+        ))
+    }
+
+    fn recv_timeout(
+        &mut self,
+        _id: u64,
+        timeout: Duration,
+    ) -> Result<(usize, LiveEvent), BotError> {
+        // The recorded events are already filtered by the bot ID.
+        let now = self.now();
+        let timeout = i64::try_from(timeout.as_nanos()).unwrap_or(i64::MAX);
+        let deadline = now.saturating_add(timeout);
+        let timestamp = match self.events.front() {
+            Some((timestamp, ..)) => *timestamp,
+            None => return Err(BotError::Interrupted),
+        };
+        if timestamp > deadline {
+            match self.mode {
+                ReplayMode::Virtual => self.clock = deadline,
+                ReplayMode::RealTime => thread::sleep(Duration::from_nanos(timeout as u64)),
+            }
+            return Err(BotError::Timeout);
+        }
+        match self.mode {
+            ReplayMode::Virtual => self.clock = self.clock.max(timestamp),
+            ReplayMode::RealTime => {
+                if timestamp > now {
+                    thread::sleep(Duration::from_nanos((timestamp - now) as u64));
+                }
+            }
+        }
+        let (_, inst_no, event) = self.events.pop_front().unwrap();
+        Ok((inst_no, event))
+    }
+
+    fn send(&mut self, _id: u64, inst_no: usize, request: LiveRequest) -> Result<(), BotError> {
+        let timestamp = self.now();
+        self.sent_requests.push((timestamp, inst_no, request));
+        Ok(())
+    }
+
+    fn now(&self) -> i64 {
+        match self.mode {
+            ReplayMode::Virtual => self.clock,
+            ReplayMode::RealTime => self.start_ts + self.start_instant.elapsed().as_nanos() as i64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        depth::{HashMapMarketDepth, MarketDepth},
+        live::{
+            ipc::{
+                replay::{
+                    read_session,
+                    RecordingChannel,
+                    ReplayChannel,
+                    ReplayMode,
+                    SessionRecord,
+                },
+                Channel,
+            },
+            Instrument,
+            LiveBotBuilder,
+        },
+        types::{
+            Bot,
+            Event,
+            LiveEvent,
+            LiveRequest,
+            OrdType,
+            TimeInForce,
+            LOCAL_ASK_DEPTH_EVENT,
+            LOCAL_BID_DEPTH_EVENT,
+        },
+    };
+
+    fn feed(timestamp: i64, ev: u64, px: f64) -> SessionRecord {
+        SessionRecord::Recv {
+            timestamp,
+            inst_no: 0,
+            event: LiveEvent::Feed {
+                symbol: "BTCUSDT".to_string(),
+                event: Event {
+                    ev,
+                    exch_ts: timestamp - 100,
+                    local_ts: timestamp,
+                    px,
+                    qty: 1.0,
+                    order_id: 0,
+                    ival: 0,
+                    fval: 0.0,
+                },
+            },
+        }
+    }
+
+    fn replay(channel: ReplayChannel) -> (Vec<i64>, i64, i64, usize) {
+        let mut hbt = LiveBotBuilder::new()
+            .register(Instrument::new(
+                "connector",
+                "BTCUSDT",
+                0.1,
+                0.001,
+                HashMapMarketDepth::new(0.1, 0.001),
+                0,
+            ))
+            .build_with_channel(channel)
+            .unwrap();
+        let mut timestamps = Vec::new();
+        while hbt.elapse(1_000_000_000).unwrap() {
+            timestamps.push(hbt.current_timestamp());
+            if timestamps.len() == 1 {
+                hbt.submit_buy_order(0, 1, 100.0, 1.0, TimeInForce::GTC, OrdType::Limit, false)
+                    .unwrap();
+            }
+        }
+        let depth = hbt.depth(0);
+        (
+            timestamps,
+            depth.best_bid_tick(),
+            depth.best_ask_tick(),
+            hbt.orders(0).len(),
+        )
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let records = vec![
+            feed(1_000, LOCAL_BID_DEPTH_EVENT, 99.9),
+            feed(2_000, LOCAL_ASK_DEPTH_EVENT, 100.2),
+            feed(5_000, LOCAL_BID_DEPTH_EVENT, 100.0),
+            feed(3_000_000_000, LOCAL_ASK_DEPTH_EVENT, 100.1),
+        ];
+
+        // Records a session by wrapping a channel that delivers the records above.
+        let path = std::env::temp_dir().join("hftbacktest_test_record_and_replay.bin");
+        let mut channel =
+            RecordingChannel::new(ReplayChannel::new(records, ReplayMode::Virtual), &path).unwrap();
+        while let Ok((_, LiveEvent::Feed { event, .. })) = channel.recv_timeout(0, Duration::MAX) {
+            assert_eq!(event.local_ts, channel.now());
+        }
+        let request = LiveRequest::RegisterInstrument {
+            symbol: "BTCUSDT".to_string(),
+            tick_size: 0.1,
+            lot_size: 0.001,
+        };
+        channel.send(0, 0, request).unwrap();
+        drop(channel);
+
+        let records = read_session(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(records.len(), 5);
+        assert!(matches!(
+            records[3],
+            SessionRecord::Recv {
+                timestamp: 3_000_000_000,
+                ..
+            }
+        ));
+        assert!(matches!(records[4], SessionRecord::Send { .. }));
+
+        let channel = ReplayChannel::new(records.clone(), ReplayMode::Virtual);
+        assert_eq!(channel.recorded_requests().len(), 1);
+        let (timestamps, best_bid_tick, best_ask_tick, num_orders) = replay(channel);
+        // The bot runs on the replay clock, which starts from the first record's timestamp.
+        assert_eq!(timestamps, vec![1_000_001_000, 2_000_001_000]);
+        assert_eq!(best_bid_tick, 1000);
+        assert_eq!(best_ask_tick, 1001);
+        assert_eq!(num_orders, 1);
+
+        // Replaying the same session produces the same result.
+        assert_eq!(
+            replay(ReplayChannel::new(records, ReplayMode::Virtual)),
+            (timestamps, best_bid_tick, best_ask_tick, num_orders)
+        );
+    }
+}
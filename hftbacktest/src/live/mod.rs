@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 
 pub use bot::{BotError, LiveBot, LiveBotBuilder};
+pub use depth::LiveMarketDepth;
 pub use recorder::LoggingRecorder;
 
 use crate::{
@@ -9,6 +10,7 @@ use crate::{
 };
 
 mod bot;
+mod depth;
 pub mod ipc;
 mod recorder;
 
@@ -22,6 +24,7 @@ pub struct Instrument<MD> {
     last_trades: Vec<Event>,
     orders: HashMap<OrderId, Order>,
     last_feed_latency: Option<(i64, i64)>,
+    last_depth_latency: Option<(i64, i64)>,
     last_order_latency: Option<(i64, i64, i64)>,
     state: StateValues,
 }
@@ -52,6 +55,7 @@ impl<MD> Instrument<MD> {
             last_trades: Vec::with_capacity(last_trades_capacity),
             orders: Default::default(),
             last_feed_latency: None,
+            last_depth_latency: None,
             last_order_latency: None,
             state: Default::default(),
         }
@@ -1 +1 @@
-pub use crate::{depth::*, types::*, utils::*};
+pub use crate::{depth::*, risk::*, strategy::*, types::*, utils::*};
@@ -0,0 +1,573 @@
+use std::collections::{HashMap, VecDeque};
+
+use thiserror::Error;
+
+use crate::{
+    depth::MarketDepth,
+    types::{Bot, OrdType, Order, OrderId, Side, Status, TimeInForce},
+};
+
+/// How [`KillSwitch`] flattens the positions when it trips.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Flatten {
+    /// Leaves the positions as they are.
+    None,
+    /// Flattens the positions with market orders.
+    Market,
+    /// Flattens the positions with IOC limit orders priced `slippage_ticks` through the best price
+    /// on the opposite side.
+    Ioc { slippage_ticks: i64 },
+}
+
+/// The post-trade limits monitored by [`KillSwitch`], either for an asset or for the portfolio.
+/// No limit is set by default.
+///
+/// The equity is the profit and loss since the monitoring starts, valuing the position at the mid
+/// price, and the realized loss is computed from the fills on an average cost basis. Both are net
+/// of the fees set by [`KillSwitch::fees`].
+#[derive(Clone, Debug, Default)]
+pub struct KillSwitchLimits {
+    min_equity: Option<f64>,
+    max_drawdown: Option<f64>,
+    max_loss: Option<(f64, i64)>,
+    max_inventory_age: Option<i64>,
+}
+
+impl KillSwitchLimits {
+    /// Constructs `KillSwitchLimits` without any limit.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets the minimum equity, which is usually negative as the equity starts from zero.
+    pub fn min_equity(self, min_equity: f64) -> Self {
+        Self {
+            min_equity: Some(min_equity),
+            ..self
+        }
+    }
+
+    /// Sets the maximum drawdown of the equity from its peak.
+    pub fn max_drawdown(self, max_drawdown: f64) -> Self {
+        Self {
+            max_drawdown: Some(max_drawdown),
+            ..self
+        }
+    }
+
+    /// Sets the maximum realized loss within the `window`.
+    pub fn max_loss(self, max_loss: f64, window: i64) -> Self {
+        Self {
+            max_loss: Some((max_loss, window)),
+            ..self
+        }
+    }
+
+    /// Sets the maximum duration for which a position can be held without being closed or
+    /// reversed. This is only checked for an asset.
+    pub fn max_inventory_age(self, max_inventory_age: i64) -> Self {
+        Self {
+            max_inventory_age: Some(max_inventory_age),
+            ..self
+        }
+    }
+}
+
+/// The reason for which [`KillSwitch`] trips. `asset_no` is `None` if a portfolio limit is
+/// breached.
+#[derive(Error, Clone, Debug, PartialEq)]
+pub enum KillReason {
+    #[error("equity {equity} falls below the minimum equity {min_equity}")]
+    MinEquity {
+        asset_no: Option<usize>,
+        equity: f64,
+        min_equity: f64,
+    },
+    #[error("drawdown {drawdown} exceeds the maximum drawdown {max_drawdown}")]
+    MaxDrawdown {
+        asset_no: Option<usize>,
+        drawdown: f64,
+        max_drawdown: f64,
+    },
+    #[error("realized loss {loss} within {window} exceeds the maximum loss {max_loss}")]
+    MaxLoss {
+        asset_no: Option<usize>,
+        loss: f64,
+        max_loss: f64,
+        window: i64,
+    },
+    #[error("inventory age {age} exceeds the maximum inventory age {max_inventory_age}")]
+    MaxInventoryAge {
+        asset_no: usize,
+        age: i64,
+        max_inventory_age: i64,
+    },
+}
+
+/// The profit and loss of an asset tracked from the fills.
+#[derive(Default)]
+struct AssetMonitor {
+    initialized: bool,
+    orders: HashMap<OrderId, Order>,
+    position: f64,
+    avg_price: f64,
+    cash: f64,
+    equity: f64,
+    peak_equity: f64,
+    realized: VecDeque<(i64, f64)>,
+    last_position: f64,
+    inventory_since: Option<i64>,
+}
+
+impl AssetMonitor {
+    fn apply_fill(
+        &mut self,
+        timestamp: i64,
+        side: Side,
+        qty: f64,
+        price: f64,
+        contract_size: f64,
+        fee_rate: f64,
+    ) {
+        let signed_qty = qty * AsRef::<f64>::as_ref(&side);
+        let fee = qty * price * contract_size * fee_rate;
+        self.cash -= signed_qty * price * contract_size + fee;
+        if self.position * signed_qty < 0.0 {
+            let closed_qty = qty.min(self.position.abs());
+            let pnl =
+                (price - self.avg_price) * closed_qty * self.position.signum() * contract_size;
+            self.realized.push_back((timestamp, pnl - fee));
+            if qty > self.position.abs() {
+                self.avg_price = price;
+            }
+        } else {
+            if fee != 0.0 {
+                self.realized.push_back((timestamp, -fee));
+            }
+            self.avg_price =
+                (self.avg_price * self.position.abs() + price * qty) / (self.position.abs() + qty);
+        }
+        self.position += signed_qty;
+    }
+
+    fn realized_loss(&self, timestamp: i64, window: i64) -> f64 {
+        -self
+            .realized
+            .iter()
+            .filter(|(realized_timestamp, _)| *realized_timestamp > timestamp - window)
+            .map(|(_, pnl)| pnl)
+            .sum::<f64>()
+    }
+}
+
+/// Monitors the equity, the drawdown from the peak, the realized loss within a time window, and
+/// the inventory age of each asset and of the portfolio. When a limit is breached, it trips:
+/// it cancels all open orders, optionally flattens the positions, and locks the bot, which
+/// [`RiskManagedBot`](crate::risk::RiskManagedBot) enforces by rejecting all further orders.
+///
+/// The kill switch only relies on the [`Bot`] interface, so it works the same in both
+/// backtesting and live trading. Fills are detected from the change in the executed quantity of
+/// the orders observed at each check, so it should be checked before inactive orders are cleared;
+/// [`RiskManagedBot`](crate::risk::RiskManagedBot) does this automatically. The position is taken
+/// from the bot, and a change in the position that isn't observed as a fill, such as a
+/// liquidation, is valued at the mid price.
+///
+/// Once tripped, it keeps flattening the positions at every check until they are closed, waiting
+/// for the previous flattening order of the asset to complete before sending another.
+///
+/// **Example**
+/// ```ignore
+/// let kill_switch = KillSwitch::new()
+///     .portfolio_limits(KillSwitchLimits::new().max_drawdown(1000.0))
+///     .flatten(Flatten::Market);
+/// let mut hbt = RiskManagedBot::new(hbt).kill_switch(kill_switch);
+/// ```
+pub struct KillSwitch {
+    asset_limits: HashMap<usize, KillSwitchLimits>,
+    portfolio_limits: KillSwitchLimits,
+    contract_size: HashMap<usize, f64>,
+    fees: HashMap<usize, (f64, f64)>,
+    flatten: Flatten,
+    assets: Vec<AssetMonitor>,
+    portfolio_peak_equity: f64,
+    next_order_id: OrderId,
+    flatten_orders: HashMap<usize, OrderId>,
+    tripped: Option<KillReason>,
+}
+
+impl Default for KillSwitch {
+    fn default() -> Self {
+        Self {
+            asset_limits: Default::default(),
+            portfolio_limits: Default::default(),
+            contract_size: Default::default(),
+            fees: Default::default(),
+            flatten: Flatten::None,
+            assets: Vec::new(),
+            portfolio_peak_equity: 0.0,
+            // `u64::MAX` is reserved for the liquidation reports in backtesting.
+            next_order_id: OrderId::MAX - 1,
+            flatten_orders: Default::default(),
+            tripped: None,
+        }
+    }
+}
+
+impl KillSwitch {
+    /// Constructs a `KillSwitch` without any limit.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets the limits of the asset.
+    pub fn asset_limits(mut self, asset_no: usize, limits: KillSwitchLimits) -> Self {
+        self.asset_limits.insert(asset_no, limits);
+        self
+    }
+
+    /// Sets the limits of the portfolio, which sums up the equity and the realized loss of all
+    /// assets.
+    pub fn portfolio_limits(self, portfolio_limits: KillSwitchLimits) -> Self {
+        Self {
+            portfolio_limits,
+            ..self
+        }
+    }
+
+    /// Sets the contract size of the asset, by which the value of the position is multiplied. The
+    /// default value is `1.0`.
+    pub fn contract_size(mut self, asset_no: usize, contract_size: f64) -> Self {
+        self.contract_size.insert(asset_no, contract_size);
+        self
+    }
+
+    /// Sets the maker and taker fee rates of the asset, which are charged on the traded value of
+    /// the fills. A negative fee rate is a rebate. The default values are `0.0`.
+    pub fn fees(mut self, asset_no: usize, maker_fee: f64, taker_fee: f64) -> Self {
+        self.fees.insert(asset_no, (maker_fee, taker_fee));
+        self
+    }
+
+    /// Sets how to flatten the positions when the kill switch trips. The flattening orders use
+    /// order IDs counting down from `u64::MAX - 1`. The default value is [`Flatten::None`].
+    pub fn flatten(self, flatten: Flatten) -> Self {
+        Self { flatten, ..self }
+    }
+
+    /// Returns the reason for which the kill switch has tripped, or `None` if it hasn't.
+    pub fn tripped(&self) -> Option<&KillReason> {
+        self.tripped.as_ref()
+    }
+
+    /// Returns the equity of the asset, which is the profit and loss since the monitoring starts.
+    pub fn equity(&self, asset_no: usize) -> f64 {
+        self.assets
+            .get(asset_no)
+            .map(|asset| asset.equity)
+            .unwrap_or(0.0)
+    }
+
+    /// Updates the equity and the realized loss from the fills since the last update, without
+    /// checking the limits.
+    pub fn update<MD, I>(&mut self, hbt: &I)
+    where
+        MD: MarketDepth,
+        I: Bot<MD>,
+    {
+        let timestamp = hbt.current_timestamp();
+        let max_window = self
+            .asset_limits
+            .values()
+            .chain([&self.portfolio_limits])
+            .filter_map(|limits| limits.max_loss.map(|(_, window)| window))
+            .max()
+            .unwrap_or(0);
+        self.assets.resize_with(hbt.num_assets(), Default::default);
+        for (asset_no, asset) in self.assets.iter_mut().enumerate() {
+            let contract_size = *self.contract_size.get(&asset_no).unwrap_or(&1.0);
+            let (maker_fee, taker_fee) = *self.fees.get(&asset_no).unwrap_or(&(0.0, 0.0));
+            let depth = hbt.depth(asset_no);
+            let mid = (depth.best_bid() + depth.best_ask()) / 2.0;
+            let orders = hbt.orders(asset_no);
+            let position = hbt.position(asset_no);
+            if !asset.initialized {
+                if !mid.is_finite() {
+                    continue;
+                }
+                asset.initialized = true;
+                asset.position = position;
+                asset.avg_price = mid;
+                asset.cash = -asset.position * mid * contract_size;
+            } else {
+                for order in orders.values() {
+                    let exec_qty = filled_qty(order)
+                        - asset.orders.get(&order.order_id).map_or(0.0, filled_qty);
+                    if exec_qty > 0.0 {
+                        asset.apply_fill(
+                            timestamp,
+                            order.side,
+                            exec_qty,
+                            order.exec_price(),
+                            contract_size,
+                            if order.maker { maker_fee } else { taker_fee },
+                        );
+                    }
+                }
+                if position != asset.position && mid.is_finite() {
+                    asset.cash -= (position - asset.position) * mid * contract_size;
+                    asset.position = position;
+                }
+            }
+            asset.orders.clone_from(orders);
+
+            if mid.is_finite() {
+                asset.equity = asset.cash + asset.position * mid * contract_size;
+            }
+            asset.peak_equity = asset.peak_equity.max(asset.equity);
+            while asset
+                .realized
+                .front()
+                .is_some_and(|(realized_timestamp, _)| {
+                    *realized_timestamp <= timestamp - max_window
+                })
+            {
+                asset.realized.pop_front();
+            }
+
+            // A reversed position starts aging again.
+            if position == 0.0 {
+                asset.inventory_since = None;
+            } else if asset.inventory_since.is_none() || asset.last_position * position < 0.0 {
+                asset.inventory_since = Some(timestamp);
+            }
+            asset.last_position = position;
+        }
+        let portfolio_equity: f64 = self.assets.iter().map(|asset| asset.equity).sum();
+        self.portfolio_peak_equity = self.portfolio_peak_equity.max(portfolio_equity);
+    }
+
+    /// Updates the equity and the realized loss, and checks the limits. If a limit is breached, it
+    /// trips and returns the reason. Once tripped, it keeps canceling the open orders and
+    /// flattening the positions at every check.
+    pub fn check<MD, I>(&mut self, hbt: &mut I) -> Result<Option<KillReason>, I::Error>
+    where
+        MD: MarketDepth,
+        I: Bot<MD>,
+    {
+        self.update(hbt);
+
+        if self.tripped.is_some() {
+            self.cancel_all(hbt)?;
+            self.flatten_all(hbt)?;
+            return Ok(None);
+        }
+        match self.breach(hbt.current_timestamp()) {
+            Some(reason) => {
+                self.tripped = Some(reason.clone());
+                self.cancel_all(hbt)?;
+                self.flatten_all(hbt)?;
+                Ok(Some(reason))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn breach(&self, timestamp: i64) -> Option<KillReason> {
+        for (asset_no, asset) in self.assets.iter().enumerate() {
+            if let Some(limits) = self.asset_limits.get(&asset_no) {
+                let reason = check_limits(
+                    limits,
+                    Some(asset_no),
+                    asset.equity,
+                    asset.peak_equity,
+                    |window| asset.realized_loss(timestamp, window),
+                );
+                if reason.is_some() {
+                    return reason;
+                }
+                if let (Some(max_inventory_age), Some(inventory_since)) =
+                    (limits.max_inventory_age, asset.inventory_since)
+                {
+                    let age = timestamp - inventory_since;
+                    if age > max_inventory_age {
+                        return Some(KillReason::MaxInventoryAge {
+                            asset_no,
+                            age,
+                            max_inventory_age,
+                        });
+                    }
+                }
+            }
+        }
+        check_limits(
+            &self.portfolio_limits,
+            None,
+            self.assets.iter().map(|asset| asset.equity).sum(),
+            self.portfolio_peak_equity,
+            |window| {
+                self.assets
+                    .iter()
+                    .map(|asset| asset.realized_loss(timestamp, window))
+                    .sum()
+            },
+        )
+    }
+
+    fn cancel_all<MD, I>(&mut self, hbt: &mut I) -> Result<(), I::Error>
+    where
+        MD: MarketDepth,
+        I: Bot<MD>,
+    {
+        for asset_no in 0..hbt.num_assets() {
+            let order_ids: Vec<OrderId> = hbt
+                .orders(asset_no)
+                .values()
+                .filter(|order| order.cancellable())
+                .map(|order| order.order_id)
+                .collect();
+            for order_id in order_ids {
+                hbt.cancel(asset_no, order_id, false)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flatten_all<MD, I>(&mut self, hbt: &mut I) -> Result<(), I::Error>
+    where
+        MD: MarketDepth,
+        I: Bot<MD>,
+    {
+        let (order_type, time_in_force, slippage_ticks) = match self.flatten {
+            Flatten::None => return Ok(()),
+            Flatten::Market => (OrdType::Market, TimeInForce::GTC, 0),
+            Flatten::Ioc { slippage_ticks } => (OrdType::Limit, TimeInForce::IOC, slippage_ticks),
+        };
+        for asset_no in 0..hbt.num_assets() {
+            let in_process = self
+                .flatten_orders
+                .get(&asset_no)
+                .and_then(|order_id| hbt.orders(asset_no).get(order_id))
+                .is_some_and(|order| order.active() || order.pending());
+            if in_process {
+                continue;
+            }
+            let position = hbt.position(asset_no);
+            let depth = hbt.depth(asset_no);
+            let qty = (position.abs() / depth.lot_size()).round() * depth.lot_size();
+            if qty <= 0.0 {
+                continue;
+            }
+            let order_id = self.next_order_id;
+            self.next_order_id -= 1;
+            self.flatten_orders.insert(asset_no, order_id);
+            if position > 0.0 {
+                let price = (depth.best_bid_tick() - slippage_ticks) as f64 * depth.tick_size();
+                hbt.submit_sell_order(
+                    asset_no,
+                    order_id,
+                    price,
+                    qty,
+                    time_in_force,
+                    order_type,
+                    false,
+                )?;
+            } else {
+                let price = (depth.best_ask_tick() + slippage_ticks) as f64 * depth.tick_size();
+                hbt.submit_buy_order(
+                    asset_no,
+                    order_id,
+                    price,
+                    qty,
+                    time_in_force,
+                    order_type,
+                    false,
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the executed quantity of the order so far. Expired or canceled orders may have no
+/// leaves quantity, so only filled orders are counted.
+fn filled_qty(order: &Order) -> f64 {
+    match order.status {
+        Status::Filled | Status::PartiallyFilled => order.qty - order.leaves_qty,
+        _ => 0.0,
+    }
+}
+
+/// Checks the limits other than the inventory age.
+fn check_limits(
+    limits: &KillSwitchLimits,
+    asset_no: Option<usize>,
+    equity: f64,
+    peak_equity: f64,
+    realized_loss: impl Fn(i64) -> f64,
+) -> Option<KillReason> {
+    if let Some(min_equity) = limits.min_equity {
+        if equity < min_equity {
+            return Some(KillReason::MinEquity {
+                asset_no,
+                equity,
+                min_equity,
+            });
+        }
+    }
+    if let Some(max_drawdown) = limits.max_drawdown {
+        let drawdown = peak_equity - equity;
+        if drawdown > max_drawdown {
+            return Some(KillReason::MaxDrawdown {
+                asset_no,
+                drawdown,
+                max_drawdown,
+            });
+        }
+    }
+    if let Some((max_loss, window)) = limits.max_loss {
+        let loss = realized_loss(window);
+        if loss > max_loss {
+            return Some(KillReason::MaxLoss {
+                asset_no,
+                loss,
+                max_loss,
+                window,
+            });
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{risk::kill_switch::AssetMonitor, types::Side};
+
+    #[test]
+    fn test_realized_loss() {
+        let mut asset = AssetMonitor::default();
+        asset.apply_fill(0, Side::Buy, 1.0, 100.0, 1.0, 0.0);
+        asset.apply_fill(10, Side::Buy, 1.0, 102.0, 1.0, 0.0);
+        assert_eq!(asset.avg_price, 101.0);
+
+        // Reverses the position, realizing the loss of the closed quantity only.
+        asset.apply_fill(20, Side::Sell, 3.0, 100.0, 1.0, 0.0);
+        assert_eq!(asset.position, -1.0);
+        assert_eq!(asset.avg_price, 100.0);
+        assert_eq!(asset.realized_loss(20, 100), 2.0);
+        assert_eq!(asset.realized_loss(120, 100), 0.0);
+        assert_eq!(asset.cash, 98.0);
+    }
+
+    #[test]
+    fn test_fees() {
+        let mut asset = AssetMonitor::default();
+        asset.apply_fill(0, Side::Buy, 2.0, 100.0, 1.0, 0.001);
+        assert_eq!(asset.cash, -200.2);
+        assert_eq!(asset.realized_loss(0, 100), 0.2);
+
+        // The fee is added to the loss of the closed quantity, and a rebate reduces it.
+        asset.apply_fill(10, Side::Sell, 1.0, 99.0, 1.0, -0.0001);
+        assert!((asset.realized_loss(10, 100) - 1.1901).abs() < 1e-9);
+        assert!((asset.cash + 101.1901).abs() < 1e-9);
+    }
+}
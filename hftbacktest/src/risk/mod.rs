@@ -0,0 +1,945 @@
+mod kill_switch;
+
+use std::collections::{HashMap, VecDeque};
+
+pub use kill_switch::{Flatten, KillReason, KillSwitch, KillSwitchLimits};
+use thiserror::Error;
+
+use crate::{
+    depth::MarketDepth,
+    types::{
+        Bot,
+        Event,
+        L3Bot,
+        OrdType,
+        Order,
+        OrderId,
+        OrderRequest,
+        Side,
+        StateValues,
+        Status,
+        TimeInForce,
+        TimerId,
+    },
+};
+
+/// The reference price from which the price band is measured.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PriceReference {
+    /// The mid price of the market depth.
+    Mid,
+    /// The price of the last market trade. Until a trade is received, the mid price is used. The
+    /// last trades are taken from [`Bot::last_trades`], so in backtesting, the last trades
+    /// capacity of the asset must be set.
+    LastTrade,
+}
+
+/// The pre-trade risk limits of an asset, which are checked by [`RiskManagedBot`]. No limit is set
+/// by default.
+///
+/// **Example**
+/// ```
+/// use hftbacktest::risk::{PriceReference, RiskLimits};
+///
+/// let limits = RiskLimits::new()
+///     .max_position(10.0)
+///     .max_order_qty(1.0)
+///     .price_band(PriceReference::Mid, 0.01)
+///     .max_order_rate(10, 1_000_000_000);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RiskLimits {
+    max_position: Option<f64>,
+    max_order_qty: Option<f64>,
+    max_order_notional: Option<f64>,
+    price_band: Option<(PriceReference, f64)>,
+    max_open_orders: Option<usize>,
+    max_order_rate: Option<(usize, i64)>,
+    clip: bool,
+}
+
+impl RiskLimits {
+    /// Constructs `RiskLimits` without any limit.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets the maximum absolute position, which is checked against the position plus the open
+    /// orders on the same side, as if they were all filled.
+    pub fn max_position(self, max_position: f64) -> Self {
+        Self {
+            max_position: Some(max_position),
+            ..self
+        }
+    }
+
+    /// Sets the maximum quantity of an order.
+    pub fn max_order_qty(self, max_order_qty: f64) -> Self {
+        Self {
+            max_order_qty: Some(max_order_qty),
+            ..self
+        }
+    }
+
+    /// Sets the maximum notional value of an order, which is the price multiplied by the quantity.
+    pub fn max_order_notional(self, max_order_notional: f64) -> Self {
+        Self {
+            max_order_notional: Some(max_order_notional),
+            ..self
+        }
+    }
+
+    /// Sets the price band, which rejects limit orders, including stop-limit and take-profit-limit
+    /// orders, whose price deviates from the reference price by more than `max_deviation` as a
+    /// fraction of the reference price. Market orders are not checked.
+    pub fn price_band(self, reference: PriceReference, max_deviation: f64) -> Self {
+        Self {
+            price_band: Some((reference, max_deviation)),
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of open orders, including the orders that are not yet
+    /// acknowledged.
+    pub fn max_open_orders(self, max_open_orders: usize) -> Self {
+        Self {
+            max_open_orders: Some(max_open_orders),
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of orders that can be submitted or modified within the `window`.
+    pub fn max_order_rate(self, max_orders: usize, window: i64) -> Self {
+        Self {
+            max_order_rate: Some((max_orders, window)),
+            ..self
+        }
+    }
+
+    /// Sets whether to clip the quantity of an order that exceeds the maximum order quantity, the
+    /// maximum order notional, or the maximum position, instead of rejecting it. The clipped
+    /// quantity is rounded down to the lot size, and the order is still rejected if nothing is
+    /// left. The default value is `false`.
+    pub fn clip(self, clip: bool) -> Self {
+        Self { clip, ..self }
+    }
+}
+
+/// The reason for which [`RiskManagedBot`] rejects an order.
+#[derive(Error, Clone, Debug, PartialEq)]
+pub enum RiskViolation {
+    #[error("position {position} would exceed the maximum position {max_position}")]
+    MaxPosition { position: f64, max_position: f64 },
+    #[error("order quantity {qty} exceeds the maximum order quantity {max_order_qty}")]
+    MaxOrderQty { qty: f64, max_order_qty: f64 },
+    #[error("order notional {notional} exceeds the maximum order notional {max_order_notional}")]
+    MaxOrderNotional {
+        notional: f64,
+        max_order_notional: f64,
+    },
+    #[error("order price {price} is outside the price band around {reference_price}")]
+    PriceBand { price: f64, reference_price: f64 },
+    #[error("the number of open orders reaches the maximum {max_open_orders}")]
+    MaxOpenOrders { max_open_orders: usize },
+    #[error("the number of orders within {window} reaches the maximum {max_orders}")]
+    MaxOrderRate { max_orders: usize, window: i64 },
+    #[error("the kill switch has tripped: {0}")]
+    KillSwitch(KillReason),
+}
+
+/// Errors that [`RiskManagedBot`] returns.
+#[derive(Error, Debug)]
+pub enum RiskError<E> {
+    /// The order is rejected by the pre-trade risk check and is not sent.
+    #[error("order {order_id} of asset {asset_no} is rejected: {violation}")]
+    Rejected {
+        asset_no: usize,
+        order_id: OrderId,
+        violation: RiskViolation,
+    },
+    /// An error from the wrapped bot.
+    #[error(transparent)]
+    Bot(#[from] E),
+}
+
+/// Wraps a [`Bot`] to check new and modified orders against the [`RiskLimits`] of the asset before
+/// they reach the bot. An order that violates a limit is rejected with
+/// [`RiskError::Rejected`], which carries the reason, or its quantity is clipped if clipping is
+/// enabled.
+///
+/// Since the checks only rely on the [`Bot`] interface, the same orders are rejected in both
+/// backtesting and live trading.
+///
+/// If a [`KillSwitch`] is set, it is checked after every call that can change the orders or the
+/// positions, and once it trips, all orders are rejected with [`RiskViolation::KillSwitch`].
+///
+/// **Example**
+/// ```ignore
+/// let mut hbt = RiskManagedBot::new(hbt).limits(0, RiskLimits::new().max_position(10.0));
+/// match hbt.submit_buy_order(0, order_id, price, qty, TimeInForce::GTX, OrdType::Limit, false) {
+///     Err(RiskError::Rejected { violation, .. }) => println!("{violation}"),
+///     result => { result?; }
+/// }
+/// ```
+pub struct RiskManagedBot<I> {
+    bot: I,
+    limits: HashMap<usize, RiskLimits>,
+    last_trade_px: HashMap<usize, f64>,
+    order_timestamps: HashMap<usize, VecDeque<i64>>,
+    kill_switch: Option<KillSwitch>,
+}
+
+impl<I> RiskManagedBot<I> {
+    /// Constructs a `RiskManagedBot` that wraps the bot.
+    pub fn new(bot: I) -> Self {
+        Self {
+            bot,
+            limits: Default::default(),
+            last_trade_px: Default::default(),
+            order_timestamps: Default::default(),
+            kill_switch: None,
+        }
+    }
+
+    /// Sets the risk limits of the asset. Orders for the assets without the risk limits are not
+    /// checked.
+    pub fn limits(mut self, asset_no: usize, limits: RiskLimits) -> Self {
+        self.limits.insert(asset_no, limits);
+        self
+    }
+
+    /// Sets the kill switch that monitors the equity, the drawdown, the realized loss, and the
+    /// inventory age.
+    pub fn kill_switch(self, kill_switch: KillSwitch) -> Self {
+        Self {
+            kill_switch: Some(kill_switch),
+            ..self
+        }
+    }
+
+    /// Returns the reason for which the kill switch has tripped, or `None` if it hasn't or no kill
+    /// switch is set.
+    pub fn kill_reason(&self) -> Option<&KillReason> {
+        self.kill_switch
+            .as_ref()
+            .and_then(|kill_switch| kill_switch.tripped())
+    }
+
+    /// Returns the wrapped bot.
+    pub fn inner(&self) -> &I {
+        &self.bot
+    }
+
+    /// Returns the wrapped bot mutably. Orders submitted directly through it are not checked.
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.bot
+    }
+
+    /// Unwraps the bot.
+    pub fn into_inner(self) -> I {
+        self.bot
+    }
+
+    /// Checks the order and returns the quantity to be sent. For a modification, the order to be
+    /// modified is excluded from the open orders. The orders in `pending`, which are checked
+    /// earlier in the same batch but not yet submitted, are counted as open orders.
+    #[allow(clippy::too_many_arguments)]
+    fn check<MD>(
+        &mut self,
+        asset_no: usize,
+        order_id: OrderId,
+        side: Side,
+        price: f64,
+        qty: f64,
+        order_type: OrdType,
+        modify: bool,
+        pending: &[OrderRequest],
+    ) -> Result<f64, RiskError<I::Error>>
+    where
+        MD: MarketDepth,
+        I: Bot<MD>,
+    {
+        if let Some(reason) = self.kill_reason() {
+            return Err(RiskError::Rejected {
+                asset_no,
+                order_id,
+                violation: RiskViolation::KillSwitch(reason.clone()),
+            });
+        }
+        if let Some(trade) = self.bot.last_trades(asset_no).last() {
+            self.last_trade_px.insert(asset_no, trade.px);
+        }
+        let limits = match self.limits.get(&asset_no) {
+            Some(limits) => limits,
+            None => return Ok(qty),
+        };
+        let reject = |violation| RiskError::Rejected {
+            asset_no,
+            order_id,
+            violation,
+        };
+        let timestamp = self.bot.current_timestamp();
+        let orders = self.bot.orders(asset_no);
+
+        if let Some((max_orders, window)) = limits.max_order_rate {
+            let order_timestamps = self.order_timestamps.entry(asset_no).or_default();
+            while order_timestamps
+                .front()
+                .is_some_and(|&order_timestamp| order_timestamp <= timestamp - window)
+            {
+                order_timestamps.pop_front();
+            }
+            if order_timestamps.len() >= max_orders {
+                return Err(reject(RiskViolation::MaxOrderRate { max_orders, window }));
+            }
+        }
+
+        if let Some(max_open_orders) = limits.max_open_orders {
+            let num_open_orders =
+                orders.values().filter(|order| is_open(order)).count() + pending.len();
+            if !modify && num_open_orders >= max_open_orders {
+                return Err(reject(RiskViolation::MaxOpenOrders { max_open_orders }));
+            }
+        }
+
+        let depth = self.bot.depth(asset_no);
+        let mid = (depth.best_bid() + depth.best_ask()) / 2.0;
+        if let Some((reference, max_deviation)) = limits.price_band {
+            let reference_price = match reference {
+                PriceReference::Mid => mid,
+                PriceReference::LastTrade => *self.last_trade_px.get(&asset_no).unwrap_or(&mid),
+            };
+            // Conditional limit orders are checked on their limit price as well.
+            if order_type.triggered() == OrdType::Limit
+                && reference_price.is_finite()
+                && (price - reference_price).abs() > reference_price * max_deviation
+            {
+                return Err(reject(RiskViolation::PriceBand {
+                    price,
+                    reference_price,
+                }));
+            }
+        }
+
+        let lot_size = depth.lot_size();
+        let clip = |max_qty: f64| (max_qty / lot_size + 1e-9).floor() * lot_size;
+        let mut qty = qty;
+        if let Some(max_order_qty) = limits.max_order_qty {
+            if qty > max_order_qty {
+                if !limits.clip || clip(max_order_qty) <= 0.0 {
+                    return Err(reject(RiskViolation::MaxOrderQty { qty, max_order_qty }));
+                }
+                qty = clip(max_order_qty);
+            }
+        }
+        if let Some(max_order_notional) = limits.max_order_notional {
+            let notional = price * qty;
+            if notional > max_order_notional {
+                if !limits.clip || clip(max_order_notional / price) <= 0.0 {
+                    return Err(reject(RiskViolation::MaxOrderNotional {
+                        notional,
+                        max_order_notional,
+                    }));
+                }
+                qty = clip(max_order_notional / price);
+            }
+        }
+        if let Some(max_position) = limits.max_position {
+            let open_qty: f64 = orders
+                .values()
+                .filter(|order| order.side == side && order.order_id != order_id && is_open(order))
+                .map(|order| order.leaves_qty)
+                .chain(
+                    pending
+                        .iter()
+                        .filter(|order| order.side == side)
+                        .map(|order| order.qty),
+                )
+                .sum();
+            let position = self.bot.position(asset_no) * AsRef::<f64>::as_ref(&side) + open_qty;
+            if position + qty > max_position {
+                if !limits.clip || clip(max_position - position) <= 0.0 {
+                    return Err(reject(RiskViolation::MaxPosition {
+                        position: position + qty,
+                        max_position,
+                    }));
+                }
+                qty = clip(max_position - position);
+            }
+        }
+
+        if limits.max_order_rate.is_some() {
+            self.order_timestamps
+                .entry(asset_no)
+                .or_default()
+                .push_back(timestamp);
+        }
+        Ok(qty)
+    }
+
+    /// Checks the kill switch, if it is set.
+    fn monitor<MD>(&mut self) -> Result<(), RiskError<I::Error>>
+    where
+        MD: MarketDepth,
+        I: Bot<MD>,
+    {
+        if let Some(kill_switch) = self.kill_switch.as_mut() {
+            kill_switch.check(&mut self.bot)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns `true` if the order is open or is being submitted.
+fn is_open(order: &Order) -> bool {
+    order.active() || order.req == Status::New
+}
+
+impl<MD, I> Bot<MD> for RiskManagedBot<I>
+where
+    MD: MarketDepth,
+    I: Bot<MD>,
+{
+    type Error = RiskError<I::Error>;
+
+    #[inline]
+    fn current_timestamp(&self) -> i64 {
+        self.bot.current_timestamp()
+    }
+
+    #[inline]
+    fn num_assets(&self) -> usize {
+        self.bot.num_assets()
+    }
+
+    #[inline]
+    fn position(&self, asset_no: usize) -> f64 {
+        self.bot.position(asset_no)
+    }
+
+    #[inline]
+    fn state_values(&self, asset_no: usize) -> &StateValues {
+        self.bot.state_values(asset_no)
+    }
+
+    #[inline]
+    fn depth(&self, asset_no: usize) -> &MD {
+        self.bot.depth(asset_no)
+    }
+
+    #[inline]
+    fn last_trades(&self, asset_no: usize) -> &[Event] {
+        self.bot.last_trades(asset_no)
+    }
+
+    #[inline]
+    fn clear_last_trades(&mut self, asset_no: Option<usize>) {
+        // Keeps the last trade price for the price band before the trades are cleared.
+        match asset_no {
+            Some(asset_no) => {
+                if let Some(trade) = self.bot.last_trades(asset_no).last() {
+                    self.last_trade_px.insert(asset_no, trade.px);
+                }
+            }
+            None => {
+                for asset_no in 0..self.bot.num_assets() {
+                    if let Some(trade) = self.bot.last_trades(asset_no).last() {
+                        self.last_trade_px.insert(asset_no, trade.px);
+                    }
+                }
+            }
+        }
+        self.bot.clear_last_trades(asset_no)
+    }
+
+    #[inline]
+    fn custom_data<D>(&self, asset_no: usize, stream_no: usize) -> Option<&D>
+    where
+        D: 'static,
+    {
+        self.bot.custom_data(asset_no, stream_no)
+    }
+
+    #[inline]
+    fn orders(&self, asset_no: usize) -> &HashMap<OrderId, Order> {
+        self.bot.orders(asset_no)
+    }
+
+    #[inline]
+    fn submit_buy_order(
+        &mut self,
+        asset_no: usize,
+        order_id: OrderId,
+        price: f64,
+        qty: f64,
+        time_in_force: TimeInForce,
+        order_type: OrdType,
+        wait: bool,
+    ) -> Result<bool, Self::Error> {
+        let qty = self.check(
+            asset_no,
+            order_id,
+            Side::Buy,
+            price,
+            qty,
+            order_type,
+            false,
+            &[],
+        )?;
+        let result = self.bot.submit_buy_order(
+            asset_no,
+            order_id,
+            price,
+            qty,
+            time_in_force,
+            order_type,
+            wait,
+        )?;
+        self.monitor()?;
+        Ok(result)
+    }
+
+    #[inline]
+    fn submit_sell_order(
+        &mut self,
+        asset_no: usize,
+        order_id: OrderId,
+        price: f64,
+        qty: f64,
+        time_in_force: TimeInForce,
+        order_type: OrdType,
+        wait: bool,
+    ) -> Result<bool, Self::Error> {
+        let qty = self.check(
+            asset_no,
+            order_id,
+            Side::Sell,
+            price,
+            qty,
+            order_type,
+            false,
+            &[],
+        )?;
+        let result = self.bot.submit_sell_order(
+            asset_no,
+            order_id,
+            price,
+            qty,
+            time_in_force,
+            order_type,
+            wait,
+        )?;
+        self.monitor()?;
+        Ok(result)
+    }
+
+    #[inline]
+    fn submit_order(
+        &mut self,
+        asset_no: usize,
+        mut order: OrderRequest,
+        wait: bool,
+    ) -> Result<bool, Self::Error> {
+        order.qty = self.check(
+            asset_no,
+            order.order_id,
+            order.side,
+            order.price,
+            order.qty,
+            order.order_type,
+            false,
+            &[],
+        )?;
+        let result = self.bot.submit_order(asset_no, order, wait)?;
+        self.monitor()?;
+        Ok(result)
+    }
+
+    #[inline]
+    fn cancel(
+        &mut self,
+        asset_no: usize,
+        order_id: OrderId,
+        wait: bool,
+    ) -> Result<bool, Self::Error> {
+        let result = self.bot.cancel(asset_no, order_id, wait)?;
+        self.monitor()?;
+        Ok(result)
+    }
+
+    fn submit_orders(
+        &mut self,
+        asset_no: usize,
+        orders: Vec<OrderRequest>,
+        wait: bool,
+    ) -> Result<bool, Self::Error> {
+        // Checks all orders before submitting any of them, so a violation rejects the whole batch.
+        let mut checked: Vec<OrderRequest> = Vec::with_capacity(orders.len());
+        for mut order in orders {
+            match self.check(
+                asset_no,
+                order.order_id,
+                order.side,
+                order.price,
+                order.qty,
+                order.order_type,
+                false,
+                &checked,
+            ) {
+                Ok(qty) => {
+                    order.qty = qty;
+                    checked.push(order);
+                }
+                Err(error) => {
+                    // The orders checked so far are not sent, so they don't count towards the order
+                    // rate.
+                    if self
+                        .limits
+                        .get(&asset_no)
+                        .is_some_and(|limits| limits.max_order_rate.is_some())
+                    {
+                        let order_timestamps = self.order_timestamps.entry(asset_no).or_default();
+                        order_timestamps
+                            .truncate(order_timestamps.len().saturating_sub(checked.len()));
+                    }
+                    return Err(error);
+                }
+            }
+        }
+        let result = self.bot.submit_orders(asset_no, checked, wait)?;
+        self.monitor()?;
+        Ok(result)
+    }
+
+    #[inline]
+    fn cancel_orders(
+        &mut self,
+        asset_no: usize,
+        order_ids: &[OrderId],
+        wait: bool,
+    ) -> Result<bool, Self::Error> {
+        let result = self.bot.cancel_orders(asset_no, order_ids, wait)?;
+        self.monitor()?;
+        Ok(result)
+    }
+
+    #[inline]
+    fn cancel_all(&mut self, asset_no: usize, wait: bool) -> Result<bool, Self::Error> {
+        let result = self.bot.cancel_all(asset_no, wait)?;
+        self.monitor()?;
+        Ok(result)
+    }
+
+    #[inline]
+    fn modify(
+        &mut self,
+        asset_no: usize,
+        order_id: OrderId,
+        price: f64,
+        qty: f64,
+        wait: bool,
+    ) -> Result<bool, Self::Error> {
+        // Leaves an unknown order to the bot, which reports the error.
+        let order = self
+            .bot
+            .orders(asset_no)
+            .get(&order_id)
+            .map(|order| (order.side, order.order_type));
+        let qty = match order {
+            Some((side, order_type)) => {
+                self.check(asset_no, order_id, side, price, qty, order_type, true, &[])?
+            }
+            None => qty,
+        };
+        let result = self.bot.modify(asset_no, order_id, price, qty, wait)?;
+        self.monitor()?;
+        Ok(result)
+    }
+
+    #[inline]
+    fn clear_inactive_orders(&mut self, asset_no: Option<usize>) {
+        // Catches the fills of the orders to be cleared.
+        if let Some(kill_switch) = self.kill_switch.as_mut() {
+            kill_switch.update(&self.bot);
+        }
+        self.bot.clear_inactive_orders(asset_no)
+    }
+
+    #[inline]
+    fn wait_order_response(
+        &mut self,
+        asset_no: usize,
+        order_id: OrderId,
+        timeout: i64,
+    ) -> Result<bool, Self::Error> {
+        let result = self.bot.wait_order_response(asset_no, order_id, timeout)?;
+        self.monitor()?;
+        Ok(result)
+    }
+
+    #[inline]
+    fn wait_next_feed(
+        &mut self,
+        include_order_resp: bool,
+        timeout: i64,
+    ) -> Result<bool, Self::Error> {
+        let result = self.bot.wait_next_feed(include_order_resp, timeout)?;
+        self.monitor()?;
+        Ok(result)
+    }
+
+    #[inline]
+    fn elapse(&mut self, duration: i64) -> Result<bool, Self::Error> {
+        let result = self.bot.elapse(duration)?;
+        self.monitor()?;
+        Ok(result)
+    }
+
+    #[inline]
+    fn elapse_bt(&mut self, duration: i64) -> Result<bool, Self::Error> {
+        let result = self.bot.elapse_bt(duration)?;
+        self.monitor()?;
+        Ok(result)
+    }
+
+    #[inline]
+    fn set_timer(&mut self, timer_id: TimerId, timestamp: i64, interval: i64) {
+        self.bot.set_timer(timer_id, timestamp, interval)
+    }
+
+    #[inline]
+    fn cancel_timer(&mut self, timer_id: TimerId) -> bool {
+        self.bot.cancel_timer(timer_id)
+    }
+
+    #[inline]
+    fn fired_timers(&self) -> &[TimerId] {
+        self.bot.fired_timers()
+    }
+
+    #[inline]
+    fn close(&mut self) -> Result<(), Self::Error> {
+        Ok(self.bot.close()?)
+    }
+
+    #[inline]
+    fn feed_latency(&self, asset_no: usize) -> Option<(i64, i64)> {
+        self.bot.feed_latency(asset_no)
+    }
+
+    #[inline]
+    fn depth_latency(&self, asset_no: usize) -> Option<(i64, i64)> {
+        self.bot.depth_latency(asset_no)
+    }
+
+    #[inline]
+    fn order_latency(&self, asset_no: usize) -> Option<(i64, i64, i64)> {
+        self.bot.order_latency(asset_no)
+    }
+}
+
+impl<MD, I> L3Bot<MD> for RiskManagedBot<I>
+where
+    MD: MarketDepth,
+    I: L3Bot<MD>,
+{
+    #[inline]
+    fn queue_ahead(&self, asset_no: usize, order_id: OrderId) -> Option<f64> {
+        self.bot.queue_ahead(asset_no, order_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backtest::{
+            data::Data,
+            fixture::{asset_builder, build, feed_events, order_request},
+            models::ConstantLatency,
+            Backtest,
+        },
+        depth::HashMapMarketDepth,
+        risk::{
+            Flatten,
+            KillReason,
+            KillSwitch,
+            KillSwitchLimits,
+            PriceReference,
+            RiskError,
+            RiskLimits,
+            RiskManagedBot,
+            RiskViolation,
+        },
+        types::{Bot, OrdType, Side, Status, TimeInForce},
+    };
+
+    fn build_bot(limits: RiskLimits) -> RiskManagedBot<Backtest<HashMapMarketDepth>> {
+        let hbt = build(
+            asset_builder(&Data::from_items(&feed_events(100, false)))
+                .latency_model(ConstantLatency::new(50, 50)),
+        );
+        RiskManagedBot::new(hbt).limits(0, limits)
+    }
+
+    fn violation<E>(result: Result<bool, RiskError<E>>) -> Option<RiskViolation> {
+        match result {
+            Err(RiskError::Rejected { violation, .. }) => Some(violation),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_rejection() {
+        let mut hbt = build_bot(
+            RiskLimits::new()
+                .max_order_qty(1.0)
+                .max_position(1.5)
+                .price_band(PriceReference::Mid, 0.01)
+                .max_open_orders(2),
+        );
+        hbt.elapse(10_000).unwrap();
+
+        let result = hbt.submit_buy_order(0, 1, 99.9, 2.0, TimeInForce::GTC, OrdType::Limit, true);
+        assert_eq!(
+            violation(result),
+            Some(RiskViolation::MaxOrderQty {
+                qty: 2.0,
+                max_order_qty: 1.0
+            })
+        );
+
+        let result = hbt.submit_buy_order(0, 2, 90.0, 1.0, TimeInForce::GTC, OrdType::Limit, true);
+        assert!(matches!(
+            violation(result),
+            Some(RiskViolation::PriceBand { .. })
+        ));
+
+        hbt.submit_buy_order(0, 3, 99.9, 1.0, TimeInForce::GTC, OrdType::Limit, true)
+            .unwrap();
+        // The open buy order counts toward the position.
+        let result = hbt.submit_buy_order(0, 4, 99.8, 1.0, TimeInForce::GTC, OrdType::Limit, true);
+        assert_eq!(
+            violation(result),
+            Some(RiskViolation::MaxPosition {
+                position: 2.0,
+                max_position: 1.5
+            })
+        );
+
+        hbt.submit_sell_order(0, 5, 100.2, 1.0, TimeInForce::GTC, OrdType::Limit, true)
+            .unwrap();
+        let result =
+            hbt.submit_sell_order(0, 6, 100.3, 1.0, TimeInForce::GTC, OrdType::Limit, true);
+        assert_eq!(
+            violation(result),
+            Some(RiskViolation::MaxOpenOrders { max_open_orders: 2 })
+        );
+        assert_eq!(hbt.orders(0).len(), 2);
+    }
+
+    #[test]
+    fn test_price_band_conditional_limit_order() {
+        let mut hbt = build_bot(RiskLimits::new().price_band(PriceReference::Mid, 0.01));
+        hbt.elapse(10_000).unwrap();
+
+        let mut order = order_request(1, Side::Buy, OrdType::StopLimit, 90.0, 1.0);
+        order.trigger_price = 100.2;
+        assert!(matches!(
+            violation(hbt.submit_order(0, order, true)),
+            Some(RiskViolation::PriceBand { .. })
+        ));
+
+        // The price of a market order isn't checked.
+        let mut order = order_request(2, Side::Buy, OrdType::StopMarket, 90.0, 1.0);
+        order.trigger_price = 100.2;
+        hbt.submit_order(0, order, true).unwrap();
+        assert_eq!(hbt.orders(0).len(), 1);
+    }
+
+    #[test]
+    fn test_clip() {
+        let mut hbt = build_bot(RiskLimits::new().max_position(1.5).clip(true));
+        hbt.elapse(10_000).unwrap();
+
+        hbt.submit_buy_order(0, 1, 99.9, 1.0, TimeInForce::GTC, OrdType::Limit, true)
+            .unwrap();
+        hbt.submit_buy_order(0, 2, 99.8, 1.0, TimeInForce::GTC, OrdType::Limit, true)
+            .unwrap();
+        assert_eq!(hbt.orders(0).get(&2).unwrap().qty, 0.5);
+
+        // Nothing is left after clipping.
+        let result = hbt.submit_buy_order(0, 3, 99.7, 1.0, TimeInForce::GTC, OrdType::Limit, true);
+        assert!(matches!(
+            violation(result),
+            Some(RiskViolation::MaxPosition { .. })
+        ));
+    }
+
+    #[test]
+    fn test_order_rate() {
+        let mut hbt = build_bot(RiskLimits::new().max_order_rate(2, 10_000));
+        hbt.elapse(10_000).unwrap();
+
+        hbt.submit_buy_order(0, 1, 99.9, 1.0, TimeInForce::GTC, OrdType::Limit, false)
+            .unwrap();
+        hbt.submit_buy_order(0, 2, 99.8, 1.0, TimeInForce::GTC, OrdType::Limit, false)
+            .unwrap();
+        let result = hbt.submit_buy_order(0, 3, 99.7, 1.0, TimeInForce::GTC, OrdType::Limit, false);
+        assert_eq!(
+            violation(result),
+            Some(RiskViolation::MaxOrderRate {
+                max_orders: 2,
+                window: 10_000
+            })
+        );
+
+        hbt.elapse(10_000).unwrap();
+        hbt.submit_buy_order(0, 3, 99.7, 1.0, TimeInForce::GTC, OrdType::Limit, false)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_kill_switch() {
+        let kill_switch = KillSwitch::new()
+            .asset_limits(0, KillSwitchLimits::new().min_equity(-0.01))
+            .flatten(Flatten::Market);
+        let mut hbt = build_bot(RiskLimits::new()).kill_switch(kill_switch);
+        hbt.elapse(10_000).unwrap();
+
+        hbt.submit_buy_order(0, 1, 99.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)
+            .unwrap();
+        assert_eq!(hbt.kill_reason(), None);
+
+        // Buying at the best ask drops the equity valued at the mid price below the minimum.
+        hbt.submit_buy_order(0, 2, 100.1, 1.0, TimeInForce::GTC, OrdType::Limit, true)
+            .unwrap();
+        assert_eq!(hbt.position(0), 1.0);
+        assert!(matches!(
+            hbt.kill_reason(),
+            Some(KillReason::MinEquity {
+                asset_no: Some(0),
+                ..
+            })
+        ));
+
+        // The open order is canceled and the position is flattened.
+        hbt.elapse(1_000).unwrap();
+        assert_eq!(hbt.orders(0).get(&1).unwrap().status, Status::Canceled);
+        assert_eq!(hbt.position(0), 0.0);
+
+        let result = hbt.submit_buy_order(0, 3, 99.0, 1.0, TimeInForce::GTC, OrdType::Limit, true);
+        assert!(matches!(
+            violation(result),
+            Some(RiskViolation::KillSwitch(KillReason::MinEquity { .. }))
+        ));
+
+        // A position opened afterwards, bypassing the checks, is flattened at the next check.
+        hbt.inner_mut()
+            .submit_buy_order(0, 4, 100.1, 1.0, TimeInForce::GTC, OrdType::Limit, true)
+            .unwrap();
+        assert_eq!(hbt.position(0), 1.0);
+        hbt.elapse(1_000).unwrap();
+        hbt.elapse(1_000).unwrap();
+        assert_eq!(hbt.position(0), 0.0);
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+
+use crate::{
+    depth::MarketDepth,
+    types::{Bot, Event, Order, OrderId, Status, TimerId},
+};
+
+/// Provides callbacks for an event-driven strategy, which is run by [`StrategyDriver`].
+///
+/// Since the callbacks are invoked through the [`Bot`] interface, the same strategy can run in
+/// both backtesting and live trading. All callbacks have a default implementation that does
+/// nothing, so only the necessary callbacks need to be implemented.
+pub trait Strategy<MD, I>
+where
+    MD: MarketDepth,
+    I: Bot<MD>,
+{
+    type Error: From<I::Error>;
+
+    /// Called when a market depth feed is received for the asset, after [`Strategy::on_trade`] is
+    /// called for the trades received together. Trade feeds alone don't invoke this. The market
+    /// depth reflects the received feed.
+    fn on_depth(&mut self, _hbt: &mut I, _asset_no: usize) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Called for each market trade received for the asset. In backtesting, the last trades
+    /// capacity of the asset must be set for trades to be received.
+    fn on_trade(
+        &mut self,
+        _hbt: &mut I,
+        _asset_no: usize,
+        _trade: &Event,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Called when an order is updated by a response from the exchange.
+    ///
+    /// * `old` - The order before the update. `None` if the order is not known before the update.
+    /// * `new` - The updated order.
+    fn on_order_update(
+        &mut self,
+        _hbt: &mut I,
+        _asset_no: usize,
+        _old: Option<&Order>,
+        _new: &Order,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Called when an order is executed, after [`Strategy::on_order_update`] is called for the
+    /// update. The executed quantity and price are available from [`Order::exec_qty`] and
+    /// [`Order::exec_price`].
+    fn on_fill(
+        &mut self,
+        _hbt: &mut I,
+        _asset_no: usize,
+        _order: &Order,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Called when a timer set by [`Bot::set_timer`] fires, after the other callbacks are called
+    /// for what was received together.
+    fn on_timer(&mut self, _hbt: &mut I, _timer_id: TimerId) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Runs a [`Strategy`] by waiting for the next feed or order response and then dispatching what
+/// has changed to the strategy's callbacks.
+///
+/// In backtesting, the driver steps through every feed timestamp, so the strategy observes the
+/// same granularity as the data. Events that have the same timestamp are dispatched together.
+///
+/// The driver takes the last trades and clears them from the [`Bot`], so trades are only delivered
+/// through [`Strategy::on_trade`]. Timers are set through [`Bot::set_timer`], either before
+/// running the driver or within the callbacks, and the timers reported by [`Bot::fired_timers`]
+/// are delivered through [`Strategy::on_timer`].
+///
+/// **Example**
+/// ```ignore
+/// hbt.set_timer(0, start_timestamp, 100_000_000);
+/// let mut driver = StrategyDriver::new();
+/// driver.run(&mut hbt, &mut strategy)?;
+/// ```
+pub struct StrategyDriver {
+    timeout: i64,
+}
+
+impl Default for StrategyDriver {
+    fn default() -> Self {
+        Self {
+            timeout: 1_000_000_000,
+        }
+    }
+}
+
+impl StrategyDriver {
+    /// Constructs a `StrategyDriver`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum duration to wait for the next feed or order response. A timer that fires
+    /// wakes up the wait earlier. Nanoseconds is the default unit. However, unit should be the same as
+    /// the data's timestamp unit.
+    ///
+    /// The default value is `1_000_000_000`.
+    pub fn timeout(self, timeout: i64) -> Self {
+        Self { timeout }
+    }
+
+    /// Runs the strategy until the end of the data in backtesting, or until the bot is
+    /// interrupted in live trading.
+    pub fn run<MD, I, S>(&mut self, hbt: &mut I, strategy: &mut S) -> Result<(), S::Error>
+    where
+        MD: MarketDepth,
+        I: Bot<MD>,
+        S: Strategy<MD, I>,
+    {
+        let num_assets = hbt.num_assets();
+        let mut orders: Vec<HashMap<OrderId, Order>> = (0..num_assets)
+            .map(|asset_no| hbt.orders(asset_no).clone())
+            .collect();
+        let mut depth_latency: Vec<_> = (0..num_assets)
+            .map(|asset_no| hbt.depth_latency(asset_no))
+            .collect();
+        hbt.clear_last_trades(None);
+
+        loop {
+            // Even if the end of the data is reached, the events processed until then are still
+            // dispatched.
+            let cont = hbt.wait_next_feed(true, self.timeout)?;
+
+            // Collects what has changed before invoking any callbacks, so that the requests made
+            // within the callbacks are not dispatched as updates.
+            let mut changes = Vec::with_capacity(num_assets);
+            for asset_no in 0..num_assets {
+                let trades = hbt.last_trades(asset_no).to_vec();
+                let latency = hbt.depth_latency(asset_no);
+                let depth_updated = latency != depth_latency[asset_no];
+                depth_latency[asset_no] = latency;
+                let updates: Vec<_> = hbt
+                    .orders(asset_no)
+                    .values()
+                    .filter_map(|order| match orders[asset_no].get(&order.order_id) {
+                        Some(prev) if !is_updated(prev, order) => None,
+                        prev => Some((prev.cloned(), order.clone())),
+                    })
+                    .collect();
+                changes.push((trades, depth_updated, updates));
+            }
+            hbt.clear_last_trades(None);
+            let fired_timers = hbt.fired_timers().to_vec();
+
+            for (asset_no, (trades, depth_updated, updates)) in changes.iter().enumerate() {
+                for trade in trades {
+                    strategy.on_trade(hbt, asset_no, trade)?;
+                }
+                if *depth_updated {
+                    strategy.on_depth(hbt, asset_no)?;
+                }
+                for (old, new) in updates {
+                    strategy.on_order_update(hbt, asset_no, old.as_ref(), new)?;
+                    if is_filled(old.as_ref(), new) {
+                        strategy.on_fill(hbt, asset_no, new)?;
+                    }
+                }
+            }
+
+            for timer_id in fired_timers {
+                strategy.on_timer(hbt, timer_id)?;
+            }
+
+            for (asset_no, orders) in orders.iter_mut().enumerate() {
+                orders.clone_from(hbt.orders(asset_no));
+            }
+
+            if !cont {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn is_updated(prev: &Order, order: &Order) -> bool {
+    prev.status != order.status
+        || prev.req != order.req
+        || prev.exch_timestamp != order.exch_timestamp
+        || prev.leaves_qty != order.leaves_qty
+        || prev.qty != order.qty
+        || prev.price_tick != order.price_tick
+}
+
+fn is_filled(prev: Option<&Order>, order: &Order) -> bool {
+    (order.status == Status::Filled || order.status == Status::PartiallyFilled)
+        && order.exec_qty > 0.0
+        && prev.map_or(true, |prev| prev.exch_timestamp != order.exch_timestamp)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backtest::{
+            data::Data,
+            fixture::{asset_builder, build, feed_events},
+            models::ConstantLatency,
+            Backtest,
+            BacktestError,
+        },
+        depth::HashMapMarketDepth,
+        strategy::{Strategy, StrategyDriver},
+        types::{Bot, Event, OrdType, Order, Status, TimeInForce, TimerId},
+    };
+
+    #[derive(Default)]
+    struct TestStrategy {
+        num_depth: usize,
+        num_trades: usize,
+        timers: Vec<(TimerId, i64)>,
+        updates: Vec<Status>,
+        fills: Vec<f64>,
+    }
+
+    impl Strategy<HashMapMarketDepth, Backtest<HashMapMarketDepth>> for TestStrategy {
+        type Error = BacktestError;
+
+        fn on_depth(
+            &mut self,
+            hbt: &mut Backtest<HashMapMarketDepth>,
+            asset_no: usize,
+        ) -> Result<(), Self::Error> {
+            self.num_depth += 1;
+            if self.num_depth == 10 {
+                hbt.submit_buy_order(
+                    asset_no,
+                    1,
+                    100.1,
+                    1.0,
+                    TimeInForce::GTC,
+                    OrdType::Limit,
+                    false,
+                )?;
+            }
+            Ok(())
+        }
+
+        fn on_trade(
+            &mut self,
+            _hbt: &mut Backtest<HashMapMarketDepth>,
+            _asset_no: usize,
+            _trade: &Event,
+        ) -> Result<(), Self::Error> {
+            self.num_trades += 1;
+            Ok(())
+        }
+
+        fn on_order_update(
+            &mut self,
+            _hbt: &mut Backtest<HashMapMarketDepth>,
+            _asset_no: usize,
+            _old: Option<&Order>,
+            new: &Order,
+        ) -> Result<(), Self::Error> {
+            self.updates.push(new.status);
+            Ok(())
+        }
+
+        fn on_fill(
+            &mut self,
+            _hbt: &mut Backtest<HashMapMarketDepth>,
+            _asset_no: usize,
+            order: &Order,
+        ) -> Result<(), Self::Error> {
+            self.fills.push(order.exec_qty);
+            Ok(())
+        }
+
+        fn on_timer(
+            &mut self,
+            hbt: &mut Backtest<HashMapMarketDepth>,
+            timer_id: TimerId,
+        ) -> Result<(), Self::Error> {
+            self.timers.push((timer_id, hbt.current_timestamp()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_strategy_driver() {
+        let mut hbt = build(
+            asset_builder(&Data::from_items(&feed_events(100, true)))
+                .latency_model(ConstantLatency::new(50, 50))
+                .last_trades_capacity(100),
+        );
+
+        hbt.set_timer(1, 10_000, 10_000);
+        hbt.set_timer(2, 55_000, 0);
+
+        let mut strategy = TestStrategy::default();
+        StrategyDriver::new().run(&mut hbt, &mut strategy).unwrap();
+
+        assert_eq!(strategy.num_depth, 50);
+        assert_eq!(strategy.num_trades, 50);
+        let mut timers: Vec<_> = (1..10).map(|i| (1, i * 10_000)).collect();
+        timers.insert(5, (2, 55_000));
+        assert_eq!(strategy.timers, timers);
+        assert_eq!(strategy.updates, vec![Status::Filled]);
+        assert_eq!(strategy.fills, vec![1.0]);
+        assert_eq!(hbt.position(0), 1.0);
+    }
+}
@@ -313,6 +313,8 @@ pub const UNTIL_END_OF_DATA: i64 = i64::MAX;
 
 pub type OrderId = u64;
 
+pub type TimerId = u64;
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum WaitOrderResponse {
     None,
@@ -412,6 +414,8 @@ pub enum Status {
     Canceled = 4,
     PartiallyFilled = 5,
     Rejected = 6,
+    /// Used as a request status to modify an order's price and quantity.
+    Replaced = 7,
     /// This occurs when the [`Connector`](`crate::connector::Connector`) receives an order status
     /// value that does not have a corresponding enum value.
     Unsupported = 255,
@@ -429,6 +433,8 @@ pub enum TimeInForce {
     FOK = 2,
     /// Immediate or Cancel
     IOC = 3,
+    /// Good 'Til Date, which is canceled by the exchange at the order's expiry timestamp.
+    GTD = 4,
     /// This occurs when the [`Connector`](`crate::connector::Connector`) receives a time-in-force
     /// value that does not have a corresponding enum value.
     Unsupported = 255,
@@ -441,30 +447,129 @@ impl AsRef<str> for TimeInForce {
             TimeInForce::GTX => "GTX",
             TimeInForce::FOK => "FOK",
             TimeInForce::IOC => "IOC",
+            TimeInForce::GTD => "GTD",
             TimeInForce::Unsupported => panic!("TimeInForce::Unsupported"),
         }
     }
 }
 
 /// Order type
+///
+/// Conditional orders, which are stop and take-profit orders, rest on the exchange without being
+/// in the order book until the trigger price is reached. Once triggered, a conditional order turns
+/// into a market or limit order with the same order ID.
 #[derive(Clone, Copy, Eq, PartialEq, Debug, Decode, Encode)]
 #[repr(u8)]
 pub enum OrdType {
     Limit = 0,
     Market = 1,
+    /// A market order that is triggered when the price moves against the order side, that is,
+    /// rises to the trigger price for a buy order or falls to it for a sell order.
+    StopMarket = 2,
+    /// A limit order that is triggered in the same way as [`OrdType::StopMarket`].
+    StopLimit = 3,
+    /// A market order that is triggered when the price moves in favor of the order side, that is,
+    /// falls to the trigger price for a buy order or rises to it for a sell order.
+    TakeProfitMarket = 4,
+    /// A limit order that is triggered in the same way as [`OrdType::TakeProfitMarket`].
+    TakeProfitLimit = 5,
     Unsupported = 255,
 }
 
+impl OrdType {
+    /// Returns `true` if this is a conditional order type.
+    pub fn is_conditional(&self) -> bool {
+        matches!(
+            self,
+            OrdType::StopMarket
+                | OrdType::StopLimit
+                | OrdType::TakeProfitMarket
+                | OrdType::TakeProfitLimit
+        )
+    }
+
+    /// Returns the order type into which a conditional order turns once triggered. Other order
+    /// types are returned as they are.
+    pub fn triggered(&self) -> OrdType {
+        match self {
+            OrdType::StopMarket | OrdType::TakeProfitMarket => OrdType::Market,
+            OrdType::StopLimit | OrdType::TakeProfitLimit => OrdType::Limit,
+            ord_type => *ord_type,
+        }
+    }
+}
+
 impl AsRef<str> for OrdType {
     fn as_ref(&self) -> &'static str {
         match self {
             OrdType::Limit => "LIMIT",
             OrdType::Market => "MARKET",
+            OrdType::StopMarket => "STOP_MARKET",
+            OrdType::StopLimit => "STOP",
+            OrdType::TakeProfitMarket => "TAKE_PROFIT_MARKET",
+            OrdType::TakeProfitLimit => "TAKE_PROFIT",
             OrdType::Unsupported => panic!("OrdType::Unsupported"),
         }
     }
 }
 
+/// The price against which the trigger price of a conditional order is evaluated.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Decode, Encode)]
+#[repr(u8)]
+pub enum TriggerPriceType {
+    /// The last trade price.
+    LastPrice = 0,
+    /// The mark price.
+    MarkPrice = 1,
+    /// This occurs when the [`Connector`](`crate::connector::Connector`) receives a trigger price
+    /// type value that does not have a corresponding enum value.
+    Unsupported = 255,
+}
+
+impl AsRef<str> for TriggerPriceType {
+    fn as_ref(&self) -> &'static str {
+        match self {
+            TriggerPriceType::LastPrice => "CONTRACT_PRICE",
+            TriggerPriceType::MarkPrice => "MARK_PRICE",
+            TriggerPriceType::Unsupported => panic!("TriggerPriceType::Unsupported"),
+        }
+    }
+}
+
+/// Self-trade prevention mode, which determines what happens when an incoming order would match a
+/// resting order of the same account. The mode of the incoming order applies.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Decode, Encode)]
+#[repr(u8)]
+pub enum SelfTradePrevention {
+    /// Allows self-trades.
+    None = 0,
+    /// Expires the incoming order.
+    CancelNewest = 1,
+    /// Expires the resting orders that the incoming order would match.
+    CancelOldest = 2,
+    /// Expires both the incoming order and the resting orders that it would match.
+    CancelBoth = 3,
+    /// Decreases the quantities of both orders by the smaller of their leaves quantities, expiring
+    /// the order that has no quantity left.
+    Decrement = 4,
+    /// This occurs when the [`Connector`](`crate::connector::Connector`) receives a self-trade
+    /// prevention value that does not have a corresponding enum value.
+    Unsupported = 255,
+}
+
+impl AsRef<str> for SelfTradePrevention {
+    fn as_ref(&self) -> &'static str {
+        match self {
+            SelfTradePrevention::None => "NONE",
+            SelfTradePrevention::CancelNewest => "EXPIRE_TAKER",
+            SelfTradePrevention::CancelOldest => "EXPIRE_MAKER",
+            SelfTradePrevention::CancelBoth => "EXPIRE_BOTH",
+            SelfTradePrevention::Decrement => "DECREMENT",
+            SelfTradePrevention::Unsupported => panic!("SelfTradePrevention::Unsupported"),
+        }
+    }
+}
+
 /// Provides cloning of `Box<dyn Any>`, which is utilized in [Order] for the additional data used in
 /// [`QueueModel`](`crate::backtest::models::QueueModel`).
 ///
@@ -533,6 +638,31 @@ pub struct Order {
     pub status: Status,
     pub side: Side,
     pub time_in_force: TimeInForce,
+    /// Self-trade prevention mode applied when this order would match a resting order of the same
+    /// account.
+    pub self_trade_prevention: SelfTradePrevention,
+    /// Volume-weighted average executed price when a liquidity-taking order sweeps multiple price
+    /// levels in a single execution, or `0.0` otherwise, in which case the executed price is
+    /// given by `exec_price_tick`.
+    pub avg_exec_price: f64,
+    /// Trigger price in ticks (`trigger_price / tick_size`) of a conditional order.
+    pub trigger_price_tick: i64,
+    /// The order ID of the other order of the one-cancels-other pair, only valid if `oco` is
+    /// `true`.
+    pub oco_order_id: OrderId,
+    /// The price against which the trigger price of a conditional order is evaluated.
+    pub trigger_price_type: TriggerPriceType,
+    /// Whether this order is one of a one-cancels-other pair. Once either order is executed or
+    /// triggered, the other is expired.
+    pub oco: bool,
+    /// Exchange timestamp at which a [`TimeInForce::GTD`] order expires.
+    pub expire_timestamp: i64,
+    /// Whether the order can only reduce the position. The exchange reduces the quantity of a
+    /// reduce-only order that exceeds the position and expires one that would increase it.
+    pub reduce_only: bool,
+    /// The order ID assigned by the exchange, which identifies this order in the Level3
+    /// Market-By-Order feed, or `0` if it is not available. This is only available in a live bot.
+    pub exch_order_id: u64,
 }
 
 impl Order {
@@ -563,6 +693,15 @@ impl Order {
             q: Box::new(()),
             maker: false,
             order_type,
+            self_trade_prevention: SelfTradePrevention::None,
+            avg_exec_price: 0.0,
+            trigger_price_tick: 0,
+            oco_order_id: 0,
+            trigger_price_type: TriggerPriceType::LastPrice,
+            oco: false,
+            expire_timestamp: 0,
+            reduce_only: false,
+            exch_order_id: 0,
         }
     }
 
@@ -571,9 +710,36 @@ impl Order {
         self.price_tick as f64 * self.tick_size
     }
 
-    /// Returns the executed price, only available when this order is executed.
+    /// Returns the executed price, only available when this order is executed. If the execution
+    /// sweeps multiple price levels, this is the volume-weighted average price.
     pub fn exec_price(&self) -> f64 {
-        self.exec_price_tick as f64 * self.tick_size
+        if self.avg_exec_price != 0.0 {
+            self.avg_exec_price
+        } else {
+            self.exec_price_tick as f64 * self.tick_size
+        }
+    }
+
+    /// Returns the trigger price of a conditional order.
+    pub fn trigger_price(&self) -> f64 {
+        self.trigger_price_tick as f64 * self.tick_size
+    }
+
+    /// Returns whether this conditional order is triggered at the given price. Orders that are not
+    /// conditional are never triggered.
+    pub fn is_triggered_at(&self, price: f64) -> bool {
+        let trigger_price = self.trigger_price();
+        match (self.order_type, self.side) {
+            (OrdType::StopMarket | OrdType::StopLimit, Side::Buy)
+            | (OrdType::TakeProfitMarket | OrdType::TakeProfitLimit, Side::Sell) => {
+                price >= trigger_price
+            }
+            (OrdType::StopMarket | OrdType::StopLimit, Side::Sell)
+            | (OrdType::TakeProfitMarket | OrdType::TakeProfitLimit, Side::Buy) => {
+                price <= trigger_price
+            }
+            _ => false,
+        }
     }
 
     /// Returns whether this order is cancelable.
@@ -617,6 +783,17 @@ impl Order {
         self.q = order.q.clone();
         self.maker = order.maker;
         self.order_type = order.order_type;
+        self.self_trade_prevention = order.self_trade_prevention;
+        self.avg_exec_price = order.avg_exec_price;
+        self.trigger_price_tick = order.trigger_price_tick;
+        self.oco_order_id = order.oco_order_id;
+        self.trigger_price_type = order.trigger_price_type;
+        self.oco = order.oco;
+        self.expire_timestamp = order.expire_timestamp;
+        self.reduce_only = order.reduce_only;
+        if order.exch_order_id != 0 {
+            self.exch_order_id = order.exch_order_id;
+        }
     }
 }
 
@@ -638,6 +815,15 @@ impl Debug for Order {
             .field("order_id", &self.order_id)
             .field("maker", &self.maker)
             .field("order_type", &self.order_type)
+            .field("self_trade_prevention", &self.self_trade_prevention)
+            .field("avg_exec_price", &self.avg_exec_price)
+            .field("trigger_price_tick", &self.trigger_price_tick)
+            .field("oco_order_id", &self.oco_order_id)
+            .field("trigger_price_type", &self.trigger_price_type)
+            .field("oco", &self.oco)
+            .field("expire_timestamp", &self.expire_timestamp)
+            .field("reduce_only", &self.reduce_only)
+            .field("exch_order_id", &self.exch_order_id)
             .finish()
     }
 }
@@ -662,6 +848,15 @@ impl Decode for Order {
             status: Decode::decode(decoder)?,
             side: Decode::decode(decoder)?,
             time_in_force: Decode::decode(decoder)?,
+            self_trade_prevention: Decode::decode(decoder)?,
+            avg_exec_price: Decode::decode(decoder)?,
+            trigger_price_tick: Decode::decode(decoder)?,
+            oco_order_id: Decode::decode(decoder)?,
+            trigger_price_type: Decode::decode(decoder)?,
+            oco: Decode::decode(decoder)?,
+            expire_timestamp: Decode::decode(decoder)?,
+            reduce_only: Decode::decode(decoder)?,
+            exch_order_id: Decode::decode(decoder)?,
         })
     }
 }
@@ -686,6 +881,15 @@ impl<'de> BorrowDecode<'de> for Order {
             status: Decode::decode(decoder)?,
             side: Decode::decode(decoder)?,
             time_in_force: Decode::decode(decoder)?,
+            self_trade_prevention: Decode::decode(decoder)?,
+            avg_exec_price: Decode::decode(decoder)?,
+            trigger_price_tick: Decode::decode(decoder)?,
+            oco_order_id: Decode::decode(decoder)?,
+            trigger_price_type: Decode::decode(decoder)?,
+            oco: Decode::decode(decoder)?,
+            expire_timestamp: Decode::decode(decoder)?,
+            reduce_only: Decode::decode(decoder)?,
+            exch_order_id: Decode::decode(decoder)?,
         })
     }
 }
@@ -708,6 +912,15 @@ impl Encode for Order {
         self.status.encode(encoder)?;
         self.side.encode(encoder)?;
         self.time_in_force.encode(encoder)?;
+        self.self_trade_prevention.encode(encoder)?;
+        self.avg_exec_price.encode(encoder)?;
+        self.trigger_price_tick.encode(encoder)?;
+        self.oco_order_id.encode(encoder)?;
+        self.trigger_price_type.encode(encoder)?;
+        self.oco.encode(encoder)?;
+        self.expire_timestamp.encode(encoder)?;
+        self.reduce_only.encode(encoder)?;
+        self.exch_order_id.encode(encoder)?;
         Ok(())
     }
 }
@@ -717,6 +930,11 @@ impl Encode for Order {
 pub enum LiveRequest {
     /// An order request, a tuple consisting of an asset number and an [`Order`].
     Order { symbol: String, order: Order },
+    /// A batch of order requests for the same symbol, which the connector maps to the exchange's
+    /// batch endpoints where available.
+    Orders { symbol: String, orders: Vec<Order> },
+    /// A request to cancel all open orders for the symbol.
+    CancelAll { symbol: String },
     /// A request to add an instrument for trading.
     RegisterInstrument {
         symbol: String,
@@ -730,7 +948,7 @@ pub enum LiveRequest {
 /// **Note:** In a live bot, currently only `position` value is delivered correctly, and other
 /// values are invalid.
 #[repr(C)]
-#[derive(PartialEq, Clone, Debug, Default)]
+#[derive(PartialEq, Clone, Debug, Default, Encode, Decode)]
 pub struct StateValues {
     pub position: f64,
     /// Backtest only
@@ -745,6 +963,19 @@ pub struct StateValues {
     pub trading_volume: f64,
     /// Backtest only
     pub trading_value: f64,
+    /// Backtest only. The cumulative funding payment, which is negative when the funding is
+    /// received.
+    pub funding: f64,
+    /// Backtest only. The initial margin used by the position and the open orders, if a margin
+    /// model is set.
+    pub initial_margin: f64,
+    /// Backtest only. The maintenance margin of the position, if a margin model is set.
+    pub maintenance_margin: f64,
+    /// Backtest only
+    pub num_liquidations: i64,
+    /// Backtest only. The value at which the position is settled when the contract expires, which
+    /// is positive when a long position is settled.
+    pub settlement: f64,
 }
 
 /// Provides errors that can occur in builders.
@@ -771,6 +1002,17 @@ pub struct OrderRequest {
     pub side: Side,
     pub time_in_force: TimeInForce,
     pub order_type: OrdType,
+    pub self_trade_prevention: SelfTradePrevention,
+    /// Trigger price of a conditional order, which is ignored for the other order types.
+    pub trigger_price: f64,
+    pub trigger_price_type: TriggerPriceType,
+    /// The order ID of the other order if this order is one of a one-cancels-other pair. Both
+    /// orders of the pair should refer to each other.
+    pub oco_order_id: Option<OrderId>,
+    /// Exchange timestamp at which the order expires if its time-in-force is
+    /// [`TimeInForce::GTD`].
+    pub expire_timestamp: i64,
+    pub reduce_only: bool,
 }
 
 /// Provides a bot interface for backtesting and live trading.
@@ -811,6 +1053,16 @@ where
     ///                trades in any assets will be cleared.
     fn clear_last_trades(&mut self, asset_no: Option<usize>);
 
+    /// Returns the latest item of the custom data stream received by the local, or `None` if no
+    /// item has been received yet. Panics if the stream does not exist or its item type is not
+    /// `D`. Custom data streams are not supported in live trading, so `None` is returned there.
+    ///
+    /// * `asset_no` - Asset number to which the custom data stream is attached.
+    /// * `stream_no` - Stream number in the order in which the stream is added to the asset.
+    fn custom_data<D>(&self, asset_no: usize, stream_no: usize) -> Option<&D>
+    where
+        D: 'static;
+
     /// Returns a hash map of order IDs and their corresponding [`Order`]s.
     ///
     /// * `asset_no` - Asset number from which orders will be retrieved.
@@ -888,6 +1140,64 @@ where
         wait: bool,
     ) -> Result<bool, Self::Error>;
 
+    /// Places multiple orders at once.
+    ///
+    /// In backtesting, each order is sent separately and experiences its own order entry latency.
+    /// In live trading, the orders are sent to the connector in a single request, which is mapped
+    /// to the exchange's batch order endpoint.
+    ///
+    /// * `asset_no` - Asset number at which this command will be executed.
+    /// * `orders` - Orders to place.
+    /// * `wait` - If true, wait until the responses of all orders are received.
+    fn submit_orders(
+        &mut self,
+        asset_no: usize,
+        orders: Vec<OrderRequest>,
+        wait: bool,
+    ) -> Result<bool, Self::Error>;
+
+    /// Cancels multiple orders at once.
+    ///
+    /// In backtesting, each cancel request is sent separately and experiences its own order entry
+    /// latency. In live trading, the cancel requests are sent to the connector in a single request,
+    /// which is mapped to the exchange's batch cancel endpoint.
+    ///
+    /// * `asset_no` - Asset number at which this command will be executed.
+    /// * `order_ids` - Order IDs to cancel.
+    /// * `wait` - If true, wait until the responses of all cancel requests are received.
+    fn cancel_orders(
+        &mut self,
+        asset_no: usize,
+        order_ids: &[OrderId],
+        wait: bool,
+    ) -> Result<bool, Self::Error>;
+
+    /// Cancels all cancellable orders of the asset. In live trading, this is mapped to the
+    /// exchange's cancel-all endpoint.
+    ///
+    /// * `asset_no` - Asset number at which this command will be executed.
+    /// * `wait` - If true, wait until the responses of all cancel requests are received.
+    fn cancel_all(&mut self, asset_no: usize, wait: bool) -> Result<bool, Self::Error>;
+
+    /// Modifies the price and quantity of the specified order.
+    ///
+    /// Depending on the exchange, a price change or a quantity increase loses the order's queue
+    /// priority, while a quantity decrease retains it.
+    ///
+    /// * `asset_no` - Asset number at which this command will be executed.
+    /// * `order_id` - Order ID to modify.
+    /// * `price` - New order price.
+    /// * `qty` - New order quantity.
+    /// * `wait` - If true, wait until the order modification response is received.
+    fn modify(
+        &mut self,
+        asset_no: usize,
+        order_id: OrderId,
+        price: f64,
+        qty: f64,
+        wait: bool,
+    ) -> Result<bool, Self::Error>;
+
     /// Clears inactive orders from the local orders whose status is neither [`Status::New`] nor
     /// [`Status::PartiallyFilled`].
     fn clear_inactive_orders(&mut self, asset_no: Option<usize>);
@@ -933,17 +1243,54 @@ where
     ///   the data is reached before the specified timestamp, it returns `Ok(false)`.
     fn elapse_bt(&mut self, duration: i64) -> Result<bool, Self::Error>;
 
+    /// Sets a timer that wakes up [elapse()](Self::elapse()) and
+    /// [wait_next_feed()](Self::wait_next_feed()) when it fires. If a timer with the same ID
+    /// exists, it is replaced. Waiting for a specific order's response is not woken up by timers,
+    /// but the timers that fire during the wait are still reported. A timer set in the past fires
+    /// at the current timestamp.
+    ///
+    /// * `timer_id` - The unique timer ID.
+    /// * `timestamp` - The timestamp at which the timer fires for the first time.
+    /// * `interval` - The interval at which the timer fires again. If zero, it fires only once.
+    fn set_timer(&mut self, timer_id: TimerId, timestamp: i64, interval: i64);
+
+    /// Cancels the timer. Returns `true` if the timer exists.
+    fn cancel_timer(&mut self, timer_id: TimerId) -> bool;
+
+    /// Returns the IDs of the timers fired during the last [elapse()](Self::elapse()) or
+    /// [wait_next_feed()](Self::wait_next_feed()), including the timers fired while waiting for
+    /// order responses since then.
+    fn fired_timers(&self) -> &[TimerId];
+
     /// Closes this backtester or bot.
     fn close(&mut self) -> Result<(), Self::Error>;
 
     /// Returns the last feed's exchange timestamp and local receipt timestamp.
     fn feed_latency(&self, asset_no: usize) -> Option<(i64, i64)>;
 
+    /// Returns the last market depth feed's exchange timestamp and local receipt timestamp. Unlike
+    /// [feed_latency()](Self::feed_latency()), this isn't updated by trade feeds.
+    fn depth_latency(&self, asset_no: usize) -> Option<(i64, i64)>;
+
     /// Returns the last order's request timestamp, exchange timestamp, and response receipt
     /// timestamp.
     fn order_latency(&self, asset_no: usize) -> Option<(i64, i64, i64)>;
 }
 
+/// Provides the queue position of the bot's own orders in the Level3 Market-By-Order feed, so that
+/// the same Level3 strategy runs in both backtesting with
+/// [`L3Local`](crate::backtest::proc::L3Local) and live trading.
+pub trait L3Bot<MD>: Bot<MD>
+where
+    MD: MarketDepth,
+{
+    /// Returns the quantity ahead of the order in the queue at its price level, which is the total
+    /// quantity of the orders in the Market-By-Order feed at the same price that have been placed
+    /// earlier. Returns `None` if the order's position in the feed is unknown, such as when the
+    /// order is not open or the asset doesn't process a Level3 feed.
+    fn queue_ahead(&self, asset_no: usize, order_id: OrderId) -> Option<f64>;
+}
+
 /// Provides bot statistics and [`StateValues`] recording features for backtesting result analysis
 /// or live bot logging.
 pub trait Recorder {
@@ -32,8 +32,15 @@ from .order import (
     CANCELED,
     GTC,
     GTX,
+    GTD,
     LIMIT,
     MARKET,
+    STOP_MARKET,
+    STOP_LIMIT,
+    TAKE_PROFIT_MARKET,
+    TAKE_PROFIT_LIMIT,
+    LAST_PRICE,
+    MARK_PRICE,
 )
 from .recorder import Recorder
 from .types import (
@@ -96,9 +103,16 @@ __all__ = (
     # Time-In-Force
     'GTC',
     'GTX',
+    'GTD',
 
     'LIMIT',
     'MARKET',
+    'STOP_MARKET',
+    'STOP_LIMIT',
+    'TAKE_PROFIT_MARKET',
+    'TAKE_PROFIT_LIMIT',
+    'LAST_PRICE',
+    'MARK_PRICE',
     
     'Recorder'
 )
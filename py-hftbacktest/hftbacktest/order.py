@@ -41,6 +41,9 @@ PARTIALLY_FILLED = 5
 #: REJECTED
 REJECTED = 6
 
+#: REPLACED
+REPLACED = 7
+
 #: Good 'till cancel
 GTC = 0
 
@@ -53,12 +56,33 @@ FOK = 2
 #: Immediate or cancel
 IOC = 3
 
+#: Good 'till date
+GTD = 4
+
 #: LIMIT
 LIMIT = 0
 
 #: MARKET
 MARKET = 1
 
+#: STOP_MARKET
+STOP_MARKET = 2
+
+#: STOP_LIMIT
+STOP_LIMIT = 3
+
+#: TAKE_PROFIT_MARKET
+TAKE_PROFIT_MARKET = 4
+
+#: TAKE_PROFIT_LIMIT
+TAKE_PROFIT_LIMIT = 5
+
+#: Triggered by the last trade price
+LAST_PRICE = 0
+
+#: Triggered by the mark price
+MARK_PRICE = 1
+
 
 class Order:
     arr: from_dtype(order_dtype)[:]
@@ -77,7 +101,10 @@ class Order:
     def exec_price(self) -> float64:
         """
         Returns the executed price. This is only valid if :obj:`status` is :const:`FILLED` or :const:`PARTIALLY_FILLED`.
+        If the execution sweeps multiple price levels, this is the volume-weighted average price.
         """
+        if self.arr[0].avg_exec_price != 0.0:
+            return self.arr[0].avg_exec_price
         return self.arr[0].exec_price_tick * self.arr[0].tick_size
 
     @property
@@ -163,6 +190,12 @@ class Order:
 
             * :const:`MARKET`
             * :const:`LIMIT`
+            * :const:`STOP_MARKET`
+            * :const:`STOP_LIMIT`
+            * :const:`TAKE_PROFIT_MARKET`
+            * :const:`TAKE_PROFIT_LIMIT`
+
+        A conditional order's type changes to :const:`MARKET` or :const:`LIMIT` once it is triggered.
         """
         return self.arr[0].order_type
 
@@ -212,8 +245,77 @@ class Order:
             * :const:`GTX`
             * :const:`FOK`
             * :const:`IOC`
+            * :const:`GTD`
         """
         return self.arr[0].time_in_force
 
+    @property
+    def self_trade_prevention(self) -> uint8:
+        """
+        Returns the self-trade prevention mode of the order: 0 for none, 1 for cancel newest, 2 for cancel oldest, 3 for
+        cancel both, and 4 for decrement.
+        """
+        return self.arr[0].self_trade_prevention
+
+    @property
+    def trigger_price_tick(self) -> int64:
+        """
+        Returns the trigger price in ticks of a conditional order.
+        """
+        return self.arr[0].trigger_price_tick
+
+    @property
+    def trigger_price(self) -> float64:
+        """
+        Returns the trigger price of a conditional order.
+        """
+        return self.arr[0].trigger_price_tick * self.arr[0].tick_size
+
+    @property
+    def trigger_price_type(self) -> uint8:
+        """
+        Returns the price that triggers a conditional order.
+
+            * :const:`LAST_PRICE`
+            * :const:`MARK_PRICE`
+        """
+        return self.arr[0].trigger_price_type
+
+    @property
+    def oco(self) -> bool:
+        """
+        Returns whether the order is a leg of a one-cancels-other pair.
+        """
+        return self.arr[0].oco
+
+    @property
+    def oco_order_id(self) -> uint64:
+        """
+        Returns the order ID of the other leg of a one-cancels-other pair. This is only valid if :obj:`oco` is `True`.
+        """
+        return self.arr[0].oco_order_id
+
+    @property
+    def expire_timestamp(self) -> int64:
+        """
+        Returns the exchange timestamp at which the order expires if its Time-In-Force is :const:`GTD`.
+        """
+        return self.arr[0].expire_timestamp
+
+    @property
+    def reduce_only(self) -> bool:
+        """
+        Returns whether the order can only reduce the position.
+        """
+        return self.arr[0].reduce_only
+
+    @property
+    def exch_order_id(self) -> uint64:
+        """
+        Returns the order ID assigned by the exchange, which identifies the order in the Level3 Market-By-Order feed,
+        or `0` if it is not available. This is only available in a live bot.
+        """
+        return self.arr[0].exch_order_id
+
 
 Order_ = jitclass(Order)
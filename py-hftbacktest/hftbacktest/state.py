@@ -37,5 +37,25 @@ class StateValues:
     def trading_value(self) -> float64:
         return self.arr[0].trading_value
 
+    @property
+    def funding(self) -> float64:
+        return self.arr[0].funding
+
+    @property
+    def initial_margin(self) -> float64:
+        return self.arr[0].initial_margin
+
+    @property
+    def maintenance_margin(self) -> float64:
+        return self.arr[0].maintenance_margin
+
+    @property
+    def num_liquidations(self) -> int64:
+        return self.arr[0].num_liquidations
+
+    @property
+    def settlement(self) -> float64:
+        return self.arr[0].settlement
+
 
 StateValues_ = jitclass(StateValues)
@@ -66,7 +66,12 @@ state_values_dtype = np.dtype(
         ('fee', 'f8'),
         ('num_trades', 'i8'),
         ('trading_volume', 'f8'),
-        ('trading_value', 'f8')
+        ('trading_value', 'f8'),
+        ('funding', 'f8'),
+        ('initial_margin', 'f8'),
+        ('maintenance_margin', 'f8'),
+        ('num_liquidations', 'i8'),
+        ('settlement', 'f8')
     ],
     align=True
 )
@@ -105,7 +110,16 @@ order_dtype = np.dtype(
         ('req', 'u1'),
         ('status', 'u1'),
         ('side', 'i1'),
-        ('time_in_force', 'u1')
+        ('time_in_force', 'u1'),
+        ('self_trade_prevention', 'u1'),
+        ('avg_exec_price', 'f8'),
+        ('trigger_price_tick', 'i8'),
+        ('oco_order_id', 'u8'),
+        ('trigger_price_type', 'u1'),
+        ('oco', 'bool'),
+        ('expire_timestamp', 'i8'),
+        ('reduce_only', 'bool'),
+        ('exch_order_id', 'u8')
     ],
     align=True
 )
@@ -119,7 +133,8 @@ record_dtype = np.dtype(
         ('fee', 'f8'),
         ('num_trades', 'i8'),
         ('trading_volume', 'f8'),
-        ('trading_value', 'f8')
+        ('trading_value', 'f8'),
+        ('funding', 'f8')
     ],
     align=True
 )
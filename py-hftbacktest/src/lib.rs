@@ -27,6 +27,7 @@ use hftbacktest::{
         proc::{
             L3Local,
             L3NoPartialFillExchange,
+            L3PartialFillExchange,
             Local,
             LocalProcessor,
             NoPartialFillExchange,
@@ -43,7 +44,7 @@ use hftbacktest::{
 };
 use hftbacktest_derive::build_asset;
 pub use order::*;
-use pyo3::{exceptions::PyValueError, prelude::*};
+use pyo3::prelude::*;
 
 use crate::live::{HashMapMarketDepthLiveBot, ROIVectorMarketDepthLiveBot};
 
@@ -396,7 +397,9 @@ impl BacktestAsset {
     }
 
     /// Uses the `PartiallFillExchange <https://docs.rs/hftbacktest/latest/hftbacktest/backtest/proc/struct.PartialFillExchange.html>`_
-    /// for the exchange model.
+    /// for the exchange model. With the L3 queue model, the
+    /// `L3PartialFillExchange <https://docs.rs/hftbacktest/latest/hftbacktest/backtest/proc/type.L3PartialFillExchange.html>`_
+    /// is used.
     pub fn partial_fill_exchange(mut slf: PyRefMut<Self>) -> PyRefMut<Self> {
         slf.exch_kind = ExchangeKind::PartialFillExchange {};
         slf
@@ -471,14 +474,6 @@ pub fn build_hashmap_backtest(assets: Vec<PyRefMut<BacktestAsset>>) -> PyResult<
     let mut local = Vec::new();
     let mut exch = Vec::new();
     for asset in assets {
-        if let (QueueModel::L3FIFOQueueModel {}, ExchangeKind::PartialFillExchange {}) =
-            (&asset.queue_model, &asset.exch_kind)
-        {
-            return PyResult::Err(PyErr::new::<PyValueError, _>(
-                "L3PartialFillExchange is unsupported.",
-            ));
-        }
-
         let asst = build_asset!(
             asset,
             HashMapMarketDepth,
@@ -525,14 +520,6 @@ pub fn build_roivec_backtest(assets: Vec<PyRefMut<BacktestAsset>>) -> PyResult<u
     let mut local = Vec::new();
     let mut exch = Vec::new();
     for asset in assets {
-        if let (QueueModel::L3FIFOQueueModel {}, ExchangeKind::PartialFillExchange {}) =
-            (&asset.queue_model, &asset.exch_kind)
-        {
-            return PyResult::Err(PyErr::new::<PyValueError, _>(
-                "L3PartialFillExchange is unsupported.",
-            ));
-        }
-
         let asst = build_asset!(
             asset,
             ROIVectorMarketDepth,